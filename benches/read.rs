@@ -0,0 +1,46 @@
+//! Compares reading large values with and without the `mmap` feature
+//! Run with `cargo bench --bench read` and `cargo bench --bench read --features mmap`
+
+use configstore::Configstore;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200;
+
+fn bench<F>(name: &str, mut read: F)
+where
+    F: FnMut(),
+{
+    // Warms up the page cache so every run reads from memory
+    read();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        read();
+    }
+    let per_read: Duration = start.elapsed() / ITERATIONS;
+    println!(
+        "{:<28} {:>10.1} µs/read",
+        name,
+        per_read.as_secs_f64() * 1e6
+    );
+}
+
+fn main() {
+    let config_store = Configstore::temp().unwrap();
+    let blob: Vec<u8> = (0..16 * 1024 * 1024).map(|i| i as u8).collect();
+    config_store.set_blob("cache", &blob).unwrap();
+    let list: Vec<u64> = (0..200_000).collect();
+    config_store.set("list", &list).unwrap();
+
+    bench("get_blob 16 MiB", || {
+        black_box(config_store.get_blob("cache").unwrap());
+    });
+    bench("get_bytes 16 MiB", || {
+        let bytes = config_store.get_bytes("cache").unwrap();
+        // Touches every page, like a reader going through the whole value
+        black_box(bytes.iter().step_by(4096).map(|b| *b as u64).sum::<u64>());
+    });
+    bench("get 200k element list", || {
+        black_box(config_store.get::<Vec<u64>>("list").unwrap());
+    });
+}
@@ -0,0 +1,253 @@
+use crate::atomic::AtomicFile;
+use crate::storage::Backend;
+use crate::{Configstore, Error};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::Mutex;
+
+/// Permissions and group the file of a key is written with, registered with `set_access`,
+/// like a config file of a system service that admin tools of a group read too
+/// Only applied on Unix, to stores of the files backend
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Access, Configstore};
+///
+/// let config_store = Configstore::temp().unwrap();
+/// match config_store.set_access("service", Access::group_readable("adm")) {
+///     Ok(()) => config_store.set("service", "listen = 8080").unwrap(),
+///     // On Windows, or when the current user is not a member of adm
+///     Err(e) => eprintln!("{}", e),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    mode: u32,
+    group: Option<String>,
+}
+
+impl Access {
+    /// Files with the permission bits mode, like `0o640`
+    /// The owner must be able to read and write them
+    pub fn mode(mode: u32) -> Self {
+        Access { mode, group: None }
+    }
+
+    /// Files readable by the members of a group and by their owner, who can write them (`0640`)
+    pub fn group_readable(group: &str) -> Self {
+        Access::mode(0o640).group(group)
+    }
+
+    /// Gives the files to a group, by name or numeric id
+    /// The current user must be a member of the group, unless it is root
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+}
+
+/// An Access with its group resolved, applied to the files of a key
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FileAccess {
+    mode: u32,
+    gid: Option<u32>,
+}
+
+impl FileAccess {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    pub(crate) fn apply(&self, file: &File) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if self.gid.is_some() {
+                std::os::unix::fs::fchown(file, None, self.gid)?;
+            }
+            file.set_permissions(std::fs::Permissions::from_mode(self.mode))?;
+        }
+        Ok(())
+    }
+}
+
+/// The access of the keys registered with `set_access`
+#[derive(Default)]
+pub(crate) struct AccessRules {
+    by_key: Mutex<HashMap<String, FileAccess>>,
+}
+
+impl AccessRules {
+    pub(crate) fn get(&self, key: &str) -> Option<FileAccess> {
+        self.by_key.lock().unwrap().get(key).copied()
+    }
+
+    pub(crate) fn keys(&self) -> Vec<String> {
+        self.by_key.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+impl Configstore {
+    /// Writes the files of a key with other permissions than the store's, from now on
+    /// The file the key already has is changed right away. Replaces the previous access of key
+    /// Files with a custom access keep their own file when the store is compacted
+    ///
+    /// # Errors
+    /// Fails with `Error::AccessUnsupported` if the access cannot be applied: on other platforms
+    /// than Unix, with other backends than the files one, to a secure store when the group or
+    /// others would get access, if the owner could not read and write the file, if the group does
+    /// not exist or if the current user cannot give files to it
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn set_access(&self, key: &str, access: Access) -> Result<()> {
+        self.check_writable()?;
+        let unsupported = |reason: String| -> anyhow::Error {
+            Error::AccessUnsupported {
+                key: key.to_string(),
+                reason,
+            }
+            .into()
+        };
+        if !cfg!(unix) {
+            return Err(unsupported(
+                "file permissions and groups are only applied on Unix".to_string(),
+            ));
+        }
+        if self.inner.storage.backend != Backend::Files {
+            return Err(unsupported(format!(
+                "the {:?} backend does not keep each key in a file of its own",
+                self.inner.storage.backend
+            )));
+        }
+        if access.mode & !0o777 != 0 {
+            return Err(unsupported(format!(
+                "{:o} has bits other than the permission bits 0777",
+                access.mode
+            )));
+        }
+        if access.mode & 0o600 != 0o600 {
+            return Err(unsupported(format!(
+                "the store could not read and write files with permissions {:o}",
+                access.mode
+            )));
+        }
+        if self.inner.storage.is_private() && access.mode & 0o077 != 0 {
+            return Err(unsupported(
+                "the store is secure, only its owner can enter its directory".to_string(),
+            ));
+        }
+        let gid = match &access.group {
+            Some(group) => Some(
+                resolve_group(group)
+                    .ok_or_else(|| unsupported(format!("there is no group {}", group)))?,
+            ),
+            None => None,
+        };
+        let file_access = FileAccess {
+            mode: access.mode,
+            gid,
+        };
+        // Tried on a file that is thrown away, so that writes do not fail later
+        let probe = AtomicFile::create_private(&self.dir().join("access-probe"))?;
+        if let Err(e) = file_access.apply(probe.as_file()) {
+            return Err(unsupported(match &access.group {
+                Some(group) if e.kind() == std::io::ErrorKind::PermissionDenied => format!(
+                    "the current user cannot give files to group {}, it is not a member of it",
+                    group
+                ),
+                _ => e.to_string(),
+            }));
+        }
+        drop(probe);
+        self.inner
+            .access
+            .by_key
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), file_access);
+        for name in self.access_names(key) {
+            match File::open(self.dir().join(&name)) {
+                Ok(file) => file_access.apply(&file)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// The names of the entries a key with a custom access can have
+    pub(crate) fn access_names(&self, key: &str) -> Vec<String> {
+        self.formats()
+            .map(|format| format.extension())
+            .chain(std::iter::once(crate::blob::BLOB_EXTENSION))
+            .map(|extension| self.entry_name(key, extension))
+            .collect()
+    }
+}
+
+/// The id of a group, by name or numeric id
+fn resolve_group(group: &str) -> Option<u32> {
+    if let Ok(gid) = group.parse() {
+        return Some(gid);
+    }
+    let groups = std::fs::read_to_string("/etc/group").ok()?;
+    gid_in_groups(&groups, group)
+}
+
+/// The id of a group in the contents of `/etc/group`
+fn gid_in_groups(groups: &str, name: &str) -> Option<u32> {
+    groups
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 3 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    fn unsupported(result: Result<()>) -> bool {
+        matches!(
+            result.unwrap_err().downcast_ref::<Error>(),
+            Some(Error::AccessUnsupported { .. })
+        )
+    }
+
+    #[test]
+    fn keys_get_their_own_permissions() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("service", "old").unwrap();
+        let gid = std::fs::metadata(config_store.dir()).unwrap().gid();
+        config_store
+            .set_access("service", Access::group_readable(&gid.to_string()))
+            .unwrap();
+        let metadata = || std::fs::metadata(config_store.config_path("service")).unwrap();
+        assert_eq!(metadata().permissions().mode() & 0o777, 0o640);
+        config_store.set("service", "new").unwrap();
+        config_store.compact().unwrap();
+        assert_eq!(metadata().permissions().mode() & 0o777, 0o640);
+        assert_eq!(metadata().gid(), gid);
+
+        assert!(unsupported(
+            config_store.set_access("a", Access::mode(0o4755))
+        ));
+        assert!(unsupported(
+            config_store.set_access("a", Access::mode(0o044))
+        ));
+        assert!(unsupported(
+            config_store.set_access("a", Access::group_readable("no-such-group"))
+        ));
+        let secure = config_store.reopen().secure(true).build().unwrap();
+        assert!(unsupported(secure.set_access("a", Access::mode(0o640))));
+        assert_eq!(
+            gid_in_groups("root:x:0:\nadm:x:4:syslog,ann\n", "adm"),
+            Some(4)
+        );
+        let names: Vec<_> = std::fs::read_dir(config_store.dir())
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.contains("probe"))
+            .collect();
+        assert!(names.is_empty());
+    }
+}
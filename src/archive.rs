@@ -0,0 +1,369 @@
+use crate::atomic;
+use crate::storage::{self, Backend, Entry};
+use crate::Configstore;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the archive file inside the store directory, hidden so that it is never listed as a key
+pub(crate) const ARCHIVE_NAME: &str = ".archive";
+
+/// Name of the file locked while the archive is rewritten
+const LOCK_NAME: &str = ".archive.lock";
+
+/// First bytes of archive files, followed by their entries
+const MAGIC: &[u8] = b"CSARCHV1";
+
+/// An entry kept in the archive
+#[derive(Clone)]
+struct Archived {
+    bytes: Arc<Vec<u8>>,
+    /// Milliseconds since the unix epoch
+    modified: u64,
+}
+
+type Entries = BTreeMap<String, Archived>;
+
+/// The archive as last read, with the modification time and size of its file then
+struct Cached {
+    modified: SystemTime,
+    len: u64,
+    entries: Arc<Entries>,
+}
+
+/// The entries `compact` moved out of their own files into a single file of the store directory,
+/// for the files backend. An entry written again gets its own file back, which takes precedence
+/// over its archived copy until the next `compact`
+pub(crate) struct Archive {
+    dir: PathBuf,
+    cache: Mutex<Option<Cached>>,
+}
+
+impl Archive {
+    pub(crate) fn open(dir: &Path) -> Self {
+        Archive {
+            dir: dir.to_path_buf(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn path(&self) -> PathBuf {
+        self.dir.join(ARCHIVE_NAME)
+    }
+
+    /// The archived entries, read again only when the archive changed since it was last read
+    fn entries_by_name(&self) -> Result<Arc<Entries>> {
+        let path = self.path();
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Arc::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let (modified, len) = (metadata.modified()?, metadata.len());
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(cached) = cache.as_ref() {
+            if cached.modified == modified && cached.len == len {
+                return Ok(cached.entries.clone());
+            }
+        }
+        let entries = Arc::new(decode(&std::fs::read(&path)?)?);
+        *cache = Some(Cached {
+            modified,
+            len,
+            entries: entries.clone(),
+        });
+        Ok(entries)
+    }
+
+    /// Changes the archive while holding its lock, so that changes of other processes are not lost
+    /// Returns what change returned, the archive is only written again if change returns true
+    fn update<F>(&self, private: bool, change: F) -> Result<bool>
+    where
+        F: FnOnce(&mut Entries) -> bool,
+    {
+        let _lock = atomic::lock(&self.dir.join(LOCK_NAME))?;
+        let mut entries = Entries::clone(&*self.entries_by_name()?);
+        if !change(&mut entries) {
+            return Ok(false);
+        }
+        let path = self.path();
+        if entries.is_empty() {
+            std::fs::remove_file(&path)?;
+        } else if private {
+            atomic::write_private(&path, &encode(&entries))?;
+        } else {
+            atomic::write(&path, &encode(&entries))?;
+        }
+        // The next read compares the new file with the cache
+        *self.cache.lock().unwrap() = None;
+        Ok(true)
+    }
+
+    /// The bytes of an archived entry, None if it is not archived
+    pub(crate) fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .entries_by_name()?
+            .get(name)
+            .map(|archived| archived.bytes.to_vec()))
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> Result<bool> {
+        Ok(self.entries_by_name()?.contains_key(name))
+    }
+
+    /// Removes entries from the archive, returns whether any of them was archived
+    pub(crate) fn remove(&self, names: &[&str]) -> Result<bool> {
+        if !names.iter().any(|name| self.contains(name).unwrap_or(true)) {
+            return Ok(false);
+        }
+        // Keeps the permissions the archive was written with
+        let private = is_private(&std::fs::metadata(self.path())?);
+        self.update(private, |entries| {
+            let removed = names.iter().filter(|name| entries.remove(**name).is_some());
+            removed.count() > 0
+        })
+    }
+
+    pub(crate) fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for (name, archived) in self.entries_by_name()?.iter() {
+            if let Some(key) = storage::entry_key(name) {
+                entries.push(Entry {
+                    key: key.to_string(),
+                    name: name.clone(),
+                    size: archived.bytes.len() as u64,
+                    modified: UNIX_EPOCH + Duration::from_millis(archived.modified),
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Moves the entries that have their own file into the archive, except the kept ones,
+    /// returns their names. Entries readable by the current user only keep their file,
+    /// unless the archive is private too
+    fn compact(&self, private: bool, kept: &[String]) -> Result<Vec<String>> {
+        let mut moved = Vec::new();
+        let mut files = Vec::new();
+        for entry in storage::file_entries(&self.dir)? {
+            let path = self.dir.join(&entry.name);
+            if kept.contains(&entry.name) {
+                continue;
+            }
+            if !private && is_private(&std::fs::metadata(&path)?) {
+                continue;
+            }
+            let bytes = std::fs::read(&path)?;
+            files.push((entry, bytes));
+        }
+        if files.is_empty() {
+            return Ok(moved);
+        }
+        self.update(private, |entries| {
+            for (entry, bytes) in &files {
+                let modified = entry
+                    .modified
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as u64);
+                let archived = Archived {
+                    bytes: Arc::new(bytes.clone()),
+                    modified,
+                };
+                entries.insert(entry.name.clone(), archived);
+            }
+            true
+        })?;
+        for (entry, bytes) in files {
+            if remove_if_unchanged(&self.dir, &entry.name, &bytes)? {
+                moved.push(entry.name);
+            }
+        }
+        moved.sort();
+        Ok(moved)
+    }
+}
+
+/// Removes the file of an entry if it still holds bytes, returns whether it was removed
+/// The file is moved aside before it is compared, so that a write racing with the comparison
+/// gives the entry a new file instead of being removed with the old one
+fn remove_if_unchanged(dir: &Path, name: &str, bytes: &[u8]) -> Result<bool> {
+    let path = dir.join(name);
+    let aside = dir.join(format!(".{}.compacted", name));
+    match std::fs::rename(&path, &aside) {
+        Ok(()) => (),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    }
+    let unchanged = std::fs::read(&aside)? == bytes;
+    if !unchanged {
+        // Written again since it was read, newer than its archived copy. Linking fails instead of
+        // replacing a file written after it was moved aside, which is newer still
+        match std::fs::hard_link(&aside, &path) {
+            Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e.into()),
+            _ => (),
+        }
+    }
+    std::fs::remove_file(&aside)?;
+    Ok(unchanged)
+}
+
+/// The archive file format: the magic bytes, then for each entry the length of its name,
+/// its name, its modification time, the length of its bytes and its bytes, in little endian
+fn encode(entries: &Entries) -> Vec<u8> {
+    let size = entries
+        .iter()
+        .map(|(name, archived)| 20 + name.len() + archived.bytes.len())
+        .sum::<usize>();
+    let mut encoded = Vec::with_capacity(MAGIC.len() + size);
+    encoded.extend_from_slice(MAGIC);
+    for (name, archived) in entries {
+        encoded.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        encoded.extend_from_slice(name.as_bytes());
+        encoded.extend_from_slice(&archived.modified.to_le_bytes());
+        encoded.extend_from_slice(&(archived.bytes.len() as u64).to_le_bytes());
+        encoded.extend_from_slice(&archived.bytes);
+    }
+    encoded
+}
+
+fn decode(mut encoded: &[u8]) -> Result<Entries> {
+    let corrupt = || anyhow::Error::msg("Corrupt store archive");
+    if !encoded.starts_with(MAGIC) {
+        return Err(corrupt());
+    }
+    encoded = &encoded[MAGIC.len()..];
+    let mut take = |len: usize| -> Result<&[u8]> {
+        if encoded.len() < len {
+            return Err(corrupt());
+        }
+        let (taken, rest) = encoded.split_at(len);
+        encoded = rest;
+        Ok(taken)
+    };
+    let mut entries = Entries::new();
+    loop {
+        let name_len = match take(4) {
+            Ok(len) => u32::from_le_bytes(len.try_into()?) as usize,
+            Err(_) => break,
+        };
+        let name = String::from_utf8(take(name_len)?.to_vec()).map_err(|_| corrupt())?;
+        let modified = u64::from_le_bytes(take(8)?.try_into()?);
+        let len = u64::from_le_bytes(take(8)?.try_into()?) as usize;
+        let bytes = Arc::new(take(len)?.to_vec());
+        entries.insert(name, Archived { bytes, modified });
+    }
+    Ok(entries)
+}
+
+/// Whether only the current user can read a file
+#[cfg(unix)]
+fn is_private(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o077 == 0
+}
+
+#[cfg(not(unix))]
+fn is_private(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+impl Configstore {
+    /// Moves the values and blobs of the store into a single archive file, which it keeps reading
+    /// them from, to save the inodes and the startup time of stores with thousands of small
+    /// cache-like entries. Values written afterwards get their own file again until the next
+    /// compaction, which makes `compact` cheap to run periodically. Returns the names of the
+    /// entries moved. Only the files backend is compacted, the others already are compact
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// for i in 0..1000 {
+    ///     config_store.set(&format!("thumbnail{}", i), i).unwrap();
+    /// }
+    /// assert_eq!(config_store.compact().unwrap().len(), 1000);
+    /// assert_eq!(config_store.get::<u32>("thumbnail42").unwrap(), 42);
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the archive cannot be written, the store is unchanged then
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn compact(&self) -> Result<Vec<String>> {
+        self.check_writable()?;
+        let storage = &self.inner.storage;
+        if storage.backend != Backend::Files {
+            return Ok(Vec::new());
+        }
+        storage.flush()?;
+        // Read-modify-writes wait for the entries to be moved
+        let _lock = self.lock_for(ARCHIVE_NAME)?;
+        // Files with a custom access keep it
+        let kept: Vec<String> = self
+            .inner
+            .access
+            .keys()
+            .iter()
+            .flat_map(|key| self.access_names(key))
+            .collect();
+        Archive::open(&storage.dir).compact(storage.is_private(), &kept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compacted_entries_are_read_from_the_archive() {
+        let config_store = Configstore::temp().unwrap();
+        for i in 0..50 {
+            config_store.set(&format!("key{}", i), i).unwrap();
+        }
+        config_store.set_blob("icon", &[0xff, 0x00]).unwrap();
+        config_store.set_secure("token", "s3cr3t").unwrap();
+        assert_eq!(config_store.compact().unwrap().len(), 51);
+        let names: Vec<_> = std::fs::read_dir(config_store.dir())
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        #[cfg(unix)]
+        assert_eq!(names, vec!["token.json"]);
+        assert_eq!(config_store.keys().unwrap().len(), 51);
+        assert_eq!(config_store.get::<u32>("key7").unwrap(), 7);
+        assert_eq!(config_store.get_blob("icon").unwrap(), vec![0xff, 0x00]);
+        assert!(config_store.config_path("key7").ends_with(ARCHIVE_NAME));
+
+        // Files written afterwards take precedence, deleted entries do not come back
+        config_store.set("key7", 70).unwrap();
+        assert_eq!(config_store.get::<u32>("key7").unwrap(), 70);
+        config_store.delete("key7").unwrap();
+        config_store.delete("key8").unwrap();
+        assert!(config_store.get::<u32>("key7").is_err());
+        assert!(config_store.get::<u32>("key8").is_err());
+        assert!(config_store.delete("key8").is_err());
+        config_store.rename("key9", "nine").unwrap();
+        assert_eq!(config_store.get::<u32>("nine").unwrap(), 9);
+        assert!(config_store.get::<u32>("key9").is_err());
+
+        let reopened = config_store.reopen().build().unwrap();
+        assert_eq!(reopened.get::<u32>("key42").unwrap(), 42);
+        assert_eq!(reopened.compact().unwrap(), vec!["nine.json"]);
+        assert_eq!(reopened.keys().unwrap().len(), 49);
+
+        // Files that changed since they were archived are kept, whatever their modification time
+        let dir = reopened.dir();
+        std::fs::write(dir.join("raced.json"), "2").unwrap();
+        assert!(!remove_if_unchanged(dir, "raced.json", b"1").unwrap());
+        assert_eq!(std::fs::read(dir.join("raced.json")).unwrap(), b"2");
+        assert!(remove_if_unchanged(dir, "raced.json", b"2").unwrap());
+        assert!(!dir.join("raced.json").exists());
+        assert!(!remove_if_unchanged(dir, "raced.json", b"2").unwrap());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::Error;
+use anyhow::Result;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Suffix of the temporary files, interrupted writes can leave them behind
+pub(crate) const TEMP_SUFFIX: &str = ".tmp";
+
+/// How many times an operation failing with a transient error is tried again
+const RETRIES: u32 = 5;
+
+/// Delay before the first retry, doubled before each of the next ones
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A file written under a temporary name next to its destination
+/// and renamed over it on `persist`, so readers never see a partially written file
+/// The temporary file is removed if the AtomicFile is dropped before being persisted
+pub(crate) struct AtomicFile {
+    file: Option<File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    /// Creates the temporary file with the permissions of the file it replaces, if any
+    /// so files made private by `set_secure` stay private
+    pub(crate) fn create(path: &Path) -> Result<Self> {
+        AtomicFile::open(path, false)
+    }
+
+    /// Creates the temporary file readable and writable by the current user only
+    pub(crate) fn create_private(path: &Path) -> Result<Self> {
+        AtomicFile::open(path, true)
+    }
+
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn open(path: &Path, private: bool) -> Result<Self> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let temp_path = path.with_file_name(format!(
+            ".{}.{}-{}{}",
+            file_name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            TEMP_SUFFIX
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if private {
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        }
+        let file = match retry(&temp_path, || options.open(&temp_path)) {
+            // The store directory was deleted while the store was open, by the user or
+            // a cleanup tool, the write recreates it
+            Err(e) if crate::error::is_not_found(&e) => {
+                let mut builder = std::fs::DirBuilder::new();
+                builder.recursive(true);
+                #[cfg(unix)]
+                if private {
+                    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+                }
+                if let Some(dir) = temp_path.parent() {
+                    builder.create(dir)?;
+                }
+                retry(&temp_path, || options.open(&temp_path))?
+            }
+            file => file?,
+        };
+        #[cfg(unix)]
+        crate::owner::inherit_dir_owner(&file, &temp_path)?;
+        #[cfg(unix)]
+        if !private {
+            if let Ok(metadata) = std::fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+        }
+        Ok(AtomicFile {
+            file: Some(file),
+            temp_path,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The temporary file, to change its permissions before it is persisted
+    pub(crate) fn as_file(&self) -> &File {
+        self.file.as_ref().expect("file is open until persisted")
+    }
+
+    /// Number of bytes written so far
+    pub(crate) fn len(&self) -> Result<u64> {
+        Ok(std::fs::metadata(&self.temp_path)?.len())
+    }
+
+    /// Flushes the data to disk and moves the file to its destination
+    pub(crate) fn persist(mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        retry(&self.path, || std::fs::rename(&self.temp_path, &self.path))
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file
+            .as_mut()
+            .expect("file is open until persisted")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file
+            .as_mut()
+            .expect("file is open until persisted")
+            .flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Either persist failed or it was never called, in both cases the temp file is leftover
+        if self.temp_path.exists() {
+            drop(self.file.take());
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Atomically replaces the contents of the file at path
+pub(crate) fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(bytes)?;
+    file.persist()
+}
+
+/// Atomically replaces the contents of the file at path with a file only the current user can read
+pub(crate) fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut file = AtomicFile::create_private(path)?;
+    file.write_all(bytes)?;
+    file.persist()
+}
+
+/// Whether an io error is likely to go away by itself, like a file briefly opened
+/// without sharing by an antivirus or indexer on Windows
+fn is_transient(e: &std::io::Error) -> bool {
+    // Only ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION, that Windows reports as
+    // PermissionDenied like real permission problems, which are not retried
+    e.kind() == ErrorKind::Interrupted
+        || cfg!(windows) && matches!(e.raw_os_error(), Some(32) | Some(33))
+}
+
+/// Runs a file operation on path, trying it again a few times with increasing delays
+/// while it fails with a transient error, fails with `Error::Busy` if the error persists
+pub(crate) fn retry<T, F>(path: &Path, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> std::io::Result<T>,
+{
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(e) if is_transient(&e) && attempt < RETRIES => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) if is_transient(&e) => {
+                return Err(anyhow::Error::from(e).context(Error::Busy {
+                    path: path.to_path_buf(),
+                }))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Opens and exclusively locks a lock file, waiting for other holders to release it
+/// The lock is released when the returned file is dropped
+pub(crate) fn lock(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_file_leaves_nothing_behind() {
+        let dir = std::env::temp_dir().join("configstore-rs-atomic");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dropped.json");
+        let _ = std::fs::remove_file(&path);
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        let temp_path = file.temp_path.clone();
+        assert!(temp_path.exists());
+        drop(file);
+        assert!(!temp_path.exists());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn write_replaces_contents() {
+        let dir = std::env::temp_dir().join("configstore-rs-atomic");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("replaced.json");
+        write(&path, b"first").unwrap();
+        write(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+    }
+
+    #[test]
+    fn transient_errors_are_retried_and_dirs_recreated() {
+        let mut failures = 2;
+        let value = retry(Path::new("busy.json"), || {
+            if failures > 0 {
+                failures -= 1;
+                return Err(std::io::Error::from(ErrorKind::Interrupted));
+            }
+            Ok(42)
+        })
+        .unwrap();
+        assert_eq!((value, failures), (42, 0));
+        let err = retry(Path::new("busy.json"), || -> std::io::Result<()> {
+            Err(ErrorKind::Interrupted.into())
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Busy { .. })
+        ));
+        let mut attempts = 0;
+        let err = retry(Path::new("denied.json"), || -> std::io::Result<()> {
+            attempts += 1;
+            Err(ErrorKind::PermissionDenied.into())
+        })
+        .unwrap_err();
+        assert_eq!(attempts, 1);
+        assert!(err.downcast_ref::<Error>().is_none());
+
+        let config_store = crate::Configstore::temp().unwrap();
+        std::fs::remove_dir_all(config_store.dir()).unwrap();
+        config_store.set("key", 1).unwrap();
+        assert_eq!(config_store.get::<u32>("key").unwrap(), 1);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{atomic, Configstore};
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Directory of the store holding attachments, hidden so that it is never listed as a key
+const ATTACHMENTS_DIR: &str = ".attachments";
+
+/// The field identifying attachment references in stored values
+const REFERENCE_FIELD: &str = "$attachment";
+
+/// Attachments younger than this are never collected, so that one can be referenced after attach
+const GC_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// A reference to bytes stored once by content with `attach`, to keep in values
+/// Serialized as `{"$attachment": "<hash>", "size": <bytes>}`, which is how `vacuum`
+/// finds the attachments still in use
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Attachment, Configstore};
+///
+/// let config_store = Configstore::temp().unwrap();
+/// let model = config_store.attach(&[1, 2, 3, 4]).unwrap();
+/// config_store.set("models", vec![model.clone()]).unwrap();
+/// // The same bytes are stored once
+/// assert_eq!(config_store.attach(&[1, 2, 3, 4]).unwrap(), model);
+///
+/// let models: Vec<Attachment> = config_store.get("models").unwrap();
+/// assert_eq!(config_store.read_attachment(&models[0]).unwrap(), vec![1, 2, 3, 4]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Attachment {
+    #[serde(rename = "$attachment")]
+    hash: String,
+    size: u64,
+}
+
+impl Attachment {
+    /// The hash of the content, naming its file
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// The size of the content in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// 128-bit FNV-1a of the content, wide enough that distinct contents do not collide in practice
+/// Not a cryptographic hash, attach verifies that the bytes under an existing hash are the same
+fn content_hash(bytes: &[u8]) -> String {
+    let mut hash: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    for byte in bytes {
+        hash ^= u128::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+    }
+    format!("{:032x}", hash)
+}
+
+impl Configstore {
+    /// Stores bytes by their content and returns a reference to keep in values, for large
+    /// payloads like downloaded artifacts. Identical bytes are stored once, however often attached
+    /// Attachments no value references anymore are deleted by `vacuum`, check the Attachment docs for usage
+    ///
+    /// # Errors
+    /// Could error if the attachment cannot be written. Fails with `Error::ReadOnly` on read-only stores
+    pub fn attach(&self, bytes: &[u8]) -> Result<Attachment> {
+        self.check_writable()?;
+        let attachment = Attachment {
+            hash: content_hash(bytes),
+            size: bytes.len() as u64,
+        };
+        let path = self.attachment_path(&attachment);
+        match std::fs::read(&path) {
+            Ok(existing) if existing == bytes => {
+                // Refreshes the grace period of an attachment being reused
+                std::fs::File::options()
+                    .write(true)
+                    .open(&path)?
+                    .set_modified(SystemTime::now())?;
+                return Ok(attachment);
+            }
+            Ok(_) => {
+                return Err(anyhow::Error::msg(format!(
+                    "Attachment {} already holds other bytes",
+                    attachment.hash
+                )))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+        std::fs::create_dir_all(self.dir().join(ATTACHMENTS_DIR))?;
+        atomic::write(&path, bytes)?;
+        Ok(attachment)
+    }
+
+    /// Reads the bytes of an attachment
+    ///
+    /// # Errors
+    /// Could error if the attachment was deleted by `vacuum` or cannot be read
+    pub fn read_attachment(&self, attachment: &Attachment) -> Result<Vec<u8>> {
+        Ok(std::fs::read(self.attachment_path(attachment))?)
+    }
+
+    /// The file holding an attachment, to open it with other programs
+    pub fn attachment_path(&self, attachment: &Attachment) -> PathBuf {
+        self.dir().join(ATTACHMENTS_DIR).join(&attachment.hash)
+    }
+
+    /// Counts the references to each attachment held by the values of the store
+    pub(crate) fn attachment_references(&self) -> Result<BTreeMap<String, usize>> {
+        let mut references = BTreeMap::new();
+        for key in self.keys()? {
+            match self.get_raw(&key) {
+                Ok(value) => count_references(&value, &mut references),
+                Err(e) if crate::error::is_not_found(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(references)
+    }
+
+    /// Deletes the attachments no value references that are older than the grace period,
+    /// returns their hashes and total size
+    pub(crate) fn collect_attachments(&self) -> Result<(Vec<String>, u64)> {
+        let read_dir = match std::fs::read_dir(self.dir().join(ATTACHMENTS_DIR)) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(e.into()),
+        };
+        let references = self.attachment_references()?;
+        let mut collected = Vec::new();
+        let mut freed_bytes = 0;
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            let hash = dir_entry.file_name().to_string_lossy().into_owned();
+            let metadata = dir_entry.metadata()?;
+            let recent = metadata
+                .modified()?
+                .elapsed()
+                .map_or(true, |age| age < GC_GRACE);
+            if references.contains_key(&hash) || recent || hash.starts_with('.') {
+                continue;
+            }
+            std::fs::remove_file(dir_entry.path())?;
+            freed_bytes += metadata.len();
+            collected.push(hash);
+        }
+        collected.sort();
+        Ok((collected, freed_bytes))
+    }
+}
+
+fn count_references(value: &Value, references: &mut BTreeMap<String, usize>) {
+    match value {
+        Value::Object(fields) => {
+            if let Some(Value::String(hash)) = fields.get(REFERENCE_FIELD) {
+                *references.entry(hash.clone()).or_insert(0) += 1;
+            }
+            for field in fields.values() {
+                count_references(field, references);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                count_references(item, references);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vacuum_collects_unreferenced_attachments() {
+        let config_store = Configstore::temp().unwrap();
+        let kept = config_store.attach(b"kept").unwrap();
+        let dropped = config_store.attach(b"dropped").unwrap();
+        let fresh = config_store.attach(b"fresh").unwrap();
+        config_store
+            .set("cache", serde_json::json!({"items": [kept, kept]}))
+            .unwrap();
+        assert_eq!(
+            config_store.attachment_references().unwrap()[kept.hash()],
+            2
+        );
+
+        let old = SystemTime::now() - GC_GRACE * 2;
+        for attachment in &[&kept, &dropped] {
+            let path = config_store.attachment_path(attachment);
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(old).unwrap();
+        }
+        let report = config_store.vacuum().unwrap();
+        assert_eq!(report.attachments, vec![dropped.hash().to_string()]);
+        assert_eq!(report.freed_bytes, dropped.size());
+        assert!(config_store.read_attachment(&kept).is_ok());
+        assert!(config_store.read_attachment(&fresh).is_ok());
+        assert!(config_store.read_attachment(&dropped).is_err());
+    }
+}
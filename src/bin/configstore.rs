@@ -0,0 +1,177 @@
+//! Inspects and edits the store of any application using configstore
+//! Built with the `cli` feature: `cargo install configstore --features cli`
+
+use anyhow::Result;
+use configstore::{AppUI, Configstore, Format, Scope};
+use serde_json::Value;
+use std::io::{ErrorKind, Write};
+
+const USAGE: &str = "Usage: configstore [options] <app> <command> [args]
+
+Commands:
+    list                 Lists the keys of the store
+    get <key>            Prints the value of a key as json
+    set <key> <value>    Sets a key, the value is parsed as json or stored as a string
+    delete <key>         Deletes a key
+    path [key]           Prints the directory of the store, or the file of a key
+    export               Prints every key and value as a single json object
+
+Options:
+    --machine            Use the machine wide store instead of the user's
+    --gui                The app is a graphical application
+    --profile <name>     Use one of the app's named profiles
+    --format <format>    The format the app stores values in: json, toml, plist, msgpack or cbor
+    --read-only          Never modify the store";
+
+struct Args {
+    app_name: String,
+    command: String,
+    rest: Vec<String>,
+    gui: bool,
+    scope: Scope,
+    profile: Option<String>,
+    format: Format,
+    read_only: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut positional = Vec::new();
+    let mut gui = false;
+    let mut scope = Scope::User;
+    let mut profile = None;
+    let mut format = Format::Json;
+    let mut read_only = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--machine" => scope = Scope::Machine,
+            "--gui" => gui = true,
+            "--read-only" => read_only = true,
+            "--profile" => profile = Some(next_value(&mut args, "--profile")?),
+            "--format" => format = parse_format(&next_value(&mut args, "--format")?)?,
+            "-h" | "--help" => return Err(anyhow::Error::msg(USAGE)),
+            _ if arg.starts_with("--") => {
+                return Err(anyhow::Error::msg(format!("Unknown option {}", arg)))
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let (app_name, command) = match (positional.next(), positional.next()) {
+        (Some(app_name), Some(command)) => (app_name, command),
+        _ => return Err(anyhow::Error::msg(USAGE)),
+    };
+    Ok(Args {
+        app_name,
+        command,
+        rest: positional.collect(),
+        gui,
+        scope,
+        profile,
+        format,
+        read_only,
+    })
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow::Error::msg(format!("{} expects a value", option)))
+}
+
+fn parse_format(format: &str) -> Result<Format> {
+    match format {
+        "json" => Ok(Format::Json),
+        #[cfg(feature = "toml")]
+        "toml" => Ok(Format::Toml),
+        #[cfg(feature = "plist")]
+        "plist" => Ok(Format::Plist),
+        #[cfg(feature = "msgpack")]
+        "msgpack" => Ok(Format::MessagePack),
+        #[cfg(feature = "cbor")]
+        "cbor" => Ok(Format::Cbor),
+        _ => Err(anyhow::Error::msg(format!(
+            "Unsupported format {}, it may require enabling its feature",
+            format
+        ))),
+    }
+}
+
+fn open(args: &Args) -> Result<Configstore> {
+    let app_ui = if args.gui {
+        AppUI::Graphical
+    } else {
+        AppUI::CommandLine
+    };
+    let mut builder = Configstore::builder(&args.app_name, app_ui)
+        .scope(args.scope)
+        .format(args.format)
+        .read_only(args.read_only);
+    if let Some(profile) = &args.profile {
+        builder = builder.profile(profile);
+    }
+    builder.build()
+}
+
+fn key_arg(args: &Args) -> Result<&str> {
+    args.rest
+        .first()
+        .map(String::as_str)
+        .ok_or_else(|| anyhow::Error::msg(format!("{} expects a key", args.command)))
+}
+
+fn run(args: Args) -> Result<()> {
+    let store = open(&args)?;
+    let mut out = std::io::stdout().lock();
+    match args.command.as_str() {
+        "list" => {
+            for key in store.keys()? {
+                writeln!(out, "{}", key)?;
+            }
+        }
+        "get" => {
+            let value = store.get_raw(key_arg(&args)?)?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&value)?)?;
+        }
+        "set" => {
+            let key = key_arg(&args)?;
+            let raw = args
+                .rest
+                .get(1)
+                .ok_or_else(|| anyhow::Error::msg("set expects a value"))?;
+            let value = serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.clone()));
+            store.set_raw(key, value)?;
+        }
+        "delete" => store.delete(key_arg(&args)?)?,
+        "path" => match args.rest.first() {
+            Some(key) => writeln!(out, "{}", store.config_path(key).display())?,
+            None => writeln!(out, "{}", store.dir().display())?,
+        },
+        "export" => {
+            let mut values = serde_json::Map::new();
+            for key in store.keys()? {
+                let value = store.get_raw(&key)?;
+                values.insert(key, value);
+            }
+            writeln!(out, "{}", serde_json::to_string_pretty(&values)?)?;
+        }
+        command => {
+            return Err(anyhow::Error::msg(format!(
+                "Unknown command {}\n\n{}",
+                command, USAGE
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = parse_args(std::env::args().skip(1)).and_then(run) {
+        // Piping into a command like head closes stdout early, which is not an error
+        if e.downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == ErrorKind::BrokenPipe)
+        {
+            return;
+        }
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::Configstore;
+use anyhow::Result;
+
+pub(crate) const BLOB_EXTENSION: &str = "bin";
+
+impl Configstore {
+    /// Stores raw bytes under a key, bypassing serialization
+    /// The bytes are saved as is in $CONFIG/configstore-rs/$APPNAME/key.bin with the files backend
+    /// Blobs live next to regular values, a key can hold both a value and a blob
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set_blob("avatar", &[0x89, 0x50, 0x4e, 0x47]).unwrap();
+    /// let avatar = config_store.get_blob("avatar").unwrap();
+    /// assert_eq!(avatar, vec![0x89, 0x50, 0x4e, 0x47]);
+    /// ```
+    ///
+    /// # Errors
+    /// Possible errors if the blob file cannot be written
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn set_blob(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        self.write_entry(key, &self.entry_name(key, BLOB_EXTENSION), bytes, false)
+    }
+
+    /// Check the set_blob docs for usage
+    /// # Errors
+    /// Could produce errors if the blob was never set or cannot be read
+    pub fn get_blob(&self, key: &str) -> Result<Vec<u8>> {
+        let name = self.entry_name(key, BLOB_EXTENSION);
+        let bytes = self.inner.storage.read(&name)?;
+        self.touch(&name);
+        Ok(bytes)
+    }
+
+    /// Deletes the blob stored under a key
+    /// # Errors
+    /// Could produce errors if the blob was never set or cannot be removed
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn delete_blob(&self, key: &str) -> Result<()> {
+        self.remove_entry(key, &self.entry_name(key, BLOB_EXTENSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn blobs_are_stored_raw() {
+        let config_store = Configstore::temp().unwrap();
+        let bytes: Vec<u8> = (0..=255).collect();
+        config_store.set_blob("bytes", &bytes).unwrap();
+        config_store.set("bytes", String::from("value")).unwrap();
+        let raw = std::fs::read(config_store.dir().join("bytes.bin")).unwrap();
+        assert_eq!(raw, bytes);
+        assert_eq!(config_store.get_blob("bytes").unwrap(), bytes);
+        assert_eq!(config_store.get::<String>("bytes").unwrap(), "value");
+        config_store.delete_blob("bytes").unwrap();
+        assert!(config_store.get_blob("bytes").is_err());
+    }
+}
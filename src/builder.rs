@@ -0,0 +1,613 @@
+use crate::compression::{self, Compression};
+use crate::fallback::{Fallback, FallbackCallback};
+use crate::journal::Journal;
+use crate::location::{DirKind, Location, Scope, TempDir};
+use crate::owner::{self, OwnerPolicy};
+use crate::profile::{self, PROFILES_DIR};
+use crate::storage::Storage;
+use crate::symlink::SymlinkPolicy;
+use crate::{Backend, Configstore, Eviction, Format, Inner, Metrics, ReadMode, CONFIG_STORE_NAME};
+use anyhow::Result;
+use platform_dirs::AppUI;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Builder to customize how a Configstore is created
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI, Scope};
+///
+/// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+///     .scope(Scope::User)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ConfigstoreBuilder {
+    app_name: String,
+    app_ui: AppUI,
+    profile: Option<String>,
+    location: Location,
+    temp: bool,
+    pretty: bool,
+    sort_keys: bool,
+    format: Format,
+    key_formats: BTreeMap<String, Format>,
+    read_only: bool,
+    validate_reads: bool,
+    read_mode: ReadMode,
+    recover_corrupt: bool,
+    interpolate: bool,
+    defaults: Option<(String, Format)>,
+    vendor_dir: Option<PathBuf>,
+    secure: bool,
+    debounce: Option<Duration>,
+    journal: bool,
+    ipc_events: bool,
+    owner_policy: OwnerPolicy,
+    symlink_policy: SymlinkPolicy,
+    compression: Option<Compression>,
+    compression_threshold: usize,
+    quota: Option<u64>,
+    eviction: Eviction,
+    trash: Option<Duration>,
+    inherits: Option<String>,
+    metrics: Option<Box<dyn Metrics>>,
+    fallback: Option<FallbackCallback>,
+    backend: Backend,
+}
+
+impl ConfigstoreBuilder {
+    pub(crate) fn new(app_name: &str, app_ui: AppUI) -> Self {
+        ConfigstoreBuilder {
+            app_name: app_name.to_string(),
+            app_ui,
+            profile: None,
+            location: Location::default(),
+            temp: false,
+            pretty: false,
+            sort_keys: false,
+            format: Format::default(),
+            key_formats: BTreeMap::new(),
+            read_only: false,
+            validate_reads: false,
+            read_mode: ReadMode::default(),
+            recover_corrupt: false,
+            interpolate: false,
+            defaults: None,
+            vendor_dir: None,
+            secure: false,
+            debounce: None,
+            journal: false,
+            ipc_events: false,
+            owner_policy: OwnerPolicy::default(),
+            symlink_policy: SymlinkPolicy::default(),
+            compression: None,
+            compression_threshold: compression::DEFAULT_THRESHOLD,
+            quota: None,
+            eviction: Eviction::default(),
+            trash: None,
+            inherits: None,
+            metrics: None,
+            fallback: None,
+            backend: Backend::default(),
+        }
+    }
+
+    /// Opens one of the app's named profiles instead of the default one
+    pub fn profile(mut self, profile: &str) -> Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    /// Sets whether the store is per-user (the default) or shared by the whole machine
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.location.scope = scope;
+        self
+    }
+
+    /// Sets which platform directory the store lives in, defaults to the config directory
+    pub fn dir_kind(mut self, dir_kind: DirKind) -> Self {
+        self.location.kind = dir_kind;
+        self
+    }
+
+    /// Follows the XDG Base Directory spec on every platform instead of only on Linux
+    /// so macOS stores land in `~/.config` (or `$XDG_CONFIG_HOME`) instead of `~/Library`
+    pub fn strict_xdg(mut self, strict_xdg: bool) -> Self {
+        self.location.strict_xdg = strict_xdg;
+        self
+    }
+
+    /// Creates the store in a unique temporary directory, removed when the store is dropped
+    /// Meant for tests, which then never see each other's values nor the user's real settings
+    pub fn temp(mut self, temp: bool) -> Self {
+        self.temp = temp;
+        self
+    }
+
+    /// Keeps the store in a project directory, like `.myapp`, found in the current directory
+    /// or its closest parent that has one, the way tools like rustfmt find their settings
+    /// Falls back to the user store when no parent has the directory, so apps only
+    /// use per-project settings once the user has created the directory
+    pub fn project(mut self, dir_name: &str) -> Self {
+        self.location.project = Some(dir_name.to_string());
+        self
+    }
+
+    /// Starts the project search from dir instead of the current directory,
+    /// for tools taking the directory to work in as an argument
+    pub fn project_start<P>(mut self, dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.location.project_start = Some(dir.into());
+        self
+    }
+
+    /// Stores the app under root instead of the platform's directories
+    pub(crate) fn root(mut self, root: std::path::PathBuf) -> Self {
+        self.location.root = Some(root);
+        self
+    }
+
+    /// Writes values as indented, human-editable JSON instead of a single line
+    /// Object keys keep the order they were serialized in, except in values going through
+    /// `serde_json::Value`, like the ones of set_raw, whose keys are sorted unless the
+    /// `preserve_order` feature is enabled
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Writes the fields of objects and the entries of maps sorted by name, so that saving
+    /// the same data twice gives the same bytes whatever the order of a HashMap, for users
+    /// keeping their config directory in git. TOML documents keep the layout of the fields
+    /// already written in them, so that comments survive
+    pub fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// Sets the file format values are stored in, defaults to json
+    /// Keys stored in another format are still read, and move to this one the next time they are set
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Stores one key in another format than the rest of the store, so that tools and editors
+    /// opening its file find the extension they expect, like a `.toml` file next to json ones
+    /// The key is read in whichever format it is stored in, and moves to this one the next time it is set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI, Format};
+    ///
+    /// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+    ///     .key_format("plugins", Format::Json)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(config_store.config_path("plugins").ends_with("plugins.json"));
+    /// ```
+    pub fn key_format(mut self, key: &str, format: Format) -> Self {
+        self.key_formats.insert(key.to_string(), format);
+        self
+    }
+
+    /// Opens the store without creating its directories, any mutation fails with `Error::ReadOnly`
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Also runs the validators registered with `add_validator` on values read by get
+    pub fn validate_reads(mut self, validate_reads: bool) -> Self {
+        self.validate_reads = validate_reads;
+        self
+    }
+
+    /// Sets how closely values read must match the types they are read as, to reject unknown fields
+    /// or fill missing ones, defaults to serde's behavior. Check the ReadMode docs for usage
+    pub fn read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// Moves files that cannot be decoded in the store's format aside with a `.broken` suffix
+    /// when they are read, and reports their key as never set instead of failing
+    /// so a single damaged file does not prevent the app from starting with its defaults
+    /// Values of the wrong type are not affected, read-only stores leave the file in place
+    pub fn recover_corrupt(mut self, recover_corrupt: bool) -> Self {
+        self.recover_corrupt = recover_corrupt;
+        self
+    }
+
+    /// Replaces `${name}` in the strings read by get with the value of the key name,
+    /// or of the environment variable name when the key is not set, like `${HOME}/projects`
+    /// Referenced keys are interpolated too, a reference cycle fails with `Error::Invalid`
+    /// Write `$${` for a literal `${`. Values are stored as written, only reads are interpolated
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+    ///     .temp(true)
+    ///     .interpolate(true)
+    ///     .build()
+    ///     .unwrap();
+    /// config_store.set("workspace", "/srv/work").unwrap();
+    /// config_store.set("logs", "${workspace}/logs").unwrap();
+    /// assert_eq!(config_store.get::<String>("logs").unwrap(), "/srv/work/logs");
+    /// ```
+    pub fn interpolate(mut self, interpolate: bool) -> Self {
+        self.interpolate = interpolate;
+        self
+    }
+
+    /// Reads the keys that are not set from the directory where distribution packages install
+    /// the app's defaults, `/usr/share/<app>/config` on Linux, `/Library/Application Support/<app>/defaults`
+    /// on macOS and `%ProgramData%\<app>\defaults` on Windows. Each key is a file like `theme.json`
+    /// Vendor defaults are only read, never written to the store, and take precedence over
+    /// the ones of `defaults_from_str`. Keys the user sets take precedence over both
+    pub fn vendor_defaults(mut self, vendor_defaults: bool) -> Self {
+        self.vendor_dir = if vendor_defaults {
+            Some(crate::vendor::dir(&self.app_name))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Same as `vendor_defaults`, reading the defaults from dir instead,
+    /// for packages installed under another prefix like `/usr/local/share`
+    pub fn vendor_defaults_dir<P>(mut self, dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.vendor_dir = Some(dir.into());
+        self
+    }
+
+    /// Keeps a backup of the last value written to each key, and makes `get` fall back to it
+    /// when a value cannot be read, like after a disk glitch or a bad hand edit, then to the
+    /// vendor or seeded default of the key, so that a damaged file never stops the app from
+    /// starting. warn is called before each step, to log the problem
+    /// Backups are kept in the store's directory, readable by the current user only
+    /// Check the Fallback docs for usage
+    pub fn fallback<F>(mut self, warn: F) -> Self
+    where
+        F: Fn(&Fallback) + Send + Sync + 'static,
+    {
+        self.fallback = Some(Box::new(warn));
+        self
+    }
+
+    /// Reads the keys that are not set from the store of another app, for settings shared by
+    /// a suite of tools like a proxy or a telemetry opt-out kept in a `mycompany-common` store
+    /// The parent store is looked up in the same scope and kind of directory, and is only read:
+    /// writes go to this store and take precedence. Inherited values take precedence over
+    /// vendor and seeded defaults, and are not listed by `keys`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let common = Configstore::new("mycompany-common", AppUI::CommandLine).unwrap();
+    /// common.set("proxy", "http://proxy.corp:3128").unwrap();
+    ///
+    /// let config_store = Configstore::builder("mycompany-cli", AppUI::CommandLine)
+    ///     .inherits("mycompany-common")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(config_store.get::<String>("proxy").unwrap(), "http://proxy.corp:3128");
+    /// ```
+    pub fn inherits(mut self, app_name: &str) -> Self {
+        self.inherits = Some(app_name.to_string());
+        self
+    }
+
+    /// Sets the default values of keys from a document in format, usually embedded in the app
+    /// with `include_str!("defaults.toml")`. Each top-level field is the default of a key,
+    /// written to the store the first time the key is read while it is not set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI, Format};
+    ///
+    /// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+    ///     .temp(true)
+    ///     .defaults_from_str(r#"{"theme": "light", "font_size": 12}"#, Format::Json)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(config_store.get::<u32>("font_size").unwrap(), 12);
+    /// ```
+    pub fn defaults_from_str(mut self, defaults: &str, format: Format) -> Self {
+        self.defaults = Some((defaults.to_string(), format));
+        self
+    }
+
+    /// Creates the store's directory and files readable by the current user only
+    /// (`0700` and `0600` on Unix), for stores holding tokens or other secrets
+    /// An existing store directory is restricted when the store is opened
+    /// Windows stores are left to the ACLs of the user's profile directory, which already
+    /// only grant access to the user, administrators and the system
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Buffers writes in memory and writes them on a background thread once no write happened
+    /// for the quiet period, for apps saving on every keystroke or slider tick
+    /// Reads see the buffered values. `flush` writes them right away, and dropping the store
+    /// writes what is left. Background write errors are returned by the next `flush`
+    /// Only applies to the files and SQLite backends
+    pub fn debounce(mut self, quiet: Duration) -> Self {
+        self.debounce = Some(quiet);
+        self
+    }
+
+    /// Decides what happens when the store's directory belongs to another user, like when
+    /// a command line tool is run with `sudo` and would leave files owned by root in the user's
+    /// directory. Files the store writes to an existing directory keep the owner of the directory
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI, OwnerPolicy};
+    ///
+    /// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+    ///     .owner_policy(OwnerPolicy::RealUser)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn owner_policy(mut self, policy: OwnerPolicy) -> Self {
+        self.owner_policy = policy;
+        self
+    }
+
+    /// Decides what writes do with the files of keys that are symbolic links, like the ones
+    /// of dotfiles managers. Defaults to writing to the file the link points to
+    /// Only applies to the files backend, check the SymlinkPolicy docs for usage
+    pub fn symlink_policy(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlink_policy = policy;
+        self
+    }
+
+    /// Appends every set and delete to a journal in the store's directory, with the time,
+    /// the key, a hash of the new value and the user and process that made the change,
+    /// for deployments that need to audit configuration changes. Read it back with `history`
+    /// Only applies to the files and SQLite backends
+    pub fn journal(mut self, journal: bool) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Shares change events with the other processes of the app that open the same store
+    /// with this option, like a command line tool, a daemon and a GUI: their sets and deletes
+    /// reach the listeners of `on_change` without polling the files, and ours reach theirs
+    /// Events are delivered on a background thread over unix domain sockets in the store's directory
+    /// Only applies to the files and indexed backends on unix, building fails on other platforms
+    /// If the sockets cannot be created, like when the directory's path is too long for one,
+    /// a warning is logged and the store is built without sharing events
+    pub fn ipc_events(mut self, ipc_events: bool) -> Self {
+        self.ipc_events = ipc_events;
+        self
+    }
+
+    /// Compresses values whose serialized size exceeds the compression threshold
+    /// Compressed values are detected on read, so the setting can be changed at any time
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Sets the size in bytes above which values are compressed, defaults to 16KiB
+    pub fn compression_threshold(mut self, threshold: usize) -> Self {
+        self.compression_threshold = threshold;
+        self
+    }
+
+    /// Limits the total size in bytes of the store's values and blobs
+    /// Writes that would exceed it follow the eviction policy
+    pub fn quota(mut self, max_bytes: u64) -> Self {
+        self.quota = Some(max_bytes);
+        self
+    }
+
+    /// Sets what happens when a write exceeds the quota, defaults to failing the write
+    pub fn eviction(mut self, eviction: Eviction) -> Self {
+        self.eviction = eviction;
+        self
+    }
+
+    /// Moves the values removed by `delete` to a trash in the store's directory instead of
+    /// removing them, so that they can be put back with `restore_deleted`
+    /// `vacuum` removes the ones deleted longer than the retention period ago
+    /// Only applies to the files and indexed backends
+    pub fn trash(mut self, retention: Duration) -> Self {
+        self.trash = Some(retention);
+        self
+    }
+
+    /// Reports the reads, writes, deletes and errors of the store to the app's metrics,
+    /// check the Metrics docs for usage
+    pub fn metrics<M>(mut self, metrics: M) -> Self
+    where
+        M: Metrics + 'static,
+    {
+        self.metrics = Some(Box::new(metrics));
+        self
+    }
+
+    /// Sets where values are kept, defaults to one file per value
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Creates the configstore, creating its directories if needed (unless read-only)
+    ///
+    /// # Errors
+    ///
+    /// Could error if the config directory cannot be found or created,
+    /// if the profile name is invalid or if the defaults cannot be parsed
+    /// Fails with `Error::ForeignDir` if the directory belongs to another user, check `owner_policy`
+    /// Machine scoped stores usually need elevated privileges to be created
+    pub fn build(mut self) -> Result<Configstore> {
+        if let Some(profile) = &self.profile {
+            profile::validate(profile)?;
+        }
+        let defaults = match &self.defaults {
+            Some((defaults, format)) => crate::defaults::parse(defaults, *format)?,
+            None => Default::default(),
+        };
+        let compression = self
+            .compression
+            .map(|compression| (compression, self.compression_threshold));
+        let temp_dir = if self.temp {
+            Some(TempDir::new())
+        } else {
+            None
+        };
+        if let Some(temp_dir) = &temp_dir {
+            self.location.root = Some(temp_dir.path().to_path_buf());
+        }
+        let parent = match &self.inherits {
+            Some(app_name) => {
+                let mut location = self.location.clone();
+                location.project = None;
+                let parent = ConfigstoreBuilder {
+                    location,
+                    read_only: true,
+                    ..ConfigstoreBuilder::new(app_name, same_ui(&self.app_ui))
+                };
+                Some(parent.build()?)
+            }
+            None => None,
+        };
+        let project_dir = self.location.project_dir();
+        let project = project_dir.is_some();
+        let app_dir = match project_dir {
+            Some(project_dir) => project_dir,
+            // Backends that do not use files only need the directory to name their entries
+            None if self.backend.uses_files() => self
+                .location
+                .root_dir(CONFIG_STORE_NAME, self.app_ui)?
+                .join(&self.app_name),
+            None => PathBuf::from(CONFIG_STORE_NAME).join(&self.app_name),
+        };
+        let (app_dir, owner) = if self.read_only || project || !self.backend.uses_files() {
+            (app_dir, None)
+        } else {
+            owner::resolve(self.owner_policy, app_dir, &self.app_name)?
+        };
+        let prefix_dir = match &self.profile {
+            Some(profile) => app_dir.join(PROFILES_DIR).join(profile),
+            None => app_dir.clone(),
+        };
+        if !self.read_only && self.backend.uses_files() {
+            owner::create_dir(
+                &prefix_dir,
+                self.location.scope,
+                self.secure,
+                owner.as_ref(),
+            )?;
+        }
+
+        if self.backend.uses_files() {
+            let mut formats: Vec<Format> = self.key_formats.values().copied().collect();
+            formats.push(self.format);
+            crate::manifest::check(&prefix_dir, &formats, self.read_only)?;
+        }
+
+        let journal = if self.journal && !self.read_only && self.backend.uses_files() {
+            Some(Journal::new(prefix_dir.clone()))
+        } else {
+            None
+        };
+        #[cfg(unix)]
+        let ipc = if self.ipc_events {
+            match crate::ipc::Ipc::bind(&prefix_dir) {
+                Ok(ipc) => Some(ipc),
+                // Like when the store directory is too long for a socket path
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Not sharing change events between processes: {:#}", e);
+                    #[cfg(not(feature = "tracing"))]
+                    eprintln!("Not sharing change events between processes: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        #[cfg(not(unix))]
+        if self.ipc_events {
+            return Err(anyhow::Error::msg(
+                "Sharing change events between processes is only supported on unix",
+            ));
+        }
+        let storage = Storage::open(
+            self.backend,
+            prefix_dir,
+            self.read_only,
+            self.secure,
+            self.symlink_policy,
+            self.debounce,
+        )?;
+
+        let trash = if self.backend.uses_files() {
+            self.trash
+        } else {
+            None
+        };
+        Ok(Configstore::from_inner(Inner {
+            storage,
+            app_dir,
+            project,
+            profile: self.profile,
+            pretty: self.pretty,
+            sort_keys: self.sort_keys,
+            format: self.format,
+            key_formats: self.key_formats,
+            listeners: Default::default(),
+            #[cfg(unix)]
+            ipc,
+            journal,
+            validators: Default::default(),
+            codecs: Default::default(),
+            access: Default::default(),
+            declarations: Default::default(),
+            validate_reads: self.validate_reads,
+            read_mode: self.read_mode,
+            recover_corrupt: self.recover_corrupt,
+            interpolate: self.interpolate,
+            vendor_dir: self.vendor_dir,
+            defaults,
+            read_only: self.read_only,
+            compression,
+            quota: self.quota,
+            eviction: self.eviction,
+            trash,
+            parent,
+            metrics: self.metrics,
+            fallback: self.fallback,
+            _temp_dir: temp_dir,
+        }))
+    }
+}
+
+/// A copy of an AppUI, which is not Clone
+fn same_ui(app_ui: &AppUI) -> AppUI {
+    match app_ui {
+        AppUI::CommandLine => AppUI::CommandLine,
+        AppUI::Graphical => AppUI::Graphical,
+    }
+}
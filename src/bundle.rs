@@ -0,0 +1,117 @@
+use crate::atomic;
+use crate::error::is_not_found;
+use crate::redact::REDACTED;
+use crate::{Configstore, Redaction};
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl Configstore {
+    /// Writes a json file describing the store for bug reports: the value of every key,
+    /// the size of every entry, the problems found by `check`, where and how the store
+    /// is kept and the platform the app runs on
+    /// Values of the keys declared secret with `declare` are replaced by `"<redacted>"`,
+    /// blobs are listed by size only
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, KeyInfo};
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.declare("token", KeyInfo::new("API token").secret(true));
+    /// config_store.set("token", "s3cr3t").unwrap();
+    /// config_store.set("theme", "dark").unwrap();
+    ///
+    /// let bundle = std::env::temp_dir().join("myApp-support.json");
+    /// config_store.export_support_bundle(&bundle).unwrap();
+    /// let contents = std::fs::read_to_string(&bundle).unwrap();
+    /// assert!(contents.contains("dark") && !contents.contains("s3cr3t"));
+    /// # std::fs::remove_file(bundle).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store cannot be listed or the file cannot be written
+    /// Values that cannot be read are described in the bundle instead of failing the export
+    pub fn export_support_bundle<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut bytes = serde_json::to_vec_pretty(&self.support_bundle()?)?;
+        bytes.push(b'\n');
+        atomic::write(path.as_ref(), &bytes)
+    }
+
+    fn support_bundle(&self) -> Result<Value> {
+        let mut values = Map::new();
+        for key in self.keys()? {
+            let value = match self.get_raw(&key) {
+                Ok(_) if self.redacts(&key, Redaction::MaskSecrets) => Value::from(REDACTED),
+                Ok(value) => value,
+                Err(e) if is_not_found(&e) => continue,
+                Err(e) => json!({ "error": format!("{:#}", e) }),
+            };
+            values.insert(key, value);
+        }
+        let issues = match self.check() {
+            Ok(issues) => issues
+                .into_iter()
+                .map(|issue| json!({ "name": issue.name, "kind": format!("{:?}", issue.kind) }))
+                .collect(),
+            Err(e) => vec![json!({ "error": format!("{:#}", e) })],
+        };
+        let stats = self.stats()?;
+        Ok(json!({
+            "configstore": env!("CARGO_PKG_VERSION"),
+            "created": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            "platform": {
+                "os": std::env::consts::OS,
+                "family": std::env::consts::FAMILY,
+                "arch": std::env::consts::ARCH,
+            },
+            "store": {
+                "dir": self.dir(),
+                "profile": self.profile(),
+                "project": self.inner.project,
+                "format": format!("{:?}", self.inner.format),
+                "backend": format!("{:?}", self.inner.storage.backend),
+                "read_only": self.inner.read_only,
+                "total_bytes": stats.total_bytes,
+                "key_count": stats.key_count,
+            },
+            "values": values,
+            "sizes": stats.key_sizes,
+            "issues": issues,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, KeyInfo};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn bundles_redact_secrets_and_report_issues() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.declare("password", KeyInfo::new("Password").secret(true));
+        config_store.set("password", "hunter2").unwrap();
+        config_store.set("volume", 7).unwrap();
+        config_store.set_blob("cache", &[0; 16]).unwrap();
+        std::fs::write(config_store.config_path("broken"), "{").unwrap();
+
+        let path = config_store.dir().join("bundle.out");
+        config_store.export_support_bundle(&path).unwrap();
+        let bundle: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(bundle["values"]["password"], json!("<redacted>"));
+        assert_eq!(bundle["values"]["volume"], json!(7));
+        assert!(bundle["values"]["broken"]["error"].is_string());
+        assert_eq!(bundle["sizes"]["cache"], json!(16));
+        assert_eq!(bundle["issues"].as_array().unwrap().len(), 1);
+        assert_eq!(bundle["store"]["backend"], json!("Files"));
+        assert_eq!(bundle["platform"]["os"], json!(std::env::consts::OS));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{Configstore, Error};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Converts values between the representation serde gives them and the one written to the store,
+/// for special encoding needs like storing dates as RFC 3339 strings or encrypting one field
+/// Values are exchanged as their json equivalent, whatever the format of the store
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Codec, Configstore};
+/// use serde_json::{json, Value};
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+///
+/// /// Stores SystemTime as seconds since the unix epoch instead of serde's struct
+/// struct UnixSeconds;
+///
+/// impl Codec for UnixSeconds {
+///     fn encode(&self, value: Value) -> Result<Value, String> {
+///         Ok(json!(value["secs_since_epoch"]))
+///     }
+///
+///     fn decode(&self, stored: Value) -> Result<Value, String> {
+///         let secs = stored.as_u64().ok_or("not a number of seconds")?;
+///         Ok(json!({"secs_since_epoch": secs, "nanos_since_epoch": 0}))
+///     }
+/// }
+///
+/// let config_store = Configstore::temp().unwrap();
+/// config_store.add_type_codec::<SystemTime, _>(UnixSeconds);
+/// let accepted = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// config_store.set("license_accepted", accepted).unwrap();
+/// assert_eq!(config_store.get_raw("license_accepted").unwrap(), json!(1_700_000_000));
+/// assert_eq!(config_store.get::<SystemTime>("license_accepted").unwrap(), accepted);
+/// ```
+pub trait Codec: Send + Sync {
+    /// Converts a value into the one written to the store
+    fn encode(&self, value: Value) -> std::result::Result<Value, String>;
+    /// Converts a value read from the store back into the one given to encode
+    fn decode(&self, stored: Value) -> std::result::Result<Value, String>;
+}
+
+/// The codecs registered with `add_codec` and `add_type_codec`
+#[derive(Default)]
+pub(crate) struct Codecs {
+    by_key: Mutex<HashMap<String, Arc<dyn Codec>>>,
+    by_type: Mutex<HashMap<&'static str, Arc<dyn Codec>>>,
+}
+
+impl Codecs {
+    /// The codecs of a value of type T written to key, in the order they encode
+    fn get<T: ?Sized>(&self, key: &str) -> Vec<Arc<dyn Codec>> {
+        let by_type = self.by_type.lock().unwrap().get(type_key::<T>()).cloned();
+        let by_key = self.by_key.lock().unwrap().get(key).cloned();
+        by_type.into_iter().chain(by_key).collect()
+    }
+}
+
+/// The name type codecs are registered under, the same for T and references to T
+fn type_key<T: ?Sized>() -> &'static str {
+    let mut name = std::any::type_name::<T>();
+    while let Some(referenced) = name
+        .strip_prefix("&mut ")
+        .or_else(|| name.strip_prefix('&'))
+    {
+        name = referenced;
+    }
+    name
+}
+
+impl Configstore {
+    /// Registers a codec converting every value written to key, through set and the other writes,
+    /// and every value read from it. Replaces the previous codec of key
+    /// Validators check values as they are written to the store, after conversion
+    /// Check the Codec docs for usage
+    pub fn add_codec<C>(&self, key: &str, codec: C)
+    where
+        C: Codec + 'static,
+    {
+        self.inner
+            .codecs
+            .by_key
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Arc::new(codec));
+    }
+
+    /// Registers a codec converting every value of type T written to or read from the store,
+    /// before the codec of its key. Replaces the previous codec of T
+    /// Types are told apart by `std::any::type_name`, the codec does not apply to values
+    /// T is nested in. References to T, like `set("key", &value)`, are converted like T
+    /// Check the Codec docs for usage
+    pub fn add_type_codec<T, C>(&self, codec: C)
+    where
+        T: ?Sized,
+        C: Codec + 'static,
+    {
+        self.inner
+            .codecs
+            .by_type
+            .lock()
+            .unwrap()
+            .insert(type_key::<T>(), Arc::new(codec));
+    }
+
+    /// Applies the codecs of key and T to a value about to be serialized,
+    /// None when there are none and the value is serialized as is
+    pub(crate) fn encode_codecs<T>(&self, key: &str, value: &T) -> Result<Option<Value>>
+    where
+        T: Serialize + ?Sized,
+    {
+        let codecs = self.inner.codecs.get::<T>(key);
+        if codecs.is_empty() {
+            return Ok(None);
+        }
+        let mut value = serde_json::to_value(value)?;
+        for codec in codecs {
+            value = codec.encode(value).map_err(|reason| invalid(key, reason))?;
+        }
+        #[cfg(feature = "encryption")]
+        if crate::encrypt::is_sealed(&value) {
+            self.mark_encrypted()?;
+        }
+        Ok(Some(value))
+    }
+
+    /// Decodes a value of type T read from key with the codecs of key and T,
+    /// None when there are none and the value is decoded as is
+    pub(crate) fn decode_codecs<T>(
+        &self,
+        key: &str,
+        decode: impl FnOnce() -> Result<Value>,
+    ) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let codecs = self.inner.codecs.get::<T>(key);
+        if codecs.is_empty() {
+            return Ok(None);
+        }
+        let mut value = decode()?;
+        for codec in codecs.iter().rev() {
+            value = codec.decode(value).map_err(|reason| invalid(key, reason))?;
+        }
+        Ok(Some(self.decode_document(key, value)?))
+    }
+}
+
+fn invalid(key: &str, reason: String) -> anyhow::Error {
+    Error::Invalid {
+        key: key.to_string(),
+        reason,
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Reverses the string of the field secret
+    struct Scramble;
+
+    impl Codec for Scramble {
+        fn encode(&self, mut value: Value) -> std::result::Result<Value, String> {
+            let secret = value["secret"].as_str().ok_or("no secret")?;
+            value["secret"] = json!(secret.chars().rev().collect::<String>());
+            Ok(value)
+        }
+
+        fn decode(&self, stored: Value) -> std::result::Result<Value, String> {
+            self.encode(stored)
+        }
+    }
+
+    #[test]
+    fn key_codecs_convert_stored_values() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.add_codec("account", Scramble);
+        config_store
+            .set("account", json!({"user": "ann", "secret": "abc"}))
+            .unwrap();
+        let stored = std::fs::read_to_string(config_store.config_path("account")).unwrap();
+        assert!(stored.contains("cba"));
+        assert_eq!(
+            config_store.get::<Value>("account").unwrap(),
+            json!({"user": "ann", "secret": "abc"})
+        );
+        let err = config_store.set("account", "no object").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Invalid { .. })
+        ));
+
+        // Type codecs also convert references to their type
+        #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+        struct Account {
+            user: String,
+            secret: String,
+        }
+        config_store.add_type_codec::<Account, _>(Scramble);
+        let account = Account {
+            user: "bob".to_string(),
+            secret: "xyz".to_string(),
+        };
+        config_store.set("typed", &account).unwrap();
+        assert_eq!(config_store.get_raw("typed").unwrap()["secret"], "zyx");
+        assert_eq!(config_store.get::<Account>("typed").unwrap(), account);
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use std::io::{Cursor, Read};
+
+/// Header written in front of compressed values so they can be told apart from plain ones
+/// The leading NUL byte never starts a valid json or toml document. In MessagePack and CBOR
+/// it is the whole document for the integer 0, so no value of the binary formats starts with
+/// the header either
+const MAGIC: &[u8] = b"\0CSZ";
+
+/// Default size in bytes above which values get compressed
+pub(crate) const DEFAULT_THRESHOLD: usize = 16 * 1024;
+
+/// Compression algorithm applied to serialized values larger than the store's threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Gzip, requires the `gzip` feature
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// Zstandard, requires the `zstd` feature
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => 1,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => 2,
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(unused_variables))]
+    fn compress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut out = MAGIC.to_vec();
+        out.push(self.id());
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                use std::io::Write;
+                let mut encoder =
+                    flate2::write::GzEncoder::new(out, flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                out.extend(zstd::encode_all(bytes, 0)?);
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Compresses the value when it is larger than the threshold
+pub(crate) fn compress(
+    compression: Option<(Compression, usize)>,
+    bytes: &[u8],
+) -> Result<Option<Vec<u8>>> {
+    match compression {
+        Some((compression, threshold)) if bytes.len() > threshold => {
+            compression.compress(bytes).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether the value starts with the magic header of compressed values
+pub(crate) fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Decompresses the value if it starts with the magic header, regardless of the store's settings
+pub(crate) fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_compressed(&bytes) {
+        return Ok(bytes);
+    }
+    let id = bytes.get(MAGIC.len()).copied();
+    #[allow(unused_variables)]
+    let payload = &bytes[(MAGIC.len() + 1).min(bytes.len())..];
+    match id {
+        #[cfg(feature = "gzip")]
+        Some(1) => {
+            let mut out = Vec::new();
+            flate2::read::GzDecoder::new(payload).read_to_end(&mut out)?;
+            Ok(out)
+        }
+        #[cfg(feature = "zstd")]
+        Some(2) => Ok(zstd::decode_all(payload)?),
+        id => Err(unsupported(id)),
+    }
+}
+
+fn unsupported(id: Option<u8>) -> anyhow::Error {
+    match id {
+        Some(1) => anyhow::Error::msg("Value is gzip compressed but the gzip feature is disabled"),
+        Some(2) => anyhow::Error::msg("Value is zstd compressed but the zstd feature is disabled"),
+        _ => anyhow::Error::msg("Value uses an unknown compression"),
+    }
+}
+
+/// Wraps a reader so that compressed values are decompressed while streaming
+pub(crate) fn reader<R>(mut inner: R) -> Result<Box<dyn Read + Send>>
+where
+    R: Read + Send + 'static,
+{
+    let mut header = Vec::with_capacity(MAGIC.len() + 1);
+    (&mut inner)
+        .take(MAGIC.len() as u64 + 1)
+        .read_to_end(&mut header)?;
+    if !header.starts_with(MAGIC) {
+        return Ok(Box::new(Cursor::new(header).chain(inner)));
+    }
+    match header.get(MAGIC.len()).copied() {
+        #[cfg(feature = "gzip")]
+        Some(1) => Ok(Box::new(flate2::read::GzDecoder::new(inner))),
+        #[cfg(feature = "zstd")]
+        Some(2) => Ok(Box::new(zstd::Decoder::new(inner)?)),
+        id => Err(unsupported(id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_values_are_not_compressed() {
+        #[cfg(feature = "gzip")]
+        assert!(compress(Some((Compression::Gzip, 10)), b"short")
+            .unwrap()
+            .is_none());
+        assert!(compress(None, b"short").unwrap().is_none());
+        assert_eq!(decompress(b"short".to_vec()).unwrap(), b"short");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_round_trip() {
+        let value = vec![b'a'; 1000];
+        let compressed = compress(Some((Compression::Gzip, 10)), &value)
+            .unwrap()
+            .unwrap();
+        assert!(compressed.len() < value.len());
+        assert_eq!(decompress(compressed).unwrap(), value);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn store_reads_compressed_and_plain_values() {
+        use crate::{AppUI, Configstore};
+        let compressed = Configstore::builder("tests-compression", AppUI::CommandLine)
+            .temp(true)
+            .compression(Compression::Gzip)
+            .compression_threshold(8)
+            .build()
+            .unwrap();
+        let large = "a".repeat(100);
+        compressed.set("large", large.clone()).unwrap();
+        compressed.set("small", String::from("b")).unwrap();
+        let raw = std::fs::read(compressed.dir().join("large.json")).unwrap();
+        assert!(raw.starts_with(MAGIC));
+        let plain = compressed.reopen().build().unwrap();
+        assert_eq!(plain.get::<String>("large").unwrap(), large);
+        assert_eq!(plain.get::<String>("small").unwrap(), "b");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        let value = vec![b'a'; 1000];
+        let compressed = compress(Some((Compression::Zstd, 10)), &value)
+            .unwrap()
+            .unwrap();
+        assert!(compressed.len() < value.len());
+        assert_eq!(decompress(compressed).unwrap(), value);
+    }
+}
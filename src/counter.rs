@@ -0,0 +1,66 @@
+use crate::{Configstore, Error};
+use anyhow::Result;
+
+impl Configstore {
+    /// Adds delta to the number held by key and returns the new number, a key that is not set
+    /// counts from 0. For usage counters, run counts and rate-limit buckets, the read and the write
+    /// are atomic like `set_if_unchanged` so concurrent increments are never lost
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// assert_eq!(config_store.increment("launches", 1).unwrap(), 1);
+    /// assert_eq!(config_store.increment("launches", 1).unwrap(), 2);
+    /// assert_eq!(config_store.increment("launches", -2).unwrap(), 0);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the stored value is not an integer, with `Error::Invalid` if the result overflows
+    /// Otherwise same as set
+    pub fn increment(&self, key: &str, delta: i64) -> Result<i64> {
+        self.update(key, |current: Option<i64>| {
+            let count = current
+                .unwrap_or(0)
+                .checked_add(delta)
+                .ok_or_else(|| Error::Invalid {
+                    key: key.to_string(),
+                    reason: format!("adding {} overflows", delta),
+                })?;
+            Ok((Some(count), count))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, Error};
+
+    #[test]
+    fn concurrent_increments_are_not_lost() {
+        let config_store = Configstore::temp().unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let store = config_store.reopen().build().unwrap();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        store.increment("runs", 1).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(config_store.get::<i64>("runs").unwrap(), 40);
+
+        config_store.set("max", i64::MAX).unwrap();
+        let err = config_store.increment("max", 1).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Invalid { .. })
+        ));
+    }
+}
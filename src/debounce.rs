@@ -0,0 +1,286 @@
+use crate::atomic;
+use crate::storage::{Backend, Entry};
+use crate::symlink::SymlinkPolicy;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The new bytes of an entry and whether they are private, None for a removed entry
+pub(crate) type Change = Option<(Vec<u8>, bool)>;
+
+/// Buffers the writes of a store and applies them on a background thread
+/// once no write happened for the quiet period, so bursts of writes touch the disk once
+pub(crate) struct Debouncer {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+pub(crate) struct Shared {
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    backend: Backend,
+    dir: PathBuf,
+    symlinks: SymlinkPolicy,
+    quiet: Duration,
+    state: Mutex<State>,
+    wake: Condvar,
+    /// Held while changes are written, so that batches reach the disk in order
+    writing: Mutex<()>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Changes not written yet, by entry name
+    pending: BTreeMap<String, Change>,
+    /// Changes being written, still served to readers until they are on disk
+    in_flight: BTreeMap<String, Change>,
+    last_change: Option<Instant>,
+    /// Why the last background write failed, reported by the next flush
+    error: Option<String>,
+    closing: bool,
+}
+
+impl Debouncer {
+    pub(crate) fn new(
+        backend: Backend,
+        dir: PathBuf,
+        symlinks: SymlinkPolicy,
+        quiet: Duration,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            backend,
+            dir,
+            symlinks,
+            quiet,
+            state: Mutex::default(),
+            wake: Condvar::new(),
+            writing: Mutex::new(()),
+        });
+        let background = Arc::clone(&shared);
+        let thread = std::thread::Builder::new()
+            .name("configstore-debounce".to_string())
+            .spawn(move || background.run())
+            .ok();
+        Debouncer { shared, thread }
+    }
+
+    pub(crate) fn shared(&self) -> &Arc<Shared> {
+        &self.shared
+    }
+
+    /// Buffers a change, or writes it right away if the background thread could not start
+    pub(crate) fn write(&self, name: &str, change: Change) -> Result<()> {
+        if self.thread.is_none() {
+            return self.shared.apply(name, change);
+        }
+        let mut state = self.shared.state();
+        state.pending.insert(name.to_string(), change);
+        state.last_change = Some(Instant::now());
+        self.shared.wake.notify_all();
+        Ok(())
+    }
+
+    /// The buffered change of an entry, None if the entry has no change waiting
+    pub(crate) fn get(&self, name: &str) -> Option<Change> {
+        let state = self.shared.state();
+        state
+            .pending
+            .get(name)
+            .or_else(|| state.in_flight.get(name))
+            .cloned()
+    }
+
+    /// Applies the buffered changes to the entries read from the disk
+    pub(crate) fn overlay(&self, entries: &mut Vec<Entry>) {
+        let state = self.shared.state();
+        for (name, change) in state.in_flight.iter().chain(&state.pending) {
+            entries.retain(|entry| &entry.name != name);
+            if let (Some((bytes, _)), Some(key)) = (change, crate::storage::entry_key(name)) {
+                entries.push(Entry {
+                    key: key.to_string(),
+                    name: name.clone(),
+                    size: bytes.len() as u64,
+                    modified: SystemTime::now(),
+                });
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn run(&self) {
+        let mut state = self.state();
+        loop {
+            let deadline = state.last_change.map(|last| last + self.quiet);
+            match deadline {
+                _ if state.closing => break,
+                None => state = self.wake.wait(state).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now < deadline {
+                        state = self
+                            .wake
+                            .wait_timeout(state, deadline - now)
+                            .unwrap_or_else(|e| e.into_inner())
+                            .0;
+                        continue;
+                    }
+                    drop(state);
+                    if let Err(e) = self.drain() {
+                        self.state().error = Some(e.to_string());
+                    }
+                    state = self.state();
+                }
+            }
+        }
+        drop(state);
+        if let Err(e) = self.drain() {
+            self.state().error = Some(e.to_string());
+        }
+    }
+
+    /// Writes every buffered change now
+    /// Fails with the error of the first change that cannot be written, or of a previous background write
+    /// Changes that cannot be written are buffered again, unless a newer one replaced them,
+    /// so that the next flush retries them
+    pub(crate) fn drain(&self) -> Result<()> {
+        let _writing = self
+            .writing
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (batch, previous_error) = {
+            let mut state = self.state();
+            state.last_change = None;
+            state.in_flight = std::mem::take(&mut state.pending);
+            (state.in_flight.clone(), state.error.take())
+        };
+        let mut result = match previous_error {
+            Some(error) => Err(anyhow::Error::msg(error)),
+            None => Ok(()),
+        };
+        let mut failed = Vec::new();
+        for (name, change) in batch {
+            if let Err(e) = self.apply(&name, change.clone()) {
+                result = result.and(Err(e));
+                failed.push((name, change));
+            }
+        }
+        let mut state = self.state();
+        state.in_flight.clear();
+        for (name, change) in failed {
+            state.pending.entry(name).or_insert(change);
+        }
+        result
+    }
+
+    /// Writes a change directly to the backend
+    fn apply(&self, name: &str, change: Change) -> Result<()> {
+        #[cfg(feature = "sqlite")]
+        if self.backend == Backend::Sqlite {
+            let db = crate::sqlite::SqliteStorage::open(&self.dir, false)?;
+            return match change {
+                Some((bytes, _)) => db.write(name, &bytes),
+                None => match db.remove(name) {
+                    Err(e) if !crate::error::is_not_found(&e) => Err(e),
+                    _ => Ok(()),
+                },
+            };
+        }
+        if self.backend == Backend::Indexed {
+            let index = crate::index::IndexStorage::open(&self.dir);
+            return match change {
+                Some((bytes, private)) => index.write(name, &bytes, private),
+                None => match index.remove(name) {
+                    Err(e) if !crate::error::is_not_found(&e) => Err(e),
+                    _ => Ok(()),
+                },
+            };
+        }
+        let path = self.dir.join(name);
+        match change {
+            Some((bytes, private)) => {
+                let path = crate::symlink::write_path(&path, self.symlinks)?;
+                if private {
+                    atomic::write_private(&path, &bytes)
+                } else {
+                    atomic::write(&path, &bytes)
+                }
+            }
+            None => {
+                match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => (),
+                }
+                crate::archive::Archive::open(&self.dir).remove(&[name])?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl crate::flush::Pending for Shared {
+    fn drain(&self) -> Result<()> {
+        Shared::drain(self)
+    }
+}
+
+impl Drop for Debouncer {
+    fn drop(&mut self) {
+        self.shared.state().closing = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_reach_the_disk_after_the_quiet_period() {
+        let config_store = Configstore::temp().unwrap();
+        let debounced = config_store
+            .reopen()
+            .debounce(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        for volume in 0..=10 {
+            debounced.set("volume", volume).unwrap();
+        }
+        debounced.set("deleted", true).unwrap();
+        debounced.delete("deleted").unwrap();
+        assert_eq!(debounced.get::<u32>("volume").unwrap(), 10);
+        assert_eq!(debounced.keys().unwrap(), vec!["volume"]);
+        assert!(config_store.get::<u32>("volume").is_err());
+
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(config_store.get::<u32>("volume").unwrap(), 10);
+
+        // A write that fails is retried by the next flush
+        let blocked = config_store.dir().join("volume.json");
+        std::fs::remove_file(&blocked).unwrap();
+        std::fs::create_dir(&blocked).unwrap();
+        debounced.set("volume", 11).unwrap();
+        assert!(debounced.flush().is_err());
+        assert_eq!(debounced.get::<u32>("volume").unwrap(), 11);
+        std::fs::remove_dir(&blocked).unwrap();
+        debounced.flush().unwrap();
+        assert_eq!(config_store.get::<u32>("volume").unwrap(), 11);
+        debounced.set("volume", 12).unwrap();
+        drop(debounced);
+        assert_eq!(config_store.get::<u32>("volume").unwrap(), 12);
+        assert!(config_store.get::<bool>("deleted").is_err());
+    }
+}
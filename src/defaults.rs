@@ -0,0 +1,132 @@
+use crate::error::not_found;
+use crate::{Configstore, Format};
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+/// Parses a document of defaults, each top-level field being the default value of a key
+pub(crate) fn parse(defaults: &str, format: Format) -> Result<Map<String, Value>> {
+    format
+        .deserialize(defaults.as_bytes())
+        .map_err(|e| e.context("Invalid defaults, they must be a table of keys"))
+}
+
+impl Configstore {
+    /// Sets the keys that are not set yet to their default value, and returns the keys it set
+    /// Keys that are set keep their value. Every component of an app can seed the keys it owns
+    /// at startup: the store is locked while it seeds, so that concurrent calls, even from other
+    /// processes, each set a key at most once and never overwrite each other
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    /// use serde_json::json;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("theme", "dark").unwrap();
+    /// let seeded = config_store
+    ///     .seed(vec![("theme", json!("light")), ("font_size", json!(12))])
+    ///     .unwrap();
+    /// assert_eq!(seeded, vec!["font_size"]);
+    /// assert_eq!(config_store.get::<String>("theme").unwrap(), "dark");
+    /// assert!(config_store.seed(vec![("font_size", json!(14))]).unwrap().is_empty());
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if a key cannot be read or written, the keys seeded before stay set
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn seed<I, K>(&self, defaults: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = (K, Value)>,
+        K: AsRef<str>,
+    {
+        self.check_writable()?;
+        let defaults: Vec<(K, Value)> = defaults.into_iter().collect();
+        if defaults.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<&str> = defaults.iter().map(|(key, _)| key.as_ref()).collect();
+        let _lock = self.lock_for(&keys.join(", "))?;
+        let mut seeded = Vec::new();
+        for (key, value) in &defaults {
+            let key = key.as_ref();
+            if self.read_stored(key)?.is_some() {
+                continue;
+            }
+            let bytes = self.encode(key, value)?;
+            self.write_bytes(key, &bytes)?;
+            seeded.push(key.to_string());
+        }
+        Ok(seeded)
+    }
+
+    /// Writes the default value of a key that is not set, and returns it in the key's format
+    /// Read-only stores return the default without writing it
+    pub(crate) fn seed_default(&self, key: &str) -> Result<(Format, Vec<u8>)> {
+        let value = self.inner.defaults.get(key).ok_or_else(|| not_found(key))?;
+        let format = self.key_format(key);
+        // Not encode, which reads the key to preserve its formatting
+        let bytes = format.serialize(value, self.inner.pretty, None)?;
+        if !self.inner.read_only {
+            self.write_bytes(key, &bytes)?;
+        }
+        Ok((format, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, Format};
+
+    #[test]
+    fn concurrent_seeds_set_each_key_once() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("volume", 3).unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let builder = config_store.reopen();
+                std::thread::spawn(move || {
+                    let defaults = ["volume", "theme", "font"]
+                        .iter()
+                        .map(|key| (key.to_string(), serde_json::json!(i)));
+                    builder.build().unwrap().seed(defaults).unwrap()
+                })
+            })
+            .collect();
+        let mut seeded: Vec<String> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        seeded.sort();
+        assert_eq!(seeded, vec!["font", "theme"]);
+        assert_eq!(config_store.get::<u32>("volume").unwrap(), 3);
+        assert_eq!(
+            config_store.get::<u32>("theme").unwrap(),
+            config_store.get::<u32>("font").unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_seeded_from_defaults() {
+        let defaults = r#"{"theme": "light", "window": {"width": 800}}"#;
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("theme", "dark").unwrap();
+        let with_defaults = config_store
+            .reopen()
+            .defaults_from_str(defaults, Format::Json)
+            .build()
+            .unwrap();
+
+        assert_eq!(with_defaults.get::<String>("theme").unwrap(), "dark");
+        assert!(config_store.get_raw("window").is_err());
+        assert_eq!(with_defaults.get_raw("window").unwrap()["width"], 800);
+        assert_eq!(config_store.get_raw("window").unwrap()["width"], 800);
+        assert!(with_defaults.get::<String>("missing").is_err());
+
+        assert!(config_store
+            .reopen()
+            .defaults_from_str("[1, 2]", Format::Json)
+            .build()
+            .is_err());
+    }
+}
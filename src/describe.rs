@@ -0,0 +1,236 @@
+use crate::Configstore;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// What settings screens and help texts show about a key, declared with `declare`
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, Constraint, KeyInfo};
+///
+/// let config_store = Configstore::temp().unwrap();
+/// config_store.declare(
+///     "font_size",
+///     KeyInfo::new("Font size")
+///         .description("Size of the editor font, in points")
+///         .constraint(Constraint::Range { min: Some(6.0), max: Some(72.0) }),
+/// );
+/// config_store.declare("api_token", KeyInfo::new("API token").secret(true));
+///
+/// for (key, info) in config_store.describe_all() {
+///     println!("{:<12} {}", key, info);
+/// }
+/// assert!(config_store.set("font_size", 100).is_err());
+/// assert!(config_store.describe("api_token").unwrap().secret);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub struct KeyInfo {
+    /// The name shown to users instead of the key
+    pub display_name: String,
+    /// What the setting does
+    pub description: Option<String>,
+    /// The values the key accepts, checked when it is written
+    pub constraint: Option<Constraint>,
+    /// Whether the value must be hidden in settings screens, logs and reports
+    pub secret: bool,
+}
+
+impl KeyInfo {
+    /// Describes a key shown as display_name
+    pub fn new(display_name: &str) -> Self {
+        KeyInfo {
+            display_name: display_name.to_string(),
+            ..KeyInfo::default()
+        }
+    }
+
+    /// Sets what the setting does
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Restricts the values the key accepts
+    pub fn constraint(mut self, constraint: Constraint) -> Self {
+        self.constraint = Some(constraint);
+        self
+    }
+
+    /// Marks the value as secret
+    pub fn secret(mut self, secret: bool) -> Self {
+        self.secret = secret;
+        self
+    }
+}
+
+impl fmt::Display for KeyInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display_name)?;
+        if let Some(description) = &self.description {
+            write!(f, ": {}", description)?;
+        }
+        if let Some(constraint) = &self.constraint {
+            write!(f, " ({})", constraint)?;
+        }
+        if self.secret {
+            write!(f, " [secret]")?;
+        }
+        Ok(())
+    }
+}
+
+/// The values a declared key accepts
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Constraint {
+    /// A number between min and max, inclusive
+    Range {
+        /// The smallest accepted number, None for no minimum
+        min: Option<f64>,
+        /// The largest accepted number, None for no maximum
+        max: Option<f64>,
+    },
+    /// One of a list of values, like the names of themes
+    OneOf(Vec<Value>),
+    /// A string of at most this many characters
+    MaxLength(usize),
+}
+
+impl Constraint {
+    /// Describes what is wrong with the value if the constraint rejects it
+    fn check(&self, value: &Value) -> Result<(), String> {
+        match self {
+            Constraint::Range { min, max } => {
+                let number = value
+                    .as_f64()
+                    .ok_or_else(|| format!("{} is not a number", value))?;
+                if min.is_some_and(|min| number < min) || max.is_some_and(|max| number > max) {
+                    return Err(format!("{} is not {}", number, self));
+                }
+                Ok(())
+            }
+            Constraint::OneOf(choices) if choices.contains(value) => Ok(()),
+            Constraint::OneOf(_) => Err(format!("{} is not {}", value, self)),
+            Constraint::MaxLength(max) => match value.as_str() {
+                Some(text) if text.chars().count() <= *max => Ok(()),
+                Some(text) => Err(format!("{:?} is longer than {} characters", text, max)),
+                None => Err(format!("{} is not a string", value)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Range {
+                min: Some(min),
+                max: Some(max),
+            } => write!(f, "between {} and {}", min, max),
+            Constraint::Range {
+                min: Some(min),
+                max: None,
+            } => write!(f, "at least {}", min),
+            Constraint::Range {
+                min: None,
+                max: Some(max),
+            } => write!(f, "at most {}", max),
+            Constraint::Range {
+                min: None,
+                max: None,
+            } => write!(f, "any number"),
+            Constraint::OneOf(choices) => {
+                let choices: Vec<_> = choices.iter().map(Value::to_string).collect();
+                write!(f, "one of {}", choices.join(", "))
+            }
+            Constraint::MaxLength(max) => write!(f, "at most {} characters", max),
+        }
+    }
+}
+
+/// The keys declared with `declare`
+#[derive(Default)]
+pub(crate) struct Declarations {
+    keys: Mutex<BTreeMap<String, KeyInfo>>,
+}
+
+impl Configstore {
+    /// Declares what a key is, for settings screens and help texts generated from the store
+    /// Keys with a constraint reject values it does not accept with `Error::Invalid`,
+    /// like a validator. Declaring a key again replaces its description, but not its constraint
+    /// Check the KeyInfo docs for usage
+    pub fn declare(&self, key: &str, info: KeyInfo) {
+        if let Some(constraint) = info.constraint.clone() {
+            self.add_validator(key, move |value: &Value| constraint.check(value));
+        }
+        self.inner
+            .declarations
+            .keys
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), info);
+    }
+
+    /// What was declared about a key, None if it was not declared
+    pub fn describe(&self, key: &str) -> Option<KeyInfo> {
+        self.inner
+            .declarations
+            .keys
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+    }
+
+    /// Every declared key with what was declared about it, in alphabetical order
+    pub fn describe_all(&self) -> Vec<(String, KeyInfo)> {
+        self.inner
+            .declarations
+            .keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, info)| (key.clone(), info.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use serde_json::json;
+
+    #[test]
+    fn constraints_reject_writes() {
+        let config_store = Configstore::temp().unwrap();
+        let theme = KeyInfo::new("Theme")
+            .constraint(Constraint::OneOf(vec![json!("light"), json!("dark")]));
+        config_store.declare("theme", theme.clone());
+        config_store.declare(
+            "name",
+            KeyInfo::new("Name").constraint(Constraint::MaxLength(3)),
+        );
+        config_store.set("theme", "dark").unwrap();
+        let err = config_store.set("theme", "blue").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Invalid {
+                key: "theme".to_string(),
+                reason: "\"blue\" is not one of \"light\", \"dark\"".to_string()
+            })
+        );
+        assert!(config_store.set("name", "Bob").is_ok());
+        assert!(config_store.set("name", "Alice").is_err());
+        assert_eq!(config_store.describe("theme"), Some(theme));
+        assert_eq!(config_store.describe("unknown"), None);
+        assert_eq!(
+            config_store.describe_all()[0].1.to_string(),
+            "Name (at most 3 characters)"
+        );
+    }
+}
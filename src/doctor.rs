@@ -0,0 +1,459 @@
+use crate::atomic::TEMP_SUFFIX;
+use crate::blob::BLOB_EXTENSION;
+use crate::manifest::MANIFEST_NAME;
+use crate::profile::PROFILES_DIR;
+use crate::{compression, Configstore, Format, Operation, ReadMode};
+use anyhow::Result;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Age after which the temporary file of a write is left over from an interrupted one,
+/// younger ones may belong to writes other processes are doing
+const ORPHAN_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Suffix appended to the files of corrupt values when they are quarantined
+pub(crate) const BROKEN_SUFFIX: &str = ".broken";
+
+/// A problem found in a store by `check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The name of the entry inside the store directory, like `key.json`
+    pub name: String,
+    /// The file holding the entry, the database file for the SQLite backend
+    pub path: PathBuf,
+    /// What is wrong with the entry
+    pub kind: IssueKind,
+}
+
+/// The kinds of problems reported by `check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IssueKind {
+    /// The value cannot be decompressed or decoded in the format of its extension
+    Corrupt {
+        /// Why decoding failed
+        reason: String,
+    },
+    /// A temporary file left behind by an interrupted write
+    Orphaned,
+    /// The entry cannot be read by the current user
+    PermissionDenied,
+    /// The extension matches no enabled format, the file may need a feature that is disabled
+    UnknownFormat,
+}
+
+/// What `vacuum` removed from a store
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct VacuumReport {
+    /// Entries quarantined because they were corrupt, like `key.json.broken`
+    pub broken: Vec<String>,
+    /// Temporary files left behind by interrupted writes
+    pub orphaned: Vec<String>,
+    /// Named profiles that held no key anymore
+    pub empty_profiles: Vec<String>,
+    /// Hashes of the attachments no value referenced anymore
+    pub attachments: Vec<String>,
+    /// Keys whose deleted value was in the trash for longer than its retention period
+    pub trashed: Vec<String>,
+    /// Feature flags whose expiration time has passed, set with `Flag::set_until`
+    pub expired_flags: Vec<String>,
+    /// Total size in bytes of the removed files
+    pub freed_bytes: u64,
+}
+
+impl Configstore {
+    /// Scans every entry of the store and reports the ones that cannot be used
+    /// Values are decoded in the format of their extension, so keys left in a previous format are checked too
+    /// Useful to build a doctor command, or to diagnose a store before reporting a bug
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, IssueKind};
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("good", 1).unwrap();
+    /// std::fs::write(config_store.config_path("bad"), "{not json").unwrap();
+    /// let issues = config_store.check().unwrap();
+    /// assert_eq!(issues.len(), 1);
+    /// assert!(matches!(issues[0].kind, IssueKind::Corrupt { .. }));
+    /// config_store.repair().unwrap();
+    /// assert!(config_store.check().unwrap().is_empty());
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store directory cannot be read
+    pub fn check(&self) -> Result<Vec<Issue>> {
+        let mut issues = Vec::new();
+        for entry in self.inner.storage.entries()? {
+            let extension = entry.extension();
+            if extension == BLOB_EXTENSION || entry.name.ends_with(BROKEN_SUFFIX) {
+                continue;
+            }
+            let kind = match Format::from_extension(extension) {
+                Some(format) => match self.inner.storage.read(&entry.name) {
+                    Ok(bytes) => match decode(format, bytes) {
+                        Ok(()) => continue,
+                        Err(e) => IssueKind::Corrupt {
+                            reason: e.to_string(),
+                        },
+                    },
+                    Err(e) if is_permission_denied(&e) => IssueKind::PermissionDenied,
+                    Err(e) => return Err(e),
+                },
+                None => IssueKind::UnknownFormat,
+            };
+            issues.push(Issue {
+                path: self.inner.storage.path(&entry.name),
+                name: entry.name,
+                kind,
+            });
+        }
+        issues.extend(self.orphaned_files()?);
+        issues.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(issues)
+    }
+
+    /// Fixes the issues that can be fixed without losing data, and returns them
+    /// Corrupt values are quarantined by appending `.broken` to their file name, which deletes
+    /// the key while keeping its contents for debugging. Orphaned temporary files are removed
+    /// Permission problems and unknown formats are left for the user to look at
+    ///
+    /// Temporary files are orphans once they are an hour old, so that writes other processes
+    /// are doing are left alone. Check the check docs for usage
+    ///
+    /// # Errors
+    /// Could error if a file cannot be renamed or removed, issues fixed before the failure stay fixed
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn repair(&self) -> Result<Vec<Issue>> {
+        self.check_writable()?;
+        let mut repaired = Vec::new();
+        for issue in self.check()? {
+            match issue.kind {
+                IssueKind::Corrupt { .. } => self.quarantine(&issue.name)?,
+                IssueKind::Orphaned => {
+                    remove_if_exists(&issue.path)?;
+                }
+                _ => continue,
+            }
+            repaired.push(issue);
+        }
+        Ok(repaired)
+    }
+
+    /// Deletes what a store accumulates over time without being used anymore: the files
+    /// quarantined by `repair` or `recover_corrupt`, the temporary files of interrupted writes
+    /// older than an hour, the directories of named profiles that are empty and not open in the
+    /// process, the attachments no value references since at least an hour and the feature flags
+    /// that have expired. Returns what was removed
+    /// Meant for a periodic maintenance task, or a cleanup command
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// std::fs::write(config_store.config_path("bad"), "{not json").unwrap();
+    /// config_store.repair().unwrap();
+    /// let report = config_store.vacuum().unwrap();
+    /// assert_eq!(report.broken, vec!["bad.json.broken"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if a file cannot be removed, files removed before the failure stay removed
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn vacuum(&self) -> Result<VacuumReport> {
+        self.check_writable()?;
+        let mut report = VacuumReport::default();
+        for entry in self.inner.storage.entries()? {
+            if entry.name.ends_with(BROKEN_SUFFIX) {
+                self.inner.storage.remove(&entry.name)?;
+                report.freed_bytes += entry.size;
+                report.broken.push(entry.name);
+            }
+        }
+        for issue in self.orphaned_files()? {
+            let size = std::fs::metadata(&issue.path).map_or(0, |m| m.len());
+            if remove_if_exists(&issue.path)? {
+                report.freed_bytes += size;
+                report.orphaned.push(issue.name);
+            }
+        }
+        for profile in self.list_profiles()? {
+            let dir = self.inner.app_dir.join(PROFILES_DIR).join(&profile);
+            // The directories of the stores open in the process are kept even if empty
+            if crate::flush::is_open(&dir) || !remove_empty_profile(&dir)? {
+                continue;
+            }
+            report.empty_profiles.push(profile);
+        }
+        let (attachments, freed_bytes) = self.collect_attachments()?;
+        report.attachments = attachments;
+        report.freed_bytes += freed_bytes;
+        let (trashed, freed_bytes) = self.purge_trash()?;
+        report.trashed = trashed;
+        report.freed_bytes += freed_bytes;
+        report.expired_flags = self.purge_expired_flags()?;
+        report.broken.sort();
+        report.orphaned.sort();
+        Ok(report)
+    }
+
+    /// Moves a corrupt entry aside so the key reads as unset, and notifies the listeners
+    pub(crate) fn quarantine(&self, name: &str) -> Result<()> {
+        self.check_writable()?;
+        let mut broken = String::from(name);
+        broken.push_str(BROKEN_SUFFIX);
+        self.inner.storage.rename(name, &broken)?;
+        match crate::storage::entry_key(name) {
+            Some(key) => self.changed(crate::ChangeEvent::Delete(key.to_string()), name),
+            None => Ok(()),
+        }
+    }
+
+    /// Decodes a value read from key, after running the read validators, then its codecs
+    /// Corrupt values are quarantined and reported as not found if the store recovers them
+    pub(crate) fn decode<T>(&self, key: &str, format: Format, bytes: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let decoded = match self.decode_codecs(key, || self.decode_stored(key, format, bytes)) {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => self.decode_stored(key, format, bytes),
+            Err(e) => Err(e),
+        };
+        self.observe(decoded, Operation::Decode, key, || {
+            self.inner
+                .storage
+                .path(&self.entry_name(key, format.extension()))
+        })
+    }
+
+    fn decode_stored<T>(&self, key: &str, format: Format, bytes: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let value = match self.inner.read_mode {
+            ReadMode::Standard => format.deserialize(bytes),
+            _ => format
+                .deserialize(bytes)
+                .and_then(|document| self.decode_document(key, document)),
+        };
+        if value.is_err() && self.inner.recover_corrupt {
+            // Only files that are not valid at all are corrupt, not values of another type
+            if format.deserialize::<IgnoredAny>(bytes).is_err() {
+                let name = self.entry_name(key, format.extension());
+                let message = if self.inner.read_only {
+                    format!("{} is corrupt", key)
+                } else {
+                    self.quarantine(&name)?;
+                    format!("{} was corrupt and moved to {}{}", key, name, BROKEN_SUFFIX)
+                };
+                return Err(std::io::Error::new(ErrorKind::NotFound, message).into());
+            }
+        }
+        self.validate_read(key, format, bytes)?;
+        value
+    }
+
+    /// Temporary files of interrupted writes, only the files backend leaves them behind
+    fn orphaned_files(&self) -> Result<Vec<Issue>> {
+        let read_dir = match std::fs::read_dir(&self.inner.storage.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut issues = Vec::new();
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            let name = match dir_entry.file_name().into_string() {
+                Ok(name) if name.starts_with('.') && name.ends_with(TEMP_SUFFIX) => name,
+                _ => continue,
+            };
+            let age = match dir_entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+            {
+                Ok(modified) => modified.elapsed().unwrap_or_default(),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if age < ORPHAN_AGE {
+                continue;
+            }
+            issues.push(Issue {
+                path: dir_entry.path(),
+                name,
+                kind: IssueKind::Orphaned,
+            });
+        }
+        Ok(issues)
+    }
+}
+
+/// Removes a file, returns false if it was already gone
+fn remove_if_exists(path: &Path) -> Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Removes the directory of a profile if it holds nothing but its manifest and lock files,
+/// returns whether it was removed. It is kept if a value is written to it meanwhile
+fn remove_empty_profile(dir: &Path) -> Result<bool> {
+    let bookkeeping = [
+        MANIFEST_NAME,
+        crate::manifest::LOCK_NAME,
+        crate::version::WRITE_LOCK_NAME,
+    ];
+    for dir_entry in std::fs::read_dir(dir)? {
+        let name = dir_entry?.file_name();
+        if !bookkeeping.iter().any(|kept| name == *kept) {
+            return Ok(false);
+        }
+    }
+    for name in bookkeeping.iter() {
+        remove_if_exists(&dir.join(name))?;
+    }
+    // Not recursive, so that values written since the directory was read are not lost
+    match std::fs::remove_dir(dir) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(_) if std::fs::read_dir(dir)?.next().is_some() => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Decodes a stored value, only to find out whether it is valid
+fn decode(format: Format, bytes: Vec<u8>) -> Result<()> {
+    let bytes = compression::decompress(bytes)?;
+    format.deserialize::<IgnoredAny>(&bytes)?;
+    Ok(())
+}
+
+fn is_permission_denied(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::PermissionDenied)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::is_not_found;
+    use crate::{ChangeEvent, Configstore, IssueKind};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    /// Makes a temporary file look left over from a write interrupted two hours ago
+    fn age(path: &Path) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))
+            .unwrap();
+    }
+
+    #[test]
+    fn check_reports_and_repair_fixes() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("good", String::from("fine")).unwrap();
+        config_store.set_blob("good", b"\0not json").unwrap();
+        let dir = config_store.dir();
+        std::fs::write(dir.join("corrupt.json"), "{\"truncated\": ").unwrap();
+        std::fs::write(dir.join("notes.txt"), "hello").unwrap();
+        std::fs::write(dir.join(".good.json.42-0.tmp"), "\"partial").unwrap();
+        age(&dir.join(".good.json.42-0.tmp"));
+        // Writes in progress are not orphans
+        std::fs::write(dir.join(".good.json.43-0.tmp"), "\"writing").unwrap();
+
+        let issues = config_store.check().unwrap();
+        let kinds: Vec<_> = issues
+            .iter()
+            .map(|issue| (issue.name.as_str(), &issue.kind))
+            .collect();
+        assert_eq!(kinds.len(), 3);
+        assert_eq!(kinds[0], (".good.json.42-0.tmp", &IssueKind::Orphaned));
+        assert!(matches!(
+            kinds[1],
+            ("corrupt.json", IssueKind::Corrupt { .. })
+        ));
+        assert_eq!(kinds[2], ("notes.txt", &IssueKind::UnknownFormat));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        config_store.on_change(move |event| recorded.lock().unwrap().push(event.clone()));
+        let repaired = config_store.repair().unwrap();
+        assert_eq!(repaired.len(), 2);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![ChangeEvent::Delete("corrupt".to_string())]
+        );
+        assert!(dir.join("corrupt.json.broken").exists());
+        assert!(!dir.join(".good.json.42-0.tmp").exists());
+        assert!(config_store.get::<String>("corrupt").is_err());
+        assert_eq!(config_store.get::<String>("good").unwrap(), "fine");
+        assert_eq!(config_store.check().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn vacuum_removes_leftovers() {
+        let config_store = Configstore::temp().unwrap();
+        let dir = config_store.dir();
+        config_store.set("kept", 1).unwrap();
+        std::fs::write(dir.join("old.json.broken"), "{").unwrap();
+        std::fs::write(dir.join(".kept.json.7-0.tmp"), "1").unwrap();
+        age(&dir.join(".kept.json.7-0.tmp"));
+        config_store
+            .flag("ended")
+            .set_until(true, SystemTime::now() - Duration::from_secs(1))
+            .unwrap();
+        config_store.flag("running").enable().unwrap();
+        config_store.reopen().profile("empty").build().unwrap();
+        // Open profiles are kept even if empty
+        let idle = config_store.reopen().profile("idle").build().unwrap();
+        let used = config_store.reopen().profile("used").build().unwrap();
+        used.set("key", 2).unwrap();
+
+        let report = config_store.vacuum().unwrap();
+        assert_eq!(report.broken, vec!["old.json.broken"]);
+        assert_eq!(report.orphaned, vec![".kept.json.7-0.tmp"]);
+        assert_eq!(report.empty_profiles, vec!["empty"]);
+        assert_eq!(report.expired_flags, vec!["ended"]);
+        assert_eq!(
+            config_store
+                .flags()
+                .unwrap()
+                .into_keys()
+                .collect::<Vec<_>>(),
+            vec!["running"]
+        );
+        assert_eq!(report.freed_bytes, 2);
+        assert_eq!(config_store.list_profiles().unwrap(), vec!["idle", "used"]);
+        drop(idle);
+        assert_eq!(config_store.get::<u32>("kept").unwrap(), 1);
+        assert_eq!(config_store.vacuum().unwrap().empty_profiles, vec!["idle"]);
+        assert_eq!(config_store.vacuum().unwrap(), Default::default());
+    }
+
+    #[test]
+    fn recover_corrupt_reads_as_unset() {
+        let strict = Configstore::temp().unwrap();
+        let recovering = strict.reopen().recover_corrupt(true).build().unwrap();
+        std::fs::write(strict.config_path("damaged"), "{\"volume\": 1").unwrap();
+        strict.set("wrong_type", String::from("text")).unwrap();
+
+        assert!(!is_not_found(&strict.get::<u32>("damaged").unwrap_err()));
+        assert!(!is_not_found(
+            &recovering.get::<u32>("wrong_type").unwrap_err()
+        ));
+        assert!(is_not_found(&recovering.get::<u32>("damaged").unwrap_err()));
+        assert!(strict.dir().join("damaged.json.broken").exists());
+        assert!(is_not_found(&strict.get::<u32>("damaged").unwrap_err()));
+        recovering.set("damaged", 2).unwrap();
+        assert_eq!(strict.get::<u32>("damaged").unwrap(), 2);
+    }
+}
@@ -0,0 +1,394 @@
+use crate::storage::Sink;
+use crate::{compression, error, ChangeEvent, Codec, Configstore, Error};
+use anyhow::Result;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+use std::io::Write;
+
+/// The field identifying encrypted payloads in stored values
+const ENCRYPTED_FIELD: &str = "$encrypted";
+
+/// The field of encrypted payloads holding the id of the key they were encrypted with
+const KEY_ID_FIELD: &str = "$key";
+
+/// A codec encrypting a whole value or some of its fields with AES-256-GCM, so that tokens
+/// and passwords are unreadable on disk while the rest of the settings stay human-readable
+/// Register it like any codec, with `add_codec` for keys or `add_type_codec` for the fields
+/// of a struct. Encrypted payloads are stored as `{"$encrypted": "<hex>", "$key": "<key id>"}`,
+/// plain values typed by hand in their place are read as is and encrypted on the next write
+/// The key usually comes from the platform's keyring, `rotate_key` replaces it. Requires the
+/// `encryption` feature
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, Encrypted};
+/// use serde_json::json;
+///
+/// // Load it from the keyring instead
+/// let key = [7; 32];
+/// let config_store = Configstore::temp().unwrap();
+/// config_store.add_codec("account", Encrypted::new(&key).fields(&["/password"]));
+/// config_store.set("account", json!({"user": "ann", "password": "hunter2"})).unwrap();
+///
+/// let stored = std::fs::read_to_string(config_store.config_path("account")).unwrap();
+/// assert!(stored.contains("ann") && !stored.contains("hunter2"));
+/// assert_eq!(
+///     config_store.get::<serde_json::Value>("account").unwrap(),
+///     json!({"user": "ann", "password": "hunter2"})
+/// );
+/// ```
+pub struct Encrypted {
+    key: LessSafeKey,
+    key_id: String,
+    fields: Vec<String>,
+    random: SystemRandom,
+}
+
+impl Encrypted {
+    /// Creates a codec encrypting whole values with a 256-bit key
+    pub fn new(key: &[u8; 32]) -> Self {
+        let digest = ring::digest::digest(&ring::digest::SHA256, key);
+        Encrypted {
+            key: LessSafeKey::new(
+                UnboundKey::new(&AES_256_GCM, key).expect("AES-256 keys are 32 bytes"),
+            ),
+            key_id: hex(&digest.as_ref()[..8]),
+            fields: Vec::new(),
+            random: SystemRandom::new(),
+        }
+    }
+
+    /// Identifies the key in the payloads it encrypts without revealing it, the start of its SHA-256
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Only encrypts the fields at these JSON pointers, like `/password` or `/tokens/github`
+    /// Missing fields are skipped
+    pub fn fields(mut self, pointers: &[&str]) -> Self {
+        self.fields = pointers.iter().map(|pointer| pointer.to_string()).collect();
+        self
+    }
+
+    /// The encrypted payload of the value at pointer, which authenticates where it belongs
+    fn seal(&self, pointer: &str, value: &Value) -> Result<Value, String> {
+        let mut nonce = [0; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| "no random nonce available")?;
+        let mut payload = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(pointer.as_bytes()),
+                &mut payload,
+            )
+            .map_err(|_| "encryption failed")?;
+        let sealed = hex(&[&nonce[..], &payload].concat());
+        Ok(json!({ ENCRYPTED_FIELD: sealed, KEY_ID_FIELD: self.key_id }))
+    }
+
+    /// The value of a payload, values that are not payloads are returned as is
+    /// Payloads written before key ids were recorded are tried with the key
+    fn open(&self, pointer: &str, stored: Value) -> Result<Value, String> {
+        let hex = match stored.get(ENCRYPTED_FIELD) {
+            Some(Value::String(hex)) => hex,
+            _ => return Ok(stored),
+        };
+        match stored.get(KEY_ID_FIELD).and_then(Value::as_str) {
+            Some(key_id) if key_id != self.key_id => {
+                return Err(format!(
+                    "{} was encrypted with key {}, not with key {}",
+                    pointer, key_id, self.key_id
+                ))
+            }
+            _ => (),
+        }
+        let invalid = || format!("{} is not a valid encrypted payload", pointer);
+        let mut bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        if bytes.len() < NONCE_LEN {
+            return Err(invalid());
+        }
+        let mut payload = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes).map_err(|_| invalid())?;
+        let plain = self
+            .key
+            .open_in_place(nonce, Aad::from(pointer.as_bytes()), &mut payload)
+            .map_err(|_| format!("{} cannot be decrypted with this key", pointer))?;
+        serde_json::from_slice(plain).map_err(|e| e.to_string())
+    }
+
+    /// Replaces the value at each pointer with what convert makes of it
+    fn convert<F>(&self, mut value: Value, convert: F) -> Result<Value, String>
+    where
+        F: Fn(&str, Value) -> Result<Value, String>,
+    {
+        if self.fields.is_empty() {
+            return convert("", value);
+        }
+        for pointer in &self.fields {
+            if let Some(field) = value.pointer_mut(pointer) {
+                *field = convert(pointer, field.take())?;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Encrypts again with new the payloads of a stored document this key encrypted, wherever
+    /// they are, returns whether there were any. Payloads of other keys are left as they are
+    fn reseal(&self, new: &Encrypted, pointer: &str, value: &mut Value) -> Result<bool, String> {
+        if value.get(ENCRYPTED_FIELD).is_some() {
+            match value.get(KEY_ID_FIELD).and_then(Value::as_str) {
+                Some(key_id) if key_id != self.key_id => return Ok(false),
+                _ => (),
+            }
+            let plain = self.open(pointer, value.take())?;
+            *value = new.seal(pointer, &plain)?;
+            return Ok(true);
+        }
+        let mut resealed = false;
+        match value {
+            Value::Object(fields) => {
+                for (name, field) in fields.iter_mut() {
+                    let escaped = name.replace('~', "~0").replace('/', "~1");
+                    let pointer = format!("{}/{}", pointer, escaped);
+                    resealed |= self.reseal(new, &pointer, field)?;
+                }
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    resealed |= self.reseal(new, &format!("{}/{}", pointer, i), item)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(resealed)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl Codec for Encrypted {
+    fn encode(&self, value: Value) -> Result<Value, String> {
+        self.convert(value, |pointer, field| self.seal(pointer, &field))
+    }
+
+    fn decode(&self, stored: Value) -> Result<Value, String> {
+        self.convert(stored, |pointer, field| self.open(pointer, field))
+    }
+}
+
+impl Configstore {
+    /// Encrypts every value the old key encrypted with the new one, returns the keys rotated
+    /// The values are all encrypted again and staged in temporary files before any of them
+    /// replaces its file, so that a value that cannot be decrypted leaves the store unchanged
+    /// Register codecs with the new key afterwards. Values encrypted with other keys are kept
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, Encrypted};
+    ///
+    /// let (old_key, new_key) = ([1; 32], [2; 32]);
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.add_codec("token", Encrypted::new(&old_key));
+    /// config_store.set("token", "s3cr3t").unwrap();
+    ///
+    /// let rotated = config_store
+    ///     .rotate_key(&Encrypted::new(&old_key), &Encrypted::new(&new_key))
+    ///     .unwrap();
+    /// assert_eq!(rotated, vec!["token"]);
+    /// config_store.add_codec("token", Encrypted::new(&new_key));
+    /// assert_eq!(config_store.get::<String>("token").unwrap(), "s3cr3t");
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with `Error::Invalid` if a value of the old key cannot be decrypted with it,
+    /// or if the values cannot be read or written. Fails with `Error::ReadOnly` on read-only stores
+    pub fn rotate_key(&self, old: &Encrypted, new: &Encrypted) -> Result<Vec<String>> {
+        self.check_writable()?;
+        // Values waiting for their quiet period are rotated too
+        self.inner.storage.flush()?;
+        let mut staged = Vec::new();
+        for key in self.keys()? {
+            let (format, bytes) = self
+                .read_stored(&key)?
+                .ok_or_else(|| error::not_found(&key))?;
+            let mut document: Value = format.deserialize(&compression::decompress(bytes)?)?;
+            let resealed = old
+                .reseal(new, "", &mut document)
+                .map_err(|reason| Error::Invalid {
+                    key: key.clone(),
+                    reason,
+                })?;
+            if !resealed {
+                continue;
+            }
+            let bytes = format.serialize(&document, self.inner.pretty, None)?;
+            let compressed = compression::compress(self.inner.compression, &bytes)?;
+            let name = self.entry_name(&key, format.extension());
+            let mut sink = self.inner.storage.sink(&name)?;
+            sink.write_all(compressed.as_deref().unwrap_or(&bytes))?;
+            sink.flush()?;
+            if let (Sink::File(file), Some(access)) = (&sink, self.inner.access.get(&key)) {
+                access.apply(file.as_file())?;
+            }
+            staged.push((key, name, format, bytes, sink));
+        }
+        if !staged.is_empty() {
+            self.mark_encrypted()?;
+        }
+        let mut rotated = Vec::new();
+        for (key, name, format, bytes, sink) in staged {
+            self.inner.storage.commit(&name, sink)?;
+            self.write_backup(&key, format, &bytes)?;
+            self.changed(ChangeEvent::Set(key.clone()), &name)?;
+            rotated.push(key);
+        }
+        Ok(rotated)
+    }
+
+    /// Flags the store as holding encrypted values in its manifest
+    pub(crate) fn mark_encrypted(&self) -> Result<()> {
+        if self.inner.read_only || !self.inner.storage.backend.uses_files() {
+            return Ok(());
+        }
+        crate::manifest::mark_encrypted(self.dir())
+    }
+}
+
+/// Whether a value holds encrypted payloads
+pub(crate) fn is_sealed(value: &Value) -> bool {
+    match value {
+        Value::Object(fields) => {
+            fields.get(ENCRYPTED_FIELD).is_some_and(Value::is_string)
+                || fields.values().any(is_sealed)
+        }
+        Value::Array(items) => items.iter().any(is_sealed),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Configstore, Error};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Account {
+        user: String,
+        token: String,
+    }
+
+    #[test]
+    fn fields_are_encrypted_and_authenticated() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.add_type_codec::<Account, _>(Encrypted::new(&[1; 32]).fields(&["/token"]));
+        let account = Account {
+            user: "ann".to_string(),
+            token: "s3cr3t".to_string(),
+        };
+        config_store.set("account", account.clone()).unwrap();
+        config_store.add_codec("whole", Encrypted::new(&[1; 32]));
+        config_store.set("whole", vec![1, 2, 3]).unwrap();
+        let stored = |key: &str| -> Value {
+            serde_json::from_slice(&std::fs::read(config_store.config_path(key)).unwrap()).unwrap()
+        };
+        assert!(stored("whole")[ENCRYPTED_FIELD].is_string());
+        assert_eq!(config_store.get::<Vec<u8>>("whole").unwrap(), vec![1, 2, 3]);
+
+        let stored = stored("account");
+        assert_eq!(stored["user"], json!("ann"));
+        let manifest = config_store.dir().join(crate::manifest::MANIFEST_NAME);
+        let manifest: Value = serde_json::from_slice(&std::fs::read(manifest).unwrap()).unwrap();
+        assert_eq!(manifest["encrypted"], true);
+        assert!(!stored.to_string().contains("s3cr3t"));
+        assert_eq!(config_store.get::<Account>("account").unwrap(), account);
+
+        // Plain values typed by hand are read as is
+        config_store
+            .set_raw("account", json!({"user": "bob", "token": "typed"}))
+            .unwrap();
+        assert_eq!(
+            config_store.get::<Account>("account").unwrap().token,
+            "typed"
+        );
+
+        // Payloads moved to another field or read with another key fail
+        let moved = json!({"user": stored["token"], "token": stored["token"]});
+        let wrong_key = Encrypted::new(&[2; 32]).fields(&["/token"]);
+        assert!(wrong_key.decode(stored).is_err());
+        let swapped = Encrypted::new(&[1; 32]).fields(&["/user"]);
+        assert!(swapped.decode(moved).is_err());
+
+        config_store
+            .set_raw(
+                "account",
+                json!({"user": "ann", "token": {"$encrypted": "zz"}}),
+            )
+            .unwrap();
+        let err = config_store.get::<Account>("account").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn rotation_encrypts_values_with_the_new_key() {
+        let config_store = Configstore::temp().unwrap();
+        let (old, new, other) = ([1; 32], [2; 32], [3; 32]);
+        config_store.add_codec("account", Encrypted::new(&old).fields(&["/token"]));
+        config_store.add_codec("whole", Encrypted::new(&old));
+        config_store.add_codec("other", Encrypted::new(&other));
+        config_store
+            .set("account", json!({"user": "ann", "token": "s3cr3t"}))
+            .unwrap();
+        config_store.set("whole", vec![1, 2]).unwrap();
+        config_store.set("other", "kept").unwrap();
+        config_store.set("plain", 1).unwrap();
+        let stored = |key: &str| -> Value {
+            serde_json::from_slice(&std::fs::read(config_store.config_path(key)).unwrap()).unwrap()
+        };
+        assert_eq!(
+            stored("whole")[KEY_ID_FIELD],
+            json!(Encrypted::new(&old).key_id())
+        );
+        let before = stored("other");
+
+        let rotated = config_store
+            .rotate_key(&Encrypted::new(&old), &Encrypted::new(&new))
+            .unwrap();
+        assert_eq!(rotated, vec!["account", "whole"]);
+        assert_eq!(stored("other"), before);
+        // Values of the new key are told apart from those of the old one
+        let err = config_store.get::<Vec<u8>>("whole").unwrap_err();
+        assert!(format!("{:#}", err).contains(Encrypted::new(&new).key_id()));
+        config_store.add_codec("account", Encrypted::new(&new).fields(&["/token"]));
+        config_store.add_codec("whole", Encrypted::new(&new));
+        assert_eq!(config_store.get::<Vec<u8>>("whole").unwrap(), vec![1, 2]);
+        assert_eq!(config_store.get_raw("account").unwrap()["token"], "s3cr3t");
+
+        // A payload of the old key that cannot be decrypted leaves every value as it was
+        let account = stored("account");
+        config_store
+            .set_raw("broken", json!({ ENCRYPTED_FIELD: "00" }))
+            .unwrap();
+        assert!(config_store
+            .rotate_key(&Encrypted::new(&new), &Encrypted::new(&old))
+            .is_err());
+        assert_eq!(stored("account"), account);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::OnceCell;
+use std::ops::{Deref, DerefMut};
+
+/// A typed handle on the value of a key, created with `Configstore::entry`
+/// The value is loaded on first access, or defaulted if the key was never set,
+/// and written back when the guard is dropped if it was mutably accessed
+///
+/// Dereferencing panics if the stored value cannot be read or decoded,
+/// call `load` first to handle that error instead
+/// Errors writing the value on drop are ignored, call `flush` to handle them
+///
+/// # Examples
+///
+/// ```
+/// use serde_derive::*;
+/// use configstore::{Configstore, AppUI};
+///
+/// #[derive(Deserialize, Serialize, Default)]
+/// struct Settings {
+///     launches: u32,
+/// }
+///
+/// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+/// {
+///     let mut settings = config_store.entry::<Settings>("entry_settings");
+///     settings.launches += 1;
+/// } // saved here
+/// let settings: Settings = config_store.get("entry_settings").unwrap();
+/// assert!(settings.launches >= 1);
+/// ```
+pub struct EntryGuard<'a, T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    store: &'a Configstore,
+    key: String,
+    value: OnceCell<T>,
+    dirty: bool,
+}
+
+impl<'a, T> EntryGuard<'a, T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    /// Loads the value if it was not yet, surfacing read and decoding errors
+    /// The value is written back on drop like with a mutable dereference
+    ///
+    /// # Errors
+    /// Fails if the key's file cannot be read or decoded as T, a key that was never set is not an error
+    pub fn load(&mut self) -> Result<&mut T> {
+        if self.value.get().is_none() {
+            let value = match self.store.get(&self.key) {
+                Ok(value) => value,
+                Err(e) if is_not_found(&e) => T::default(),
+                Err(e) => return Err(e),
+            };
+            let _ = self.value.set(value);
+        }
+        self.dirty = true;
+        Ok(self.value.get_mut().expect("value was just loaded"))
+    }
+
+    /// Writes the value if it was mutably accessed since it was loaded or last flushed
+    ///
+    /// # Errors
+    /// Same as the store's set
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(value) = self.value.get() {
+            self.store.set(&self.key, value)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn loaded(&self) -> &T {
+        self.value.get_or_init(|| match self.store.get(&self.key) {
+            Ok(value) => value,
+            Err(e) if is_not_found(&e) => T::default(),
+            Err(e) => panic!("Unable to load {}: {}", self.key, e),
+        })
+    }
+}
+
+impl<'a, T> Deref for EntryGuard<'a, T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.loaded()
+    }
+}
+
+impl<'a, T> DerefMut for EntryGuard<'a, T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.loaded();
+        self.dirty = true;
+        self.value.get_mut().expect("value was just loaded")
+    }
+}
+
+impl<'a, T> Drop for EntryGuard<'a, T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl Configstore {
+    /// Creates a typed handle on the value of a key, check the EntryGuard docs for usage
+    pub fn entry<T>(&self, key: &str) -> EntryGuard<'_, T>
+    where
+        T: Serialize + DeserializeOwned + Default,
+    {
+        EntryGuard {
+            store: self,
+            key: key.to_string(),
+            value: OnceCell::new(),
+            dirty: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChangeEvent, Configstore};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn entry_saves_mutations_on_drop() {
+        let config_store = Configstore::temp().unwrap();
+        {
+            let mut names = config_store.entry::<Vec<String>>("names");
+            assert!(names.is_empty());
+            names.push(String::from("first"));
+        }
+        assert_eq!(
+            config_store.get::<Vec<String>>("names").unwrap(),
+            vec!["first"]
+        );
+        let mut names = config_store.entry::<Vec<String>>("names");
+        names.push(String::from("second"));
+        names.flush().unwrap();
+        assert_eq!(config_store.get::<Vec<String>>("names").unwrap().len(), 2);
+        drop(names);
+
+        // Changes made through load are saved too
+        {
+            let mut names = config_store.entry::<Vec<String>>("names");
+            names.load().unwrap().push(String::from("third"));
+        }
+        assert_eq!(config_store.get::<Vec<String>>("names").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn reading_does_not_write() {
+        let config_store = Configstore::temp().unwrap();
+        let events = Arc::new(Mutex::new(Vec::<ChangeEvent>::new()));
+        let recorded = Arc::clone(&events);
+        config_store.on_change(move |event| recorded.lock().unwrap().push(event.clone()));
+        {
+            let count = config_store.entry::<u32>("count");
+            assert_eq!(*count, 0);
+        }
+        assert!(events.lock().unwrap().is_empty());
+        config_store
+            .set("count", String::from("not a number"))
+            .unwrap();
+        assert!(config_store.entry::<u32>("count").load().is_err());
+    }
+}
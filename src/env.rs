@@ -0,0 +1,167 @@
+use crate::{atomic, Configstore};
+use anyhow::Result;
+use serde_json::Value;
+use std::path::Path;
+
+impl Configstore {
+    /// Imports the variables of a `.env` file, each `KEY=value` line setting the key to the string value
+    /// Comments, blank lines and `export` prefixes are skipped, quoted values are unquoted
+    /// Keys that already exist are overwritten. Returns the keys that were set, in file order
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let path = std::env::temp_dir().join("configstore-example.env");
+    /// std::fs::write(&path, "# Service\nAPI_URL=https://example.com\nexport TOKEN=\"abc 123\"\n").unwrap();
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.from_env_file(&path).unwrap();
+    /// assert_eq!(config_store.get::<String>("TOKEN").unwrap(), "abc 123");
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read, has a line that is not a variable, or if a value cannot be written
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn from_env_file<P>(&self, path: P) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        self.check_writable()?;
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let mut imported = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => (key.trim(), value.trim()),
+                _ => {
+                    return Err(anyhow::Error::msg(format!(
+                        "Line {} of {} is not a KEY=value variable",
+                        number + 1,
+                        path.display()
+                    )))
+                }
+            };
+            self.set(key, unquote(value))?;
+            imported.push(key.to_string());
+        }
+        Ok(imported)
+    }
+
+    /// Writes keys to a `.env` file as `KEY=value` lines, for tools that read their settings from it
+    /// Strings are written as is, other values as json, and values that need it are double quoted
+    ///
+    /// # Errors
+    /// Fails if a key is not set or if the file cannot be written
+    pub fn export_env_file<P>(&self, path: P, keys: &[&str]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut contents = String::new();
+        for key in keys {
+            let value = match self.get_raw(key)? {
+                Value::String(value) => value,
+                value => value.to_string(),
+            };
+            contents.push_str(key);
+            contents.push('=');
+            contents.push_str(&quote(&value));
+            contents.push('\n');
+        }
+        atomic::write(path.as_ref(), contents.as_bytes())
+    }
+}
+
+/// Removes the quotes of a value, and its inline comment if it is not quoted
+/// Double quoted values support the `\n`, `\"` and `\\` escapes, single quoted ones are literal
+fn unquote(value: &str) -> String {
+    if let Some(inner) = value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+    {
+        return inner.to_string();
+    }
+    if let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        let mut unescaped = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some('n')) => unescaped.push('\n'),
+                ('\\', Some(escaped @ ('"' | '\\'))) => unescaped.push(escaped),
+                _ => {
+                    unescaped.push(c);
+                    continue;
+                }
+            }
+            chars.next();
+        }
+        return unescaped;
+    }
+    match value.find(" #") {
+        Some(comment) => value[..comment].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Double quotes values that would not read back the same unquoted
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| !c.is_whitespace() && !matches!(c, '"' | '\'' | '#' | '\\'));
+    if plain {
+        return value.to_string();
+    }
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_files_round_trip() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("PLAIN", "value").unwrap();
+        config_store.set("SPACED", "a \"quoted\" value\n").unwrap();
+        config_store.set("PORT", 8080).unwrap();
+        let path = config_store.dir().join("exported.env");
+        config_store
+            .export_env_file(&path, &["PLAIN", "SPACED", "PORT"])
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "PLAIN=value\nSPACED=\"a \\\"quoted\\\" value\\n\"\nPORT=8080\n"
+        );
+
+        let imported = Configstore::temp().unwrap();
+        assert_eq!(
+            imported.from_env_file(&path).unwrap(),
+            vec!["PLAIN", "SPACED", "PORT"]
+        );
+        assert_eq!(
+            imported.get::<String>("SPACED").unwrap(),
+            "a \"quoted\" value\n"
+        );
+        assert_eq!(imported.get::<String>("PORT").unwrap(), "8080");
+    }
+
+    #[test]
+    fn unquotes_values() {
+        assert_eq!(unquote("value # comment"), "value");
+        assert_eq!(unquote("'literal \\n'"), "literal \\n");
+        assert_eq!(unquote("\"a # b\""), "a # b");
+    }
+}
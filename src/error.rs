@@ -0,0 +1,227 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Errors specific to configstore
+/// They are returned wrapped in an `anyhow::Error` and can be recovered with `downcast_ref`
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI, Error};
+///
+/// let config_store = Configstore::open_read_only("myApp", AppUI::CommandLine).unwrap();
+/// let err = config_store.set("key", "value".to_string()).unwrap_err();
+/// assert_eq!(err.downcast_ref::<Error>(), Some(&Error::ReadOnly));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A mutation was attempted on a store opened in read-only mode
+    ReadOnly,
+    /// A write would make the store larger than its quota
+    QuotaExceeded {
+        /// The configured quota in bytes
+        quota: u64,
+        /// The size in bytes the store would have after the write
+        required: u64,
+    },
+    /// A validator rejected the value of a key
+    Invalid {
+        /// The key whose value was rejected
+        key: String,
+        /// What the validator found wrong with the value
+        reason: String,
+    },
+    /// A conditional write found that the key changed since its version was read
+    Conflict {
+        /// The key that was changed by someone else
+        key: String,
+    },
+    /// A write-once key was already set, check `set_once`, or a deleted key was set again, check `restore_deleted`
+    AlreadyExists {
+        /// The key that was set before
+        key: String,
+    },
+    /// Another instance of the app holds the lock of `acquire_app_lock`
+    AlreadyRunning {
+        /// The id of the process holding the lock, if it could be read
+        pid: Option<u32>,
+    },
+    /// The store was laid out by a newer version of configstore, which this one cannot read
+    IncompatibleStore {
+        /// The directory of the store
+        path: PathBuf,
+        /// The layout version recorded in the store's manifest
+        layout: u32,
+        /// The newest layout version this version of configstore reads
+        supported: u32,
+    },
+    /// A file of the store stayed locked by another program, like an antivirus scanning it,
+    /// through several retries. Trying again later usually succeeds
+    Busy {
+        /// The file that could not be accessed
+        path: PathBuf,
+    },
+    /// The store directory belongs to another user, check `OwnerPolicy`
+    ForeignDir {
+        /// The directory of the app's store
+        path: PathBuf,
+    },
+    /// The access given to a key with `set_access` cannot be applied to its files
+    AccessUnsupported {
+        /// The key the access was given to
+        key: String,
+        /// Why it cannot be applied
+        reason: String,
+    },
+    /// The schema given to `add_schema` uses keywords that values are not checked against
+    SchemaUnsupported {
+        /// The key the schema was given to
+        key: String,
+        /// Where the unsupported keyword is in the schema
+        reason: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadOnly => write!(f, "The configstore was opened in read-only mode"),
+            Error::QuotaExceeded { quota, required } => write!(
+                f,
+                "The configstore would grow to {} bytes, exceeding its quota of {} bytes",
+                required, quota
+            ),
+            Error::Invalid { key, reason } => write!(f, "Invalid value for {}: {}", key, reason),
+            Error::Conflict { key } => write!(f, "{} was changed since it was read", key),
+            Error::AlreadyExists { key } => write!(f, "{} is already set and cannot change", key),
+            Error::AlreadyRunning { pid: Some(pid) } => {
+                write!(f, "Another instance is already running as process {}", pid)
+            }
+            Error::AlreadyRunning { pid: None } => write!(f, "Another instance is already running"),
+            Error::IncompatibleStore {
+                path,
+                layout,
+                supported,
+            } => write!(
+                f,
+                "{} was written by a newer version of the app (store layout {}, this version reads up to {}), update the app to open it",
+                path.display(),
+                layout,
+                supported
+            ),
+            Error::Busy { path } => write!(
+                f,
+                "{} is in use by another program, try again in a moment",
+                path.display()
+            ),
+            Error::ForeignDir { path } => write!(
+                f,
+                "{} belongs to another user, writing to it would leave files they cannot change",
+                path.display()
+            ),
+            Error::AccessUnsupported { key, reason } => write!(
+                f,
+                "Cannot apply the requested permissions to {}: {}",
+                key, reason
+            ),
+            Error::SchemaUnsupported { key, reason } => write!(
+                f,
+                "Cannot check the values of {} against the schema: {}",
+                key, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// What a store was doing when an error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Reading the file of a key
+    Read,
+    /// Decoding the value read from the file of a key
+    Decode,
+    /// Writing the file of a key
+    Write,
+    /// Removing the file of a key
+    Delete,
+    /// Locking a key for a read-modify-write
+    Lock,
+}
+
+/// The operation, key and file an error happened on, attached as context to the errors
+/// of reads, writes, deletes and locks so that users are told which file to look at
+/// Recover it with `downcast_ref`, the underlying error, like an `Error` or an io error,
+/// can still be recovered the same way
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, ErrorContext, Operation};
+///
+/// let config_store = Configstore::temp().unwrap();
+/// std::fs::write(config_store.config_path("window"), "{").unwrap();
+/// let err = config_store.get::<Vec<u32>>("window").unwrap_err();
+/// let context = err.downcast_ref::<ErrorContext>().unwrap();
+/// assert_eq!(context.operation, Operation::Decode);
+/// assert_eq!(context.path, config_store.config_path("window"));
+/// println!("{:#}", err); // Could not decode window read from /.../window.json: EOF while parsing...
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// What the store was doing
+    pub operation: Operation,
+    /// The key the operation was on
+    pub key: String,
+    /// The file the operation was on
+    pub path: PathBuf,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (key, path) = (&self.key, self.path.display());
+        match self.operation {
+            Operation::Read => write!(f, "Could not read {} from {}", key, path),
+            Operation::Decode => write!(f, "Could not decode {} read from {}", key, path),
+            Operation::Write => write!(f, "Could not write {} to {}", key, path),
+            Operation::Delete => write!(f, "Could not delete {} from {}", key, path),
+            Operation::Lock => write!(f, "Could not lock {} with {}", key, path),
+        }
+    }
+}
+
+/// Attaches the context of an operation to its error, unless an inner operation already did
+pub(crate) fn with_context<T, P>(
+    result: anyhow::Result<T>,
+    operation: Operation,
+    key: &str,
+    path: P,
+) -> anyhow::Result<T>
+where
+    P: FnOnce() -> PathBuf,
+{
+    result.map_err(|e| {
+        if e.downcast_ref::<ErrorContext>().is_some() {
+            return e;
+        }
+        e.context(ErrorContext {
+            operation,
+            key: key.to_string(),
+            path: path(),
+        })
+    })
+}
+
+/// The error of reading a key that is not set, recognized by `is_not_found`
+pub(crate) fn not_found(key: &str) -> anyhow::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} is not set", key)).into()
+}
+
+/// Whether the error comes from a file that does not exist, usually a key that was never set
+pub(crate) fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
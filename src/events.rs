@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Describes a change made to a key through a Configstore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// The key was set to a new value
+    Set(String),
+    /// The key was deleted
+    Delete(String),
+}
+
+impl ChangeEvent {
+    /// The key that changed
+    pub fn key(&self) -> &str {
+        match self {
+            ChangeEvent::Set(key) | ChangeEvent::Delete(key) => key,
+        }
+    }
+}
+
+type Listener = Arc<dyn Fn(&ChangeEvent) + Send + Sync>;
+
+/// The callbacks registered with `on_change`, with the id `add` gave them
+#[derive(Default)]
+pub(crate) struct Listeners {
+    listeners: Mutex<Vec<(u64, Listener)>>,
+    next_id: AtomicU64,
+}
+
+impl Listeners {
+    /// Registers a listener, returns the id that removes it
+    pub(crate) fn add<F>(&self, listener: F) -> u64
+    where
+        F: Fn(&ChangeEvent) + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.listeners
+            .lock()
+            .unwrap()
+            .push((id, Arc::new(listener)));
+        id
+    }
+
+    pub(crate) fn remove(&self, id: u64) {
+        self.listeners
+            .lock()
+            .unwrap()
+            .retain(|(listener_id, _)| *listener_id != id);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.listeners.lock().unwrap().len()
+    }
+
+    /// Calls every listener, the lock is released first so listeners can use the store
+    pub(crate) fn notify(&self, event: ChangeEvent) {
+        let listeners = self.listeners.lock().unwrap().clone();
+        for (_, listener) in listeners {
+            listener(&event);
+        }
+    }
+}
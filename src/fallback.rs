@@ -0,0 +1,206 @@
+use crate::error::is_not_found;
+use crate::{atomic, Configstore, Format};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Directory of the store holding the last value written to each key, hidden so that
+/// it is never listed as a key
+const BACKUPS_DIR: &str = ".backups";
+
+/// Where `get` turns to after a key could not be read, with the builder's `fallback`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FallbackStep {
+    /// The last value the store wrote to the key
+    Backup,
+    /// The vendor default of the key, then its seeded default
+    Defaults,
+}
+
+/// Reported to the callback of the builder's `fallback` before each step of the chain
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI, Format};
+///
+/// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+///     .temp(true)
+///     .defaults_from_str(r#"{"volume": 5}"#, Format::Json)
+///     .fallback(|fallback| eprintln!("warning: {}", fallback))
+///     .build()
+///     .unwrap();
+/// config_store.set("volume", 11).unwrap();
+/// std::fs::write(config_store.config_path("volume"), "{garbage").unwrap();
+/// // Reads the backup written by set
+/// assert_eq!(config_store.get::<u32>("volume").unwrap(), 11);
+///
+/// std::fs::write(config_store.config_path("theme"), "{garbage").unwrap();
+/// assert!(config_store.get::<String>("theme").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Fallback {
+    /// The key being read
+    pub key: String,
+    /// What is tried next
+    pub step: FallbackStep,
+    /// Why the previous step failed
+    pub reason: String,
+}
+
+impl std::fmt::Display for Fallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let step = match self.step {
+            FallbackStep::Backup => "its backup",
+            FallbackStep::Defaults => "its default",
+        };
+        write!(f, "Reading {} from {}: {}", self.key, step, self.reason)
+    }
+}
+
+/// The callback of the builder's `fallback`
+pub(crate) type FallbackCallback = Box<dyn Fn(&Fallback) + Send + Sync>;
+
+impl Configstore {
+    /// Reads a key through the fallback chain after its value failed to read with error,
+    /// returns error if no step succeeds
+    pub(crate) fn fall_back<T>(&self, key: &str, error: anyhow::Error) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let warn = match &self.inner.fallback {
+            Some(warn) if !is_not_found(&error) => warn,
+            _ => return Err(error),
+        };
+        let report = |step, reason: String| {
+            warn(&Fallback {
+                key: key.to_string(),
+                step,
+                reason,
+            })
+        };
+        report(FallbackStep::Backup, format!("{:#}", error));
+        let reason = match self.read_backup(key) {
+            Ok(Some((format, bytes))) => match self.decode_plain(key, format, &bytes) {
+                Ok(value) => return Ok(value),
+                Err(e) => format!("{:#}", e),
+            },
+            Ok(None) => "there is no backup".to_string(),
+            Err(e) => format!("{:#}", e),
+        };
+        report(FallbackStep::Defaults, reason);
+        if let Ok(Some((format, bytes))) = self.read_vendor_default(key) {
+            if let Ok(value) = self.decode_plain(key, format, &bytes) {
+                return Ok(value);
+            }
+        }
+        match self.inner.defaults.get(key) {
+            Some(default) => Ok(serde_json::from_value(default.clone())?),
+            None => Err(error),
+        }
+    }
+
+    /// Keeps a copy of the value just written to a key for the fallback chain
+    pub(crate) fn write_backup(&self, key: &str, format: Format, bytes: &[u8]) -> Result<()> {
+        if self.inner.fallback.is_none() || !self.inner.storage.backend.uses_files() {
+            return Ok(());
+        }
+        let dir = self.backups_dir();
+        std::fs::create_dir_all(&dir)?;
+        // Values can be secrets, backups are readable by the current user only
+        atomic::write_private(&dir.join(self.entry_name(key, format.extension())), bytes)
+    }
+
+    /// Removes the backups of a deleted key
+    pub(crate) fn remove_backup(&self, key: &str) -> Result<()> {
+        if self.inner.fallback.is_none() {
+            return Ok(());
+        }
+        for format in self.formats() {
+            let path = self
+                .backups_dir()
+                .join(self.entry_name(key, format.extension()));
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_backup(&self, key: &str) -> Result<Option<(Format, Vec<u8>)>> {
+        for format in self.formats() {
+            let path = self
+                .backups_dir()
+                .join(self.entry_name(key, format.extension()));
+            match std::fs::read(path) {
+                Ok(bytes) => return Ok(Some((format, bytes))),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decodes bytes with the codecs of key, without recovering corrupt entries
+    fn decode_plain<T>(&self, key: &str, format: Format, bytes: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        match self.decode_codecs(key, || format.deserialize(bytes))? {
+            Some(value) => Ok(value),
+            None => format.deserialize(bytes),
+        }
+    }
+
+    fn backups_dir(&self) -> PathBuf {
+        self.dir().join(BACKUPS_DIR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn reads_fall_back_to_backups_then_defaults() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let recorded = warnings.clone();
+        let config_store = Configstore::temp().unwrap();
+        let falling_back = config_store
+            .reopen()
+            .defaults_from_str(r#"{"theme": "light"}"#, Format::Json)
+            .fallback(move |fallback| recorded.lock().unwrap().push(fallback.step))
+            .build()
+            .unwrap();
+        falling_back.set("theme", "dark").unwrap();
+        falling_back.set("volume", 7).unwrap();
+        std::fs::write(falling_back.config_path("theme"), "{").unwrap();
+        std::fs::write(falling_back.config_path("volume"), "{").unwrap();
+        assert_eq!(falling_back.get::<String>("theme").unwrap(), "dark");
+        assert_eq!(*warnings.lock().unwrap(), vec![FallbackStep::Backup]);
+
+        std::fs::write(falling_back.backups_dir().join("theme.json"), "{").unwrap();
+        assert_eq!(falling_back.get::<String>("theme").unwrap(), "light");
+        falling_back.delete("volume").unwrap();
+        std::fs::write(falling_back.config_path("volume"), "{").unwrap();
+        assert!(falling_back.get::<u32>("volume").is_err());
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec![
+                FallbackStep::Backup,
+                FallbackStep::Backup,
+                FallbackStep::Defaults,
+                FallbackStep::Backup,
+                FallbackStep::Defaults,
+            ]
+        );
+        assert!(config_store.get::<String>("theme").is_err());
+        assert!(falling_back.get::<u32>("missing").is_err());
+        assert_eq!(warnings.lock().unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,456 @@
+use crate::{Configstore, Error};
+use anyhow::Result;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::RefCell;
+
+/// How closely the values read from the store must match the types they are read as
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{AppUI, Configstore, Error, ReadMode};
+/// use serde_derive::Deserialize;
+/// use serde_json::json;
+///
+/// #[derive(Deserialize)]
+/// struct Window {
+///     width: u32,
+///     #[serde(default)]
+///     height: u32,
+/// }
+///
+/// let config_store = Configstore::builder("myApp", AppUI::Graphical)
+///     .temp(true)
+///     .read_mode(ReadMode::Strict)
+///     .build()
+///     .unwrap();
+/// // A user edited the file by hand and misspelled height
+/// config_store.set_raw("window", json!({"width": 800, "heigth": 600})).unwrap();
+/// let err = config_store.get::<Window>("window").err().unwrap();
+/// assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Invalid { .. })));
+/// assert!(format!("{:#}", err).ends_with("unknown field /heigth"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadMode {
+    /// serde's behavior: unknown fields are ignored and missing fields fail the read,
+    /// unless the type defaults them with `#[serde(default)]` (the default)
+    #[default]
+    Standard,
+    /// Fields the type does not have fail the read with `Error::Invalid`, to catch typos
+    /// in hand-edited files. Fields of maps and of `#[serde(flatten)]` structs are not checked
+    Strict,
+    /// Missing fields are filled from `Default` by the reads that know the default of the type,
+    /// `TypedStore` and `get_lenient`, other reads behave like Standard
+    Lenient,
+}
+
+impl Configstore {
+    /// Reads the value of key as T, filling the fields missing from the stored document
+    /// with the ones of `T::default()`, whatever the read mode of the store
+    /// Useful for settings structs that gained fields since they were saved
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    /// use serde_derive::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize, Default)]
+    /// struct Editor {
+    ///     tab_width: u32,
+    ///     line_numbers: bool,
+    /// }
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set_raw("editor", serde_json::json!({"tab_width": 2})).unwrap();
+    /// assert!(config_store.get::<Editor>("editor").is_err());
+    /// assert_eq!(config_store.get_lenient::<Editor>("editor").unwrap().tab_width, 2);
+    /// ```
+    ///
+    /// # Errors
+    /// Same as get, decoding errors fail with `Error::Invalid` giving the path of the faulty field
+    pub fn get_lenient<T>(&self, key: &str) -> Result<T>
+    where
+        T: DeserializeOwned + Serialize + Default,
+    {
+        let document = self.get_raw(key)?;
+        self.decode_lenient_document(key, document)
+    }
+
+    /// Decodes a document read from key as T according to the store's read mode
+    pub(crate) fn decode_document<T>(&self, key: &str, document: Value) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        match self.inner.read_mode {
+            ReadMode::Standard => Ok(serde_json::from_value(document)?),
+            ReadMode::Strict => from_value(key, &document, true),
+            ReadMode::Lenient => from_value(key, &document, false),
+        }
+    }
+
+    /// Decodes a document read from key as T, filling its missing fields from `T::default()`
+    pub(crate) fn decode_lenient_document<T>(&self, key: &str, document: Value) -> Result<T>
+    where
+        T: DeserializeOwned + Serialize + Default,
+    {
+        let mut filled = serde_json::to_value(T::default())?;
+        fill(&mut filled, document);
+        from_value(key, &filled, self.inner.read_mode == ReadMode::Strict)
+    }
+}
+
+/// Replaces the fields of defaults with the ones of document, recursively for objects
+fn fill(defaults: &mut Value, document: Value) {
+    match (defaults, document) {
+        (Value::Object(defaults), Value::Object(fields)) => {
+            for (field, value) in fields {
+                match defaults.get_mut(&field) {
+                    Some(default) => fill(default, value),
+                    None => {
+                        defaults.insert(field, value);
+                    }
+                }
+            }
+        }
+        (defaults, document) => *defaults = document,
+    }
+}
+
+/// Decodes value as T, errors give the JSON Pointer of the faulty field,
+/// and unknown fields of structs fail the decoding if strict
+fn from_value<T>(key: &str, value: &Value, strict: bool) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let state = State {
+        strict,
+        failed_at: RefCell::new(None),
+        unknown: RefCell::new(Vec::new()),
+    };
+    let decoded = T::deserialize(Tracked {
+        value,
+        pointer: String::new(),
+        state: &state,
+    });
+    let invalid = |reason| -> anyhow::Error {
+        Error::Invalid {
+            key: key.to_string(),
+            reason,
+        }
+        .into()
+    };
+    match decoded {
+        Err(e) => {
+            let pointer = state.failed_at.into_inner().unwrap_or_default();
+            let pointer = if pointer.is_empty() {
+                "/".to_string()
+            } else {
+                pointer
+            };
+            Err(invalid(format!("{}: {}", pointer, e)))
+        }
+        Ok(_) if !state.unknown.borrow().is_empty() => {
+            let unknown = state.unknown.into_inner();
+            let plural = if unknown.len() > 1 { "s" } else { "" };
+            Err(invalid(format!(
+                "unknown field{} {}",
+                plural,
+                unknown.join(", ")
+            )))
+        }
+        Ok(decoded) => Ok(decoded),
+    }
+}
+
+struct State {
+    strict: bool,
+    /// The pointer of the innermost value that failed to decode
+    failed_at: RefCell<Option<String>>,
+    /// The pointers of the fields structs do not have
+    unknown: RefCell<Vec<String>>,
+}
+
+/// Deserializes a json value while tracking the pointer of the value being decoded
+struct Tracked<'a> {
+    value: &'a Value,
+    pointer: String,
+    state: &'a State,
+}
+
+impl<'a> Tracked<'a> {
+    fn child(&self, value: &'a Value, segment: &str) -> Self {
+        let segment = segment.replace('~', "~0").replace('/', "~1");
+        Tracked {
+            value,
+            pointer: format!("{}/{}", self.pointer, segment),
+            state: self.state,
+        }
+    }
+
+    /// Records where a failure happened, inner values fail first
+    fn track<T>(&self, result: Result<T, serde_json::Error>) -> Result<T, serde_json::Error> {
+        if result.is_err() {
+            let mut failed_at = self.state.failed_at.borrow_mut();
+            if failed_at.is_none() {
+                *failed_at = Some(self.pointer.clone());
+            }
+        }
+        result
+    }
+}
+
+impl<'de, 'a: 'de> Deserializer<'de> for Tracked<'a> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let result = match self.value {
+            Value::Array(items) => visitor.visit_seq(Items {
+                parent: &self,
+                items: items.iter().enumerate(),
+            }),
+            Value::Object(fields) => visitor.visit_map(Fields {
+                parent: &self,
+                fields: fields.iter(),
+                value: None,
+            }),
+            value => value.deserialize_any(visitor),
+        };
+        self.track(result)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        known: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let (true, Value::Object(fields)) = (self.state.strict, self.value) {
+            for field in fields.keys() {
+                if !known.contains(&field.as_str()) {
+                    let pointer = self.child(self.value, field).pointer;
+                    self.state.unknown.borrow_mut().push(pointer);
+                }
+            }
+        }
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.value.deserialize_enum(name, variants, visitor);
+        self.track(result)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.value.deserialize_unit(visitor);
+        self.track(result)
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.value.deserialize_unit_struct(name, visitor);
+        self.track(result)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.value.deserialize_bytes(visitor);
+        self.track(result)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.value.deserialize_byte_buf(visitor);
+        self.track(result)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+struct Items<'p, 'a, I> {
+    parent: &'p Tracked<'a>,
+    items: I,
+}
+
+impl<'de, 'p, 'a: 'de, I> de::SeqAccess<'de> for Items<'p, 'a, I>
+where
+    I: Iterator<Item = (usize, &'a Value)>,
+{
+    type Error = serde_json::Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match self.items.next() {
+            Some((index, item)) => seed
+                .deserialize(self.parent.child(item, &index.to_string()))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct Fields<'p, 'a> {
+    parent: &'p Tracked<'a>,
+    fields: serde_json::map::Iter<'a>,
+    value: Option<(&'a String, &'a Value)>,
+}
+
+impl<'de, 'p, 'a: 'de> de::MapAccess<'de> for Fields<'p, 'a> {
+    type Error = serde_json::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.fields.next() {
+            Some((field, value)) => {
+                self.value = Some((field, value));
+                seed.deserialize(de::value::BorrowedStrDeserializer::new(field))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S>(&mut self, seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        let (field, value) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before its key"))?;
+        seed.deserialize(self.parent.child(value, field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, Error, ReadMode};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, Default)]
+    struct Plugin {
+        name: String,
+        enabled: bool,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, Default)]
+    struct Settings {
+        plugins: Vec<Plugin>,
+        theme: Option<String>,
+    }
+
+    fn reason(err: anyhow::Error) -> String {
+        match err.downcast_ref::<Error>() {
+            Some(Error::Invalid { reason, .. }) => reason.clone(),
+            _ => panic!("{} is not an Invalid error", err),
+        }
+    }
+
+    #[test]
+    fn read_modes_report_paths() {
+        let plain = Configstore::temp().unwrap();
+        let strict = plain.reopen().read_mode(ReadMode::Strict).build().unwrap();
+        let lenient = plain.reopen().read_mode(ReadMode::Lenient).build().unwrap();
+        plain
+            .set_raw(
+                "settings",
+                json!({"plugins": [{"name": "git", "enabled": true, "enabeld": false}], "colour": "red"}),
+            )
+            .unwrap();
+        assert_eq!(
+            reason(strict.get::<Settings>("settings").unwrap_err()),
+            "unknown fields /colour, /plugins/0/enabeld"
+        );
+        assert_eq!(
+            lenient.get::<Settings>("settings").unwrap().plugins[0].name,
+            "git"
+        );
+
+        plain
+            .set_raw(
+                "settings",
+                json!({"plugins": [{"name": "git", "enabled": "yes"}]}),
+            )
+            .unwrap();
+        assert!(reason(lenient.get::<Settings>("settings").unwrap_err())
+            .starts_with("/plugins/0/enabled: invalid type: string \"yes\""));
+
+        plain
+            .set_raw("settings", json!({"plugins": [{"name": "git"}]}))
+            .unwrap();
+        assert!(plain.get::<Settings>("settings").is_err());
+        assert_eq!(
+            reason(strict.get::<Settings>("settings").unwrap_err()),
+            "/plugins/0: missing field `enabled`"
+        );
+        // Items of arrays have no default to be filled from
+        assert!(plain.get_lenient::<Settings>("settings").is_err());
+        plain.set_raw("settings", json!({"theme": "dark"})).unwrap();
+        assert_eq!(
+            plain.get_lenient::<Settings>("settings").unwrap(),
+            Settings {
+                plugins: Vec::new(),
+                theme: Some("dark".to_string())
+            }
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use crate::atomic;
+use crate::storage::Entry;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Longest file name kept plain, so that paths made of a store directory, a name and the suffix
+/// of the temporary files of writes stay within the 260 characters Windows accepts
+/// It does not depend on the directory, so that a key has the same name wherever its store is
+const MAX_PLAIN_NAME: usize = 128;
+
+/// Device names Windows reserves in every directory, whatever their extension
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows forbids in file names, or that would put the file in another directory
+const FORBIDDEN: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Separates the readable part of a hashed name from its hash
+const HASH_SEPARATOR: char = '~';
+
+/// Number of hexadecimal digits of the hash of hashed names
+const HASH_LEN: usize = 16;
+
+/// Most characters of the key kept readable in a hashed name
+const READABLE_LEN: usize = 32;
+
+/// Name of the file holding the keys of hashed names, hidden so that it is never listed as a key
+const KEY_NAMES_NAME: &str = ".key_names.json";
+
+/// Name of the file locked while the keys of hashed names are written
+const LOCK_NAME: &str = ".key_names.lock";
+
+/// The file names of the keys of a store directory, the same on every platform
+/// Keys whose file would have a name or a path Windows rejects, like `con`, `a:b` or keys of
+/// hundreds of characters, are stored under a name made of a readable part of the key and its
+/// hash. The keys of these names are kept in a file of the directory, so that they are listed
+/// Writable stores move the entries older versions wrote under plain names to these when opened
+pub(crate) struct KeyNames {
+    dir: PathBuf,
+    /// Keys of the hashed names given by this process, by stem
+    given: Mutex<BTreeMap<String, String>>,
+}
+
+impl KeyNames {
+    pub(crate) fn open(dir: &Path) -> Self {
+        KeyNames {
+            dir: dir.to_path_buf(),
+            given: Mutex::default(),
+        }
+    }
+
+    /// The name of the entry of key with extension in the directory
+    pub(crate) fn entry_name(&self, key: &str, extension: &str) -> String {
+        let name = format!("{}.{}", key, extension);
+        if is_portable(key) && name.len() <= MAX_PLAIN_NAME {
+            return name;
+        }
+        let stem = hashed_stem(key, MAX_PLAIN_NAME.saturating_sub(extension.len() + 1));
+        self.given
+            .lock()
+            .unwrap()
+            .insert(stem.clone(), key.to_string());
+        format!("{}.{}", stem, extension)
+    }
+
+    /// Records the key of an entry about to be written, if its name is hashed
+    pub(crate) fn record(&self, name: &str) -> Result<()> {
+        let stem = match crate::storage::entry_key(name) {
+            Some(stem) if is_hashed(stem) => stem,
+            _ => return Ok(()),
+        };
+        let key = match self.given.lock().unwrap().get(stem) {
+            Some(key) => key.clone(),
+            None => return Ok(()),
+        };
+        if self.stored()?.contains_key(stem) {
+            return Ok(());
+        }
+        let _lock = atomic::lock(&self.dir.join(LOCK_NAME))?;
+        let mut stored = self.stored()?;
+        stored.insert(stem.to_string(), key);
+        atomic::write(
+            &self.dir.join(KEY_NAMES_NAME),
+            &serde_json::to_vec_pretty(&stored)?,
+        )
+    }
+
+    /// Replaces the stems of hashed entries by their keys
+    pub(crate) fn restore_keys(&self, entries: &mut [Entry]) -> Result<()> {
+        if !entries.iter().any(|entry| is_hashed(&entry.key)) {
+            return Ok(());
+        }
+        let stored = self.stored()?;
+        let given = self.given.lock().unwrap();
+        for entry in entries.iter_mut() {
+            if let Some(key) = stored.get(&entry.key).or_else(|| given.get(&entry.key)) {
+                entry.key = key.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// The key of an entry name, the one it was given for a hashed name
+    pub(crate) fn key_of(&self, name: &str) -> Result<Option<String>> {
+        let stem = match crate::storage::entry_key(name) {
+            Some(stem) if is_hashed(stem) => stem,
+            stem => return Ok(stem.map(str::to_string)),
+        };
+        let stored = self.stored()?;
+        let given = self.given.lock().unwrap();
+        let key = stored.get(stem).or_else(|| given.get(stem));
+        Ok(Some(key.map_or(stem, String::as_str).to_string()))
+    }
+
+    /// The entries written under another name than the one their key has now, with that name:
+    /// entries of keys that are not valid file names written under their plain name before
+    /// hashed names, like `con.json`, and names hashed with the limits of older versions
+    pub(crate) fn legacy_names(&self, entries: &[Entry]) -> Result<Vec<(String, String)>> {
+        let stored = self.stored()?;
+        let mut names = Vec::new();
+        for entry in entries {
+            let key = match stored.get(&entry.key) {
+                Some(key) if is_hashed(&entry.key) => key,
+                None if is_hashed(&entry.key) => continue,
+                _ => &entry.key,
+            };
+            let name = self.entry_name(key, entry.extension());
+            if name != entry.name {
+                names.push((entry.name.clone(), name));
+            }
+        }
+        Ok(names)
+    }
+
+    fn stored(&self) -> Result<BTreeMap<String, String>> {
+        match std::fs::read(self.dir.join(KEY_NAMES_NAME)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Whether a key can be used as is as the stem of a file name on every platform
+fn is_portable(key: &str) -> bool {
+    let device = key.split('.').next().unwrap_or_default();
+    !key.is_empty()
+        && !key.contains(|c: char| c.is_control() || FORBIDDEN.contains(&c))
+        && !key.ends_with(['.', ' '])
+        && !RESERVED
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(device))
+        && !is_hashed(key)
+}
+
+/// A stem of at most max_len bytes for a key that is not portable
+fn hashed_stem(key: &str, max_len: usize) -> String {
+    let readable_len = max_len.saturating_sub(HASH_LEN + 1).min(READABLE_LEN);
+    let mut stem = String::new();
+    for c in key.chars() {
+        let c = if c.is_alphanumeric() || c == '-' || c == '_' {
+            c
+        } else {
+            '_'
+        };
+        if stem.len() + c.len_utf8() > readable_len {
+            break;
+        }
+        stem.push(c);
+    }
+    let hash = crate::version::fnv1a(key.as_bytes());
+    format!("{}{}{:016x}", stem, HASH_SEPARATOR, hash)
+}
+
+/// Whether a stem is a hashed name
+fn is_hashed(stem: &str) -> bool {
+    match stem.rsplit_once(HASH_SEPARATOR) {
+        Some((_, hash)) => hash.len() == HASH_LEN && hash.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Configstore;
+
+    #[test]
+    fn keys_windows_rejects_get_hashed_names() {
+        let config_store = Configstore::temp().unwrap();
+        let long = "k".repeat(300);
+        let keys = [
+            "con",
+            "Aux.settings",
+            "a:b",
+            "a/b",
+            "trailing.",
+            &long,
+            "plain",
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            config_store.set(key, i).unwrap();
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(config_store.get::<usize>(key).unwrap(), i);
+        }
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        assert_eq!(config_store.keys().unwrap(), sorted);
+
+        for file in std::fs::read_dir(config_store.dir()).unwrap() {
+            let name = file.unwrap().file_name().into_string().unwrap();
+            assert!(name.len() <= MAX_PLAIN_NAME);
+            let device = name.split('.').next().unwrap();
+            assert!(!RESERVED.iter().any(|r| r.eq_ignore_ascii_case(device)));
+        }
+        assert!(config_store.config_path("plain").ends_with("plain.json"));
+        let con = config_store.config_path("con");
+        assert!(con
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("con~"));
+
+        // Names do not depend on where the store is
+        let deep = KeyNames::open(&config_store.dir().join("d".repeat(300)));
+        for key in keys.iter() {
+            assert_eq!(
+                deep.entry_name(key, "json"),
+                config_store.entry_name(key, "json")
+            );
+        }
+
+        // The keys of hashed names are listed by other processes too
+        let reopened = config_store.reopen().build().unwrap();
+        assert_eq!(reopened.keys().unwrap(), sorted);
+        reopened.delete(&long).unwrap();
+        assert!(reopened.get::<usize>(&long).is_err());
+        assert_eq!(reopened.keys().unwrap().len(), keys.len() - 1);
+
+        // Stores laid out before hashed names get them when opened
+        let old_layout = Configstore::temp().unwrap();
+        let long = "k".repeat(200);
+        for (i, key) in ["con", "a:b", long.as_str()].iter().enumerate() {
+            std::fs::write(
+                old_layout.dir().join(format!("{}.json", key)),
+                i.to_string(),
+            )
+            .unwrap();
+        }
+        // Older versions did not record that names were hashed
+        std::fs::remove_file(old_layout.dir().join(crate::manifest::MANIFEST_NAME)).unwrap();
+        let migrated = old_layout.reopen().build().unwrap();
+        assert_eq!(migrated.get::<usize>("con").unwrap(), 0);
+        assert_eq!(migrated.get::<usize>("a:b").unwrap(), 1);
+        assert_eq!(migrated.get::<usize>(&long).unwrap(), 2);
+        let mut expected = vec!["a:b", "con", long.as_str()];
+        expected.sort();
+        assert_eq!(migrated.keys().unwrap(), expected);
+        for key in ["con", "a:b", long.as_str()].iter() {
+            assert!(!old_layout.dir().join(format!("{}.json", key)).exists());
+        }
+        // Only once, the manifest records it
+        std::fs::write(old_layout.dir().join("prn.json"), "3").unwrap();
+        old_layout.reopen().build().unwrap();
+        assert!(old_layout.dir().join("prn.json").exists());
+    }
+}
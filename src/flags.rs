@@ -0,0 +1,238 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The key every flag of a store is kept in
+const FLAGS_KEY: &str = "feature_flags";
+
+/// The state of a flag, as stored and as accepted by `sync_flags`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FlagState {
+    enabled: bool,
+    /// Seconds since the unix epoch after which the flag falls back to its default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+impl FlagState {
+    fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| {
+            UNIX_EPOCH + Duration::from_secs(expires_at) <= SystemTime::now()
+        })
+    }
+}
+
+/// A rollout flag or experiment persisted in the store, created with `Configstore::flag`
+///
+/// # Examples
+///
+/// ```
+/// use configstore::Configstore;
+/// use std::time::{Duration, SystemTime};
+///
+/// let config_store = Configstore::temp().unwrap();
+/// assert!(!config_store.flag("new_ui").enabled());
+/// assert!(config_store.flag("telemetry").default(true).enabled());
+///
+/// config_store.flag("new_ui").enable().unwrap();
+/// assert!(config_store.flag("new_ui").enabled());
+///
+/// // Experiments can turn themselves off
+/// let end = SystemTime::now() + Duration::from_secs(14 * 24 * 60 * 60);
+/// config_store.flag("fast_search").set_until(true, end).unwrap();
+/// ```
+pub struct Flag<'a> {
+    store: &'a Configstore,
+    name: String,
+    default: bool,
+}
+
+impl<'a> Flag<'a> {
+    /// Sets whether the flag is enabled when it was never set or has expired, defaults to false
+    pub fn default(mut self, enabled: bool) -> Self {
+        self.default = enabled;
+        self
+    }
+
+    /// Whether the flag is enabled
+    /// Flags that were never set, have expired or cannot be read are at their default
+    pub fn enabled(&self) -> bool {
+        match self.store.read_flags() {
+            Ok(flags) => match flags.get(&self.name) {
+                Some(state) if !state.is_expired() => state.enabled,
+                _ => self.default,
+            },
+            Err(_) => self.default,
+        }
+    }
+
+    /// Enables the flag until it is changed
+    ///
+    /// # Errors
+    /// Same as the store's set
+    pub fn enable(&self) -> Result<()> {
+        self.write(true, None)
+    }
+
+    /// Disables the flag until it is changed
+    ///
+    /// # Errors
+    /// Same as the store's set
+    pub fn disable(&self) -> Result<()> {
+        self.write(false, None)
+    }
+
+    /// Enables or disables the flag until a point in time, after which it is at its default
+    ///
+    /// # Errors
+    /// Same as the store's set
+    pub fn set_until(&self, enabled: bool, expires: SystemTime) -> Result<()> {
+        let expires_at = expires
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        self.write(enabled, Some(expires_at))
+    }
+
+    /// Forgets the flag, which goes back to its default
+    ///
+    /// # Errors
+    /// Same as the store's set
+    pub fn reset(&self) -> Result<()> {
+        let mut flags = self.store.read_flags()?;
+        if flags.remove(&self.name).is_some() {
+            self.store.set(FLAGS_KEY, flags)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, enabled: bool, expires_at: Option<u64>) -> Result<()> {
+        let mut flags = self.store.read_flags()?;
+        flags.insert(
+            self.name.clone(),
+            FlagState {
+                enabled,
+                expires_at,
+            },
+        );
+        self.store.set(FLAGS_KEY, flags)
+    }
+}
+
+impl Configstore {
+    /// Gets a handle on a feature flag, check the Flag docs for usage
+    /// Flags live together in the `feature_flags` key of the store
+    pub fn flag(&self, name: &str) -> Flag<'_> {
+        Flag {
+            store: self,
+            name: name.to_string(),
+            default: false,
+        }
+    }
+
+    /// The flags that are set and have not expired, with whether they are enabled
+    ///
+    /// # Errors
+    /// Could error if the flags cannot be read or decoded
+    pub fn flags(&self) -> Result<BTreeMap<String, bool>> {
+        Ok(self
+            .read_flags()?
+            .into_iter()
+            .filter(|(_, state)| !state.is_expired())
+            .map(|(name, state)| (name, state.enabled))
+            .collect())
+    }
+
+    /// Replaces every flag with the ones of a payload, like the response of a rollout server
+    /// Each field is a flag, either a boolean or an object with `enabled` and optionally
+    /// `expires_at` in seconds since the unix epoch. Flags missing from the payload go back to their default
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    /// use serde_json::json;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.flag("old_experiment").enable().unwrap();
+    /// config_store
+    ///     .sync_flags(&json!({"new_ui": true, "beta_sync": {"enabled": true, "expires_at": 4102444800u64}}))
+    ///     .unwrap();
+    /// assert!(config_store.flag("beta_sync").enabled());
+    /// assert!(!config_store.flag("old_experiment").enabled());
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the payload is not an object of flags, in which case the flags are left unchanged
+    /// Same as set otherwise
+    pub fn sync_flags(&self, payload: &Value) -> Result<()> {
+        let payload = payload
+            .as_object()
+            .ok_or_else(|| anyhow::Error::msg("The flags payload must be an object"))?;
+        let mut flags = BTreeMap::new();
+        for (name, state) in payload {
+            let state = match state {
+                Value::Bool(enabled) => FlagState {
+                    enabled: *enabled,
+                    expires_at: None,
+                },
+                state => serde_json::from_value(state.clone()).map_err(|e| {
+                    anyhow::Error::from(e).context(format!("Invalid state for flag {}", name))
+                })?,
+            };
+            flags.insert(name.clone(), state);
+        }
+        self.set(FLAGS_KEY, flags)
+    }
+
+    /// Forgets the flags that have expired, returns their names
+    pub(crate) fn purge_expired_flags(&self) -> Result<Vec<String>> {
+        let mut flags = self.read_flags()?;
+        let expired: Vec<String> = flags
+            .iter()
+            .filter(|(_, state)| state.is_expired())
+            .map(|(name, _)| name.clone())
+            .collect();
+        if !expired.is_empty() {
+            flags.retain(|_, state| !state.is_expired());
+            self.set(FLAGS_KEY, flags)?;
+        }
+        Ok(expired)
+    }
+
+    fn read_flags(&self) -> Result<BTreeMap<String, FlagState>> {
+        match self.get(FLAGS_KEY) {
+            Ok(flags) => Ok(flags),
+            Err(e) if is_not_found(&e) => Ok(BTreeMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_flags_fall_back_to_default() {
+        let config_store = Configstore::temp().unwrap();
+        let past = SystemTime::now() - Duration::from_secs(60);
+        config_store.flag("ended").set_until(true, past).unwrap();
+        config_store.flag("kept").disable().unwrap();
+        assert!(!config_store.flag("ended").enabled());
+        assert!(config_store.flag("ended").default(true).enabled());
+        assert!(!config_store.flag("kept").default(true).enabled());
+        let flags: Vec<_> = config_store.flags().unwrap().into_iter().collect();
+        assert_eq!(flags, vec![("kept".to_string(), false)]);
+
+        config_store.flag("kept").reset().unwrap();
+        assert!(config_store.flag("kept").default(true).enabled());
+        assert!(config_store.sync_flags(&serde_json::json!([true])).is_err());
+        assert!(config_store
+            .sync_flags(&serde_json::json!({"bad": "yes"}))
+            .is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::storage::{self, Backend};
+use crate::Configstore;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once, Weak};
+
+/// Writes kept in memory, like debounced writes or autosaved settings, saved by `flush_all`
+/// before the store directory is synced
+pub(crate) trait Pending: Send + Sync {
+    fn drain(&self) -> Result<()>;
+}
+
+/// A writable store open in the process, with its writes kept in memory if any
+#[derive(Clone)]
+struct OpenStore {
+    backend: Backend,
+    dir: PathBuf,
+    pending: Option<Weak<dyn Pending>>,
+}
+
+/// The writable stores open in the process, by registration id
+static OPEN_STORES: Mutex<BTreeMap<usize, OpenStore>> = Mutex::new(BTreeMap::new());
+
+/// Lists a store among the ones flushed by `flush_all` until it is dropped
+pub(crate) struct Registration(usize);
+
+impl Registration {
+    pub(crate) fn new(backend: Backend, dir: &Path, pending: Option<Weak<dyn Pending>>) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let store = OpenStore {
+            backend,
+            dir: dir.to_path_buf(),
+            pending,
+        };
+        open_stores().insert(id, store);
+        Registration(id)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        open_stores().remove(&self.0);
+    }
+}
+
+/// Whether a writable store of the process has its files in dir
+pub(crate) fn is_open(dir: &Path) -> bool {
+    open_stores().values().any(|store| store.dir == dir)
+}
+
+/// A panicking thread may have poisoned the registry, its content is still valid
+fn open_stores() -> std::sync::MutexGuard<'static, BTreeMap<usize, OpenStore>> {
+    OPEN_STORES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Configstore {
+    /// Makes every write completed so far durable, even if the machine loses power right after
+    /// Writes are atomic without it, a crash leaves either the old or the new value
+    /// Debounced stores first write the values waiting for their quiet period
+    ///
+    /// # Errors
+    /// Could error if the store directory or database cannot be synced
+    pub fn flush(&self) -> Result<()> {
+        self.inner.storage.flush()
+    }
+
+    /// Flushes every writable store open in the process, check the flush docs
+    /// The changes autosaving TypedStores have not saved yet are saved first
+    /// Meant for shutdown paths that cannot reach the stores, like the thread handling signals
+    /// It locks, allocates and writes files, which is not async-signal-safe: never call it from
+    /// inside a signal handler, but from a thread the signals are forwarded to, like the
+    /// iterator of `signal_hook::iterator::Signals` or the handler thread of the ctrlc crate
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set("last_run", 1).unwrap();
+    /// // On the thread receiving SIGTERM, once the signal handler returned
+    /// Configstore::flush_all().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Every store is flushed even if one fails, the first error is returned
+    pub fn flush_all() -> Result<()> {
+        let stores: Vec<_> = open_stores().values().cloned().collect();
+        let mut result = Ok(());
+        for store in stores {
+            let pending = store.pending.and_then(|pending| pending.upgrade());
+            if let Some(Err(e)) = pending.map(|pending| pending.drain()) {
+                result = result.and(Err(e));
+            }
+            if let Err(e) = storage::flush_dir(store.backend, &store.dir) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    /// Flushes every open store when a thread panics, before the previous panic hook runs
+    /// Installing the hook more than once has no effect
+    /// The standard library cannot intercept signals, call `flush_all` from the thread
+    /// receiving them, not from the signal handler itself
+    pub fn flush_on_panic() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let _ = Configstore::flush_all();
+                previous(info);
+            }));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_stores_are_flushed_until_dropped() {
+        let registered = |dir: &Path| open_stores().values().any(|open| open.dir == dir);
+        let config_store = Configstore::temp().unwrap();
+        let dir = config_store.dir().to_path_buf();
+        config_store.set("key", 1).unwrap();
+        assert!(registered(&dir));
+        config_store.flush().unwrap();
+        Configstore::flush_all().unwrap();
+
+        let read_only = config_store.reopen().read_only(true).build().unwrap();
+        assert_eq!(read_only.get::<u32>("key").unwrap(), 1);
+        drop(config_store);
+        assert!(!registered(&dir));
+    }
+}
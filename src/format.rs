@@ -0,0 +1,357 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The file format values are stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Format {
+    /// Json files with a `.json` extension (the default)
+    #[default]
+    Json,
+    /// Toml files with a `.toml` extension, values must serialize to a table
+    /// Comments and formatting written by hand are preserved when a value is set again
+    #[cfg(feature = "toml")]
+    Toml,
+    /// XML property lists with a `.plist` extension, readable by `defaults` and other macOS tools
+    /// Requires the `plist` feature
+    #[cfg(feature = "plist")]
+    Plist,
+    /// MessagePack files with a `.msgpack` extension, compact and fast to decode
+    /// Requires the `msgpack` feature
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// CBOR files with a `.cbor` extension, compact and fast to decode
+    /// Requires the `cbor` feature
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Format {
+    /// The extension of the files written in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            #[cfg(feature = "toml")]
+            Format::Toml => "toml",
+            #[cfg(feature = "plist")]
+            Format::Plist => "plist",
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => "msgpack",
+            #[cfg(feature = "cbor")]
+            Format::Cbor => "cbor",
+        }
+    }
+
+    /// The MIME type of the files written in this format, for tools that serve or attach them
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            #[cfg(feature = "toml")]
+            Format::Toml => "application/toml",
+            #[cfg(feature = "plist")]
+            Format::Plist => "application/x-plist",
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => "application/vnd.msgpack",
+            #[cfg(feature = "cbor")]
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    /// Every format enabled at compile time, json first
+    pub(crate) fn all() -> Vec<Format> {
+        vec![
+            Format::Json,
+            #[cfg(feature = "toml")]
+            Format::Toml,
+            #[cfg(feature = "plist")]
+            Format::Plist,
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+        ]
+    }
+
+    /// The format whose files have this extension, if it is enabled
+    pub(crate) fn from_extension(extension: &str) -> Option<Format> {
+        Format::all()
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+
+    /// Whether serializing needs the previous contents of the file to preserve its formatting
+    pub(crate) fn preserves_formatting(self) -> bool {
+        match self {
+            Format::Json => false,
+            #[cfg(feature = "toml")]
+            Format::Toml => true,
+            #[cfg(feature = "plist")]
+            Format::Plist => false,
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => false,
+            #[cfg(feature = "cbor")]
+            Format::Cbor => false,
+        }
+    }
+
+    #[cfg_attr(not(feature = "toml"), allow(unused_variables))]
+    pub(crate) fn serialize<T>(
+        self,
+        value: &T,
+        pretty: bool,
+        previous: Option<&[u8]>,
+    ) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        match self {
+            Format::Json => {
+                if pretty {
+                    Ok(serde_json::to_vec_pretty(value)?)
+                } else {
+                    Ok(serde_json::to_vec(value)?)
+                }
+            }
+            #[cfg(feature = "toml")]
+            Format::Toml => toml::serialize(value, pretty, previous),
+            #[cfg(feature = "plist")]
+            Format::Plist => {
+                let mut bytes = Vec::new();
+                plist::to_writer_xml(&mut bytes, value)?;
+                Ok(bytes)
+            }
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => Ok(rmp_serde::to_vec_named(value)?),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub(crate) fn deserialize<T>(self, bytes: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        match self {
+            Format::Json => Ok(serde_json::from_slice(bytes)?),
+            #[cfg(feature = "toml")]
+            Format::Toml => Ok(toml_edit::de::from_slice(bytes)?),
+            #[cfg(feature = "plist")]
+            Format::Plist => Ok(plist::from_bytes(bytes)?),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => Ok(rmp_serde::from_slice(bytes)?),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Ok(ciborium::from_reader(bytes)?),
+        }
+    }
+}
+
+/// Sorts the fields of every object of a value by name
+pub(crate) fn sort_keys(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            fields.sort_keys();
+            fields.values_mut().for_each(sort_keys);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => (),
+    }
+}
+
+#[cfg(all(test, feature = "plist"))]
+mod plist_tests {
+    use super::Format;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn writes_xml_property_lists() {
+        let mut settings = BTreeMap::new();
+        settings.insert("ShowSidebar".to_string(), true);
+        let bytes = Format::Plist.serialize(&settings, false, None).unwrap();
+        let text = String::from_utf8(bytes.clone()).unwrap();
+        assert!(text.starts_with("<?xml"));
+        assert!(text.contains("<key>ShowSidebar</key>"));
+        let out: BTreeMap<String, bool> = Format::Plist.deserialize(&bytes).unwrap();
+        assert_eq!(out, settings);
+    }
+}
+
+#[cfg(all(test, feature = "msgpack", feature = "cbor"))]
+mod binary_tests {
+    use super::Format;
+    use crate::Configstore;
+    use serde_json::json;
+
+    #[test]
+    fn binary_formats_round_trip() {
+        let value = json!({"name": "configstore", "sizes": [1, 2, 3], "nested": {"ok": true}});
+        for format in &[Format::MessagePack, Format::Cbor] {
+            let bytes = format.serialize(&value, false, None).unwrap();
+            assert!(bytes.len() < serde_json::to_vec(&value).unwrap().len());
+            let out: serde_json::Value = format.deserialize(&bytes).unwrap();
+            assert_eq!(out, value);
+        }
+    }
+
+    #[test]
+    fn switching_format_migrates_files() {
+        let json = Configstore::temp().unwrap();
+        json.set("settings", json!({"theme": "dark"})).unwrap();
+        let msgpack = json.reopen().format(Format::MessagePack).build().unwrap();
+        assert_eq!(
+            msgpack.get::<serde_json::Value>("settings").unwrap(),
+            json!({"theme": "dark"})
+        );
+        assert!(!json.dir().join("settings.json").exists());
+        assert!(json.dir().join("settings.msgpack").exists());
+        assert_eq!(msgpack.keys().unwrap(), vec!["settings"]);
+        // Migrating again from the new format works the same way
+        let cbor = json.reopen().format(Format::Cbor).build().unwrap();
+        assert_eq!(
+            cbor.get::<serde_json::Value>("settings").unwrap()["theme"],
+            "dark"
+        );
+    }
+
+    #[test]
+    fn keys_keep_their_own_format() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("plain", 1).unwrap();
+        config_store
+            .set_with_format("compact", vec![1, 2], Format::Cbor)
+            .unwrap();
+        assert!(config_store
+            .config_path("compact")
+            .ends_with("compact.cbor"));
+        assert_eq!(config_store.get::<Vec<u32>>("compact").unwrap(), vec![1, 2]);
+        assert_eq!(config_store.get_raw("compact").unwrap(), json!([1, 2]));
+        assert_eq!(config_store.keys().unwrap(), vec!["compact", "plain"]);
+
+        config_store
+            .set_with_format("plain", 2, Format::MessagePack)
+            .unwrap();
+        assert!(!config_store.dir().join("plain.json").exists());
+        assert_eq!(config_store.get::<u32>("plain").unwrap(), 2);
+        // Keys given their own format are not migrated to the store's one
+        let msgpack = config_store
+            .reopen()
+            .format(Format::MessagePack)
+            .build()
+            .unwrap();
+        assert_eq!(msgpack.get::<Vec<u32>>("compact").unwrap(), vec![1, 2]);
+        assert!(msgpack.config_path("compact").ends_with("compact.cbor"));
+        config_store.delete("compact").unwrap();
+        assert!(config_store.get::<Vec<u32>>("compact").is_err());
+        assert_eq!(config_store.keys().unwrap(), vec!["plain"]);
+    }
+
+    #[test]
+    fn registered_key_formats_are_used_for_writes() {
+        let config_store = Configstore::temp().unwrap();
+        let registered = config_store
+            .reopen()
+            .key_format("cache", Format::MessagePack)
+            .build()
+            .unwrap();
+        assert!(registered.config_path("cache").ends_with("cache.msgpack"));
+        registered.set("cache", vec![1, 2]).unwrap();
+        registered.set("theme", "dark").unwrap();
+        assert!(config_store.dir().join("cache.msgpack").exists());
+        assert!(config_store.dir().join("theme.json").exists());
+        assert_eq!(config_store.get::<Vec<u32>>("cache").unwrap(), vec![1, 2]);
+        assert_eq!(Format::MessagePack.mime_type(), "application/vnd.msgpack");
+    }
+}
+
+#[cfg(feature = "toml")]
+mod toml {
+    use anyhow::Result;
+    use serde::Serialize;
+    use toml_edit::{DocumentMut, Item, Table, Value};
+
+    pub(super) fn serialize<T>(value: &T, pretty: bool, previous: Option<&[u8]>) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        let previous = previous
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+            .and_then(|text| text.parse::<DocumentMut>().ok());
+        let serialized = match previous {
+            Some(mut document) => {
+                let new = toml_edit::ser::to_document(value)?;
+                merge_table(document.as_table_mut(), new.into_table());
+                document.to_string()
+            }
+            None if pretty => toml_edit::ser::to_string_pretty(value)?,
+            None => toml_edit::ser::to_string(value)?,
+        };
+        Ok(serialized.into_bytes())
+    }
+
+    /// Applies the new values onto the existing table, keeping the decor (comments and whitespace)
+    /// of every key that still exists
+    fn merge_table(existing: &mut Table, new: Table) {
+        existing.retain(|key, _| new.contains_key(key));
+        for (key, item) in new {
+            match existing.get_mut(&key) {
+                Some(old) => merge_item(old, item),
+                None => {
+                    existing.insert(&key, item);
+                }
+            }
+        }
+    }
+
+    fn merge_item(old: &mut Item, new: Item) {
+        match (old, new) {
+            (Item::Table(old), Item::Value(Value::InlineTable(new))) => {
+                merge_table(old, new.into_table())
+            }
+            (Item::Table(old), Item::Table(new)) => merge_table(old, new),
+            (Item::Value(old), Item::Value(mut new)) => {
+                *new.decor_mut() = old.decor().clone();
+                *old = new;
+            }
+            (old, new) => *old = new,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_derive::Serialize;
+
+        #[derive(Serialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+
+        #[derive(Serialize)]
+        struct Config {
+            name: String,
+            server: Server,
+        }
+
+        #[test]
+        fn preserves_comments() {
+            let previous = "# The app name\nname = \"old\" # inline\n\n# Server settings\n[server]\nhost = \"localhost\"\nport = 80\nremoved = true\n";
+            let config = Config {
+                name: "new".to_string(),
+                server: Server {
+                    host: "localhost".to_string(),
+                    port: 8080,
+                },
+            };
+            let out = serialize(&config, false, Some(previous.as_bytes())).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "# The app name\nname = \"new\" # inline\n\n# Server settings\n[server]\nhost = \"localhost\"\nport = 8080\n"
+            );
+        }
+    }
+}
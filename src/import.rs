@@ -0,0 +1,286 @@
+use crate::{Configstore, Format};
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+impl Configstore {
+    /// Imports the settings written by the JavaScript `configstore` package for `package_name`
+    /// Reads `$XDG_CONFIG_HOME/configstore/<package_name>.json` (`~/.config` on every platform)
+    /// and stores every top level field of the json object as its own key
+    /// Keys that already exist in this store are left untouched
+    /// Returns the keys that were imported, which is empty if there is nothing to import
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// let imported = config_store.import_from_js_configstore("my-node-cli").unwrap();
+    /// for key in imported {
+    ///     println!("Kept your {} setting", key);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the file exists but is not a json object, or if a value cannot be written
+    pub fn import_from_js_configstore(&self, package_name: &str) -> Result<Vec<String>> {
+        match js_configstore_path(package_name) {
+            Some(path) if path.exists() => self.import_json_object(&path),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Imports a settings file written by another library or by hand, storing every top
+    /// level field as its own key like `import_from_js_configstore`
+    /// Files with the extension of an enabled format are read in that format, `.toml` needing
+    /// the `toml` feature, and any other file as `key = value` lines, see `import_from_rc_file`
+    ///
+    /// # Errors
+    /// Could error if the file cannot be read or is not an object of settings,
+    /// or if a value cannot be written
+    pub fn import_file<P>(&self, path: P) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = std::fs::read(path)?;
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let object = match extension.and_then(Format::from_extension) {
+            Some(format) => format.deserialize(&contents)?,
+            None => parse_rc(&String::from_utf8_lossy(&contents))
+                .map_err(|e| anyhow::Error::msg(format!("{}:{}", path.display(), e)))?,
+        };
+        self.import_object(object)
+    }
+
+    /// Imports the settings of `~/.<app_name>rc`, the kind of file many command line tools read
+    /// Lines are `key = value`, values that are booleans, numbers or json are stored as such
+    /// and others as strings, quotes around them are removed. Lines starting with `#` or `;`
+    /// are comments, and the keys under a `[section]` header are stored in one object under section
+    /// Returns the keys that were imported, which is empty if there is no such file
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// for key in config_store.import_from_rc_file("my-old-cli").unwrap() {
+    ///     println!("Kept your {} setting", key);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if a line is neither a setting, a comment nor a header,
+    /// or if a value cannot be written
+    pub fn import_from_rc_file(&self, app_name: &str) -> Result<Vec<String>> {
+        match platform_dirs::home_dir() {
+            Some(home) if home.join(format!(".{}rc", app_name)).is_file() => {
+                let path = home.join(format!(".{}rc", app_name));
+                let contents = std::fs::read_to_string(&path)?;
+                let object = parse_rc(&contents)
+                    .map_err(|e| anyhow::Error::msg(format!("{}:{}", path.display(), e)))?;
+                self.import_object(object)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Imports the settings of a crate that wrote its own files in a directory, like one
+    /// from the `directories` crate: every file with the extension of an enabled format
+    /// is stored as a single key named after the file, other files are skipped
+    ///
+    /// # Errors
+    /// Could error if the directory or a file cannot be read, or if a value cannot be written
+    pub fn import_dir<P>(&self, dir: P) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut files = Map::new();
+        for dir_entry in std::fs::read_dir(dir)? {
+            let path = dir_entry?.path();
+            let format = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .and_then(Format::from_extension);
+            let key = path.file_stem().and_then(|stem| stem.to_str());
+            if let (Some(format), Some(key), true) = (format, key, path.is_file()) {
+                files.insert(key.to_string(), format.deserialize(&std::fs::read(&path)?)?);
+            }
+        }
+        files.sort_keys();
+        self.import_object(files)
+    }
+
+    /// Imports the settings saved by the `confy` crate for app_name, in its toml file named
+    /// config_name, `default-config` when None like in confy
+    /// Returns the keys that were imported, which is empty if there is no such file
+    ///
+    /// # Errors
+    /// Could error if the file exists but is not a toml table, or if a value cannot be written
+    #[cfg(feature = "toml")]
+    pub fn import_from_confy(
+        &self,
+        app_name: &str,
+        config_name: Option<&str>,
+    ) -> Result<Vec<String>> {
+        match confy_path(app_name, config_name.unwrap_or("default-config")) {
+            Some(path) if path.is_file() => self.import_file(path),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn import_json_object(&self, path: &Path) -> Result<Vec<String>> {
+        let contents = std::fs::read(path)?;
+        self.import_object(serde_json::from_slice(&contents)?)
+    }
+
+    fn import_object(&self, object: Map<String, Value>) -> Result<Vec<String>> {
+        let mut imported = Vec::new();
+        for (key, value) in object {
+            if self.stored_format(&key)?.is_some() {
+                continue;
+            }
+            self.set(&key, value)?;
+            imported.push(key);
+        }
+        Ok(imported)
+    }
+}
+
+/// Parses `key = value` lines, errors give the line number and what is wrong with it
+fn parse_rc(contents: &str) -> std::result::Result<Map<String, Value>, String> {
+    let mut object = Map::new();
+    let mut section: Option<String> = None;
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_string();
+            object
+                .entry(name.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            section = Some(name);
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{}: expected key = value, found {:?}", index + 1, line))?;
+        let (key, value) = (key.trim().to_string(), parse_rc_value(value.trim()));
+        match &section {
+            Some(name) => match object.get_mut(name) {
+                Some(Value::Object(fields)) => {
+                    fields.insert(key, value);
+                }
+                _ => {
+                    return Err(format!(
+                        "{}: {} is both a setting and a section",
+                        index + 1,
+                        name
+                    ))
+                }
+            },
+            None => {
+                object.insert(key, value);
+            }
+        }
+    }
+    Ok(object)
+}
+
+fn parse_rc_value(value: &str) -> Value {
+    let quoted = value.len() >= 2
+        && (value.starts_with('"') && value.ends_with('"')
+            || value.starts_with('\'') && value.ends_with('\''));
+    if quoted {
+        return Value::from(&value[1..value.len() - 1]);
+    }
+    serde_json::from_str(value).unwrap_or_else(|_| Value::from(value))
+}
+
+/// Mirrors the `directories` lookup done by confy, `ProjectDirs::from("rs", "", app_name)`
+#[cfg(feature = "toml")]
+fn confy_path(app_name: &str, config_name: &str) -> Option<PathBuf> {
+    let config_dir = if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+            .join(app_name)
+            .join("config")
+    } else if cfg!(target_os = "macos") {
+        platform_dirs::home_dir()?
+            .join("Library/Application Support")
+            .join(format!("rs.{}", app_name.replace(' ', "-")))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| platform_dirs::home_dir().map(|home| home.join(".config")))?
+            .join(app_name.to_lowercase().replace(' ', ""))
+    };
+    Some(config_dir.join(format!("{}.toml", config_name)))
+}
+
+/// Mirrors the `xdg-basedir` lookup done by the JavaScript package
+fn js_configstore_path(package_name: &str) -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| platform_dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(
+        config_dir
+            .join("configstore")
+            .join(format!("{}.json", package_name)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn imports_top_level_fields() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("js_kept", String::from("rust")).unwrap();
+        let path = std::env::temp_dir().join("configstore-rs-js-import.json");
+        std::fs::write(
+            &path,
+            r#"{"js_kept": "node", "js_nested": {"a": [1, 2]}, "js_token": "abc"}"#,
+        )
+        .unwrap();
+        let imported = config_store.import_json_object(&path).unwrap();
+        assert_eq!(
+            imported,
+            vec!["js_nested".to_string(), "js_token".to_string()]
+        );
+        assert_eq!(config_store.get::<String>("js_token").unwrap(), "abc");
+        assert_eq!(config_store.get::<String>("js_kept").unwrap(), "rust");
+        let nested: serde_json::Value = config_store.get("js_nested").unwrap();
+        assert_eq!(nested, serde_json::json!({"a": [1, 2]}));
+    }
+
+    #[test]
+    fn rc_files_are_parsed_into_settings() {
+        let config_store = Configstore::temp().unwrap();
+        let path = config_store.dir().join("legacy.conf");
+        std::fs::write(
+            &path,
+            "# Written by hand\nattempts=3\ncolor = auto\nname = \"my cli\"\n\n[proxy]\nenabled = true\n",
+        )
+        .unwrap();
+        let imported = config_store.import_file(&path).unwrap();
+        assert_eq!(imported, vec!["attempts", "color", "name", "proxy"]);
+        assert_eq!(config_store.get::<String>("color").unwrap(), "auto");
+        assert_eq!(config_store.get::<u32>("attempts").unwrap(), 3);
+        assert_eq!(config_store.get::<String>("name").unwrap(), "my cli");
+        let proxy: serde_json::Value = config_store.get("proxy").unwrap();
+        assert_eq!(proxy, serde_json::json!({"enabled": true}));
+
+        std::fs::write(&path, "color = auto\nnot a setting\n").unwrap();
+        let err = config_store.import_file(&path).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with(":2: expected key = value, found \"not a setting\""));
+    }
+}
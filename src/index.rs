@@ -0,0 +1,282 @@
+use crate::atomic;
+use crate::storage::{self, Entry};
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the index file inside the store directory, hidden so that it is never listed as a key
+pub(crate) const INDEX_NAME: &str = ".index.json";
+
+/// Name of the file locked while the index is rewritten
+const LOCK_NAME: &str = ".index.lock";
+
+/// Entries up to this size are kept in the index, larger ones in their own file
+pub(crate) const INLINE_LIMIT: usize = 4096;
+
+/// An entry kept in the index, text when it is valid utf-8 so the index stays readable
+#[derive(Clone, Serialize, Deserialize)]
+struct Inline {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hex: Option<String>,
+    /// Milliseconds since the unix epoch
+    modified: u64,
+}
+
+impl Inline {
+    fn new(bytes: &[u8]) -> Self {
+        let modified = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Inline {
+                text: Some(text.to_string()),
+                hex: None,
+                modified,
+            },
+            Err(_) => Inline {
+                text: None,
+                hex: Some(bytes.iter().map(|byte| format!("{:02x}", byte)).collect()),
+                modified,
+            },
+        }
+    }
+
+    fn bytes(&self) -> Result<Vec<u8>> {
+        if let Some(text) = &self.text {
+            return Ok(text.as_bytes().to_vec());
+        }
+        let hex = self.hex.as_deref().unwrap_or_default();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(|| anyhow::Error::msg("Corrupt hex encoded index entry"))
+            })
+            .collect()
+    }
+
+    fn size(&self) -> u64 {
+        match &self.text {
+            Some(text) => text.len() as u64,
+            None => self.hex.as_ref().map_or(0, |hex| hex.len() as u64 / 2),
+        }
+    }
+}
+
+type Index = BTreeMap<String, Inline>;
+
+/// The index as last read, with the modification time and size of its file then
+struct Cached {
+    modified: SystemTime,
+    len: u64,
+    index: Arc<Index>,
+}
+
+/// Storage keeping small entries together in an index file and larger ones in their own files,
+/// so that stores of many tiny values are listed and read without opening a file per value
+/// Entries written privately always get their own file, which can be restricted to the current user
+pub(crate) struct IndexStorage {
+    dir: PathBuf,
+    cache: Mutex<Option<Cached>>,
+}
+
+impl IndexStorage {
+    pub(crate) fn open(dir: &Path) -> Self {
+        IndexStorage {
+            dir: dir.to_path_buf(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// The index, read again only when its file changed since it was last read
+    fn index(&self) -> Result<Arc<Index>> {
+        let path = self.dir.join(INDEX_NAME);
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Arc::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let (modified, len) = (metadata.modified()?, metadata.len());
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(cached) = cache.as_ref() {
+            if cached.modified == modified && cached.len == len {
+                return Ok(cached.index.clone());
+            }
+        }
+        let index: Arc<Index> = Arc::new(serde_json::from_slice(&std::fs::read(&path)?)?);
+        *cache = Some(Cached {
+            modified,
+            len,
+            index: index.clone(),
+        });
+        Ok(index)
+    }
+
+    /// Changes the index while holding its lock, so that writes of other processes are not lost
+    /// Returns what change returned, the index is only written again if change returns true
+    fn update<F>(&self, change: F) -> Result<bool>
+    where
+        F: FnOnce(&mut Index) -> bool,
+    {
+        let _lock = atomic::lock(&self.dir.join(LOCK_NAME))?;
+        let mut index = Index::clone(&*self.index()?);
+        if !change(&mut index) {
+            return Ok(false);
+        }
+        atomic::write(&self.dir.join(INDEX_NAME), &serde_json::to_vec(&index)?)?;
+        // The next read compares the new file with the cache
+        *self.cache.lock().unwrap() = None;
+        Ok(true)
+    }
+
+    /// The file holding an entry, the index file for the entries it holds
+    pub(crate) fn path(&self, name: &str) -> PathBuf {
+        match self.index() {
+            Ok(index) if index.contains_key(name) => self.dir.join(INDEX_NAME),
+            _ => self.dir.join(name),
+        }
+    }
+
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>> {
+        match self.index()?.get(name) {
+            Some(inline) => inline.bytes(),
+            None => Ok(std::fs::read(self.dir.join(name))?),
+        }
+    }
+
+    pub(crate) fn write(&self, name: &str, bytes: &[u8], private: bool) -> Result<()> {
+        if private || bytes.len() > INLINE_LIMIT {
+            let path = self.dir.join(name);
+            if private {
+                atomic::write_private(&path, bytes)?;
+            } else {
+                atomic::write(&path, bytes)?;
+            }
+            self.update(|index| index.remove(name).is_some())?;
+            return Ok(());
+        }
+        self.update(|index| {
+            index.insert(name.to_string(), Inline::new(bytes));
+            true
+        })?;
+        // A file left by a larger version of the entry would come back if it shrank out of the index
+        match std::fs::remove_file(self.dir.join(name)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> Result<()> {
+        if self.update(|index| index.remove(name).is_some())? {
+            return Ok(());
+        }
+        Ok(std::fs::remove_file(self.dir.join(name))?)
+    }
+
+    pub(crate) fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let inline = self.index()?.get(from).cloned();
+        match inline {
+            Some(inline) => {
+                self.update(|index| {
+                    index.remove(from);
+                    index.insert(to.to_string(), inline);
+                    true
+                })?;
+                match std::fs::remove_file(self.dir.join(to)) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+                    _ => Ok(()),
+                }
+            }
+            None => {
+                std::fs::rename(self.dir.join(from), self.dir.join(to))?;
+                self.update(|index| index.remove(to).is_some())?;
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn exists(&self, name: &str) -> Result<bool> {
+        Ok(self.index()?.contains_key(name) || self.dir.join(name).exists())
+    }
+
+    pub(crate) fn touch(&self, name: &str) -> Result<()> {
+        let touched = self.update(|index| match index.get_mut(name) {
+            Some(inline) => {
+                inline.modified = Inline::new(&[]).modified;
+                true
+            }
+            None => false,
+        })?;
+        if touched {
+            return Ok(());
+        }
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.dir.join(name))?;
+        Ok(file.set_modified(SystemTime::now())?)
+    }
+
+    pub(crate) fn entries(&self) -> Result<Vec<Entry>> {
+        let index = self.index()?;
+        let mut entries = storage::file_entries(&self.dir)?;
+        entries.retain(|entry| !index.contains_key(&entry.name));
+        for (name, inline) in index.iter() {
+            if let Some(key) = storage::entry_key(name) {
+                entries.push(Entry {
+                    key: key.to_string(),
+                    name: name.clone(),
+                    size: inline.size(),
+                    modified: UNIX_EPOCH + Duration::from_millis(inline.modified),
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppUI, Backend, Configstore};
+
+    #[test]
+    fn small_values_share_the_index() {
+        let config_store = Configstore::builder("tests-index", AppUI::CommandLine)
+            .temp(true)
+            .backend(Backend::Indexed)
+            .build()
+            .unwrap();
+        for i in 0..20 {
+            config_store.set(&format!("small{}", i), i).unwrap();
+        }
+        config_store.set_blob("icon", &[0xff, 0x00]).unwrap();
+        let large = "x".repeat(INLINE_LIMIT * 2);
+        config_store.set("large", &large).unwrap();
+        let files = std::fs::read_dir(config_store.dir()).unwrap();
+        let names: Vec<_> = files
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        assert_eq!(names, vec!["large.json"]);
+        assert_eq!(config_store.keys().unwrap().len(), 21);
+        assert_eq!(config_store.get::<u32>("small7").unwrap(), 7);
+        assert_eq!(config_store.get_blob("icon").unwrap(), vec![0xff, 0x00]);
+        assert_eq!(config_store.get::<String>("large").unwrap(), large);
+
+        // Values move between the index and their own file as they grow and shrink
+        config_store.set("large", "short").unwrap();
+        assert!(!config_store.dir().join("large.json").exists());
+        assert_eq!(config_store.get::<String>("large").unwrap(), "short");
+        config_store.delete("small7").unwrap();
+        assert!(config_store.get::<u32>("small7").is_err());
+        assert!(config_store.delete("small7").is_err());
+        assert!(config_store.config_path("small3").ends_with(INDEX_NAME));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::is_not_found;
+use crate::{Configstore, Format};
+use anyhow::Result;
+
+impl Configstore {
+    /// The store the keys that are not set are inherited from, with the builder's `inherits`
+    pub fn parent(&self) -> Option<&Configstore> {
+        self.inner.parent.as_ref()
+    }
+
+    /// The value of a key in the parent store and its format, None if it has none
+    pub(crate) fn read_inherited(&self, key: &str) -> Result<Option<(Format, Vec<u8>)>> {
+        let parent = match &self.inner.parent {
+            Some(parent) => parent,
+            None => return Ok(None),
+        };
+        match parent.read_bytes(key) {
+            Ok(inherited) => Ok(Some(inherited)),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AppUI, Configstore};
+
+    #[test]
+    fn unset_keys_are_read_from_the_parent() {
+        let common = Configstore::temp().unwrap();
+        common.set("proxy", "http://proxy.corp:3128").unwrap();
+        common.set("telemetry", false).unwrap();
+        let root = common.dir().parent().unwrap().to_path_buf();
+        let common_name = common.dir().file_name().unwrap().to_string_lossy();
+        let app = Configstore::builder("tool", AppUI::CommandLine)
+            .root(root.clone())
+            .inherits(&common_name)
+            .defaults_from_str(
+                r#"{"proxy": "none", "theme": "light"}"#,
+                crate::Format::Json,
+            )
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            app.get::<String>("proxy").unwrap(),
+            "http://proxy.corp:3128"
+        );
+        assert_eq!(app.get::<String>("theme").unwrap(), "light");
+        app.set("telemetry", true).unwrap();
+        assert!(app.get::<bool>("telemetry").unwrap());
+        assert!(!common.get::<bool>("telemetry").unwrap());
+        assert_eq!(app.keys().unwrap(), vec!["telemetry", "theme"]);
+        assert_eq!(app.parent().unwrap().dir(), common.dir());
+
+        let orphan = Configstore::builder("orphan", AppUI::CommandLine)
+            .root(root)
+            .inherits("no-such-suite")
+            .build()
+            .unwrap();
+        assert!(orphan.get::<String>("proxy").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{atomic, Configstore};
+use anyhow::Result;
+
+/// Marker written once the first-run setup has succeeded
+const INITIALIZED_NAME: &str = ".initialized";
+/// File locked while the first-run setup runs, so concurrent launches wait for each other
+const INIT_LOCK_NAME: &str = ".init.lock";
+
+impl Configstore {
+    /// Runs a one-time setup the first time the store is used, for example to seed defaults
+    /// Returns whether the setup ran. Concurrent first launches are serialized with a file lock,
+    /// so exactly one of them runs the setup and the others wait for it to finish
+    /// If the setup fails it runs again on the next call
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.init_if_new(|store| {
+    ///     store.set("welcome_shown", false)
+    /// }).unwrap();
+    /// assert!(!config_store.init_if_new(|_| Ok(())).unwrap());
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with the setup's error, or if the lock or marker files cannot be written
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn init_if_new<F>(&self, init: F) -> Result<bool>
+    where
+        F: FnOnce(&Configstore) -> Result<()>,
+    {
+        self.check_writable()?;
+        let marker = self.inner.storage.dir.join(INITIALIZED_NAME);
+        if marker.exists() {
+            return Ok(false);
+        }
+        let _lock = atomic::lock(&self.inner.storage.dir.join(INIT_LOCK_NAME))?;
+        // Another process may have completed the setup while we were waiting for the lock
+        if marker.exists() {
+            return Ok(false);
+        }
+        init(self)?;
+        atomic::write(&marker, b"")?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn setup_runs_once() {
+        let config_store = Configstore::temp().unwrap();
+        assert!(config_store
+            .init_if_new(|_| Err(anyhow::Error::msg("failed")))
+            .is_err());
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let runs = runs.clone();
+                let builder = config_store.reopen();
+                std::thread::spawn(move || {
+                    builder
+                        .build()
+                        .unwrap()
+                        .init_if_new(|_| {
+                            runs.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(20));
+                            Ok(())
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+        let ran: usize = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap() as usize)
+            .sum();
+        assert_eq!(ran, 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{atomic, Configstore, Error};
+use anyhow::Result;
+use uuid::Uuid;
+
+/// File holding the install id, hidden so it is not listed as a key
+const INSTALL_ID_NAME: &str = ".install_id";
+const INSTALL_ID_LOCK_NAME: &str = ".install_id.lock";
+
+impl Configstore {
+    /// A random id generated on the first call and returned unchanged afterwards
+    /// Useful to tell installations apart, for example in crash reports or analytics
+    /// Concurrent first calls from several processes all get the same id
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// let id = config_store.install_id().unwrap();
+    /// assert_eq!(config_store.install_id().unwrap(), id);
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the id file cannot be read or written, or was corrupted
+    /// Fails with `Error::ReadOnly` on read-only stores that do not have an id yet
+    pub fn install_id(&self) -> Result<Uuid> {
+        let path = self.inner.storage.dir.join(INSTALL_ID_NAME);
+        if let Some(id) = read_id(&path)? {
+            return Ok(id);
+        }
+        if self.inner.read_only {
+            return Err(Error::ReadOnly.into());
+        }
+        let _lock = atomic::lock(&self.inner.storage.dir.join(INSTALL_ID_LOCK_NAME))?;
+        // Another process may have generated the id while we were waiting for the lock
+        if let Some(id) = read_id(&path)? {
+            return Ok(id);
+        }
+        let id = Uuid::new_v4();
+        atomic::write(&path, id.hyphenated().to_string().as_bytes())?;
+        Ok(id)
+    }
+}
+
+fn read_id(path: &std::path::Path) -> Result<Option<Uuid>> {
+    match std::fs::read_to_string(path) {
+        Ok(id) => Ok(Some(Uuid::parse_str(id.trim())?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn install_id_is_stable() {
+        let config_store = Configstore::temp().unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let builder = config_store.reopen();
+                std::thread::spawn(move || builder.build().unwrap().install_id().unwrap())
+            })
+            .collect();
+        let ids: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        assert!(ids.iter().all(|id| *id == ids[0]));
+        let read_only = config_store.reopen().read_only(true).build().unwrap();
+        assert_eq!(read_only.install_id().unwrap(), ids[0]);
+        assert!(!read_only
+            .keys()
+            .unwrap()
+            .iter()
+            .any(|key| key.contains("install")));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{Configstore, Error};
+use anyhow::Result;
+use std::fs::{File, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Lock file held by the running instance of the app, next to its profiles
+const APP_LOCK_NAME: &str = ".app.lock";
+
+/// Proof that this process is the only running instance of the app, returned by `acquire_app_lock`
+/// Other instances can acquire the lock once the guard is dropped or the process exits,
+/// even if it crashed
+#[derive(Debug)]
+pub struct AppLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl AppLock {
+    /// The lock file, holding the id of the process that holds the lock
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Configstore {
+    /// Makes sure a single instance of the app runs at a time, for command line tools and daemons
+    /// The lock is held on a file of the app's directory, shared by every profile, until the guard is dropped
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, Error};
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// let _instance = match config_store.acquire_app_lock() {
+    ///     Ok(lock) => lock,
+    ///     Err(e) => match e.downcast_ref::<Error>() {
+    ///         Some(Error::AlreadyRunning { pid }) => {
+    ///             eprintln!("Already running as process {:?}", pid);
+    ///             return;
+    ///         }
+    ///         _ => panic!("{}", e),
+    ///     },
+    /// };
+    /// assert!(config_store.acquire_app_lock().is_err());
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with `Error::AlreadyRunning` if another instance holds the lock, even in this process
+    /// Could error if the lock file cannot be created. Fails with `Error::ReadOnly` on read-only stores
+    pub fn acquire_app_lock(&self) -> Result<AppLock> {
+        self.check_writable()?;
+        let path = self.inner.app_dir.join(APP_LOCK_NAME);
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                let mut pid = String::new();
+                // Windows locks prevent reading the file too
+                let _ = file.read_to_string(&mut pid);
+                return Err(Error::AlreadyRunning {
+                    pid: pid.trim().parse().ok(),
+                }
+                .into());
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(AppLock { _file: file, path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, Error};
+
+    #[test]
+    fn second_instance_is_detected() {
+        let config_store = Configstore::temp().unwrap();
+        let lock = config_store.acquire_app_lock().unwrap();
+        let other = config_store.reopen().profile("work").build().unwrap();
+        let err = other.acquire_app_lock().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::AlreadyRunning {
+                pid: Some(std::process::id())
+            })
+        );
+        drop(lock);
+        let lock = other.acquire_app_lock().unwrap();
+        assert!(lock.path().exists());
+    }
+}
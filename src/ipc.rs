@@ -0,0 +1,153 @@
+use crate::{ChangeEvent, Inner};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::io::ErrorKind;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// Directory of the store holding a socket per process sharing its changes
+const EVENTS_DIR: &str = ".events";
+
+/// How often the receiving thread checks whether the store was dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The socket a store receives the changes of other processes on, removed when the store is dropped
+pub(crate) struct Ipc {
+    socket: Arc<UnixDatagram>,
+    /// Nonblocking, so that a process not reading its events cannot stall our writes
+    sender: UnixDatagram,
+    path: PathBuf,
+}
+
+impl Ipc {
+    /// Binds a socket for this process in the events directory of the store
+    pub(crate) fn bind(store_dir: &Path) -> Result<Self> {
+        let dir = store_dir.join(EVENTS_DIR);
+        std::fs::create_dir_all(&dir)?;
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let path = dir.join(format!("{}-{}.sock", std::process::id(), &id[..8]));
+        let socket = UnixDatagram::bind(&path).map_err(|e| {
+            anyhow::Error::from(e).context(format!("Could not listen on {}", path.display()))
+        })?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let sender = UnixDatagram::unbound()?;
+        sender.set_nonblocking(true)?;
+        Ok(Ipc {
+            socket: Arc::new(socket),
+            sender,
+            path,
+        })
+    }
+
+    /// Delivers the events received from other processes to the listeners of the store
+    /// until the store is dropped
+    pub(crate) fn listen(&self, inner: Weak<Inner>) {
+        let socket = self.socket.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while inner.strong_count() > 0 {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                        continue
+                    }
+                    Err(_) => return,
+                };
+                let event = match serde_json::from_slice(&buf[..len]) {
+                    Ok(message) => decode(&message),
+                    Err(_) => None,
+                };
+                match (event, inner.upgrade()) {
+                    (Some(event), Some(inner)) => inner.listeners.notify(event),
+                    (_, None) => return,
+                    _ => (),
+                }
+            }
+        });
+    }
+
+    /// Sends an event to the other processes listening on the store
+    /// Sockets left by processes that exited are removed, other failures are ignored
+    /// as sharing events is best effort: the events of a process whose queue is full are dropped
+    pub(crate) fn broadcast(&self, event: &ChangeEvent) {
+        let message = match event {
+            ChangeEvent::Set(key) => json!({ "set": key }),
+            ChangeEvent::Delete(key) => json!({ "delete": key }),
+        };
+        let bytes = message.to_string().into_bytes();
+        let read_dir = match self.path.parent().map(std::fs::read_dir) {
+            Some(Ok(read_dir)) => read_dir,
+            _ => return,
+        };
+        for dir_entry in read_dir.flatten() {
+            let path = dir_entry.path();
+            if path == self.path {
+                continue;
+            }
+            if let Err(e) = self.sender.send_to(&bytes, &path) {
+                if matches!(e.kind(), ErrorKind::ConnectionRefused | ErrorKind::NotFound) {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Ipc {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn decode(message: &Value) -> Option<ChangeEvent> {
+    if let Some(key) = message["set"].as_str() {
+        return Some(ChangeEvent::Set(key.to_string()));
+    }
+    message["delete"]
+        .as_str()
+        .map(|key| ChangeEvent::Delete(key.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChangeEvent, Configstore};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn changes_reach_other_processes() {
+        let writer = Configstore::temp().unwrap();
+        let cli = writer.reopen().ipc_events(true).build().unwrap();
+        let daemon = writer.reopen().ipc_events(true).build().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        daemon.on_change(move |event| sender.send(event.clone()).unwrap());
+
+        cli.set("theme", "dark").unwrap();
+        cli.delete("theme").unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            ChangeEvent::Set("theme".to_string())
+        );
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            ChangeEvent::Delete("theme".to_string())
+        );
+        // Stores that do not opt in neither send nor receive
+        writer.set("volume", 3).unwrap();
+        drop(cli);
+        assert_eq!(
+            std::fs::read_dir(daemon.dir().join(super::EVENTS_DIR))
+                .unwrap()
+                .count(),
+            1
+        );
+        assert!(receiver.recv_timeout(Duration::from_millis(300)).is_err());
+
+        // Directories too long for a socket path fall back to not sharing events
+        let nested = writer.reopen().profile(&"p".repeat(120)).ipc_events(true);
+        nested.build().unwrap().set("volume", 4).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+use crate::version::fnv1a;
+use crate::{ChangeEvent, Configstore};
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File the journal is appended to, hidden so that it is never listed as a key
+const JOURNAL_NAME: &str = ".journal.jsonl";
+
+/// One change recorded in the journal, as returned by `history`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct JournalEntry {
+    /// When the change was made
+    pub time: SystemTime,
+    /// The key that was set or deleted
+    pub change: ChangeEvent,
+    /// Hash of the stored bytes of the new value, None for deletes
+    pub hash: Option<String>,
+    /// The user the process ran as, if the platform reports it
+    pub user: Option<String>,
+    /// The id of the process that made the change
+    pub process: u32,
+}
+
+/// A line of the journal file
+#[derive(Serialize, Deserialize)]
+struct Record {
+    /// Milliseconds since the unix epoch
+    time: u64,
+    op: Op,
+    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    pid: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Op {
+    Set,
+    Delete,
+}
+
+/// Appends the changes of a store to its journal file
+pub(crate) struct Journal {
+    path: PathBuf,
+    /// Keeps the lines of concurrent writers of this process whole
+    lock: Mutex<()>,
+}
+
+impl Journal {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Journal {
+            path: dir.join(JOURNAL_NAME),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn append(&self, event: &ChangeEvent, hash: Option<String>) -> Result<()> {
+        let (op, key) = match event {
+            ChangeEvent::Set(key) => (Op::Set, key),
+            ChangeEvent::Delete(key) => (Op::Delete, key),
+        };
+        let record = Record {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            op,
+            key: key.clone(),
+            hash,
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+            pid: std::process::id(),
+        };
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        // Appends of a single write are not interleaved with those of other processes
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+impl Configstore {
+    /// Lists every change recorded in the store's journal, oldest first
+    /// Stores only record changes when built with the builder's `journal` option,
+    /// but any store of the app can read the history
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{ChangeEvent, Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+    ///     .journal(true)
+    ///     .build()
+    ///     .unwrap();
+    /// config_store.set("proxy", "http://proxy.internal:3128").unwrap();
+    /// config_store.delete("proxy").unwrap();
+    ///
+    /// for entry in config_store.key_history("proxy").unwrap() {
+    ///     println!("{:?} {:?} by {:?}", entry.time, entry.change, entry.user);
+    /// }
+    /// let history = config_store.key_history("proxy").unwrap();
+    /// assert_eq!(history.last().unwrap().change, ChangeEvent::Delete("proxy".to_string()));
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the journal exists but cannot be read
+    /// Lines that cannot be decoded, like one cut short by a crash, are skipped
+    pub fn history(&self) -> Result<Vec<JournalEntry>> {
+        let path = self.dir().join(JOURNAL_NAME);
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Record>(line).ok())
+            .map(|record| JournalEntry {
+                time: UNIX_EPOCH + Duration::from_millis(record.time),
+                change: match record.op {
+                    Op::Set => ChangeEvent::Set(record.key),
+                    Op::Delete => ChangeEvent::Delete(record.key),
+                },
+                hash: record.hash,
+                user: record.user,
+                process: record.pid,
+            })
+            .collect())
+    }
+
+    /// Lists the changes of one key recorded in the journal, oldest first
+    /// Check the history docs for usage
+    ///
+    /// # Errors
+    /// Same as history
+    pub fn key_history(&self, key: &str) -> Result<Vec<JournalEntry>> {
+        let mut history = self.history()?;
+        history.retain(|entry| entry.change.key() == key);
+        Ok(history)
+    }
+
+    /// Records a change to the entry name in the journal, then notifies the listeners
+    /// The listeners are notified even if the journal cannot be written
+    pub(crate) fn changed(&self, event: ChangeEvent, name: &str) -> Result<()> {
+        let recorded = match &self.inner.journal {
+            Some(journal) => {
+                let hash = match &event {
+                    ChangeEvent::Set(_) => self
+                        .inner
+                        .storage
+                        .read(name)
+                        .ok()
+                        .map(|bytes| format!("{:016x}", fnv1a(&bytes))),
+                    ChangeEvent::Delete(_) => None,
+                };
+                journal.append(&event, hash)
+            }
+            None => Ok(()),
+        };
+        #[cfg(unix)]
+        if let Some(ipc) = &self.inner.ipc {
+            ipc.broadcast(&event);
+        }
+        self.inner.listeners.notify(event);
+        recorded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChangeEvent, Configstore};
+
+    #[test]
+    fn journal_records_sets_and_deletes() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("untracked", 1).unwrap();
+        assert!(config_store.history().unwrap().is_empty());
+
+        let journaled = config_store.reopen().journal(true).build().unwrap();
+        journaled.set("volume", 1).unwrap();
+        journaled.set("volume", 2).unwrap();
+        journaled.rename("volume", "level").unwrap();
+        journaled.delete("level").unwrap();
+
+        let history = config_store.history().unwrap();
+        let changes: Vec<_> = history.iter().map(|entry| entry.change.clone()).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ChangeEvent::Set("volume".to_string()),
+                ChangeEvent::Set("volume".to_string()),
+                ChangeEvent::Set("level".to_string()),
+                ChangeEvent::Delete("volume".to_string()),
+                ChangeEvent::Delete("level".to_string()),
+            ]
+        );
+        assert_ne!(history[0].hash, history[1].hash);
+        assert_eq!(history[1].hash, history[2].hash);
+        assert_eq!(history[4].hash, None);
+        assert_eq!(history[0].process, std::process::id());
+        assert_eq!(journaled.key_history("volume").unwrap().len(), 3);
+        assert_eq!(journaled.keys().unwrap(), vec!["untracked"]);
+    }
+}
@@ -1,11 +1,133 @@
+mod access;
+mod archive;
+mod atomic;
+mod attachment;
+mod blob;
+mod builder;
+mod bundle;
+mod codec;
+mod compression;
+mod counter;
+mod debounce;
+mod defaults;
+mod describe;
+mod doctor;
+#[cfg(feature = "encryption")]
+mod encrypt;
+mod entry;
+mod env;
+mod error;
+mod events;
+mod fallback;
+mod fields;
+mod filename;
+mod flags;
+mod flush;
+mod format;
+mod import;
+mod index;
+mod inherit;
+mod init;
+mod install_id;
+mod instance;
+#[cfg(unix)]
+mod ipc;
+mod journal;
+mod list;
+mod load;
+#[cfg(feature = "wasm")]
+mod local_storage;
+mod location;
+mod manifest;
+mod map;
+mod merge;
+mod metrics;
+mod mmap;
+mod overlay;
+mod owner;
+mod path;
+mod profile;
+#[cfg(feature = "prompt")]
+mod prompt;
+mod query;
+mod raw;
+mod redact;
+mod rename;
+mod resolve;
+mod restore;
+mod schema;
+mod secure;
+mod shared;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod state;
+mod stats;
+mod storage;
+mod stream;
+mod subscribe;
+mod symlink;
+mod sync;
+mod template;
+mod trash;
+mod typed;
+mod validate;
+mod vendor;
+mod version;
+
+pub use access::Access;
 use anyhow::Result;
-use platform_dirs::AppDirs;
+pub use attachment::Attachment;
+pub use builder::ConfigstoreBuilder;
+pub use codec::Codec;
+pub use compression::Compression;
+pub use describe::{Constraint, KeyInfo};
+pub use doctor::{Issue, IssueKind, VacuumReport};
+#[cfg(feature = "encryption")]
+pub use encrypt::Encrypted;
+pub use entry::EntryGuard;
+pub use error::{Error, ErrorContext, Operation};
+pub use events::ChangeEvent;
+use events::Listeners;
+pub use fallback::{Fallback, FallbackStep};
+pub use fields::ReadMode;
+pub use flags::Flag;
+pub use format::Format;
+pub use instance::AppLock;
+pub use journal::JournalEntry;
+pub use location::{DirKind, Scope};
+pub use merge::{merge, ArrayStrategy};
+pub use metrics::Metrics;
+pub use mmap::ValueBytes;
+pub use overlay::Overlay;
+pub use owner::OwnerPolicy;
 /// Expose so that consumer can determine the type of the application;
 pub use platform_dirs::AppUI;
-use serde::{Deserialize, Serialize};
-use std::fs::OpenOptions;
-use std::io::BufReader;
-use std::path::PathBuf;
+pub use redact::Redaction;
+pub use resolve::{Origin, Resolved, Resolver, Source};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+pub use snapshot::{Change, Snapshot};
+pub use state::PidFile;
+pub use stats::{Eviction, StoreStats};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+pub use storage::Backend;
+use storage::Storage;
+pub use stream::KeyWriter;
+pub use subscribe::Subscription;
+pub use symlink::SymlinkPolicy;
+#[cfg(feature = "http-sync")]
+pub use sync::HttpRemote;
+pub use sync::{last_write_wins, merge_fields, Conflict, FileRemote, Remote, Resolution};
+pub use typed::TypedStore;
+/// Expose so that consumers can use the id returned by install_id
+pub use uuid::Uuid;
+pub use validate::Validator;
+use validate::Validators;
+pub use version::Version;
+
 ///Configstore store configurations
 /// Will store configuration on your platforms native configuration directory
 /// # Examples
@@ -18,13 +140,83 @@ use std::path::PathBuf;
 /// let value: String = config_store.get("key").unwrap();
 /// assert_eq!("value".to_string(), value);
 /// ```
+///
+/// Clones are cheap and share the same store, including its listeners, validators
+/// and buffered writes, so a Configstore can be handed to other threads without a Mutex
+///
+/// ```
+/// use configstore::Configstore;
+///
+/// let config_store = Configstore::temp().unwrap();
+/// let worker = config_store.clone();
+/// std::thread::spawn(move || worker.set("done", true).unwrap())
+///     .join()
+///     .unwrap();
+/// assert!(config_store.get::<bool>("done").unwrap());
+/// ```
+#[derive(Clone)]
 pub struct Configstore {
-    prefix_dir: PathBuf,
+    inner: Arc<Inner>,
+}
+
+/// The state shared by the clones of a Configstore
+pub(crate) struct Inner {
+    /// The app's directory, holding the default profile and the named ones
+    app_dir: PathBuf,
+    /// Whether app_dir is a project directory instead of the app's user or machine directory
+    project: bool,
+    profile: Option<String>,
+    pretty: bool,
+    sort_keys: bool,
+    format: Format,
+    key_formats: BTreeMap<String, Format>,
+    listeners: Listeners,
+    /// Shares changes with the other processes using the store, with the `ipc_events` option
+    #[cfg(unix)]
+    ipc: Option<ipc::Ipc>,
+    journal: Option<journal::Journal>,
+    validators: Validators,
+    codecs: codec::Codecs,
+    /// The permissions of the keys given one with `set_access`
+    access: access::AccessRules,
+    declarations: describe::Declarations,
+    validate_reads: bool,
+    read_mode: ReadMode,
+    recover_corrupt: bool,
+    interpolate: bool,
+    /// Directory of the defaults shipped by package managers, read before the seeded ones
+    vendor_dir: Option<PathBuf>,
+    /// Values of the keys that are seeded when read while not set
+    defaults: serde_json::Map<String, serde_json::Value>,
+    read_only: bool,
+    compression: Option<(Compression, usize)>,
+    quota: Option<u64>,
+    eviction: Eviction,
+    /// How long deleted values are kept in the trash, with the `trash` option
+    trash: Option<std::time::Duration>,
+    /// The store of another app the keys that are not set are read from, with the `inherits` option
+    parent: Option<Configstore>,
+    metrics: Option<Box<dyn Metrics>>,
+    /// Warned before each step of the fallback chain of get, with the `fallback` option
+    fallback: Option<fallback::FallbackCallback>,
+    storage: Storage,
+    /// Removes the directory of temporary stores, declared last so that it is dropped
+    /// after the storage has closed its files
+    _temp_dir: Option<location::TempDir>,
 }
 
 const CONFIG_STORE_NAME: &str = "configstore-rs";
 
 impl Configstore {
+    pub(crate) fn from_inner(inner: Inner) -> Self {
+        let inner = Arc::new(inner);
+        #[cfg(unix)]
+        if let Some(ipc) = &inner.ipc {
+            ipc.listen(Arc::downgrade(&inner));
+        }
+        Configstore { inner }
+    }
+
     /// Creates a new configstore based on a name and a type of ui
     /// Takes:
     ///   app_name: &str representing the name of the application
@@ -42,20 +234,83 @@ impl Configstore {
     /// Could error either if your plateform does not have a config directory (All Linux, MacOs and Windows do)
     /// Or if the application is unable to create the directories for its config files
     pub fn new(app_name: &str, app_ui: AppUI) -> Result<Self> {
-        let prefix_dir = match AppDirs::new(Some(CONFIG_STORE_NAME), app_ui) {
-            Some(dir) => dir.config_dir,
-            None => return Err(anyhow::Error::msg("Unable to find config directory")),
-        };
-        let prefix_dir = prefix_dir.join(app_name);
-        std::fs::create_dir_all(prefix_dir.clone())?;
+        Self::builder(app_name, app_ui).build()
+    }
 
-        Ok(Configstore { prefix_dir })
+    /// Opens an existing store without ever modifying it
+    /// Directories are not created and every mutation fails with `Error::ReadOnly`
+    /// Useful for sandboxed processes or reading another tool's config
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::open_read_only("myApp", AppUI::CommandLine).unwrap();
+    /// assert!(config_store.delete("key").is_err());
+    ///```
+    ///
+    /// # Errors
+    ///
+    /// Could error if your platform does not have a config directory
+    pub fn open_read_only(app_name: &str, app_ui: AppUI) -> Result<Self> {
+        Self::builder(app_name, app_ui).read_only(true).build()
+    }
+
+    /// Creates a new store in one of the platform's directories instead of the config directory
+    /// Useful to persist large or regenerable data in the cache or data directory
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI, DirKind};
+    ///
+    /// let cache = Configstore::new_in("myApp", AppUI::CommandLine, DirKind::Cache).unwrap();
+    ///```
+    ///
+    /// # Errors
+    ///
+    /// Same as new
+    pub fn new_in(app_name: &str, app_ui: AppUI, dir_kind: DirKind) -> Result<Self> {
+        Self::builder(app_name, app_ui).dir_kind(dir_kind).build()
+    }
+
+    /// Creates a store in a unique temporary directory, deleted with its content when dropped
+    /// Useful in tests, which can run in parallel without sharing the user's settings
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("key", "value".to_string()).unwrap();
+    /// let dir = config_store.dir().to_path_buf();
+    /// drop(config_store);
+    /// assert!(!dir.exists());
+    ///```
+    ///
+    /// # Errors
+    ///
+    /// Could error if the temporary directory cannot be created
+    pub fn temp() -> Result<Self> {
+        Self::builder("temp", AppUI::CommandLine).temp(true).build()
+    }
+
+    /// Creates a builder to customize the configstore, for example its scope
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI, Scope};
+    ///
+    /// let builder = Configstore::builder("myApp", AppUI::CommandLine).scope(Scope::Machine);
+    ///```
+    pub fn builder(app_name: &str, app_ui: AppUI) -> ConfigstoreBuilder {
+        ConfigstoreBuilder::new(app_name, app_ui)
     }
 
     /// Sets a value in the configstore, to be retrieved at any point in time with get
     /// Overwrites any existing values with the same key, or creates a new pair
     /// value is saved as a json file in $CONFIG/configstore-rs/$APPNAME/key.json
-    /// value must implement serde::Serialize and serde::Deserialize
+    /// (or with the extension of the store's format)
+    /// value only needs to implement serde::Serialize, references like `&str` or `&MyStruct` work too
     ///
     /// # Examples
     ///
@@ -78,38 +333,470 @@ impl Configstore {
     /// # Errors
     /// Possible errors if config file cannot be oppened, or value cannot be encoded
     /// into json
+    /// Fails with `Error::ReadOnly` on read-only stores
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, value), err(level = "debug"))
+    )]
     pub fn set<T>(&self, key: &str, value: T) -> Result<()>
     where
-        T: Serialize + for<'de> Deserialize<'de>,
+        T: Serialize,
     {
-        let mut file_name = String::from(key);
-        file_name.push_str(".json");
-        let config_path = self.prefix_dir.join(&file_name);
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(config_path)?;
-        serde_json::to_writer(&file, &value)?;
-        Ok(())
+        self.check_writable()?;
+        let bytes = self.encode(key, &value)?;
+        self.write_bytes(key, &bytes)
+    }
+
+    /// Sets a value in a specific format instead of the store's one
+    /// Keys of a store can each use a different format, get detects it from the file extension
+    /// The key keeps the format when the store's format changes, setting it again with set
+    /// moves it back to the store's format
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, Format};
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set_with_format("window", vec![800, 600], Format::Json).unwrap();
+    /// let window: Vec<u32> = config_store.get("window").unwrap();
+    /// assert_eq!(window, vec![800, 600]);
+    /// ```
+    ///
+    /// # Errors
+    /// Same as set, also fails if the value cannot be represented in the format
+    pub fn set_with_format<T>(&self, key: &str, value: T, format: Format) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.check_writable()?;
+        let bytes = self.encode_as(key, &value, format)?;
+        self.write_value(key, format, &bytes, false)
     }
 
     /// Check the set docs for usage
+    /// Keys are read in whichever format they are stored in, detected from their file extension
+    /// After the store's format changed, keys stored in the previous one are migrated on first read
     /// # Errors
     /// Could produce errors if unable to open config file
     /// This could happen if the key was never set or if you manually deleted the file
     /// Otherwise could cause errors if the type cannot be decoded correctly
+    /// With the builder's `recover_corrupt` option, files that are not valid in the store's format
+    /// are moved aside and reported as a key that was never set
+    /// With the builder's `fallback` option, values that cannot be read are read from their
+    /// backup, then from their default
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err(level = "debug"))
+    )]
     pub fn get<T>(&self, key: &str) -> Result<T>
     where
-        T: Serialize + for<'de> Deserialize<'de>,
+        T: DeserializeOwned,
     {
-        let mut file_name = String::from(key);
-        file_name.push_str(".json");
-        let config_path = self.prefix_dir.join(&file_name);
-        let file = std::fs::File::open(config_path)?;
-        let buff_reader = BufReader::new(file);
-        let ret: T = serde_json::from_reader(buff_reader)?;
-        Ok(ret)
+        let value = self.read_value(key).and_then(|(format, bytes)| {
+            if self.inner.interpolate {
+                self.decode_interpolated(key, format, &bytes)
+            } else {
+                self.decode(key, format, &bytes)
+            }
+        });
+        match value {
+            Err(e) => self.fall_back(key, e),
+            value => value,
+        }
+    }
+
+    /// Deletes a key and its value from the configstore
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set("old_key", "value".to_string()).unwrap();
+    /// config_store.delete("old_key").unwrap();
+    /// assert!(config_store.get::<String>("old_key").is_err());
+    /// ```
+    ///
+    /// # Errors
+    /// Could produce errors if the key was never set or the file cannot be removed
+    /// Fails with `Error::ReadOnly` on read-only stores
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err(level = "debug"))
+    )]
+    pub fn delete(&self, key: &str) -> Result<()> {
+        self.check_writable()?;
+        let format = self
+            .stored_format(key)?
+            .ok_or_else(|| error::not_found(key))?;
+        let name = self.entry_name(key, format.extension());
+        self.trash_entry(key, &name)?;
+        self.remove_backup(key)?;
+        self.remove_entry(key, &name)?;
+        self.remove_other_formats(key, format)
+    }
+
+    /// Lists the keys holding a value, in alphabetical order
+    /// Blobs are not included
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set("listed", "value".to_string()).unwrap();
+    /// assert!(config_store.keys().unwrap().contains(&"listed".to_string()));
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store directory cannot be read
+    pub fn keys(&self) -> Result<Vec<String>> {
+        let mut keys: Vec<String> = self
+            .inner
+            .storage
+            .entries()?
+            .into_iter()
+            .filter(|entry| Format::from_extension(entry.extension()).is_some())
+            .map(|entry| entry.key)
+            .collect();
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// The directory the store keeps its files in, for example to tell users where their settings are
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// println!("Your settings are stored in {}", config_store.dir().display());
+    /// assert!(config_store.dir().ends_with("myApp"));
+    /// ```
+    pub fn dir(&self) -> &Path {
+        &self.inner.storage.dir
+    }
+
+    /// The file holding the value of a key, whether or not the key is set
+    /// Keys that are not set, or that cannot be looked up, get a file in the store's format
+    /// Useful to open the file in the user's editor
+    /// With the SQLite backend every key lives in the same database file, which is returned instead
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// let path = config_store.config_path("theme");
+    /// assert_eq!(path, config_store.dir().join("theme.json"));
+    /// ```
+    pub fn config_path(&self, key: &str) -> PathBuf {
+        let format = self
+            .stored_format(key)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| self.key_format(key));
+        self.inner
+            .storage
+            .path(&self.entry_name(key, format.extension()))
+    }
+
+    /// Registers a callback that runs whenever a key is set or deleted through this configstore
+    /// Changes made by other processes or other Configstore instances are not reported
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI, ChangeEvent};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.on_change(|event| {
+    ///     if let ChangeEvent::Set(key) = event {
+    ///         println!("{} changed", key);
+    ///     }
+    /// });
+    /// config_store.set("theme", "dark".to_string()).unwrap();
+    /// ```
+    pub fn on_change<F>(&self, listener: F)
+    where
+        F: Fn(&ChangeEvent) + Send + Sync + 'static,
+    {
+        self.inner.listeners.add(listener);
+    }
+
+    /// Creates an in-memory overlay on top of this store to preview changes before applying them
+    /// Check the Overlay docs for usage
+    pub fn overlay(&self) -> Overlay<'_> {
+        Overlay::new(self)
+    }
+
+    /// Serializes a value in the key's format, ready to be written to the key's file
+    pub(crate) fn encode<T>(&self, key: &str, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        self.encode_as(key, value, self.key_format(key))
+    }
+
+    fn encode_as<T>(&self, key: &str, value: &T, format: Format) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        let previous = if format.preserves_formatting() {
+            match self.read_bytes(key) {
+                Ok((stored, bytes)) if stored == format => Some(bytes),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        let encoded = match self.encode_codecs(key, value)? {
+            Some(encoded) => Some(encoded),
+            None if self.inner.sort_keys => Some(serde_json::to_value(value)?),
+            None => None,
+        };
+        match encoded {
+            Some(mut encoded) => {
+                if self.inner.sort_keys {
+                    format::sort_keys(&mut encoded);
+                }
+                format.serialize(&encoded, self.inner.pretty, previous.as_deref())
+            }
+            None => format.serialize(value, self.inner.pretty, previous.as_deref()),
+        }
+    }
+
+    /// Reads the decompressed value of a key along with the format it is stored in
+    pub(crate) fn read_bytes(&self, key: &str) -> Result<(Format, Vec<u8>)> {
+        let read = self.observe(self.read_key_bytes(key), Operation::Read, key, || {
+            self.config_path(key)
+        })?;
+        if let Some(metrics) = self.metrics() {
+            metrics.read(key, read.1.len());
+        }
+        Ok(read)
+    }
+
+    fn read_key_bytes(&self, key: &str) -> Result<(Format, Vec<u8>)> {
+        let (format, bytes) = match self.read_stored(key)? {
+            Some(stored) => stored,
+            None => {
+                if let Some(inherited) = self.read_inherited(key)? {
+                    return Ok(inherited);
+                }
+                match self.read_vendor_default(key)? {
+                    Some(vendor_default) => return Ok(vendor_default),
+                    None => return self.seed_default(key),
+                }
+            }
+        };
+        let name = self.entry_name(key, format.extension());
+        if let Some(metrics) = self.metrics() {
+            if self.inner.storage.is_buffered(&name) {
+                metrics.cache_hit(key);
+            }
+        }
+        self.touch(&name);
+        let bytes = compression::decompress(bytes)?;
+        if format == self.key_format(key) {
+            return Ok((format, bytes));
+        }
+        self.migrate_format(key, format, bytes)
+    }
+
+    /// Converts a key stored in another format to its own, after the store's format was changed
+    /// Keys written in another format with `set_with_format` keep it. The converted file
+    /// replaces the old one unless the store is read-only
+    fn migrate_format(
+        &self,
+        key: &str,
+        format: Format,
+        bytes: Vec<u8>,
+    ) -> Result<(Format, Vec<u8>)> {
+        if self.inner.read_only
+            || !self.inner.storage.backend.uses_files()
+            || manifest::is_pinned(self.dir(), key)?
+        {
+            return Ok((format, bytes));
+        }
+        // Values that are not valid are left for decode to report
+        let value: serde_json::Value = match format.deserialize(&bytes) {
+            Ok(value) => value,
+            Err(_) => return Ok((format, bytes)),
+        };
+        let target = self.key_format(key);
+        let converted = target.serialize(&value, self.inner.pretty, None)?;
+        let compressed = compression::compress(self.inner.compression, &converted)?;
+        self.inner.storage.write(
+            &self.key_name(key),
+            compressed.as_deref().unwrap_or(&converted),
+        )?;
+        self.remove_other_formats(key, target)?;
+        Ok((target, converted))
+    }
+
+    /// The bytes of a key as written on disk and their format, None if the key is not set
+    /// The store's format is looked up first, then every other enabled format
+    pub(crate) fn read_stored(&self, key: &str) -> Result<Option<(Format, Vec<u8>)>> {
+        for format in self.formats() {
+            match self
+                .inner
+                .storage
+                .read(&self.entry_name(key, format.extension()))
+            {
+                Ok(bytes) => return Ok(Some((format, bytes))),
+                Err(e) if error::is_not_found(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The format a key is stored in, None if the key is not set
+    pub(crate) fn stored_format(&self, key: &str) -> Result<Option<Format>> {
+        for format in self.formats() {
+            if self
+                .inner
+                .storage
+                .exists(&self.entry_name(key, format.extension()))?
+            {
+                return Ok(Some(format));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Every enabled format, the store's one first
+    fn formats(&self) -> impl Iterator<Item = Format> {
+        let format = self.inner.format;
+        std::iter::once(format).chain(Format::all().into_iter().filter(move |f| *f != format))
+    }
+
+    /// Removes the files a key has in formats other than the one it was just written in,
+    /// so that keys move to a new format as they are set
+    pub(crate) fn remove_other_formats(&self, key: &str, format: Format) -> Result<()> {
+        for other in self.formats().filter(|other| *other != format) {
+            match self
+                .inner
+                .storage
+                .remove(&self.entry_name(key, other.extension()))
+            {
+                Ok(()) => (),
+                Err(e) if error::is_not_found(&e) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_bytes(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        self.write_value(key, self.key_format(key), bytes, false)
+    }
+
+    /// Writes the value of a key in a format, private values are readable by the current user only
+    pub(crate) fn write_value(
+        &self,
+        key: &str,
+        format: Format,
+        bytes: &[u8],
+        private: bool,
+    ) -> Result<()> {
+        self.check_writable()?;
+        self.validate(key, format, bytes)?;
+        let compressed = compression::compress(self.inner.compression, bytes)?;
+        self.write_entry(
+            key,
+            &self.entry_name(key, format.extension()),
+            compressed.as_deref().unwrap_or(bytes),
+            private,
+        )?;
+        self.write_backup(key, format, bytes)?;
+        self.remove_other_formats(key, format)?;
+        if self.inner.storage.backend.uses_files() {
+            let pinned = Some(format).filter(|format| *format != self.key_format(key));
+            manifest::pin(self.dir(), key, pinned)?;
+        }
+        Ok(())
+    }
+
+    /// Atomically writes an entry of a key, journals it and notifies the listeners
+    pub(crate) fn write_entry(
+        &self,
+        key: &str,
+        name: &str,
+        bytes: &[u8],
+        private: bool,
+    ) -> Result<()> {
+        self.check_writable()?;
+        self.enforce_quota(name, bytes.len() as u64)?;
+        let written = match self.inner.access.get(key) {
+            Some(access) => self.inner.storage.write_with_access(name, bytes, access),
+            None if private => self.inner.storage.write_private(name, bytes),
+            None => self.inner.storage.write(name, bytes),
+        };
+        self.observe(written, Operation::Write, key, || {
+            self.inner.storage.path(name)
+        })?;
+        if let Some(metrics) = self.metrics() {
+            metrics.write(key, bytes.len());
+        }
+        self.changed(ChangeEvent::Set(key.to_string()), name)
+    }
+
+    /// Removes an entry of a key, journals it and notifies the listeners
+    pub(crate) fn remove_entry(&self, key: &str, name: &str) -> Result<()> {
+        self.check_writable()?;
+        self.observe(
+            self.inner.storage.remove(name),
+            Operation::Delete,
+            key,
+            || self.inner.storage.path(name),
+        )?;
+        if let Some(metrics) = self.metrics() {
+            metrics.delete(key);
+        }
+        self.changed(ChangeEvent::Delete(key.to_string()), name)
+    }
+
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.inner.read_only {
+            return Err(Error::ReadOnly.into());
+        }
+        Ok(())
+    }
+
+    /// The name of the entry holding a key's value
+    pub(crate) fn key_name(&self, key: &str) -> String {
+        self.entry_name(key, self.key_format(key).extension())
+    }
+
+    /// The format a key is written in, the one registered for it with the builder's
+    /// `key_format` or the store's one
+    pub(crate) fn key_format(&self, key: &str) -> Format {
+        self.inner
+            .key_formats
+            .get(key)
+            .copied()
+            .unwrap_or(self.inner.format)
+    }
+
+    pub(crate) fn entry_name(&self, key: &str, extension: &str) -> String {
+        self.inner.storage.entry_name(key, extension)
+    }
+
+    /// A builder opening another store of the same app in the same directory,
+    /// for tests that need several stores to share a temporary directory
+    #[cfg(test)]
+    pub(crate) fn reopen(&self) -> ConfigstoreBuilder {
+        let name = self.inner.app_dir.file_name().unwrap().to_string_lossy();
+        Self::builder(&name, AppUI::CommandLine)
+            .root(self.inner.app_dir.parent().unwrap().to_path_buf())
     }
 }
 
@@ -124,7 +811,7 @@ mod tests {
     }
     #[test]
     fn test_struct() {
-        let config_store = Configstore::new("tests", AppUI::CommandLine).unwrap();
+        let config_store = Configstore::temp().unwrap();
         let test_struct = TestStruct {
             str_test: "Hello World".to_string(),
             num: 1000,
@@ -136,7 +823,7 @@ mod tests {
 
     #[test]
     fn test_string() {
-        let config_store = Configstore::new("tests", AppUI::CommandLine).unwrap();
+        let config_store = Configstore::temp().unwrap();
         config_store.set("test2", String::from("World")).unwrap();
         let out: String = config_store.get("test2").unwrap();
         assert_eq!(out, "World".to_string());
@@ -144,7 +831,7 @@ mod tests {
 
     #[test]
     fn reset_same_type() {
-        let config_store = Configstore::new("tests", AppUI::CommandLine).unwrap();
+        let config_store = Configstore::temp().unwrap();
         let test_struct = TestStruct {
             str_test: "Hello World".to_string(),
             num: 1000,
@@ -165,7 +852,7 @@ mod tests {
 
     #[test]
     fn test_vector() {
-        let config_store = Configstore::new("tests", AppUI::CommandLine).unwrap();
+        let config_store = Configstore::temp().unwrap();
         let test_struct_1 = TestStruct {
             str_test: "Hello World".to_string(),
             num: 1000,
@@ -182,4 +869,144 @@ mod tests {
             assert_eq!(test_vec[i], *val);
         }
     }
+
+    #[test]
+    fn test_pretty() {
+        let config_store = Configstore::builder("tests-pretty", AppUI::CommandLine)
+            .temp(true)
+            .pretty(true)
+            .build()
+            .unwrap();
+        let test_struct = TestStruct {
+            str_test: "Hello World".to_string(),
+            num: 1000,
+        };
+        config_store.set("test5", test_struct.clone()).unwrap();
+        let contents = std::fs::read_to_string(config_store.dir().join("test5.json")).unwrap();
+        assert_eq!(
+            contents,
+            "{\n  \"str_test\": \"Hello World\",\n  \"num\": 1000\n}"
+        );
+        let out: TestStruct = config_store.get("test5").unwrap();
+        assert_eq!(test_struct, out);
+    }
+
+    #[test]
+    fn sorted_keys_give_identical_files() {
+        let config_store = Configstore::builder("tests-sorted", AppUI::CommandLine)
+            .temp(true)
+            .sort_keys(true)
+            .build()
+            .unwrap();
+        let mut shortcuts = std::collections::HashMap::new();
+        for (i, action) in ["save", "open", "quit", "find", "undo"].iter().enumerate() {
+            shortcuts.insert(action.to_string(), vec![i]);
+        }
+        config_store
+            .set(
+                "shortcuts",
+                serde_json::json!({"keys": shortcuts, "enabled": true}),
+            )
+            .unwrap();
+        let contents = std::fs::read_to_string(config_store.config_path("shortcuts")).unwrap();
+        assert_eq!(
+            contents,
+            r#"{"enabled":true,"keys":{"find":[3],"open":[1],"quit":[2],"save":[0],"undo":[4]}}"#
+        );
+    }
+
+    #[test]
+    fn test_serialize_only() {
+        #[derive(Serialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(skip_deserializing)]
+            #[allow(dead_code)]
+            skipped: u32,
+        }
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("str", "borrowed").unwrap();
+        let name = String::from("configstore");
+        let borrowed = Borrowed {
+            name: &name,
+            skipped: 1,
+        };
+        config_store.set("struct", &borrowed).unwrap();
+        assert_eq!(config_store.get::<String>("str").unwrap(), "borrowed");
+        let value: serde_json::Value = config_store.get("struct").unwrap();
+        assert_eq!(value["name"], "configstore");
+    }
+
+    #[test]
+    fn test_delete() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("test6", String::from("World")).unwrap();
+        config_store.delete("test6").unwrap();
+        assert!(config_store.get::<String>("test6").is_err());
+        assert!(config_store.delete("test6").is_err());
+    }
+
+    #[test]
+    fn test_read_only() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("test8", String::from("World")).unwrap();
+        let read_only = config_store.reopen().read_only(true).build().unwrap();
+        let out: String = read_only.get("test8").unwrap();
+        assert_eq!(out, "World".to_string());
+        let err = read_only.set("test8", String::from("Other")).unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::ReadOnly));
+        let err = read_only.delete("test8").unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::ReadOnly));
+    }
+
+    #[test]
+    fn test_read_only_does_not_create_dir() {
+        let read_only = Configstore::builder("tests-read-only-missing", AppUI::CommandLine)
+            .temp(true)
+            .read_only(true)
+            .build()
+            .unwrap();
+        assert!(!read_only.dir().exists());
+    }
+
+    #[test]
+    fn test_on_change() {
+        use std::sync::{Arc, Mutex};
+        let config_store = Configstore::temp().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        config_store.on_change(move |event| recorded.lock().unwrap().push(event.clone()));
+        config_store.set("test7", String::from("World")).unwrap();
+        config_store.delete("test7").unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ChangeEvent::Set("test7".to_string()),
+                ChangeEvent::Delete("test7".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clones_share_state() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        let config_store = Configstore::temp().unwrap();
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&changes);
+        config_store.on_change(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let clone = config_store.clone();
+                std::thread::spawn(move || clone.set(&format!("thread{}", i), i).unwrap())
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(changes.load(Ordering::SeqCst), 4);
+        assert_eq!(config_store.keys().unwrap().len(), 4);
+    }
 }
@@ -0,0 +1,119 @@
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+impl Configstore {
+    /// Appends an item to the array held by key, a key that is not set starts an empty array
+    /// The read and the write are atomic like `set_if_unchanged`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.list_push("history", "build").unwrap();
+    /// config_store.list_push("history", "test").unwrap();
+    /// assert_eq!(config_store.get::<Vec<String>>("history").unwrap(), vec!["build", "test"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the stored value is not an array, otherwise same as set
+    pub fn list_push<T>(&self, key: &str, item: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.list_push_bounded(key, item, usize::MAX)
+    }
+
+    /// Appends an item to the array held by key like `list_push`, then removes its oldest items
+    /// until it holds at most max_len, for recent files and other most recently used lists
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// for file in &["a.txt", "b.txt", "c.txt"] {
+    ///     config_store.list_remove_where("recent", |recent: &String| recent == file).unwrap();
+    ///     config_store.list_push_bounded("recent", file, 2).unwrap();
+    /// }
+    /// assert_eq!(config_store.get::<Vec<String>>("recent").unwrap(), vec!["b.txt", "c.txt"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Same as list_push
+    pub fn list_push_bounded<T>(&self, key: &str, item: T, max_len: usize) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let item = serde_json::to_value(item)?;
+        self.update(key, |items: Option<Vec<Value>>| {
+            let mut items = items.unwrap_or_default();
+            items.push(item);
+            let excess = items.len().saturating_sub(max_len);
+            items.drain(..excess);
+            Ok((Some(items), ()))
+        })
+    }
+
+    /// Removes the items of the array held by key that match the predicate,
+    /// and returns how many were removed. The read and the write are atomic like `list_push`
+    /// Check the list_push_bounded docs for usage
+    ///
+    /// # Errors
+    /// Fails if the stored value is not an array of T, otherwise same as set
+    /// A key that is not set holds no item and is not an error
+    pub fn list_remove_where<T, F>(&self, key: &str, mut predicate: F) -> Result<usize>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(&T) -> bool,
+    {
+        self.update(key, |items: Option<Vec<T>>| {
+            let mut items = match items {
+                Some(items) => items,
+                None => return Ok((None, 0)),
+            };
+            let len = items.len();
+            items.retain(|item| !predicate(item));
+            let removed = len - items.len();
+            Ok((Some(items).filter(|_| removed > 0), removed))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn bounded_lists_trim_oldest_items() {
+        let config_store = Configstore::temp().unwrap();
+        for n in 0..5 {
+            config_store.list_push_bounded("recent", n, 3).unwrap();
+        }
+        assert_eq!(
+            config_store.get::<Vec<u32>>("recent").unwrap(),
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            config_store
+                .list_remove_where("recent", |n: &u32| *n != 3)
+                .unwrap(),
+            2
+        );
+        assert_eq!(config_store.get::<Vec<u32>>("recent").unwrap(), vec![3]);
+        assert_eq!(
+            config_store
+                .list_remove_where("unset", |_: &u32| true)
+                .unwrap(),
+            0
+        );
+        assert!(config_store.keys().unwrap() == vec!["recent"]);
+        config_store.set("scalar", 1).unwrap();
+        assert!(config_store.list_push("scalar", 2).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Most threads reading keys at once, more rarely helps as reads become bound by the disk
+const MAX_THREADS: usize = 8;
+
+impl Configstore {
+    /// Reads and decodes the value of every key, spreading the reads over a few threads,
+    /// to cut the startup time of apps keeping dozens of files. Keys holding only a blob are skipped
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    /// use serde_json::Value;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("theme", "dark").unwrap();
+    /// config_store.set("volume", 7).unwrap();
+    /// let settings = config_store.load_all::<Value>().unwrap();
+    /// assert_eq!(settings["volume"], 7);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails like get with the first value that cannot be read or decoded as T
+    pub fn load_all<T>(&self) -> Result<BTreeMap<String, T>>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let keys = self.keys()?;
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |threads| threads.get())
+            .min(MAX_THREADS)
+            .min(keys.len());
+        let next = AtomicUsize::new(0);
+        let loaded = Mutex::new(BTreeMap::new());
+        let load = || -> Result<()> {
+            while let Some(key) = keys.get(next.fetch_add(1, Ordering::Relaxed)) {
+                match self.get(key) {
+                    Ok(value) => {
+                        loaded.lock().unwrap().insert(key.clone(), value);
+                    }
+                    // Deleted since it was listed, or a blob
+                    Err(e) if is_not_found(&e) => (),
+                    Err(e) => {
+                        // Stops the other threads early
+                        next.store(keys.len(), Ordering::Relaxed);
+                        return Err(e.context(format!("Could not load {}", key)));
+                    }
+                }
+            }
+            Ok(())
+        };
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (1..threads).map(|_| scope.spawn(load)).collect();
+            let mut result = load();
+            for worker in workers {
+                let worker_result = worker.join().expect("Loading thread panicked");
+                result = result.and(worker_result);
+            }
+            result
+        })?;
+        Ok(loaded.into_inner().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn loads_every_value_concurrently() {
+        let config_store = Configstore::temp().unwrap();
+        for i in 0..40 {
+            config_store.set(&format!("key{}", i), i).unwrap();
+        }
+        config_store.set_blob("icon", b"png").unwrap();
+        let values = config_store.load_all::<u32>().unwrap();
+        assert_eq!(values.len(), 40);
+        assert_eq!(values["key17"], 17);
+
+        config_store.set("name", "not a number").unwrap();
+        let err = config_store.load_all::<u32>().unwrap_err();
+        assert_eq!(err.to_string(), "Could not load name");
+    }
+}
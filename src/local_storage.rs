@@ -0,0 +1,140 @@
+use crate::storage::{self, Entry};
+use anyhow::Result;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use wasm_bindgen::JsValue;
+
+/// Prefix of values that are not valid utf-8 and are stored hex encoded,
+/// localStorage only holds strings
+const HEX_MARKER: char = '\u{1}';
+
+/// Storage keeping every entry as an item of the browser's localStorage
+/// Items are named after the store directory followed by the entry name,
+/// like `configstore-rs/myApp/key.json`, so stores of different apps and profiles stay apart
+pub(crate) struct LocalStorage {
+    storage: web_sys::Storage,
+    prefix: String,
+}
+
+impl LocalStorage {
+    pub(crate) fn open(dir: &Path) -> Result<Self> {
+        let storage = web_sys::window()
+            .ok_or_else(|| anyhow::Error::msg("localStorage requires a browser window"))?
+            .local_storage()
+            .map_err(js_error)?
+            .ok_or_else(|| anyhow::Error::msg("localStorage is disabled in this browser"))?;
+        Ok(LocalStorage {
+            storage,
+            prefix: format!("{}/", dir.to_string_lossy()),
+        })
+    }
+
+    fn item(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>> {
+        let item = self.storage.get_item(&self.item(name)).map_err(js_error)?;
+        decode(&item.ok_or_else(|| crate::error::not_found(name))?)
+    }
+
+    pub(crate) fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.storage
+            .set_item(&self.item(name), &encode(bytes))
+            .map_err(js_error)
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> Result<()> {
+        if !self.exists(name)? {
+            return Err(crate::error::not_found(name));
+        }
+        self.storage.remove_item(&self.item(name)).map_err(js_error)
+    }
+
+    pub(crate) fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let bytes = self.read(from)?;
+        self.write(to, &bytes)?;
+        self.remove(from)
+    }
+
+    pub(crate) fn exists(&self, name: &str) -> Result<bool> {
+        let item = self.storage.get_item(&self.item(name)).map_err(js_error)?;
+        Ok(item.is_some())
+    }
+
+    /// Every entry directly in the store, entries of profiles are nested deeper and skipped
+    /// localStorage keeps no modification times, so eviction removes entries in arbitrary order
+    pub(crate) fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for index in 0..self.storage.length().map_err(js_error)? {
+            let item = match self.storage.key(index).map_err(js_error)? {
+                Some(item) => item,
+                None => continue,
+            };
+            let name = match item.strip_prefix(&self.prefix) {
+                Some(name) if !name.contains('/') && !name.starts_with('.') => name,
+                _ => continue,
+            };
+            if let Some(key) = storage::entry_key(name) {
+                entries.push(Entry {
+                    key: key.to_string(),
+                    size: self.read(name)?.len() as u64,
+                    modified: UNIX_EPOCH,
+                    name: name.to_string(),
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Keeps text values readable in the browser's developer tools, hex encodes the others
+fn encode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.starts_with(HEX_MARKER) => text.to_string(),
+        _ => {
+            let mut encoded = String::with_capacity(bytes.len() * 2 + 1);
+            encoded.push(HEX_MARKER);
+            for byte in bytes {
+                encoded.push_str(&format!("{:02x}", byte));
+            }
+            encoded
+        }
+    }
+}
+
+fn decode(item: &str) -> Result<Vec<u8>> {
+    let hex = match item.strip_prefix(HEX_MARKER) {
+        Some(hex) => hex,
+        None => return Ok(item.as_bytes().to_vec()),
+    };
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| anyhow::Error::msg("Corrupt hex encoded localStorage item"))
+        })
+        .collect()
+}
+
+fn js_error(value: JsValue) -> anyhow::Error {
+    anyhow::Error::msg(format!("localStorage error: {:?}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_values_round_trip() {
+        for bytes in [
+            &b"{\"a\": 1}"[..],
+            &[0, 159, 146, 150],
+            "\u{1}text".as_bytes(),
+        ] {
+            assert_eq!(decode(&encode(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(encode(b"\"plain\""), "\"plain\"");
+    }
+}
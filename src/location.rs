@@ -0,0 +1,375 @@
+use crate::Configstore;
+use anyhow::Result;
+use platform_dirs::{AppDirs, AppUI};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Whether a store belongs to the current user or is shared by every user of the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scope {
+    /// Stored under the current user's config directory (the default)
+    #[default]
+    User,
+    /// Stored under the system-wide config directory
+    /// `/etc` on Linux, `/Library/Application Support` on macOS and `%PROGRAMDATA%` on Windows
+    /// Writing usually requires elevated privileges
+    Machine,
+}
+
+/// Which of the platform's directories a store lives in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum DirKind {
+    /// The platform's config directory (the default)
+    #[default]
+    Config,
+    /// The platform's cache directory, for regenerable data the system may clean up
+    Cache,
+    /// The platform's data directory, for larger application data
+    Data,
+    /// The platform's state directory (`XDG_STATE_HOME` on Linux), for state that should
+    /// persist between runs but is not worth backing up like logs or history
+    State,
+}
+
+/// Options deciding where a store lives
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Location {
+    pub(crate) scope: Scope,
+    pub(crate) kind: DirKind,
+    /// Follow the XDG Base Directory spec on every platform, not only on Linux
+    pub(crate) strict_xdg: bool,
+    /// Overrides the platform directories, used by temporary stores
+    pub(crate) root: Option<PathBuf>,
+    /// The name of the project directory searched for, like `.myapp`
+    pub(crate) project: Option<String>,
+    /// Where the project search starts, defaults to the current directory
+    pub(crate) project_start: Option<PathBuf>,
+}
+
+impl Location {
+    /// Resolves the directory that holds every app's store
+    pub(crate) fn root_dir(&self, prefix: &str, app_ui: AppUI) -> Result<PathBuf> {
+        if let Some(root) = &self.root {
+            return Ok(root.clone());
+        }
+        match self.scope {
+            Scope::Machine => Ok(machine_dir(self.kind).join(prefix)),
+            Scope::User => {
+                if let Some(base) = BASE_DIR.get() {
+                    return Ok(in_base_dir(base, self.kind).join(prefix));
+                }
+                if self.strict_xdg {
+                    return Ok(xdg_dir(self.kind)?.join(prefix));
+                }
+                user_dir(self.kind, prefix, app_ui)
+            }
+        }
+    }
+
+    /// Searches the project directory in the start directory and its parents
+    /// Returns None if the store is not project local or if no parent has the directory
+    pub(crate) fn project_dir(&self) -> Option<PathBuf> {
+        let name = self.project.as_ref()?;
+        let start = match &self.project_start {
+            Some(start) => start.clone(),
+            None => std::env::current_dir().ok()?,
+        };
+        start
+            .ancestors()
+            .map(|dir| dir.join(name))
+            .find(|dir| dir.is_dir())
+    }
+}
+
+/// Resolves the user directory of a kind on platforms without a base directory
+fn user_dir(kind: DirKind, prefix: &str, app_ui: AppUI) -> Result<PathBuf> {
+    if cfg!(target_os = "android") {
+        return Err(anyhow::Error::msg(
+            "Android apps must call Configstore::set_base_dir with the directory \
+             returned by Context.getFilesDir() before creating a store",
+        ));
+    }
+    if cfg!(target_os = "ios") {
+        return Ok(ios_dir(kind)?.join(prefix));
+    }
+    match AppDirs::new(Some(prefix), app_ui) {
+        Some(dir) => Ok(match kind {
+            DirKind::Config => dir.config_dir,
+            DirKind::Cache => dir.cache_dir,
+            DirKind::Data => dir.data_dir,
+            DirKind::State => dir.state_dir,
+        }),
+        None => Err(anyhow::Error::msg("Unable to find config directory")),
+    }
+}
+
+/// The directory set with `set_base_dir`, replacing the platform's user directories
+static BASE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+impl Configstore {
+    /// Sets the directory that holds the user stores of every app in this process,
+    /// instead of the platform's directories. Each kind of directory gets a subdirectory of it
+    /// Required on Android, with the directory returned by `Context.getFilesDir()`,
+    /// and useful for portable apps keeping their settings next to the executable
+    /// Must be called before creating any store, machine scoped stores are not affected
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let files_dir = std::env::temp_dir().join("configstore-base-dir-example");
+    /// Configstore::set_base_dir(&files_dir).unwrap();
+    /// let config_store = Configstore::new("myApp", AppUI::Graphical).unwrap();
+    /// assert!(config_store.dir().starts_with(&files_dir));
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if a different base directory was already set, or if the path is relative
+    pub fn set_base_dir<P>(dir: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        if !dir.is_absolute() {
+            return Err(anyhow::Error::msg(format!(
+                "The base directory {} must be an absolute path",
+                dir.display()
+            )));
+        }
+        let current = BASE_DIR.get_or_init(|| dir.to_path_buf());
+        if current != dir {
+            return Err(anyhow::Error::msg(format!(
+                "The base directory was already set to {}",
+                current.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether the store lives in a project directory found by the builder's `project` option
+    /// rather than in the user or machine directories
+    pub fn is_project(&self) -> bool {
+        self.inner.project
+    }
+}
+
+/// The subdirectory of a base directory holding the stores of a kind
+fn in_base_dir(base: &Path, kind: DirKind) -> PathBuf {
+    base.join(match kind {
+        DirKind::Config => "config",
+        DirKind::Cache => "cache",
+        DirKind::Data => "data",
+        DirKind::State => "state",
+    })
+}
+
+/// The directories of the app's sandbox, iOS has no XDG or platform-dirs equivalent
+fn ios_dir(kind: DirKind) -> Result<PathBuf> {
+    let home = platform_dirs::home_dir()
+        .ok_or_else(|| anyhow::Error::msg("Unable to find the app's sandbox directory"))?;
+    Ok(match kind {
+        DirKind::Cache => home.join("Library").join("Caches"),
+        DirKind::Config | DirKind::Data | DirKind::State => {
+            home.join("Library").join("Application Support")
+        }
+    })
+}
+
+/// A unique directory under the system's temp directory, removed with its content when dropped
+#[derive(Debug)]
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        TempDir(std::env::temp_dir().join(format!(
+            "configstore-rs-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos
+        )))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The XDG base directory for a kind, from its environment variable or the spec's default
+fn xdg_dir(kind: DirKind) -> Result<PathBuf> {
+    let (variable, default) = match kind {
+        DirKind::Config => ("XDG_CONFIG_HOME", ".config"),
+        DirKind::Cache => ("XDG_CACHE_HOME", ".cache"),
+        DirKind::Data => ("XDG_DATA_HOME", ".local/share"),
+        DirKind::State => ("XDG_STATE_HOME", ".local/state"),
+    };
+    // The spec asks to ignore relative paths
+    if let Some(dir) = std::env::var_os(variable).map(PathBuf::from) {
+        if dir.is_absolute() {
+            return Ok(dir);
+        }
+    }
+    match platform_dirs::home_dir() {
+        Some(home) => Ok(home.join(default)),
+        None => Err(anyhow::Error::msg("Unable to find home directory")),
+    }
+}
+
+fn machine_dir(kind: DirKind) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        std::env::var_os("PROGRAMDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("C:\\ProgramData"))
+    } else if cfg!(target_os = "macos") {
+        match kind {
+            DirKind::Cache => PathBuf::from("/Library/Caches"),
+            DirKind::Config | DirKind::Data | DirKind::State => {
+                PathBuf::from("/Library/Application Support")
+            }
+        }
+    } else {
+        match kind {
+            DirKind::Config => PathBuf::from("/etc"),
+            DirKind::Cache => PathBuf::from("/var/cache"),
+            DirKind::Data | DirKind::State => PathBuf::from("/var/lib"),
+        }
+    }
+}
+
+/// Creates the store directory, turning permission failures into an actionable message
+/// Private directories are created, or restricted if they exist, accessible to the current user only
+pub(crate) fn create_dir(dir: &Path, scope: Scope, private: bool) -> Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    if private {
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    }
+    match crate::atomic::retry(dir, || builder.create(dir)) {
+        Ok(()) if private => crate::secure::restrict_dir(dir),
+        Ok(()) => Ok(()),
+        Err(e) if is_permission_denied(&e) && scope == Scope::Machine => {
+            Err(anyhow::Error::msg(format!(
+                "Permission denied creating machine-wide config directory {}, \
+                 creating it requires administrator privileges",
+                dir.display()
+            )))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn is_permission_denied(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::PermissionDenied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_scope_is_user() {
+        assert_eq!(Scope::default(), Scope::User);
+    }
+
+    #[test]
+    fn machine_scope_resolves_to_system_dir() {
+        let location = Location {
+            scope: Scope::Machine,
+            ..Location::default()
+        };
+        let dir = location
+            .root_dir("configstore-rs", AppUI::CommandLine)
+            .unwrap();
+        assert_eq!(dir, machine_dir(DirKind::Config).join("configstore-rs"));
+        if cfg!(target_os = "linux") {
+            assert_eq!(dir, PathBuf::from("/etc/configstore-rs"));
+        }
+    }
+
+    #[test]
+    fn dir_kinds_resolve_to_matching_app_dirs() {
+        let app_dirs = AppDirs::new(Some("configstore-rs"), AppUI::CommandLine).unwrap();
+        let resolve = |kind| {
+            let location = Location {
+                kind,
+                ..Location::default()
+            };
+            location
+                .root_dir("configstore-rs", AppUI::CommandLine)
+                .unwrap()
+        };
+        assert_eq!(resolve(DirKind::Config), app_dirs.config_dir);
+        assert_eq!(resolve(DirKind::Cache), app_dirs.cache_dir);
+        assert_eq!(resolve(DirKind::Data), app_dirs.data_dir);
+        assert_eq!(resolve(DirKind::State), app_dirs.state_dir);
+    }
+
+    #[test]
+    fn base_dir_has_a_subdirectory_per_kind() {
+        let base = PathBuf::from("/data/user/0/com.example/files");
+        assert_eq!(in_base_dir(&base, DirKind::Config), base.join("config"));
+        assert_eq!(in_base_dir(&base, DirKind::Cache), base.join("cache"));
+        assert!(Configstore::set_base_dir("relative").is_err());
+    }
+
+    #[test]
+    fn project_dir_is_searched_upwards() {
+        let temp_dir = TempDir::new();
+        let project = temp_dir.path().join(".myapp");
+        let nested = temp_dir.path().join("src").join("bin");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+            .project(".myapp")
+            .project_start(&nested)
+            .build()
+            .unwrap();
+        assert!(config_store.is_project());
+        assert_eq!(config_store.dir(), project);
+        config_store.set("indent", 4).unwrap();
+        assert!(project.join("indent.json").exists());
+
+        let fallback = Configstore::builder("myApp", AppUI::CommandLine)
+            .project(".otherapp")
+            .project_start(&nested)
+            .temp(true)
+            .build()
+            .unwrap();
+        assert!(!fallback.is_project());
+        assert!(!fallback.dir().starts_with(temp_dir.path()));
+    }
+
+    #[test]
+    fn strict_xdg_uses_xdg_defaults() {
+        let location = Location {
+            strict_xdg: true,
+            ..Location::default()
+        };
+        let dir = location
+            .root_dir("configstore-rs", AppUI::Graphical)
+            .unwrap();
+        let expected = match std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+            Some(dir) if dir.is_absolute() => dir,
+            _ => platform_dirs::home_dir().unwrap().join(".config"),
+        };
+        assert_eq!(dir, expected.join("configstore-rs"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{atomic, Error, Format};
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Version of the layout of store directories written by this crate
+/// Increased when older versions of the crate could no longer read the stores it writes
+pub(crate) const LAYOUT_VERSION: u32 = 1;
+
+/// Name of the manifest inside the store directory, hidden so that it is never listed as a key
+pub(crate) const MANIFEST_NAME: &str = ".manifest.json";
+
+/// Name of the file locked while the manifest is updated
+pub(crate) const LOCK_NAME: &str = ".manifest.lock";
+
+/// Describes how a store directory is laid out, for the versions of the crate opening it later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Manifest {
+    layout: u32,
+    /// The extensions of the formats values were written in
+    #[serde(default)]
+    formats: BTreeSet<String>,
+    /// Whether values were written with the Encrypted codec
+    #[serde(default)]
+    encrypted: bool,
+    /// Whether the entries of keys that are not valid file names were moved to hashed names
+    #[serde(default)]
+    hashed_names: bool,
+    /// The keys written with `set_with_format` in another format than their own, with the
+    /// extension of that format, which are not migrated when read
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pinned: BTreeMap<String, String>,
+    /// The version of the crate that last updated the manifest
+    #[serde(default)]
+    written_by: String,
+}
+
+/// Checks that the store in dir can be read by this version of the crate,
+/// failing with `Error::IncompatibleStore` if a newer one laid it out
+/// Writable stores record the formats they write in their manifest, created on first open
+pub(crate) fn check(dir: &Path, formats: &[Format], read_only: bool) -> Result<()> {
+    let previous = read(dir)?;
+    if let Some(manifest) = &previous {
+        if manifest.layout > LAYOUT_VERSION {
+            return Err(Error::IncompatibleStore {
+                path: dir.to_path_buf(),
+                layout: manifest.layout,
+                supported: LAYOUT_VERSION,
+            }
+            .into());
+        }
+    }
+    let extensions: BTreeSet<String> = formats
+        .iter()
+        .map(|format| format.extension().to_string())
+        .collect();
+    let recorded = previous.is_some_and(|manifest| manifest.formats.is_superset(&extensions));
+    if read_only || recorded {
+        return Ok(());
+    }
+    update(dir, |manifest| manifest.formats.extend(extensions))
+}
+
+/// Whether a key was written with `set_with_format` in another format than its own
+pub(crate) fn is_pinned(dir: &Path, key: &str) -> Result<bool> {
+    Ok(read(dir)?.is_some_and(|manifest| manifest.pinned.contains_key(key)))
+}
+
+/// Records the format a key was written in with `set_with_format`, None when it was written
+/// in its own format again
+pub(crate) fn pin(dir: &Path, key: &str, format: Option<Format>) -> Result<()> {
+    let pinned = format.map(|format| format.extension().to_string());
+    if read(dir)?.map_or(pinned.is_none(), |manifest| {
+        manifest.pinned.get(key) == pinned.as_ref()
+    }) {
+        return Ok(());
+    }
+    update(dir, |manifest| match pinned {
+        Some(extension) => {
+            manifest.pinned.insert(key.to_string(), extension);
+        }
+        None => {
+            manifest.pinned.remove(key);
+        }
+    })
+}
+
+/// Records that values were encrypted by the Encrypted codec
+#[cfg(feature = "encryption")]
+pub(crate) fn mark_encrypted(dir: &Path) -> Result<()> {
+    if read(dir)?.is_some_and(|manifest| manifest.encrypted) {
+        return Ok(());
+    }
+    update(dir, |manifest| manifest.encrypted = true)
+}
+
+/// Whether the entries of the store were moved to hashed names, check `Storage::migrate_names`
+pub(crate) fn has_hashed_names(dir: &Path) -> Result<bool> {
+    Ok(read(dir)?.is_some_and(|manifest| manifest.hashed_names))
+}
+
+/// Records that the entries of the store were moved to hashed names
+pub(crate) fn mark_hashed_names(dir: &Path) -> Result<()> {
+    update(dir, |manifest| manifest.hashed_names = true)
+}
+
+/// Changes the manifest while holding its lock, so that changes of other processes are not lost
+/// The manifest is created if the store has none yet, and only written if change changed it
+fn update<F>(dir: &Path, change: F) -> Result<()>
+where
+    F: FnOnce(&mut Manifest),
+{
+    let _lock = atomic::lock(&dir.join(LOCK_NAME))?;
+    let previous = read(dir)?;
+    let mut manifest = previous.clone().unwrap_or_else(|| Manifest {
+        layout: LAYOUT_VERSION,
+        formats: BTreeSet::new(),
+        encrypted: false,
+        hashed_names: false,
+        pinned: BTreeMap::new(),
+        written_by: String::new(),
+    });
+    change(&mut manifest);
+    if previous.as_ref() == Some(&manifest) {
+        return Ok(());
+    }
+    write(dir, manifest)
+}
+
+fn read(dir: &Path) -> Result<Option<Manifest>> {
+    let path = dir.join(MANIFEST_NAME);
+    match std::fs::read(&path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes).map_err(|e| {
+            anyhow::Error::from(e).context(format!("Could not read {}", path.display()))
+        })?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write(dir: &Path, mut manifest: Manifest) -> Result<()> {
+    manifest.written_by = format!("configstore {}", env!("CARGO_PKG_VERSION"));
+    let mut bytes = serde_json::to_vec_pretty(&manifest)?;
+    bytes.push(b'\n');
+    atomic::write(&dir.join(MANIFEST_NAME), &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Configstore;
+
+    #[test]
+    fn newer_layouts_are_refused() {
+        let config_store = Configstore::temp().unwrap();
+        let path = config_store.dir().join(MANIFEST_NAME);
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(manifest["layout"], LAYOUT_VERSION);
+        assert_eq!(manifest["formats"], serde_json::json!(["json"]));
+
+        std::fs::write(&path, r#"{"layout": 99, "formats": ["json"]}"#).unwrap();
+        let err = config_store.reopen().build().err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::IncompatibleStore {
+                path: config_store.dir().to_path_buf(),
+                layout: 99,
+                supported: LAYOUT_VERSION
+            })
+        );
+        assert!(config_store.reopen().read_only(true).build().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+impl Configstore {
+    /// Sets one field of the object held by key, a key that is not set starts an empty object
+    /// Unlike `set_path`, the field is taken as is, so names with dots like host names work
+    /// The read and the write are atomic like `set_if_unchanged`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.map_insert("tokens", "api.example.com", "t0k3n").unwrap();
+    /// config_store.map_insert("tokens", "git.example.com", "s3cr3t").unwrap();
+    /// let token: Option<String> = config_store.map_get("tokens", "api.example.com").unwrap();
+    /// assert_eq!(token.as_deref(), Some("t0k3n"));
+    /// assert!(config_store.map_remove("tokens", "git.example.com").unwrap());
+    /// assert_eq!(config_store.map_get::<String>("tokens", "git.example.com").unwrap(), None);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the stored value is not an object, otherwise same as set
+    pub fn map_insert<T>(&self, key: &str, field: &str, value: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let value = serde_json::to_value(value)?;
+        self.update(key, |fields: Option<Map<String, Value>>| {
+            let mut fields = fields.unwrap_or_default();
+            fields.insert(field.to_string(), value);
+            Ok((Some(fields), ()))
+        })
+    }
+
+    /// Reads one field of the object held by key, None if the key or the field is not set
+    /// Check the map_insert docs for usage
+    ///
+    /// # Errors
+    /// Fails if the stored value is not an object or the field cannot be decoded as T
+    pub fn map_get<T>(&self, key: &str, field: &str) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut fields: Map<String, Value> = match self.get(key) {
+            Ok(fields) => fields,
+            Err(e) if is_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match fields.remove(field) {
+            Some(value) => Ok(Some(serde_json::from_value(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes one field of the object held by key, and returns whether it was set
+    /// The read and the write are atomic like `map_insert`
+    ///
+    /// # Errors
+    /// Same as map_insert, a key that is not set holds no field and is not an error
+    pub fn map_remove(&self, key: &str, field: &str) -> Result<bool> {
+        self.update(key, |fields: Option<Map<String, Value>>| {
+            let mut fields = match fields {
+                Some(fields) => fields,
+                None => return Ok((None, false)),
+            };
+            let removed = fields.remove(field).is_some();
+            Ok((Some(fields).filter(|_| removed), removed))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use serde_json::json;
+
+    #[test]
+    fn map_fields_are_changed_in_place() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .set("accounts", json!({"work": {"user": "ann"}}))
+            .unwrap();
+        config_store
+            .map_insert("accounts", "home", json!({"user": "bob"}))
+            .unwrap();
+        assert_eq!(
+            config_store.get_raw("accounts").unwrap(),
+            json!({"work": {"user": "ann"}, "home": {"user": "bob"}})
+        );
+        assert!(config_store.map_remove("accounts", "work").unwrap());
+        assert!(!config_store.map_remove("accounts", "work").unwrap());
+        assert!(!config_store.map_remove("unset", "work").unwrap());
+        assert_eq!(config_store.map_get::<u32>("unset", "work").unwrap(), None);
+        config_store.set("list", vec![1]).unwrap();
+        assert!(config_store.map_insert("list", "field", 1).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// How arrays changed on both sides of a merge are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayStrategy {
+    /// Keep the array that was changed last (the default)
+    #[default]
+    Replace,
+    /// Keep the items added on either side and drop the items removed on either side
+    Union,
+}
+
+/// When each leaf of a value was last changed, in milliseconds since the unix epoch
+/// Leaves are addressed by their JSON pointer, like `/ui/theme`
+pub(crate) type Stamps = BTreeMap<String, u64>;
+
+/// A version of a value taking part in a merge
+#[derive(Clone, Copy)]
+pub(crate) struct Side<'a> {
+    pub(crate) value: Option<&'a Value>,
+    pub(crate) stamps: &'a Stamps,
+    /// When the value as a whole was last changed, for leaves without a stamp
+    pub(crate) modified: u64,
+}
+
+impl<'a> Side<'a> {
+    /// When the value at path, or anything below it, was last changed
+    fn changed_at(&self, path: &str) -> u64 {
+        self.stamps
+            .range(path.to_string()..)
+            .take_while(|(leaf, _)| leaf.starts_with(path))
+            .filter(|(leaf, _)| leaf.len() == path.len() || leaf[path.len()..].starts_with('/'))
+            .map(|(_, stamp)| *stamp)
+            .max()
+            .unwrap_or(self.modified)
+    }
+}
+
+/// Deep merges two values, the fields of `remote` winning over the fields of `local`
+/// Objects are merged field by field, arrays are combined according to the strategy
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{merge, ArrayStrategy};
+/// use serde_json::json;
+///
+/// let local = json!({"ui": {"theme": "dark", "size": 12}, "plugins": ["git"]});
+/// let remote = json!({"ui": {"size": 14}, "plugins": ["lsp"]});
+/// assert_eq!(
+///     merge(&local, &remote, ArrayStrategy::Union),
+///     json!({"ui": {"theme": "dark", "size": 14}, "plugins": ["git", "lsp"]})
+/// );
+/// ```
+pub fn merge(local: &Value, remote: &Value, arrays: ArrayStrategy) -> Value {
+    let stamps = Stamps::new();
+    let local = Side {
+        value: Some(local),
+        stamps: &stamps,
+        modified: 0,
+    };
+    let remote_side = Side {
+        value: Some(remote),
+        stamps: &stamps,
+        modified: 1,
+    };
+    three_way("", None, &local, &remote_side, arrays).unwrap_or_else(|| remote.clone())
+}
+
+/// Merges two versions of a value that both derive from base
+/// Changes made on only one side are kept, changes made on both sides keep the newest one
+pub(crate) fn three_way(
+    path: &str,
+    base: Option<&Value>,
+    local: &Side,
+    remote: &Side,
+    arrays: ArrayStrategy,
+) -> Option<Value> {
+    if local.value == remote.value || remote.value == base {
+        return local.value.cloned();
+    }
+    if local.value == base {
+        return remote.value.cloned();
+    }
+    match (local.value, remote.value) {
+        (Some(Value::Object(l)), Some(Value::Object(r))) => {
+            let b = base.and_then(Value::as_object);
+            let mut merged = Map::new();
+            for field in l
+                .keys()
+                .chain(r.keys().filter(|field| !l.contains_key(*field)))
+            {
+                let field_path = format!("{}/{}", path, escape(field));
+                let value = three_way(
+                    &field_path,
+                    b.and_then(|b| b.get(field)),
+                    &Side {
+                        value: l.get(field),
+                        ..*local
+                    },
+                    &Side {
+                        value: r.get(field),
+                        ..*remote
+                    },
+                    arrays,
+                );
+                if let Some(value) = value {
+                    merged.insert(field.clone(), value);
+                }
+            }
+            Some(Value::Object(merged))
+        }
+        (Some(Value::Array(l)), Some(Value::Array(r))) if arrays == ArrayStrategy::Union => {
+            let empty = Vec::new();
+            let b = base.and_then(Value::as_array).unwrap_or(&empty);
+            let mut merged: Vec<Value> = l
+                .iter()
+                .filter(|item| r.contains(item) || !b.contains(item))
+                .cloned()
+                .collect();
+            for item in r {
+                if !merged.contains(item) && !b.contains(item) {
+                    merged.push(item.clone());
+                }
+            }
+            Some(Value::Array(merged))
+        }
+        _ if remote.changed_at(path) > local.changed_at(path) => remote.value.cloned(),
+        _ => local.value.cloned(),
+    }
+}
+
+/// Stamps the leaves of value, leaves equal to the previous version keep their stamp
+/// and the others are stamped with modified
+pub(crate) fn stamp(value: &Value, previous: &[Side], modified: u64) -> Stamps {
+    let mut leaves = Vec::new();
+    collect_leaves(value, String::new(), &mut leaves);
+    leaves
+        .into_iter()
+        .map(|(path, leaf)| {
+            let stamp = previous
+                .iter()
+                .filter(|side| side.value.and_then(|value| value.pointer(&path)) == Some(leaf))
+                .map(|side| side.stamps.get(&path).copied().unwrap_or(side.modified))
+                .max()
+                .unwrap_or(modified);
+            (path, stamp)
+        })
+        .collect()
+}
+
+/// The leaves of value with their JSON pointer, arrays and empty objects are leaves
+pub(crate) fn collect_leaves<'a>(
+    value: &'a Value,
+    path: String,
+    leaves: &mut Vec<(String, &'a Value)>,
+) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (field, value) in map {
+                collect_leaves(value, format!("{}/{}", path, escape(field)), leaves);
+            }
+        }
+        _ => leaves.push((path, value)),
+    }
+}
+
+/// Escapes a field for use in a JSON pointer
+fn escape(field: &str) -> String {
+    field.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn three_way_keeps_changes_of_both_sides() {
+        let base = json!({"theme": "light", "size": 12, "plugins": ["git", "fmt"]});
+        let local = json!({"theme": "dark", "size": 12, "plugins": ["git", "lsp"]});
+        let remote = json!({"theme": "light", "size": 14, "plugins": ["git", "fmt", "ai"]});
+        let local_stamps = stamp(&local, &[], 1);
+        let remote_stamps = stamp(&remote, &[], 2);
+        let side = |value, stamps, modified| Side {
+            value: Some(value),
+            stamps,
+            modified,
+        };
+        let merged = three_way(
+            "",
+            Some(&base),
+            &side(&local, &local_stamps, 1),
+            &side(&remote, &remote_stamps, 2),
+            ArrayStrategy::Union,
+        );
+        assert_eq!(
+            merged,
+            Some(json!({"theme": "dark", "size": 14, "plugins": ["git", "lsp", "ai"]}))
+        );
+    }
+
+    #[test]
+    fn newest_field_wins() {
+        let base = json!({"volume": 1, "muted": false});
+        let local = json!({"volume": 2, "muted": true});
+        let remote = json!({"volume": 3, "muted": false});
+        // The remote changed the volume after the local change, the local muted later
+        let mut local_stamps = Stamps::new();
+        local_stamps.insert("/volume".to_string(), 10);
+        local_stamps.insert("/muted".to_string(), 30);
+        let mut remote_stamps = Stamps::new();
+        remote_stamps.insert("/volume".to_string(), 20);
+        let merged = three_way(
+            "",
+            Some(&base),
+            &Side {
+                value: Some(&local),
+                stamps: &local_stamps,
+                modified: 30,
+            },
+            &Side {
+                value: Some(&remote),
+                stamps: &remote_stamps,
+                modified: 20,
+            },
+            ArrayStrategy::Replace,
+        );
+        assert_eq!(merged, Some(json!({"volume": 3, "muted": true})));
+    }
+}
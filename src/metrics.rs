@@ -0,0 +1,196 @@
+use crate::error::{self, is_not_found, ErrorContext};
+use crate::{Configstore, Operation};
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Receives what a store does, to feed the app's own metrics pipeline
+/// Every method does nothing by default, implement the ones of the counters the app keeps
+/// Called on the thread doing the operation, implementations should be quick
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI, Metrics, Operation};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct Counters {
+///     reads: AtomicU64,
+///     written_bytes: AtomicU64,
+///     errors: AtomicU64,
+/// }
+///
+/// impl Metrics for Counters {
+///     fn read(&self, _key: &str, _bytes: usize) {
+///         self.reads.fetch_add(1, Ordering::Relaxed);
+///     }
+///
+///     fn write(&self, _key: &str, bytes: usize) {
+///         self.written_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+///     }
+///
+///     fn error(&self, _key: &str, _operation: Operation) {
+///         self.errors.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counters = Arc::new(Counters::default());
+/// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+///     .temp(true)
+///     .metrics(counters.clone())
+///     .build()
+///     .unwrap();
+/// config_store.set("volume", 11).unwrap();
+/// config_store.get::<u32>("volume").unwrap();
+/// assert_eq!(counters.reads.load(Ordering::Relaxed), 1);
+/// assert_eq!(counters.written_bytes.load(Ordering::Relaxed), 2);
+/// ```
+pub trait Metrics: Send + Sync {
+    /// A value or blob of bytes was read for key
+    fn read(&self, key: &str, bytes: usize) {
+        let _ = (key, bytes);
+    }
+
+    /// A read of key was served from memory, like a debounced write not on disk yet
+    fn cache_hit(&self, key: &str) {
+        let _ = key;
+    }
+
+    /// A value or blob of bytes was written to key, after compression
+    fn write(&self, key: &str, bytes: usize) {
+        let _ = (key, bytes);
+    }
+
+    /// Key was deleted
+    fn delete(&self, key: &str) {
+        let _ = key;
+    }
+
+    /// An operation on key failed, reading a key that is not set is not a failure
+    fn error(&self, key: &str, operation: Operation) {
+        let _ = (key, operation);
+    }
+}
+
+impl<M> Metrics for std::sync::Arc<M>
+where
+    M: Metrics + ?Sized,
+{
+    fn read(&self, key: &str, bytes: usize) {
+        (**self).read(key, bytes)
+    }
+
+    fn cache_hit(&self, key: &str) {
+        (**self).cache_hit(key)
+    }
+
+    fn write(&self, key: &str, bytes: usize) {
+        (**self).write(key, bytes)
+    }
+
+    fn delete(&self, key: &str) {
+        (**self).delete(key)
+    }
+
+    fn error(&self, key: &str, operation: Operation) {
+        (**self).error(key, operation)
+    }
+}
+
+impl Configstore {
+    /// The metrics the store reports to, with the builder's `metrics`
+    pub(crate) fn metrics(&self) -> Option<&dyn Metrics> {
+        self.inner.metrics.as_deref()
+    }
+
+    /// Attaches the context of an operation to its error like `error::with_context`,
+    /// and reports the errors that no inner operation reported yet
+    pub(crate) fn observe<T, P>(
+        &self,
+        result: Result<T>,
+        operation: Operation,
+        key: &str,
+        path: P,
+    ) -> Result<T>
+    where
+        P: FnOnce() -> PathBuf,
+    {
+        if let (Err(e), Some(metrics)) = (&result, self.metrics()) {
+            if e.downcast_ref::<ErrorContext>().is_none() && !is_not_found(e) {
+                metrics.error(key, operation);
+            }
+        }
+        error::with_context(result, operation, key, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppUI;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Metrics for Recorder {
+        fn read(&self, key: &str, bytes: usize) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("read {} {}", key, bytes));
+        }
+
+        fn cache_hit(&self, key: &str) {
+            self.0.lock().unwrap().push(format!("hit {}", key));
+        }
+
+        fn write(&self, key: &str, bytes: usize) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("write {} {}", key, bytes));
+        }
+
+        fn delete(&self, key: &str) {
+            self.0.lock().unwrap().push(format!("delete {}", key));
+        }
+
+        fn error(&self, key: &str, operation: Operation) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("error {} {:?}", key, operation));
+        }
+    }
+
+    #[test]
+    fn operations_are_reported() {
+        let recorder = Arc::new(Recorder::default());
+        let config_store = Configstore::builder("metrics", AppUI::CommandLine)
+            .temp(true)
+            .debounce(Duration::from_secs(60))
+            .metrics(recorder.clone())
+            .build()
+            .unwrap();
+        config_store.set("name", "ann").unwrap();
+        config_store.get::<String>("name").unwrap();
+        assert!(config_store.get::<u32>("name").is_err());
+        assert!(config_store.get::<String>("missing").is_err());
+        config_store.delete("name").unwrap();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "write name 5",
+                "hit name",
+                "read name 5",
+                "hit name",
+                "read name 5",
+                "error name Decode",
+                "delete name",
+            ]
+        );
+    }
+}
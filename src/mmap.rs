@@ -0,0 +1,212 @@
+use crate::{Configstore, Format};
+use anyhow::Result;
+use std::fmt;
+use std::ops::Deref;
+
+/// Files smaller than this are read, mapping them costs more than copying them
+#[cfg(all(feature = "mmap", unix))]
+pub(crate) const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+/// Bytes of a store entry, returned by `get_bytes`
+/// Either read into memory or, with the `mmap` feature, mapped from their file without copying
+pub struct ValueBytes(Inner);
+
+enum Inner {
+    Owned(Vec<u8>),
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(Mmap),
+}
+
+impl ValueBytes {
+    /// Whether the bytes are mapped from their file instead of read into memory
+    pub fn is_mapped(&self) -> bool {
+        match &self.0 {
+            Inner::Owned(_) => false,
+            #[cfg(all(feature = "mmap", unix))]
+            Inner::Mapped(_) => true,
+        }
+    }
+
+    /// Copies the bytes into a vector, without copying if they were read into memory
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.0 {
+            Inner::Owned(bytes) => bytes,
+            #[cfg(all(feature = "mmap", unix))]
+            Inner::Mapped(map) => map.to_vec(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for ValueBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        ValueBytes(Inner::Owned(bytes))
+    }
+}
+
+impl Deref for ValueBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Inner::Owned(bytes) => bytes,
+            #[cfg(all(feature = "mmap", unix))]
+            Inner::Mapped(map) => map,
+        }
+    }
+}
+
+impl AsRef<[u8]> for ValueBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for ValueBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueBytes")
+            .field("len", &self.len())
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
+
+/// A read-only private mapping of a whole file
+#[cfg(all(feature = "mmap", unix))]
+pub(crate) struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is read-only and owned by the Mmap, like a Box<[u8]>
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Send for Mmap {}
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Sync for Mmap {}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Mmap {
+    /// Maps a file, None if it is smaller than `MMAP_THRESHOLD`
+    pub(crate) fn open(path: &std::path::Path) -> Result<Option<Mmap>> {
+        use std::convert::TryFrom;
+        use std::os::unix::io::AsRawFd;
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < MMAP_THRESHOLD {
+            return Ok(None);
+        }
+        let len = usize::try_from(len)?;
+        // SAFETY: the file is open for reading and len is its non-zero size
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Some(Mmap { ptr, len }))
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the mapping stays valid until dropped. The store replaces files by renaming
+        // new ones over them, which leaves the mapped file untouched
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: ptr and len describe a mapping created by mmap and not unmapped yet
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+impl Configstore {
+    /// Gets the blob of a key without copying it, for large blobs read often like caches loaded
+    /// at startup. With the `mmap` feature, blobs of 64 KiB or more of the files backend
+    /// are mapped from their file instead of read
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set_blob("index", &[7; 100_000]).unwrap();
+    /// let index = config_store.get_bytes("index").unwrap();
+    /// assert_eq!(index.len(), 100_000);
+    /// assert_eq!(index[0], 7);
+    /// ```
+    ///
+    /// # Errors
+    /// Same as get_blob
+    /// Mapped files must not be truncated by other programs while the bytes are in use,
+    /// which would crash the process, the store itself never modifies files in place
+    pub fn get_bytes(&self, key: &str) -> Result<ValueBytes> {
+        #[cfg(all(feature = "mmap", unix))]
+        {
+            let name = self.entry_name(key, crate::blob::BLOB_EXTENSION);
+            if let Some(map) = self.inner.storage.map(&name)? {
+                self.touch(&name);
+                return Ok(ValueBytes(Inner::Mapped(map)));
+            }
+        }
+        self.get_blob(key).map(ValueBytes::from)
+    }
+
+    /// Reads the decompressed value of a key like `read_bytes`,
+    /// mapping large uncompressed files with the `mmap` feature
+    pub(crate) fn read_value(&self, key: &str) -> Result<(Format, ValueBytes)> {
+        #[cfg(all(feature = "mmap", unix))]
+        if let Some(format) = self.stored_format(key)? {
+            let name = self.entry_name(key, format.extension());
+            match self.inner.storage.map(&name)? {
+                Some(map) if !crate::compression::is_compressed(&map) => {
+                    self.touch(&name);
+                    return Ok((format, ValueBytes(Inner::Mapped(map))));
+                }
+                _ => (),
+            }
+        }
+        let (format, bytes) = self.read_bytes(key)?;
+        Ok((format, bytes.into()))
+    }
+}
+
+#[cfg(all(test, feature = "mmap", unix))]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn large_entries_are_mapped() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set_blob("small", b"bytes").unwrap();
+        assert!(!config_store.get_bytes("small").unwrap().is_mapped());
+
+        let large = vec![1u8; 100_000];
+        config_store.set_blob("large", &large).unwrap();
+        let mapped = config_store.get_bytes("large").unwrap();
+        assert!(mapped.is_mapped());
+        // Replacing the blob leaves the mapped file intact
+        config_store.set_blob("large", b"replaced").unwrap();
+        assert_eq!(&*mapped, &large[..]);
+
+        config_store.set("list", vec![2u32; 40_000]).unwrap();
+        let (_, bytes) = config_store.read_value("list").unwrap();
+        assert!(bytes.is_mapped());
+        assert_eq!(config_store.get::<Vec<u32>>("list").unwrap().len(), 40_000);
+    }
+}
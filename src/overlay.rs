@@ -0,0 +1,168 @@
+use crate::error::is_not_found;
+use crate::{ChangeEvent, Configstore};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{Error as IoError, ErrorKind};
+
+/// An in-memory layer on top of a Configstore
+/// Writes stay in memory until `commit` is called, reads see the pending writes first
+/// Dropping the overlay (or calling `discard`) throws away the pending writes
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI};
+///
+/// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+/// config_store.set("volume", 10).unwrap();
+///
+/// let mut preview = config_store.overlay();
+/// preview.set("volume", 11).unwrap();
+/// assert_eq!(preview.get::<i32>("volume").unwrap(), 11);
+/// assert_eq!(config_store.get::<i32>("volume").unwrap(), 10);
+///
+/// preview.commit().unwrap();
+/// assert_eq!(config_store.get::<i32>("volume").unwrap(), 11);
+/// ```
+pub struct Overlay<'a> {
+    store: &'a Configstore,
+    changes: BTreeMap<String, Option<Vec<u8>>>,
+}
+
+impl<'a> Overlay<'a> {
+    pub(crate) fn new(store: &'a Configstore) -> Self {
+        Overlay {
+            store,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// Sets a value in the overlay only, the underlying store is untouched
+    /// # Errors
+    /// Possible errors if the value cannot be encoded
+    pub fn set<T>(&mut self, key: &str, value: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let bytes = self.store.encode(key, &value)?;
+        self.changes.insert(key.to_string(), Some(bytes));
+        Ok(())
+    }
+
+    /// Gets a value, pending writes in the overlay take precedence over the store
+    /// # Errors
+    /// Same as the store's get, also fails if the key was deleted in the overlay
+    pub fn get<T>(&self, key: &str) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        match self.changes.get(key) {
+            Some(Some(bytes)) => self.store.decode(key, self.store.key_format(key), bytes),
+            Some(None) => Err(IoError::new(
+                ErrorKind::NotFound,
+                format!("{} was deleted in the overlay", key),
+            )
+            .into()),
+            None => self.store.get(key),
+        }
+    }
+
+    /// Marks a key as deleted in the overlay only
+    pub fn delete(&mut self, key: &str) {
+        self.changes.insert(key.to_string(), None);
+    }
+
+    /// The changes that would be applied by `commit`, ordered by key
+    pub fn pending(&self) -> Vec<ChangeEvent> {
+        self.changes
+            .iter()
+            .map(|(key, change)| match change {
+                Some(_) => ChangeEvent::Set(key.clone()),
+                None => ChangeEvent::Delete(key.clone()),
+            })
+            .collect()
+    }
+
+    /// Applies every pending change to the underlying store
+    /// Deleting a key that does not exist in the store is not an error
+    /// # Errors
+    /// Could fail if a file cannot be written or removed, changes applied before the failure are kept
+    pub fn commit(self) -> Result<()> {
+        for (key, change) in &self.changes {
+            match change {
+                Some(bytes) => self.store.write_bytes(key, bytes)?,
+                None => match self.store.delete(key) {
+                    Ok(()) => (),
+                    Err(e) if is_not_found(&e) => (),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Throws away every pending change, same as dropping the overlay
+    pub fn discard(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChangeEvent, Codec, Configstore};
+    use serde_json::{json, Value};
+
+    /// Stores values inside an object
+    struct Wrapped;
+
+    impl Codec for Wrapped {
+        fn encode(&self, value: Value) -> Result<Value, String> {
+            Ok(json!({ "value": value }))
+        }
+
+        fn decode(&self, stored: Value) -> Result<Value, String> {
+            Ok(stored["value"].clone())
+        }
+    }
+
+    #[test]
+    fn discard_leaves_store_untouched() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .set("discarded", String::from("before"))
+            .unwrap();
+        let mut overlay = config_store.overlay();
+        overlay.set("discarded", String::from("after")).unwrap();
+        overlay.delete("other");
+        assert_eq!(
+            overlay.pending(),
+            vec![
+                ChangeEvent::Set("discarded".to_string()),
+                ChangeEvent::Delete("other".to_string())
+            ]
+        );
+        overlay.discard();
+        let out: String = config_store.get("discarded").unwrap();
+        assert_eq!(out, "before");
+
+        // Pending values are decoded like stored ones
+        config_store.add_codec("wrapped", Wrapped);
+        let mut overlay = config_store.overlay();
+        overlay.set("wrapped", 6u32).unwrap();
+        assert_eq!(overlay.get::<u32>("wrapped").unwrap(), 6);
+    }
+
+    #[test]
+    fn commit_applies_deletes() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .set("committed", String::from("before"))
+            .unwrap();
+        let mut overlay = config_store.overlay();
+        overlay.delete("committed");
+        overlay.delete("never-set");
+        assert!(overlay.get::<String>("committed").is_err());
+        overlay.commit().unwrap();
+        assert!(config_store.get::<String>("committed").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::location::{self, Scope};
+use crate::Error;
+use anyhow::Result;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// What a store does when its directory belongs to another user than the one running the app,
+/// like a command line tool run with `sudo` that would leave files the user can no longer change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OwnerPolicy {
+    /// Uses the directory anyway (the default)
+    #[default]
+    Ignore,
+    /// Fails to build the store with `Error::ForeignDir`
+    Error,
+    /// When run with sudo, uses the store of the user who ran sudo, even if the environment
+    /// points to root's home, and hands the directories it creates to that user
+    /// Fails like `Error` when the directory belongs to another user without sudo
+    RealUser,
+    /// Uses a store in the current user's runtime directory instead (`XDG_RUNTIME_DIR`, or the
+    /// temp directory), which the system usually clears at logout or reboot
+    RuntimeDir,
+}
+
+/// The user who ran the app through sudo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SudoUser {
+    uid: u32,
+    gid: u32,
+    home: Option<PathBuf>,
+}
+
+impl SudoUser {
+    /// The user from the variables sudo sets, None when not run with sudo or run by root itself
+    #[cfg(unix)]
+    fn current() -> Option<SudoUser> {
+        let var = |name| std::env::var(name).ok();
+        let uid = var("SUDO_UID")?.parse().ok().filter(|uid| *uid != 0)?;
+        let gid = var("SUDO_GID")?.parse().ok()?;
+        let home = var("SUDO_USER").and_then(|name| {
+            let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+            home_in_passwd(&passwd, &name)
+        });
+        Some(SudoUser { uid, gid, home })
+    }
+
+    #[cfg(not(unix))]
+    fn current() -> Option<SudoUser> {
+        None
+    }
+
+    /// Moves a directory of the current home into the user's home
+    fn rebase(&self, dir: PathBuf) -> PathBuf {
+        let (home, user_home) = match (platform_dirs::home_dir(), &self.home) {
+            (Some(home), Some(user_home)) => (home, user_home),
+            _ => return dir,
+        };
+        match dir.strip_prefix(&home) {
+            Ok(relative) => user_home.join(relative),
+            Err(_) => dir,
+        }
+    }
+
+    /// Gives a directory created for the user back to them
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn hand_over(&self, path: &Path) -> Result<()> {
+        #[cfg(unix)]
+        std::os::unix::fs::chown(path, Some(self.uid), Some(self.gid))?;
+        Ok(())
+    }
+}
+
+/// The home directory of a user in the contents of `/etc/passwd`
+fn home_in_passwd(passwd: &str, name: &str) -> Option<PathBuf> {
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 7 && fields[0] == name)
+        .map(|fields| PathBuf::from(fields[5]))
+}
+
+/// Applies the policy to the directory of an app's store, returning the directory to use
+/// and the user to hand the directories created for it to
+pub(crate) fn resolve(
+    policy: OwnerPolicy,
+    app_dir: PathBuf,
+    app_name: &str,
+) -> Result<(PathBuf, Option<SudoUser>)> {
+    if policy == OwnerPolicy::Ignore {
+        return Ok((app_dir, None));
+    }
+    let sudo = SudoUser::current();
+    if let (OwnerPolicy::RealUser, Some(user)) = (policy, &sudo) {
+        return Ok((user.rebase(app_dir), sudo));
+    }
+    if !is_foreign(&app_dir, sudo.as_ref()) {
+        return Ok((app_dir, None));
+    }
+    match policy {
+        OwnerPolicy::RuntimeDir => Ok((runtime_dir().join(app_name), None)),
+        _ => Err(Error::ForeignDir { path: app_dir }.into()),
+    }
+}
+
+/// Whether writing to the directory would leave files its owner cannot change,
+/// or is not possible at all
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn is_foreign(dir: &Path, sudo: Option<&SudoUser>) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let owner = dir
+            .ancestors()
+            .find_map(|ancestor| std::fs::metadata(ancestor).ok())
+            .map(|metadata| metadata.uid());
+        if sudo.is_some_and(|user| owner == Some(user.uid)) {
+            return true;
+        }
+    }
+    if !dir.is_dir() {
+        return false;
+    }
+    let probe = dir.join(format!(".owner-probe-{}", std::process::id()));
+    match File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            false
+        }
+        Err(e) => e.kind() == ErrorKind::PermissionDenied,
+    }
+}
+
+/// The directory holding the runtime stores of every app, private to the current user
+fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join(crate::CONFIG_STORE_NAME),
+        _ => {
+            let user = std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_default();
+            std::env::temp_dir().join(format!("{}-{}", crate::CONFIG_STORE_NAME, user))
+        }
+    }
+}
+
+/// Creates the store directory like `location::create_dir`, handing the directories
+/// it creates to the user who ran sudo
+pub(crate) fn create_dir(
+    dir: &Path,
+    scope: Scope,
+    private: bool,
+    owner: Option<&SudoUser>,
+) -> Result<()> {
+    let existing = dir.ancestors().find(|ancestor| ancestor.exists());
+    location::create_dir(dir, scope, private)?;
+    if let Some(user) = owner {
+        for created in dir
+            .ancestors()
+            .take_while(|ancestor| Some(*ancestor) != existing)
+        {
+            user.hand_over(created)?;
+        }
+    }
+    Ok(())
+}
+
+/// Gives a file created by root the owner of its directory, so that the files an app run with
+/// sudo writes to a user's store stay writable by that user
+#[cfg(unix)]
+pub(crate) fn inherit_dir_owner(file: &File, path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    if file.metadata()?.uid() != 0 {
+        return Ok(());
+    }
+    let dir = match path.parent() {
+        Some(dir) => std::fs::metadata(dir)?,
+        None => return Ok(()),
+    };
+    if dir.uid() != 0 {
+        std::os::unix::fs::fchown(file, Some(dir.uid()), Some(dir.gid()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sudo_users_get_their_own_home() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\
+                      alice:x:1000:1000:Alice,,,:/home/alice:/bin/zsh\n";
+        assert_eq!(
+            home_in_passwd(passwd, "alice"),
+            Some(PathBuf::from("/home/alice"))
+        );
+        assert_eq!(home_in_passwd(passwd, "bob"), None);
+
+        let home = platform_dirs::home_dir().unwrap();
+        let user = SudoUser {
+            uid: 1000,
+            gid: 1000,
+            home: Some(PathBuf::from("/home/alice")),
+        };
+        assert_eq!(
+            user.rebase(home.join(".config").join("configstore-rs")),
+            PathBuf::from("/home/alice/.config/configstore-rs")
+        );
+        assert_eq!(
+            user.rebase(PathBuf::from("/etc/app")),
+            PathBuf::from("/etc/app")
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+impl Configstore {
+    /// Reads a single field of the value stored at key, addressed by a dotted path
+    /// Numeric segments index into arrays, so `"servers.0.host"` reads the host of the first server
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set_path("settings", "ui.theme.color", "blue").unwrap();
+    /// let color: String = config_store.get_path("settings", "ui.theme.color").unwrap();
+    /// assert_eq!(color, "blue");
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the key was never set, if nothing is stored at the path
+    /// or if the field cannot be decoded as T
+    pub fn get_path<T>(&self, key: &str, path: &str) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let document = self.get_raw(key)?;
+        let mut current = &document;
+        for segment in segments(path) {
+            current = match current {
+                Value::Object(map) => map.get(segment),
+                Value::Array(array) => segment.parse::<usize>().ok().and_then(|i| array.get(i)),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!("No value at path {} of key {}", path, key))
+            })?;
+        }
+        self.decode_document(key, current.clone())
+    }
+
+    /// Replaces a single field of the value stored at key, leaving the rest of the document untouched
+    /// Missing objects along the path are created, as is the whole document if the key was never set
+    ///
+    /// # Errors
+    /// Fails if the path goes through a value that is neither an object nor an array,
+    /// if an array index is out of bounds or if the document cannot be written back
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn set_path<T>(&self, key: &str, path: &str, value: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.check_writable()?;
+        let mut document = match self.get_raw(key) {
+            Ok(document) => document,
+            Err(e) if crate::error::is_not_found(&e) => Value::Object(Default::default()),
+            Err(e) => return Err(e),
+        };
+        let mut current = &mut document;
+        for segment in segments(path) {
+            current = match current {
+                Value::Object(map) => map
+                    .entry(segment.to_string())
+                    .or_insert_with(|| Value::Object(Default::default())),
+                Value::Array(array) => segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(move |i| array.get_mut(i))
+                    .ok_or_else(|| {
+                        anyhow::Error::msg(format!(
+                            "Index {} of path {} is out of bounds in key {}",
+                            segment, path, key
+                        ))
+                    })?,
+                _ => {
+                    return Err(anyhow::Error::msg(format!(
+                        "Cannot set path {} of key {}, {} is not an object",
+                        path, key, segment
+                    )))
+                }
+            };
+        }
+        *current = serde_json::to_value(value)?;
+        self.set_raw(key, document)
+    }
+}
+
+/// Splits a dotted path, an empty path addresses the whole document
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('.').filter(|segment| !segment.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use serde_json::json;
+
+    #[test]
+    fn set_path_only_touches_the_field() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .set(
+                "settings",
+                json!({"ui": {"theme": "light", "size": 12}, "servers": [{"host": "a"}]}),
+            )
+            .unwrap();
+        config_store
+            .set_path("settings", "ui.theme", "dark")
+            .unwrap();
+        config_store
+            .set_path("settings", "servers.0.host", "b")
+            .unwrap();
+        assert_eq!(
+            config_store.get::<serde_json::Value>("settings").unwrap(),
+            json!({"ui": {"theme": "dark", "size": 12}, "servers": [{"host": "b"}]})
+        );
+        assert_eq!(
+            config_store.get_path::<u32>("settings", "ui.size").unwrap(),
+            12
+        );
+        assert!(config_store
+            .get_path::<String>("settings", "ui.missing")
+            .is_err());
+        assert!(config_store
+            .set_path("settings", "ui.size.inner", 1)
+            .is_err());
+        assert!(config_store.set_path("settings", "servers.3", 1).is_err());
+    }
+
+    #[test]
+    fn set_path_creates_missing_document() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set_path("created", "a.b", true).unwrap();
+        assert_eq!(
+            config_store.get::<serde_json::Value>("created").unwrap(),
+            json!({"a": {"b": true}})
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{AppUI, Configstore};
+use anyhow::Result;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Directory inside the app's store holding one subdirectory per profile
+/// Hidden so that the profiles are never listed as keys of the default profile
+pub(crate) const PROFILES_DIR: &str = ".profiles";
+
+/// Checks that a profile name can be used as a directory name
+pub(crate) fn validate(profile: &str) -> Result<()> {
+    if profile.is_empty() || profile.starts_with('.') || profile.contains(['/', '\\']) {
+        return Err(anyhow::Error::msg(format!(
+            "Invalid profile name {:?}, it must be non empty, not start with '.' and not contain path separators",
+            profile
+        )));
+    }
+    Ok(())
+}
+
+impl Configstore {
+    /// Creates a store for one of the app's named profiles
+    /// Each profile keeps its own keys, in a subdirectory of the app's store
+    /// Useful for tools juggling multiple accounts or environments
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let work = Configstore::with_profile("myApp", AppUI::CommandLine, "work").unwrap();
+    /// work.set("profile_key", "value".to_string()).unwrap();
+    /// assert!(work.list_profiles().unwrap().contains(&"work".to_string()));
+    ///```
+    ///
+    /// # Errors
+    ///
+    /// Same as new, or if the profile name contains path separators
+    pub fn with_profile(app_name: &str, app_ui: AppUI, profile: &str) -> Result<Self> {
+        Self::builder(app_name, app_ui).profile(profile).build()
+    }
+
+    /// The profile this store was opened with, None for the default profile
+    pub fn profile(&self) -> Option<&str> {
+        self.inner.profile.as_deref()
+    }
+
+    /// Lists the app's named profiles in alphabetical order, the default profile is not included
+    ///
+    /// # Errors
+    /// Could error if the profiles directory cannot be read
+    pub fn list_profiles(&self) -> Result<Vec<String>> {
+        let read_dir = match std::fs::read_dir(self.inner.app_dir.join(PROFILES_DIR)) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut profiles = Vec::new();
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            if !dir_entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Ok(name) = dir_entry.file_name().into_string() {
+                if validate(&name).is_ok() {
+                    profiles.push(name);
+                }
+            }
+        }
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    /// Copies every key and blob of a profile into a new profile
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::with_profile("myApp", AppUI::CommandLine, "staging").unwrap();
+    /// config_store.set("endpoint", "https://staging.example.com".to_string()).unwrap();
+    /// let _ = config_store.delete_profile("staging-copy");
+    /// config_store.copy_profile("staging", "staging-copy").unwrap();
+    /// let copy = Configstore::with_profile("myApp", AppUI::CommandLine, "staging-copy").unwrap();
+    /// assert_eq!(copy.get::<String>("endpoint").unwrap(), "https://staging.example.com");
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if `from` does not exist, if `to` already exists or if a file cannot be copied
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn copy_profile(&self, from: &str, to: &str) -> Result<()> {
+        self.check_writable()?;
+        validate(from)?;
+        validate(to)?;
+        let profiles_dir = self.inner.app_dir.join(PROFILES_DIR);
+        let source = profiles_dir.join(from);
+        let target = profiles_dir.join(to);
+        if !source.is_dir() {
+            return Err(anyhow::Error::msg(format!(
+                "Profile {} does not exist",
+                from
+            )));
+        }
+        if target.exists() {
+            return Err(anyhow::Error::msg(format!("Profile {} already exists", to)));
+        }
+        copy_dir(&source, &target)
+    }
+
+    /// Deletes a named profile and everything stored in it
+    ///
+    /// # Errors
+    /// Fails if the profile does not exist or cannot be removed
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn delete_profile(&self, profile: &str) -> Result<()> {
+        self.check_writable()?;
+        validate(profile)?;
+        Ok(std::fs::remove_dir_all(
+            self.inner.app_dir.join(PROFILES_DIR).join(profile),
+        )?)
+    }
+}
+
+/// Recursively copies a directory, skipping temporary files left by interrupted writes
+fn copy_dir(source: &Path, target: &Path) -> Result<()> {
+    std::fs::create_dir_all(target)?;
+    for dir_entry in std::fs::read_dir(source)? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name();
+        if name.to_string_lossy().ends_with(crate::atomic::TEMP_SUFFIX) {
+            continue;
+        }
+        if dir_entry.file_type()?.is_dir() {
+            copy_dir(&dir_entry.path(), &target.join(&name))?;
+        } else {
+            std::fs::copy(dir_entry.path(), target.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AppUI, Configstore};
+
+    #[test]
+    fn profiles_are_isolated() {
+        let default = Configstore::temp().unwrap();
+        let work = default.reopen().profile("work").build().unwrap();
+        work.set("account", String::from("work@example.com"))
+            .unwrap();
+        assert!(default.get::<String>("account").is_err());
+        assert!(!default.keys().unwrap().contains(&"account".to_string()));
+        assert_eq!(work.profile(), Some("work"));
+
+        work.copy_profile("work", "home").unwrap();
+        assert!(work.copy_profile("work", "home").is_err());
+        let home = default.reopen().profile("home").build().unwrap();
+        assert_eq!(home.get::<String>("account").unwrap(), "work@example.com");
+        assert_eq!(default.list_profiles().unwrap(), vec!["home", "work"]);
+
+        default.delete_profile("home").unwrap();
+        assert_eq!(default.list_profiles().unwrap(), vec!["work"]);
+    }
+
+    #[test]
+    fn invalid_profile_names() {
+        for name in &["", ".hidden", "a/b", "..\\up"] {
+            let builder = Configstore::builder("tests-profiles", AppUI::CommandLine)
+                .temp(true)
+                .profile(name);
+            assert!(builder.build().is_err());
+        }
+    }
+}
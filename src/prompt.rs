@@ -0,0 +1,156 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+impl Configstore {
+    /// Gets the value of a key, asking the user for it on the terminal when it is not set
+    /// and saving the answer, for the first run of command line tools
+    /// The answer is parsed with FromStr, empty or invalid answers are asked again
+    /// Keys declared secret with `declare` are typed without echo on unix terminals
+    /// and saved like with `set_secure`. Requires the `prompt` feature
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use configstore::{Configstore, AppUI, KeyInfo};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.declare("api_token", KeyInfo::new("API token").secret(true));
+    /// let token: String = config_store.get_or_prompt("api_token", "Enter your API token: ").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if stdin is closed before a valid answer, or if the answer cannot be saved
+    /// Same as get otherwise
+    pub fn get_or_prompt<T>(&self, key: &str, message: &str) -> Result<T>
+    where
+        T: DeserializeOwned + Serialize + FromStr,
+        T::Err: Display,
+    {
+        let hidden = self.is_secret(key);
+        self.get_or_ask(key, || {
+            let stdin = std::io::stdin();
+            let mut stderr = std::io::stderr();
+            ask(message, &mut stdin.lock(), &mut stderr, hidden)
+        })
+    }
+
+    fn get_or_ask<T>(&self, key: &str, ask: impl FnOnce() -> Result<T>) -> Result<T>
+    where
+        T: DeserializeOwned + Serialize,
+    {
+        match self.get(key) {
+            Err(e) if is_not_found(&e) => (),
+            value => return value,
+        }
+        let answer = ask()?;
+        if self.is_secret(key) {
+            self.set_secure(key, &answer)?;
+        } else {
+            self.set(key, &answer)?;
+        }
+        Ok(answer)
+    }
+
+    fn is_secret(&self, key: &str) -> bool {
+        self.describe(key).is_some_and(|info| info.secret)
+    }
+}
+
+/// Asks until an answer parses, prompts and errors go to output
+fn ask<T>(message: &str, input: &mut dyn BufRead, output: &mut dyn Write, hidden: bool) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    loop {
+        write!(output, "{}", message)?;
+        output.flush()?;
+        let mut line = String::new();
+        let read = if hidden {
+            let read = without_echo(|| input.read_line(&mut line));
+            // The newline typed by the user was not echoed
+            writeln!(output)?;
+            read
+        } else {
+            input.read_line(&mut line)
+        }?;
+        if read == 0 {
+            return Err(anyhow::Error::msg("No answer, the input was closed"));
+        }
+        let answer = line.trim_end_matches(['\r', '\n']);
+        if answer.is_empty() {
+            continue;
+        }
+        match answer.parse() {
+            Ok(answer) => return Ok(answer),
+            Err(e) => writeln!(output, "Invalid answer: {}", e)?,
+        }
+    }
+}
+
+/// Runs read with the echo of the terminal of stdin turned off, as is when stdin is not a terminal
+#[cfg(unix)]
+fn without_echo<R>(read: impl FnOnce() -> R) -> R {
+    // SAFETY: termios is plain data, filled by tcgetattr before it is used
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    // SAFETY: termios is a valid termios to fill
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+        return read();
+    }
+    let mut silent = termios;
+    silent.c_lflag &= !libc::ECHO;
+    silent.c_lflag |= libc::ECHONL;
+    // SAFETY: silent is the termios of stdin with echo turned off
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &silent) };
+    let result = read();
+    // SAFETY: termios is the termios stdin had before
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) };
+    result
+}
+
+/// Runs read, answers are echoed on platforms other than unix
+#[cfg(not(unix))]
+fn without_echo<R>(read: impl FnOnce() -> R) -> R {
+    read()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyInfo;
+
+    #[test]
+    fn answers_are_asked_again_until_valid_and_saved() {
+        let config_store = Configstore::temp().unwrap();
+        let mut input: &[u8] = b"\nlots\n8080\n";
+        let mut output = Vec::new();
+        let port: u16 = config_store
+            .get_or_ask("port", || ask("Port: ", &mut input, &mut output, false))
+            .unwrap();
+        assert_eq!(port, 8080);
+        assert_eq!(config_store.get::<u16>("port").unwrap(), 8080);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("Port: ").count(), 3);
+        assert!(output.contains("Invalid answer: invalid digit"));
+
+        // Set keys are not asked for
+        let port: u16 = config_store
+            .get_or_ask("port", || panic!("asked for a set key"))
+            .unwrap();
+        assert_eq!(port, 8080);
+
+        config_store.declare("token", KeyInfo::new("API token").secret(true));
+        let token: String = config_store
+            .get_or_ask("token", || Ok("s3cr3t".to_string()))
+            .unwrap();
+        assert_eq!(token, "s3cr3t");
+        let mut closed: &[u8] = b"";
+        assert!(ask::<String>("Token: ", &mut closed, &mut Vec::new(), true).is_err());
+    }
+}
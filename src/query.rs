@@ -0,0 +1,153 @@
+use crate::blob::BLOB_EXTENSION;
+use crate::{Configstore, Format};
+use anyhow::Result;
+
+impl Configstore {
+    /// Lists the keys holding a value whose name starts with prefix, in alphabetical order
+    /// Only the names of the entries are listed, values are not read
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("cache.icons", 1).unwrap();
+    /// config_store.set("cache.thumbnails", 2).unwrap();
+    /// config_store.set("theme", "dark").unwrap();
+    /// assert_eq!(config_store.keys_with_prefix("cache.").unwrap(), vec!["cache.icons", "cache.thumbnails"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store directory cannot be read
+    pub fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = self.keys()?;
+        keys.retain(|key| key.starts_with(prefix));
+        Ok(keys)
+    }
+
+    /// Deletes the value and blob of every key matching a glob pattern, and returns the deleted keys
+    /// Values are deleted as with delete, they go to the trash when the store keeps one
+    /// and their backups are removed, so that get does not fall back to them
+    /// `*` matches any sequence of characters and `?` any single character, like `cache.*`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("cache.icons", 1).unwrap();
+    /// config_store.set_blob("cache.thumbnails", b"...").unwrap();
+    /// config_store.set("theme", "dark").unwrap();
+    /// assert_eq!(config_store.remove_matching("cache.*").unwrap().len(), 2);
+    /// assert_eq!(config_store.keys().unwrap(), vec!["theme"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store directory cannot be read or if an entry cannot be removed,
+    /// entries removed before the failure stay removed
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn remove_matching(&self, pattern: &str) -> Result<Vec<String>> {
+        self.check_writable()?;
+        let mut removed = Vec::new();
+        // Keys whose value was deleted along with its other formats
+        let mut deleted_values = Vec::new();
+        for entry in self.inner.storage.entries()? {
+            if !glob_match(pattern, &entry.key) {
+                continue;
+            }
+            let extension = entry.extension();
+            if extension == BLOB_EXTENSION {
+                self.remove_entry(&entry.key, &entry.name)?;
+            } else if let Some(format) = Format::from_extension(extension) {
+                if deleted_values.contains(&entry.key) {
+                    continue;
+                }
+                // Like delete, so that the value can be restored from the trash
+                self.trash_entry(&entry.key, &entry.name)?;
+                self.remove_backup(&entry.key)?;
+                self.remove_entry(&entry.key, &entry.name)?;
+                self.remove_other_formats(&entry.key, format)?;
+                deleted_values.push(entry.key.clone());
+            } else {
+                continue;
+            }
+            removed.push(entry.key);
+        }
+        removed.sort();
+        removed.dedup();
+        Ok(removed)
+    }
+}
+
+/// Whether text matches a pattern where `*` matches any sequence of characters and `?` one character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last star was, and the text position it currently matches up to
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last star match one more character
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_whole_keys() {
+        assert!(glob_match("cache.*", "cache.icons"));
+        assert!(glob_match("cache.*", "cache."));
+        assert!(!glob_match("cache.*", "mycache.icons"));
+        assert!(glob_match("*.log?", "app.log1"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+
+        let temp = Configstore::temp().unwrap();
+        let config_store = temp
+            .reopen()
+            .trash(std::time::Duration::from_secs(60))
+            .build()
+            .unwrap();
+        config_store.set("cache.icons", 1).unwrap();
+        config_store.set("theme", "dark").unwrap();
+        assert_eq!(
+            config_store.remove_matching("cache.*").unwrap(),
+            vec!["cache.icons"]
+        );
+        assert_eq!(config_store.trashed_keys().unwrap(), vec!["cache.icons"]);
+        config_store.restore_deleted("cache.icons").unwrap();
+        assert_eq!(config_store.get::<u32>("cache.icons").unwrap(), 1);
+
+        // Removed values are not read back from their backup
+        let falling_back = temp.reopen().fallback(|_| ()).build().unwrap();
+        falling_back.set("cache.thumbnails", 2).unwrap();
+        falling_back.remove_matching("cache.*").unwrap();
+        std::fs::write(falling_back.config_path("cache.thumbnails"), "{garbage").unwrap();
+        assert!(falling_back.get::<u32>("cache.thumbnails").is_err());
+    }
+}
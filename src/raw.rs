@@ -0,0 +1,78 @@
+use crate::Configstore;
+use anyhow::Result;
+use serde_json::Value;
+
+impl Configstore {
+    /// Reads the document stored at key without knowing its type
+    /// Values stored in toml or plist are converted to their json equivalent
+    /// Useful for generic tools like a settings editor or a doctor command
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    /// use serde_json::json;
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set_raw("window", json!({"width": 800, "height": 600})).unwrap();
+    /// let window = config_store.get_raw("window").unwrap();
+    /// assert_eq!(window["width"], 800);
+    /// ```
+    ///
+    /// # Errors
+    /// Could produce errors if the key was never set or its file cannot be decoded
+    pub fn get_raw(&self, key: &str) -> Result<Value> {
+        let (format, bytes) = self.read_bytes(key)?;
+        self.decode(key, format, &bytes)
+    }
+
+    /// Replaces the document stored at key, check the get_raw docs for usage
+    ///
+    /// # Errors
+    /// Could produce errors if the value cannot be represented in the store's format
+    /// (toml has no null for example) or if the file cannot be written
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn set_raw(&self, key: &str, value: Value) -> Result<()> {
+        self.check_writable()?;
+        let bytes = self.encode(key, &value)?;
+        self.write_bytes(key, &bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use serde_derive::*;
+    use serde_json::json;
+
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Window {
+        width: u32,
+        height: u32,
+    }
+
+    #[test]
+    fn raw_documents_match_typed_values() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .set(
+                "window",
+                Window {
+                    width: 800,
+                    height: 600,
+                },
+            )
+            .unwrap();
+        let mut raw = config_store.get_raw("window").unwrap();
+        assert_eq!(raw, json!({"width": 800, "height": 600}));
+        raw["width"] = json!(1024);
+        config_store.set_raw("window", raw).unwrap();
+        assert_eq!(
+            config_store.get::<Window>("window").unwrap(),
+            Window {
+                width: 1024,
+                height: 600
+            }
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use std::fmt::Write;
+
+/// Replaces the values of secret keys in dumps and support bundles
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// Which values `debug_dump` hides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Redaction {
+    /// Shows every value as is
+    None,
+    /// Hides the values of the keys declared secret with `declare` (the default)
+    #[default]
+    MaskSecrets,
+    /// Hides every value, only listing the keys
+    MaskAll,
+}
+
+impl Configstore {
+    /// Lists every key with its value as json, one `key = value` line per key in alphabetical order,
+    /// to implement commands like `myapp config list` without leaking tokens into terminal history or logs
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, KeyInfo, Redaction};
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.declare("token", KeyInfo::new("API token").secret(true));
+    /// config_store.set("token", "s3cr3t").unwrap();
+    /// config_store.set("theme", "dark").unwrap();
+    ///
+    /// let dump = config_store.debug_dump(Redaction::MaskSecrets).unwrap();
+    /// print!("{}", dump);
+    /// assert_eq!(dump, "theme = \"dark\"\ntoken = <redacted>\n");
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store cannot be listed
+    /// Values that cannot be read are shown with the reason instead of failing the dump
+    pub fn debug_dump(&self, redaction: Redaction) -> Result<String> {
+        let mut dump = String::new();
+        for key in self.keys()? {
+            let value = match self.get_raw(&key) {
+                Ok(_) if self.redacts(&key, redaction) => REDACTED.to_string(),
+                Ok(value) => value.to_string(),
+                Err(e) if is_not_found(&e) => continue,
+                Err(e) => format!("<unreadable: {:#}>", e),
+            };
+            writeln!(dump, "{} = {}", key, value)?;
+        }
+        Ok(dump)
+    }
+
+    /// Whether the value of key must be hidden with the redaction
+    pub(crate) fn redacts(&self, key: &str, redaction: Redaction) -> bool {
+        match redaction {
+            Redaction::None => false,
+            Redaction::MaskSecrets => self.describe(key).is_some_and(|info| info.secret),
+            Redaction::MaskAll => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, KeyInfo, Redaction};
+
+    #[test]
+    fn dumps_mask_secrets() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.declare("password", KeyInfo::new("Password").secret(true));
+        config_store.set("password", "hunter2").unwrap();
+        config_store.set("size", 3).unwrap();
+        assert_eq!(
+            config_store.debug_dump(Redaction::None).unwrap(),
+            "password = \"hunter2\"\nsize = 3\n"
+        );
+        assert_eq!(
+            config_store.debug_dump(Redaction::default()).unwrap(),
+            "password = <redacted>\nsize = 3\n"
+        );
+        assert_eq!(
+            config_store.debug_dump(Redaction::MaskAll).unwrap(),
+            "password = <redacted>\nsize = <redacted>\n"
+        );
+    }
+}
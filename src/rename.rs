@@ -0,0 +1,146 @@
+use crate::blob::BLOB_EXTENSION;
+use crate::error::{is_not_found, not_found};
+use crate::{compression, ChangeEvent, Configstore, Format};
+use anyhow::Result;
+
+impl Configstore {
+    /// Moves the value and blob of a key to another key, replacing whatever the other key held
+    /// Each entry is renamed in one step, so readers see either the old or the new key complete
+    /// Useful for settings migrations
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("colour", "blue").unwrap();
+    /// config_store.rename("colour", "color").unwrap();
+    /// assert_eq!(config_store.get::<String>("color").unwrap(), "blue");
+    /// assert!(config_store.get::<String>("colour").is_err());
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with a not found error if the key holds neither a value nor a blob,
+    /// or if the new key rejects the value with `Error::Invalid`
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn rename(&self, old_key: &str, new_key: &str) -> Result<()> {
+        self.transfer(old_key, new_key, false)
+    }
+
+    /// Copies the value and blob of a key to another key, replacing whatever the other key held
+    /// Each entry of the copy is written atomically, like with set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("layout", vec!["editor", "terminal"]).unwrap();
+    /// config_store.copy("layout", "layout_backup").unwrap();
+    /// assert_eq!(config_store.get::<Vec<String>>("layout_backup").unwrap().len(), 2);
+    /// ```
+    ///
+    /// # Errors
+    /// Same as rename, also fails with `Error::QuotaExceeded` if the copy does not fit the quota
+    pub fn copy(&self, src: &str, dst: &str) -> Result<()> {
+        self.transfer(src, dst, true)
+    }
+
+    fn transfer(&self, from: &str, to: &str, keep: bool) -> Result<()> {
+        self.check_writable()?;
+        let format = self.stored_format(from)?;
+        let blob = self.entry_name(from, BLOB_EXTENSION);
+        let has_blob = self.inner.storage.exists(&blob)?;
+        if format.is_none() && !has_blob {
+            return Err(not_found(from));
+        }
+        if from == to {
+            return Ok(());
+        }
+        if let Some(format) = format {
+            let name = self.entry_name(from, format.extension());
+            let bytes = self.inner.storage.read(&name)?;
+            self.validate(to, format, &compression::decompress(bytes.clone())?)?;
+            self.transfer_entry(
+                &name,
+                to,
+                &self.entry_name(to, format.extension()),
+                &bytes,
+                keep,
+            )?;
+            self.remove_other_formats(to, format)?;
+        } else {
+            for format in Format::all() {
+                self.remove_if_exists(&self.entry_name(to, format.extension()))?;
+            }
+        }
+        let target = self.entry_name(to, BLOB_EXTENSION);
+        if has_blob {
+            let bytes = if keep {
+                self.inner.storage.read(&blob)?
+            } else {
+                Vec::new()
+            };
+            self.transfer_entry(&blob, to, &target, &bytes, keep)?;
+        } else {
+            self.remove_if_exists(&target)?;
+        }
+        if !keep {
+            self.changed(ChangeEvent::Delete(from.to_string()), &blob)?;
+        }
+        Ok(())
+    }
+
+    /// Copies bytes to the entry target of key to, or renames the entry name to it
+    fn transfer_entry(
+        &self,
+        name: &str,
+        to: &str,
+        target: &str,
+        bytes: &[u8],
+        keep: bool,
+    ) -> Result<()> {
+        if keep {
+            return self.write_entry(to, target, bytes, false);
+        }
+        self.inner.storage.rename(name, target)?;
+        self.changed(ChangeEvent::Set(to.to_string()), target)
+    }
+
+    fn remove_if_exists(&self, name: &str) -> Result<()> {
+        match self.inner.storage.remove(name) {
+            Err(e) if !is_not_found(&e) => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn rename_replaces_value_and_blob() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("old", "value").unwrap();
+        config_store.set_blob("old", b"bytes").unwrap();
+        config_store.set("new", 1).unwrap();
+        config_store.set_blob("stale", b"stale").unwrap();
+        config_store.set("stale", 2).unwrap();
+
+        config_store.rename("old", "new").unwrap();
+        assert_eq!(config_store.get::<String>("new").unwrap(), "value");
+        assert_eq!(config_store.get_blob("new").unwrap(), b"bytes");
+        assert!(config_store.get_blob("old").is_err());
+        assert_eq!(config_store.keys().unwrap(), vec!["new", "stale"]);
+
+        config_store.delete("new").unwrap();
+        config_store.copy("new", "stale").unwrap();
+        assert!(config_store.get::<u32>("stale").is_err());
+        assert_eq!(config_store.get_blob("stale").unwrap(), b"bytes");
+        assert_eq!(config_store.get_blob("new").unwrap(), b"bytes");
+        assert!(config_store.rename("missing", "new").is_err());
+    }
+}
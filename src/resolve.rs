@@ -0,0 +1,567 @@
+use crate::error::{is_not_found, not_found};
+use crate::merge::{self, ArrayStrategy};
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Where a layer of a Resolver takes its values from
+enum Layer<'a> {
+    Store(&'a Configstore),
+    Env(String),
+    Value(String, Value),
+}
+
+/// Reads keys through several layers of settings, like command line overrides on top of
+/// environment variables on top of project, user and machine stores
+/// Objects are merged field by field, each field taking the value of the first layer that sets it
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, Resolver};
+/// use serde_json::json;
+///
+/// let user = Configstore::temp().unwrap();
+/// let machine = Configstore::temp().unwrap();
+/// user.set_raw("ui", json!({"theme": "dark"})).unwrap();
+/// machine.set_raw("ui", json!({"theme": "light", "size": 12})).unwrap();
+///
+/// let resolver = Resolver::new()
+///     .value("cli", "ui", json!({"size": 14}))
+///     .store("user", &user)
+///     .store("machine", &machine);
+/// let resolved = resolver.resolve_with_provenance("ui").unwrap();
+/// assert_eq!(resolved.value, json!({"theme": "dark", "size": 14}));
+/// for (field, origin) in &resolved.origins {
+///     println!("{} = {} from {}", field, resolved.value.pointer(field).unwrap(), origin);
+/// }
+/// ```
+#[derive(Default)]
+pub struct Resolver<'a> {
+    layers: Vec<(String, Layer<'a>)>,
+}
+
+/// A value merged from the layers of a Resolver, with where each of its fields came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved {
+    /// The merged value
+    pub value: Value,
+    /// The origin of each leaf of the value, by JSON pointer like `/ui/theme`
+    /// Values that are not objects have a single leaf, the empty pointer
+    pub origins: BTreeMap<String, Origin>,
+}
+
+/// The layer a field of a resolved value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The name the layer was added with, like `user`
+    pub layer: String,
+    /// The file or environment variable holding the field
+    pub source: Option<Source>,
+}
+
+/// Where a layer keeps a field
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Source {
+    /// The file of a store, the database file for the SQLite backend
+    File(PathBuf),
+    /// An environment variable
+    Env(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(Source::File(path)) => write!(f, "{} ({})", self.layer, path.display()),
+            Some(Source::Env(variable)) => write!(f, "{} (${})", self.layer, variable),
+            None => f.write_str(&self.layer),
+        }
+    }
+}
+
+impl<'a> Resolver<'a> {
+    /// Creates a resolver without layers, layers added first take precedence
+    pub fn new() -> Self {
+        Resolver::default()
+    }
+
+    /// Adds a layer reading the keys of a store
+    pub fn store(mut self, layer: &str, store: &'a Configstore) -> Self {
+        self.layers.push((layer.to_string(), Layer::Store(store)));
+        self
+    }
+
+    /// Adds a layer reading environment variables named after the prefix and the key,
+    /// `MYAPP_UI` sets the key `ui` and `MYAPP_UI__THEME` its `theme` field
+    /// Variables holding valid json are parsed, others are read as strings
+    pub fn env(mut self, layer: &str, prefix: &str) -> Self {
+        self.layers
+            .push((layer.to_string(), Layer::Env(prefix.to_string())));
+        self
+    }
+
+    /// Adds a layer holding a single value for key, like an override passed on the command line
+    pub fn value(mut self, layer: &str, key: &str, value: Value) -> Self {
+        self.layers
+            .push((layer.to_string(), Layer::Value(key.to_string(), value)));
+        self
+    }
+
+    /// Adds a layer holding the arguments parsed from the command line as the value of key,
+    /// like a struct deriving both `clap::Parser` and Serialize, for `flag > env > config`
+    /// precedence. Fields that are None, like options that were not passed, are left to the
+    /// next layers, flags should be `Option<bool>` to fall back to them
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, Resolver};
+    /// use serde_derive::{Deserialize, Serialize};
+    ///
+    /// // Also derives clap::Parser in a real app
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Args {
+    ///     host: Option<String>,
+    ///     port: Option<u16>,
+    /// }
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("server", serde_json::json!({"host": "example.com", "port": 80})).unwrap();
+    ///
+    /// let args = Args { host: None, port: Some(8080) };
+    /// let server: Args = Resolver::new()
+    ///     .args("cli", "server", &args)
+    ///     .unwrap()
+    ///     .env("env", "MYAPP_")
+    ///     .store("config", &config_store)
+    ///     .resolve("server")
+    ///     .unwrap();
+    /// assert_eq!(server.host.as_deref(), Some("example.com"));
+    /// assert_eq!(server.port, Some(8080));
+    ///
+    /// // When the user passed --save
+    /// config_store.save_args("server", &args).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the arguments cannot be serialized
+    pub fn args<A>(self, layer: &str, key: &str, args: &A) -> Result<Self>
+    where
+        A: Serialize,
+    {
+        let value = without_nulls(serde_json::to_value(args)?);
+        Ok(self.value(layer, key, value))
+    }
+
+    /// Merges the value of key in every layer, check the Resolver docs for usage
+    ///
+    /// # Errors
+    /// Fails with a not found error if no layer sets the key, or if a store cannot be read
+    pub fn resolve<T>(&self, key: &str) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        Ok(serde_json::from_value(
+            self.resolve_with_provenance(key)?.value,
+        )?)
+    }
+
+    /// Merges the value of key in every layer, and reports which layer each field came from
+    /// Useful to implement commands like `myapp config show --origin`
+    ///
+    /// # Errors
+    /// Fails with a not found error if no layer sets the key, or if a store cannot be read
+    pub fn resolve_with_provenance(&self, key: &str) -> Result<Resolved> {
+        let mut found = Vec::new();
+        for (name, layer) in &self.layers {
+            if let Some(value) = read(layer, key)? {
+                found.push((name, value));
+            }
+        }
+        let merged = found
+            .iter()
+            .rev()
+            .map(|(_, (value, _))| value)
+            .fold(None, |merged: Option<Value>, value| {
+                Some(match merged {
+                    Some(merged) => merge::merge(&merged, value, ArrayStrategy::Replace),
+                    None => value.clone(),
+                })
+            })
+            .ok_or_else(|| not_found(key))?;
+
+        let mut leaves = Vec::new();
+        merge::collect_leaves(&merged, String::new(), &mut leaves);
+        let origins = leaves
+            .into_iter()
+            .filter_map(|(path, leaf)| {
+                let (name, (_, locate)) = found
+                    .iter()
+                    .find(|(_, (value, _))| value.pointer(&path) == Some(leaf))?;
+                let origin = Origin {
+                    layer: name.to_string(),
+                    source: locate(&path),
+                };
+                Some((path, origin))
+            })
+            .collect();
+        Ok(Resolved {
+            value: merged,
+            origins,
+        })
+    }
+}
+
+impl Configstore {
+    /// Saves the arguments parsed from the command line over the value of key, for options
+    /// like `--save`. Fields that are None are left as stored, check `Resolver::args`
+    ///
+    /// # Errors
+    /// Fails if the arguments cannot be serialized, same as set otherwise
+    pub fn save_args<A>(&self, key: &str, args: &A) -> Result<()>
+    where
+        A: Serialize,
+    {
+        let args = without_nulls(serde_json::to_value(args)?);
+        let value = match self.get_raw(key) {
+            Ok(stored) => merge::merge(&stored, &args, ArrayStrategy::Replace),
+            Err(e) if is_not_found(&e) => args,
+            Err(e) => return Err(e),
+        };
+        self.set_raw(key, value)
+    }
+}
+
+#[cfg(feature = "clap")]
+impl<'a> Resolver<'a> {
+    /// Adds a layer holding the options parsed by clap as the fields of the value of key,
+    /// `--dry-run` setting the field `dry_run`. Options that were not passed are left to the next
+    /// layers, defaults of clap included, so that a stored value wins over them
+    /// Values holding valid json are parsed like environment variables, options passed
+    /// several values or several times are arrays, `save` is left out, check `resolve_matches`
+    pub fn matches(self, layer: &str, key: &str, matches: &clap::ArgMatches) -> Self {
+        self.value(layer, key, passed(matches))
+    }
+}
+
+#[cfg(feature = "clap")]
+impl Configstore {
+    /// Reads key with the options parsed by clap over the environment variables named after
+    /// env_prefix, like `MYAPP_`, over the store, and saves the options over the stored value
+    /// when the user passed the `save` flag, like `--save`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use clap::{Arg, ArgAction, Command};
+    /// use configstore::Configstore;
+    /// use serde_derive::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Server {
+    ///     host: String,
+    ///     port: u16,
+    /// }
+    ///
+    /// let command = Command::new("myapp")
+    ///     .arg(Arg::new("host").long("host"))
+    ///     .arg(Arg::new("port").long("port"))
+    ///     .arg(Arg::new("save").long("save").action(ArgAction::SetTrue));
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("server", serde_json::json!({"host": "example.com", "port": 80})).unwrap();
+    ///
+    /// let matches = command.get_matches_from(["myapp", "--port", "8080", "--save"]);
+    /// let server: Server = config_store.resolve_matches("server", &matches, "MYAPP_").unwrap();
+    /// assert_eq!((server.host.as_str(), server.port), ("example.com", 8080));
+    /// assert_eq!(config_store.get_raw("server").unwrap()["port"], 8080);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with a not found error if neither the options, the environment nor the store set key,
+    /// if the merged value does not deserialize to T, same as set when saving
+    pub fn resolve_matches<T>(
+        &self,
+        key: &str,
+        matches: &clap::ArgMatches,
+        env_prefix: &str,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let save = matches!(matches.try_get_one::<bool>("save"), Ok(Some(true)));
+        if save {
+            self.save_args(key, &passed(matches))?;
+        }
+        Resolver::new()
+            .matches("cli", key, matches)
+            .env("env", env_prefix)
+            .store("config", self)
+            .resolve(key)
+    }
+}
+
+/// The options passed on the command line or through clap's env, as an object
+#[cfg(feature = "clap")]
+fn passed(matches: &clap::ArgMatches) -> Value {
+    use clap::parser::ValueSource;
+    let mut fields = Map::new();
+    for id in matches.ids() {
+        let id = id.as_str();
+        let source = matches.value_source(id);
+        if id == "save" || source.is_none() || source == Some(ValueSource::DefaultValue) {
+            continue;
+        }
+        let mut values: Vec<Value> = match matches.get_raw(id) {
+            Some(raw) => raw
+                .map(|raw| {
+                    let text = raw.to_string_lossy().into_owned();
+                    serde_json::from_str(&text).unwrap_or(Value::String(text))
+                })
+                .collect(),
+            None => continue,
+        };
+        let value = match values.len() {
+            1 => values.remove(0),
+            _ => Value::Array(values),
+        };
+        fields.insert(id.replace('-', "_"), value);
+    }
+    Value::Object(fields)
+}
+
+/// Removes the null fields of objects, the options that were not passed
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(_, field)| !field.is_null())
+                .map(|(name, field)| (name, without_nulls(field)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// Finds where a leaf of a layer's value is kept
+type Locate = Box<dyn Fn(&str) -> Option<Source>>;
+
+/// Reads key from a layer, None if the layer does not set it
+fn read(layer: &Layer, key: &str) -> Result<Option<(Value, Locate)>> {
+    match layer {
+        Layer::Store(store) => match store.get_raw(key) {
+            Ok(value) => {
+                let path = store.config_path(key);
+                Ok(Some((
+                    value,
+                    Box::new(move |_: &str| Some(Source::File(path.clone()))),
+                )))
+            }
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        },
+        Layer::Env(prefix) => Ok(read_env(prefix, key)),
+        Layer::Value(value_key, value) if value_key == key => {
+            Ok(Some((value.clone(), Box::new(|_: &str| None))))
+        }
+        Layer::Value(..) => Ok(None),
+    }
+}
+
+fn read_env(prefix: &str, key: &str) -> Option<(Value, Locate)> {
+    let name = env_name(prefix, key);
+    let mut value = None;
+    let mut variables = BTreeMap::new();
+    // vars panics on variables that are not unicode, which cannot configure a key anyway
+    let mut vars: Vec<_> = std::env::vars_os()
+        .filter_map(|(variable, text)| {
+            Some((variable.into_string().ok()?, text.into_string().ok()?))
+        })
+        .filter(|(variable, _)| variable.starts_with(&name))
+        .collect();
+    // Variables setting a whole value go first so that the ones setting fields merge into it
+    vars.sort_by_key(|(variable, _)| variable.len());
+    for (variable, text) in vars {
+        let parsed = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        if variable == name {
+            value = Some(parsed);
+            variables.insert(String::new(), variable);
+            continue;
+        }
+        let fields = match variable[name.len()..].strip_prefix("__") {
+            Some(fields) if !fields.is_empty() => fields.to_lowercase(),
+            _ => continue,
+        };
+        let mut nested = parsed;
+        let mut pointer = String::new();
+        for field in fields.split("__").collect::<Vec<_>>().into_iter().rev() {
+            let mut object = Map::new();
+            object.insert(field.to_string(), nested);
+            nested = Value::Object(object);
+            pointer = format!("/{}{}", field, pointer);
+        }
+        value = Some(match value {
+            Some(value) => merge::merge(&value, &nested, ArrayStrategy::Replace),
+            None => nested,
+        });
+        variables.insert(pointer, variable);
+    }
+    let locate = move |path: &str| {
+        variables
+            .iter()
+            .filter(|(pointer, _)| {
+                path.starts_with(pointer.as_str())
+                    && (path.len() == pointer.len() || path[pointer.len()..].starts_with('/'))
+            })
+            .max_by_key(|(pointer, _)| pointer.len())
+            .map(|(_, variable)| Source::Env(variable.clone()))
+    };
+    Some((value?, Box::new(locate)))
+}
+
+/// The environment variable of a key, `ui-theme` with the `MYAPP_` prefix is `MYAPP_UI_THEME`
+fn env_name(prefix: &str, key: &str) -> String {
+    let key: String = key
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+    format!("{}{}", prefix, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fields_come_from_the_first_layer_setting_them() {
+        let user = Configstore::temp().unwrap();
+        let machine = Configstore::temp().unwrap();
+        user.set_raw("editor", json!({"ui": {"theme": "dark"}, "tabs": 2}))
+            .unwrap();
+        machine
+            .set_raw("editor", json!({"ui": {"theme": "light", "font": "mono"}}))
+            .unwrap();
+        std::env::set_var("CONFIGSTORE_RESOLVE_TEST_EDITOR__UI__FONT", "\"serif\"");
+        // Variables that are not unicode are skipped
+        #[cfg(unix)]
+        std::env::set_var(
+            "CONFIGSTORE_RESOLVE_TEST_EDITOR__UI__THEME",
+            <std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(b"\xff"),
+        );
+
+        let resolved = Resolver::new()
+            .value("cli", "editor", json!({"tabs": 4}))
+            .value("cli", "other", json!(1))
+            .env("env", "CONFIGSTORE_RESOLVE_TEST_")
+            .store("user", &user)
+            .store("machine", &machine)
+            .resolve_with_provenance("editor")
+            .unwrap();
+        assert_eq!(
+            resolved.value,
+            json!({"ui": {"theme": "dark", "font": "serif"}, "tabs": 4})
+        );
+        let origins: Vec<_> = resolved
+            .origins
+            .iter()
+            .map(|(path, origin)| (path.as_str(), origin.to_string()))
+            .collect();
+        assert_eq!(
+            origins,
+            vec![
+                ("/tabs", "cli".to_string()),
+                (
+                    "/ui/font",
+                    "env ($CONFIGSTORE_RESOLVE_TEST_EDITOR__UI__FONT)".to_string()
+                ),
+                (
+                    "/ui/theme",
+                    format!("user ({})", user.config_path("editor").display())
+                ),
+            ]
+        );
+
+        let args = Resolver::new()
+            .args(
+                "cli",
+                "editor",
+                &json!({"tabs": null, "ui": {"font": "sans"}}),
+            )
+            .unwrap()
+            .store("user", &user)
+            .resolve::<Value>("editor")
+            .unwrap();
+        assert_eq!(
+            args,
+            json!({"ui": {"theme": "dark", "font": "sans"}, "tabs": 2})
+        );
+        user.save_args("editor", &json!({"tabs": 8, "ui": null}))
+            .unwrap();
+        assert_eq!(
+            user.get_raw("editor").unwrap(),
+            json!({"ui": {"theme": "dark"}, "tabs": 8})
+        );
+        assert!(is_not_found(
+            &Resolver::new()
+                .store("user", &user)
+                .resolve::<Value>("missing")
+                .unwrap_err()
+        ));
+    }
+
+    #[cfg(feature = "clap")]
+    #[test]
+    fn clap_options_override_the_store() {
+        use clap::{Arg, ArgAction, Command};
+
+        let command = Command::new("myapp")
+            .arg(Arg::new("tabs").long("tabs").default_value("4"))
+            .arg(
+                Arg::new("word-wrap")
+                    .long("word-wrap")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("plugin").long("plugin").action(ArgAction::Append))
+            .arg(Arg::new("save").long("save").action(ArgAction::SetTrue));
+        let user = Configstore::temp().unwrap();
+        user.set_raw("editor", json!({"tabs": 2, "theme": "dark"}))
+            .unwrap();
+
+        let matches = command.clone().get_matches_from([
+            "myapp",
+            "--word-wrap",
+            "--plugin",
+            "git",
+            "--plugin",
+            "lsp",
+        ]);
+        let resolved: Value = user
+            .resolve_matches("editor", &matches, "CONFIGSTORE_CLAP_TEST_")
+            .unwrap();
+        assert_eq!(
+            resolved,
+            json!({"tabs": 2, "theme": "dark", "word_wrap": true, "plugin": ["git", "lsp"]})
+        );
+        assert_eq!(
+            user.get_raw("editor").unwrap(),
+            json!({"tabs": 2, "theme": "dark"})
+        );
+
+        let matches = command.get_matches_from(["myapp", "--tabs", "8", "--save"]);
+        let resolved: Value = user
+            .resolve_matches("editor", &matches, "CONFIGSTORE_CLAP_TEST_")
+            .unwrap();
+        assert_eq!(resolved, json!({"tabs": 8, "theme": "dark"}));
+        assert_eq!(resolved, user.get_raw("editor").unwrap());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::storage::entry_key;
+use crate::{atomic, Configstore};
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Directory of the store holding a directory per restore point
+/// Hidden so that restore points are never listed as keys
+const RESTORE_POINTS_DIR: &str = ".restore_points";
+
+/// Checks that a label can be used as a directory name
+fn validate(label: &str) -> Result<()> {
+    if label.is_empty() || label.starts_with('.') || label.contains(['/', '\\']) {
+        return Err(anyhow::Error::msg(format!(
+            "Invalid restore point label {:?}, it must be non empty, not start with '.' and not contain path separators",
+            label
+        )));
+    }
+    Ok(())
+}
+
+impl Configstore {
+    /// Copies every value and blob of the store aside under a label, to roll the whole
+    /// configuration back with `restore_from_point`, like before an upgrade migrates it
+    /// Replaces the restore point of the same label. Restore points are kept in the store's
+    /// directory until removed with `remove_restore_point`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("theme", "light").unwrap();
+    /// config_store.create_restore_point("before-2.0").unwrap();
+    ///
+    /// // The upgrade migrates the settings
+    /// config_store.set("theme", "solarized").unwrap();
+    /// config_store.set("layout", "compact").unwrap();
+    ///
+    /// // Support asks the user to reset to before the upgrade
+    /// config_store.restore_from_point("before-2.0").unwrap();
+    /// assert_eq!(config_store.get::<String>("theme").unwrap(), "light");
+    /// assert!(config_store.get::<String>("layout").is_err());
+    /// assert_eq!(config_store.restore_points().unwrap(), vec!["before-2.0"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the label is empty, starts with '.' or contains path separators, if the store
+    /// does not keep files, like with the localStorage backend, or if an entry cannot be copied
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn create_restore_point(&self, label: &str) -> Result<()> {
+        self.check_writable()?;
+        let dir = self.restore_point_dir(label)?;
+        let temp_dir = dir.with_file_name(format!(".{}{}", label, atomic::TEMP_SUFFIX));
+        remove_dir(&temp_dir)?;
+        std::fs::create_dir_all(&temp_dir)?;
+        for entry in self.inner.storage.entries()? {
+            let bytes = match self.inner.storage.read(&entry.name) {
+                Ok(bytes) => bytes,
+                // Deleted since it was listed
+                Err(e) if crate::error::is_not_found(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            // Values can be secrets, restore points are readable by the current user only
+            atomic::write_private(&temp_dir.join(&entry.name), &bytes)?;
+        }
+        remove_dir(&dir)?;
+        std::fs::rename(&temp_dir, &dir)?;
+        Ok(())
+    }
+
+    /// Puts every value and blob of the store back to what it was when the restore point
+    /// was created, deleting the ones created since. Listeners are told about every key
+    /// that is written or deleted, the restore point is kept
+    /// Check the create_restore_point docs for usage
+    ///
+    /// # Errors
+    /// Fails if there is no restore point with this label, or if an entry cannot be written
+    /// or deleted, entries restored before the failure stay restored
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn restore_from_point(&self, label: &str) -> Result<()> {
+        self.check_writable()?;
+        let dir = self.restore_point_dir(label)?;
+        let read_dir = std::fs::read_dir(&dir).map_err(|e| {
+            anyhow::Error::from(e).context(format!("No restore point labeled {}", label))
+        })?;
+        let mut restored = BTreeSet::new();
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            let name = match dir_entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let key = match entry_key(&name) {
+                Some(key) => key.to_string(),
+                None => continue,
+            };
+            let bytes = std::fs::read(dir_entry.path())?;
+            let unchanged = match self.inner.storage.read(&name) {
+                Ok(current) => current == bytes,
+                Err(e) if crate::error::is_not_found(&e) => false,
+                Err(e) => return Err(e),
+            };
+            if !unchanged {
+                self.write_entry(&key, &name, &bytes, false)?;
+            }
+            restored.insert(name);
+        }
+        for entry in self.inner.storage.entries()? {
+            if !restored.contains(&entry.name) {
+                self.remove_entry(&entry.key, &entry.name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The labels of the restore points of the store, in alphabetical order
+    ///
+    /// # Errors
+    /// Could error if the restore points directory cannot be read
+    pub fn restore_points(&self) -> Result<Vec<String>> {
+        let read_dir = match std::fs::read_dir(self.dir().join(RESTORE_POINTS_DIR)) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut labels = Vec::new();
+        for dir_entry in read_dir {
+            match dir_entry?.file_name().into_string() {
+                // Restore points being created are hidden
+                Ok(label) if !label.starts_with('.') => labels.push(label),
+                _ => (),
+            }
+        }
+        labels.sort();
+        Ok(labels)
+    }
+
+    /// Deletes a restore point, does nothing if there is none with this label
+    ///
+    /// # Errors
+    /// Fails if the label is invalid or if the restore point cannot be deleted
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn remove_restore_point(&self, label: &str) -> Result<()> {
+        self.check_writable()?;
+        remove_dir(&self.restore_point_dir(label)?)
+    }
+
+    fn restore_point_dir(&self, label: &str) -> Result<PathBuf> {
+        validate(label)?;
+        if !self.inner.storage.backend.uses_files() {
+            return Err(anyhow::Error::msg(
+                "Restore points need a backend keeping its data in files",
+            ));
+        }
+        Ok(self.dir().join(RESTORE_POINTS_DIR).join(label))
+    }
+}
+
+fn remove_dir(dir: &std::path::Path) -> Result<()> {
+    match std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn restore_points_roll_back_values_and_blobs() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("kept", 1).unwrap();
+        config_store.set("modified", 2).unwrap();
+        config_store.set_blob("icon", b"old").unwrap();
+        config_store.create_restore_point("v1").unwrap();
+        config_store.create_restore_point("v1").unwrap();
+
+        config_store.set("modified", 20).unwrap();
+        config_store.set("added", 3).unwrap();
+        config_store.set_blob("icon", b"new").unwrap();
+        config_store.restore_from_point("v1").unwrap();
+        assert_eq!(config_store.keys().unwrap(), vec!["kept", "modified"]);
+        assert_eq!(config_store.get::<u32>("modified").unwrap(), 2);
+        assert_eq!(config_store.get_blob("icon").unwrap(), b"old");
+
+        assert!(config_store.restore_from_point("v2").is_err());
+        assert!(config_store.create_restore_point("../v2").is_err());
+        config_store.remove_restore_point("v1").unwrap();
+        assert!(config_store.restore_points().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,523 @@
+use serde_json::{json, Map, Value};
+
+/// The JSON Schema draft the emitted schemas follow
+pub(crate) const DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
+/// Infers the JSON Schema of documents shaped like value, with value as their default
+/// Fields are not required, settings structs fill missing ones with `#[serde(default)]`,
+/// and values that are null, like unset options, accept anything
+pub(crate) fn infer(value: &Value) -> Value {
+    shape(value, true)
+}
+
+/// The items of arrays are described by the shape of the first one, without defaults
+fn shape(value: &Value, defaults: bool) -> Value {
+    let mut schema = match value {
+        Value::Null => return json!({}),
+        Value::Bool(_) => json!({"type": "boolean"}),
+        Value::Number(number) if number.is_f64() => json!({"type": "number"}),
+        Value::Number(_) => json!({"type": "integer"}),
+        Value::String(_) => json!({"type": "string"}),
+        Value::Array(items) => match items.first() {
+            Some(item) => json!({"type": "array", "items": shape(item, false)}),
+            None => json!({"type": "array"}),
+        },
+        Value::Object(fields) => {
+            let properties: Map<String, Value> = fields
+                .iter()
+                .map(|(field, value)| (field.clone(), shape(value, defaults)))
+                .collect();
+            return json!({"type": "object", "properties": properties});
+        }
+    };
+    if defaults {
+        schema["default"] = value.clone();
+    }
+    schema
+}
+
+/// Keywords `check` enforces
+const ENFORCED: &[&str] = &[
+    "$ref",
+    "type",
+    "enum",
+    "const",
+    "allOf",
+    "anyOf",
+    "oneOf",
+    "not",
+    "if",
+    "then",
+    "else",
+    "properties",
+    "required",
+    "additionalProperties",
+    "minProperties",
+    "maxProperties",
+    "propertyNames",
+    "dependencies",
+    "items",
+    "additionalItems",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+    "contains",
+    "minLength",
+    "maxLength",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+];
+
+/// Keywords that describe values without constraining them, `format` included as draft-07 allows
+const ANNOTATIONS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "format",
+    "readOnly",
+    "writeOnly",
+    "deprecated",
+    "definitions",
+    "$defs",
+    "contentMediaType",
+    "contentEncoding",
+];
+
+/// How many `$ref` can be followed for a single part of a value, to stop on cycles
+const MAX_REFS: usize = 64;
+
+/// Describes the first keyword of a schema `check` cannot enforce, like `pattern`,
+/// or a `$ref` that does not point inside the schema, None if every keyword is enforced
+pub(crate) fn unsupported(schema: &Value) -> Option<String> {
+    unsupported_at(schema, schema, "")
+}
+
+fn unsupported_at(root: &Value, schema: &Value, pointer: &str) -> Option<String> {
+    let schema = match schema {
+        Value::Bool(_) => return None,
+        Value::Object(schema) => schema,
+        _ => {
+            return Some(format!(
+                "{}: a schema is an object or a boolean",
+                at(pointer)
+            ))
+        }
+    };
+    for (keyword, value) in schema {
+        let pointer = format!("{}/{}", pointer, escape(keyword));
+        if !ENFORCED.contains(&keyword.as_str()) && !ANNOTATIONS.contains(&keyword.as_str()) {
+            return Some(format!(
+                "{}: the {} keyword is not supported",
+                pointer, keyword
+            ));
+        }
+        let subschemas: Vec<(String, &Value)> = match (keyword.as_str(), value) {
+            ("$ref", Value::String(reference)) => match resolve(root, reference) {
+                Some(_) => Vec::new(),
+                None => {
+                    return Some(format!(
+                        "{}: only references inside the schema are supported, not {}",
+                        pointer, reference
+                    ))
+                }
+            },
+            ("properties", Value::Object(schemas))
+            | ("definitions", Value::Object(schemas))
+            | ("$defs", Value::Object(schemas)) => schemas
+                .iter()
+                .map(|(name, schema)| (escape(name), schema))
+                .collect(),
+            ("dependencies", Value::Object(dependencies)) => dependencies
+                .iter()
+                .filter(|(_, dependency)| !dependency.is_array())
+                .map(|(name, schema)| (escape(name), schema))
+                .collect(),
+            ("allOf", Value::Array(schemas))
+            | ("anyOf", Value::Array(schemas))
+            | ("oneOf", Value::Array(schemas))
+            | ("items", Value::Array(schemas)) => schemas
+                .iter()
+                .enumerate()
+                .map(|(i, schema)| (i.to_string(), schema))
+                .collect(),
+            (
+                "not"
+                | "if"
+                | "then"
+                | "else"
+                | "items"
+                | "additionalItems"
+                | "additionalProperties"
+                | "contains"
+                | "propertyNames",
+                schema,
+            ) => vec![(String::new(), schema)],
+            _ => Vec::new(),
+        };
+        for (child, subschema) in subschemas {
+            let pointer = if child.is_empty() {
+                pointer.clone()
+            } else {
+                format!("{}/{}", pointer, child)
+            };
+            if let Some(problem) = unsupported_at(root, subschema, &pointer) {
+                return Some(problem);
+            }
+        }
+    }
+    None
+}
+
+/// The part of the schema a local reference like `#/definitions/Mode` points to
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+/// Checks a value against a schema, returns a description of each problem prefixed by
+/// the JSON Pointer to the part of the value it is about
+/// Only the keywords of ENFORCED are checked, schemas are vetted by `unsupported` first
+pub(crate) fn check(schema: &Value, value: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    check_at(schema, schema, value, "", &mut problems, 0);
+    problems
+}
+
+/// Whether a value matches a part of the root schema
+fn matches(root: &Value, schema: &Value, value: &Value, refs: usize) -> bool {
+    let mut problems = Vec::new();
+    check_at(root, schema, value, "", &mut problems, refs);
+    problems.is_empty()
+}
+
+fn check_at(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    pointer: &str,
+    problems: &mut Vec<String>,
+    refs: usize,
+) {
+    let at = at(pointer);
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Object(schema) => schema,
+        _ => return problems.push(format!("{}: no value is allowed", at)),
+    };
+    if let Some(Value::String(reference)) = schema.get("$ref") {
+        match resolve(root, reference) {
+            Some(_) if refs >= MAX_REFS => {
+                problems.push(format!("{}: {} refers to itself endlessly", at, reference))
+            }
+            Some(target) => check_at(root, target, value, pointer, problems, refs + 1),
+            None => problems.push(format!("{}: {} refers to nothing", at, reference)),
+        }
+    }
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(name) => vec![name],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.iter().any(|name| has_type(value, name)) {
+            let expected = types.join(" or ");
+            return problems.push(format!("{}: expected {}, found {}", at, expected, value));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            problems.push(format!(
+                "{}: {} is not one of {}",
+                at,
+                value,
+                Value::from(allowed.clone())
+            ));
+        }
+    }
+    if let Some(allowed) = schema.get("const") {
+        if allowed != value {
+            problems.push(format!("{}: expected {}, found {}", at, allowed, value));
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("allOf") {
+        for schema in schemas {
+            check_at(root, schema, value, pointer, problems, refs);
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+        if !schemas
+            .iter()
+            .any(|schema| matches(root, schema, value, refs))
+        {
+            problems.push(format!(
+                "{}: {} matches none of the allowed schemas",
+                at, value
+            ));
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+        let matching = schemas
+            .iter()
+            .filter(|schema| matches(root, schema, value, refs))
+            .count();
+        if matching != 1 {
+            problems.push(format!(
+                "{}: {} matches {} of the schemas instead of exactly one",
+                at, value, matching
+            ));
+        }
+    }
+    if let Some(schema) = schema.get("not") {
+        if matches(root, schema, value, refs) {
+            problems.push(format!("{}: {} matches a forbidden schema", at, value));
+        }
+    }
+    if let Some(condition) = schema.get("if") {
+        let branch = if matches(root, condition, value, refs) {
+            schema.get("then")
+        } else {
+            schema.get("else")
+        };
+        if let Some(branch) = branch {
+            check_at(root, branch, value, pointer, problems, refs);
+        }
+    }
+    match value {
+        Value::Object(fields) => check_object(root, schema, fields, pointer, problems, refs),
+        Value::Array(items) => check_array(root, schema, items, pointer, problems, refs),
+        Value::String(text) => {
+            let length = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if length < min {
+                    problems.push(format!("{}: is shorter than {} characters", at, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if length > max {
+                    problems.push(format!("{}: is longer than {} characters", at, max));
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or(f64::NAN);
+            let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+            let out_of_range = [
+                ("less than", bound("minimum").filter(|min| number < *min)),
+                ("more than", bound("maximum").filter(|max| number > *max)),
+                (
+                    "at most",
+                    bound("exclusiveMinimum").filter(|min| number <= *min),
+                ),
+                (
+                    "at least",
+                    bound("exclusiveMaximum").filter(|max| number >= *max),
+                ),
+            ];
+            for (comparison, limit) in out_of_range.iter() {
+                if let Some(limit) = limit {
+                    problems.push(format!("{}: {} is {} {}", at, number, comparison, limit));
+                }
+            }
+            if let Some(divisor) = bound("multipleOf").filter(|divisor| *divisor > 0.0) {
+                let quotient = number / divisor;
+                if (quotient - quotient.round()).abs() > 1e-9 {
+                    problems.push(format!(
+                        "{}: {} is not a multiple of {}",
+                        at, number, divisor
+                    ));
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn check_object(
+    root: &Value,
+    schema: &Map<String, Value>,
+    fields: &Map<String, Value>,
+    pointer: &str,
+    problems: &mut Vec<String>,
+    refs: usize,
+) {
+    let at = at(pointer);
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for field in required.iter().filter_map(Value::as_str) {
+            if !fields.contains_key(field) {
+                problems.push(format!("{}: missing required field {:?}", at, field));
+            }
+        }
+    }
+    let count = fields.len() as u64;
+    if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+        if count < min {
+            problems.push(format!("{}: has {} fields, fewer than {}", at, count, min));
+        }
+    }
+    if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+        if count > max {
+            problems.push(format!("{}: has {} fields, more than {}", at, count, max));
+        }
+    }
+    if let Some(Value::Object(dependencies)) = schema.get("dependencies") {
+        for (field, dependency) in dependencies {
+            if !fields.contains_key(field) {
+                continue;
+            }
+            match dependency {
+                Value::Array(needed) => {
+                    for needed in needed.iter().filter_map(Value::as_str) {
+                        if !fields.contains_key(needed) {
+                            problems.push(format!(
+                                "{}: field {:?} needs field {:?}",
+                                at, field, needed
+                            ));
+                        }
+                    }
+                }
+                schema => check_at(
+                    root,
+                    schema,
+                    &Value::Object(fields.clone()),
+                    pointer,
+                    problems,
+                    refs,
+                ),
+            }
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (field, value) in fields {
+        let pointer = format!("{}/{}", pointer, escape(field));
+        if let Some(names) = schema.get("propertyNames") {
+            if !matches(root, names, &Value::from(field.clone()), refs) {
+                problems.push(format!(
+                    "{}: {:?} is not an allowed field name",
+                    pointer, field
+                ));
+            }
+        }
+        match properties.and_then(|properties| properties.get(field)) {
+            Some(field_schema) => check_at(root, field_schema, value, &pointer, problems, refs),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => problems.push(format!("{}: unknown field", pointer)),
+                Some(additional) => check_at(root, additional, value, &pointer, problems, refs),
+                None => (),
+            },
+        }
+    }
+}
+
+fn check_array(
+    root: &Value,
+    schema: &Map<String, Value>,
+    items: &[Value],
+    pointer: &str,
+    problems: &mut Vec<String>,
+    refs: usize,
+) {
+    let at = at(pointer);
+    let count = items.len() as u64;
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if count < min {
+            problems.push(format!("{}: has {} items, fewer than {}", at, count, min));
+        }
+    }
+    if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+        if count > max {
+            problems.push(format!("{}: has {} items, more than {}", at, count, max));
+        }
+    }
+    if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+        for (index, item) in items.iter().enumerate() {
+            if items[..index].contains(item) {
+                problems.push(format!("{}/{}: {} is repeated", pointer, index, item));
+            }
+        }
+    }
+    if let Some(contained) = schema.get("contains") {
+        if !items
+            .iter()
+            .any(|item| matches(root, contained, item, refs))
+        {
+            problems.push(format!("{}: no item matches the expected schema", at));
+        }
+    }
+    for (index, item) in items.iter().enumerate() {
+        // An array of schemas describes the items one by one, additionalItems the ones after
+        let item_schema = match schema.get("items") {
+            Some(Value::Array(schemas)) => {
+                schemas.get(index).or_else(|| schema.get("additionalItems"))
+            }
+            item_schema => item_schema,
+        };
+        if let Some(item_schema) = item_schema {
+            let pointer = format!("{}/{}", pointer, index);
+            check_at(root, item_schema, item, &pointer, problems, refs);
+        }
+    }
+}
+
+/// Where a problem is, `/` for the whole value
+fn at(pointer: &str) -> &str {
+    if pointer.is_empty() {
+        "/"
+    } else {
+        pointer
+    }
+}
+
+/// Escapes a field name to be part of a JSON Pointer
+fn escape(field: &str) -> String {
+    field.replace('~', "~0").replace('/', "~1")
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("null", Value::Null) | ("boolean", Value::Bool(_)) | ("string", Value::String(_)) => true,
+        ("array", Value::Array(_))
+        | ("object", Value::Object(_))
+        | ("number", Value::Number(_)) => true,
+        ("integer", Value::Number(number)) => {
+            number.is_i64() || number.is_u64() || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas_follow_the_shape_of_values() {
+        let schema = infer(&json!({
+            "theme": "dark",
+            "ratio": 1.5,
+            "plugins": [{"name": "git"}],
+            "proxy": null,
+        }));
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "theme": {"type": "string", "default": "dark"},
+                    "ratio": {"type": "number", "default": 1.5},
+                    "plugins": {
+                        "type": "array",
+                        "items": {"type": "object", "properties": {"name": {"type": "string"}}},
+                        "default": [{"name": "git"}],
+                    },
+                    "proxy": {},
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Configstore;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+impl Configstore {
+    /// Sets a value in a file readable by the current user only (`0600` on Unix),
+    /// for tokens and other secrets kept in a store that is not entirely secure
+    /// The file stays private when the key is set again with set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set_secure("token", "s3cr3t").unwrap();
+    /// assert_eq!(config_store.get::<String>("token").unwrap(), "s3cr3t");
+    /// ```
+    ///
+    /// # Errors
+    /// Same as set
+    pub fn set_secure<T>(&self, key: &str, value: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.check_writable()?;
+        let bytes = self.encode(key, &value)?;
+        self.write_value(key, self.key_format(key), &bytes, true)
+    }
+}
+
+/// Makes a file readable and writable by its owner only, files written
+/// by the store are created private instead, which leaves no window where they are readable
+#[cfg(feature = "sqlite")]
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub(crate) fn restrict_file(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Makes a directory accessible by its owner only
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub(crate) fn restrict_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::Configstore;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn mode(path: &std::path::Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn secure_files_stay_private() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set_secure("token", "first").unwrap();
+        config_store.set("token", "second").unwrap();
+        assert_eq!(mode(&config_store.config_path("token")), 0o600);
+
+        let secure = config_store.reopen().secure(true).build().unwrap();
+        assert_eq!(mode(secure.dir()), 0o700);
+        secure.set("password", "hunter2").unwrap();
+        assert_eq!(mode(&secure.config_path("password")), 0o600);
+    }
+}
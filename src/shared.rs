@@ -0,0 +1,94 @@
+use crate::{profile, AppUI, Configstore, ConfigstoreBuilder};
+use anyhow::Result;
+
+/// Directory of a shared store holding the store of each app of the suite
+/// Hidden so that the apps are never listed as keys of the common area
+const APPS_DIR: &str = ".apps";
+
+impl Configstore {
+    /// Opens the common area of a namespace shared by a suite of related apps, like several
+    /// command line tools of the same company sharing their auth state. Each app gets its own
+    /// store inside the namespace with `app_scope`
+    /// Apps can run at the same time, update shared keys with the atomic operations like
+    /// `compare_and_swap`, `set_once` or `map_insert` so that none overwrites the changes of another
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let common = Configstore::shared("myCompany", AppUI::CommandLine).unwrap();
+    /// common.map_insert("sessions", "api.mycompany.com", "t0k3n").unwrap();
+    ///
+    /// let deploy = common.app_scope("deploy").unwrap();
+    /// deploy.set("last_target", "staging").unwrap();
+    /// assert!(common.get::<String>("last_target").is_err());
+    /// assert_eq!(common.app_scopes().unwrap(), vec!["deploy"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Same as new
+    pub fn shared(namespace: &str, app_ui: AppUI) -> Result<Self> {
+        Self::new(namespace, app_ui)
+    }
+
+    /// Opens the store of one app of a shared namespace, kept apart from the common area
+    /// and from the other apps. Check the shared docs for usage
+    ///
+    /// # Errors
+    /// Same as new, or if the app name is empty, starts with '.' or contains path separators
+    pub fn app_scope(&self, app_name: &str) -> Result<Self> {
+        self.app_scope_builder(app_name)?.build()
+    }
+
+    /// A builder for the store of one app of a shared namespace, to customize it like any other store
+    ///
+    /// # Errors
+    /// Fails if the app name is empty, starts with '.' or contains path separators
+    pub fn app_scope_builder(&self, app_name: &str) -> Result<ConfigstoreBuilder> {
+        profile::validate(app_name)?;
+        Ok(Self::builder(app_name, AppUI::CommandLine).root(self.inner.app_dir.join(APPS_DIR)))
+    }
+
+    /// Lists the apps of the namespace that opened their store, in alphabetical order
+    ///
+    /// # Errors
+    /// Could error if the directory of the apps cannot be read
+    pub fn app_scopes(&self) -> Result<Vec<String>> {
+        let read_dir = match std::fs::read_dir(self.inner.app_dir.join(APPS_DIR)) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut apps = Vec::new();
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            if dir_entry.file_type()?.is_dir() {
+                apps.push(dir_entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        apps.sort();
+        Ok(apps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn apps_are_scoped_inside_the_namespace() {
+        let common = Configstore::temp().unwrap();
+        common.set("auth", "shared").unwrap();
+        let build = common.app_scope("build").unwrap();
+        let deploy = common.app_scope("deploy").unwrap();
+        build.set("jobs", 4).unwrap();
+
+        assert!(deploy.get::<u32>("jobs").is_err());
+        assert!(build.get::<String>("auth").is_err());
+        assert_eq!(common.keys().unwrap(), vec!["auth"]);
+        assert!(build.dir().starts_with(common.dir()));
+        assert_eq!(common.app_scopes().unwrap(), vec!["build", "deploy"]);
+        assert!(common.app_scope("../escape").is_err());
+    }
+}
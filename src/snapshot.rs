@@ -0,0 +1,203 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The values of every key of a store at one point in time, taken with `snapshot`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Snapshot {
+    values: BTreeMap<String, Value>,
+}
+
+impl Snapshot {
+    /// The value a key had when the snapshot was taken
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// The keys that were set when the snapshot was taken, in alphabetical order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+}
+
+/// How a key differs from a snapshot, as returned by `diff`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The key was set after the snapshot was taken
+    Added {
+        /// The key that was set
+        key: String,
+        /// Its current value
+        value: Value,
+    },
+    /// The key was deleted after the snapshot was taken
+    Removed {
+        /// The key that was deleted
+        key: String,
+        /// Its value in the snapshot
+        value: Value,
+    },
+    /// The key holds another value than in the snapshot
+    Modified {
+        /// The key that changed
+        key: String,
+        /// Its value in the snapshot
+        before: Value,
+        /// Its current value
+        after: Value,
+    },
+}
+
+impl Change {
+    /// The key that changed
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Added { key, .. }
+            | Change::Removed { key, .. }
+            | Change::Modified { key, .. } => key,
+        }
+    }
+}
+
+impl Configstore {
+    /// Captures the value of every key, to find out later what an operation changed with `diff`
+    /// or to undo it with `restore`. Blobs are not included
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Change, Configstore};
+    /// use serde_json::json;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("theme", "light").unwrap();
+    /// let before = config_store.snapshot().unwrap();
+    ///
+    /// // An interactive setup wizard runs
+    /// config_store.set("theme", "dark").unwrap();
+    /// config_store.set("telemetry", false).unwrap();
+    ///
+    /// for change in config_store.diff(&before).unwrap() {
+    ///     println!("{:?}", change);
+    /// }
+    /// assert_eq!(config_store.diff(&before).unwrap()[1], Change::Modified {
+    ///     key: "theme".to_string(),
+    ///     before: json!("light"),
+    ///     after: json!("dark"),
+    /// });
+    /// config_store.restore(&before).unwrap();
+    /// assert!(config_store.diff(&before).unwrap().is_empty());
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store directory cannot be read or if a value cannot be decoded
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let mut values = BTreeMap::new();
+        for key in self.keys()? {
+            match self.get_raw(&key) {
+                Ok(value) => {
+                    values.insert(key, value);
+                }
+                // Deleted since it was listed
+                Err(e) if is_not_found(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Snapshot { values })
+    }
+
+    /// Lists the keys that changed since the snapshot was taken, ordered by key
+    /// Check the snapshot docs for usage
+    ///
+    /// # Errors
+    /// Same as snapshot
+    pub fn diff(&self, snapshot: &Snapshot) -> Result<Vec<Change>> {
+        let current = self.snapshot()?;
+        let mut changes = Vec::new();
+        for (key, before) in &snapshot.values {
+            match current.values.get(key) {
+                None => changes.push(Change::Removed {
+                    key: key.clone(),
+                    value: before.clone(),
+                }),
+                Some(after) if after != before => changes.push(Change::Modified {
+                    key: key.clone(),
+                    before: before.clone(),
+                    after: after.clone(),
+                }),
+                Some(_) => (),
+            }
+        }
+        for (key, value) in current.values {
+            if !snapshot.values.contains_key(&key) {
+                changes.push(Change::Added { key, value });
+            }
+        }
+        changes.sort_by(|a, b| a.key().cmp(b.key()));
+        Ok(changes)
+    }
+
+    /// Puts every key back to its value in the snapshot, deleting the keys set since
+    /// Only the keys that changed are written. Returns the changes that were undone
+    ///
+    /// # Errors
+    /// Same as snapshot, also fails if a value cannot be written, changes undone before the failure stay undone
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn restore(&self, snapshot: &Snapshot) -> Result<Vec<Change>> {
+        self.check_writable()?;
+        let changes = self.diff(snapshot)?;
+        for change in &changes {
+            match change {
+                Change::Added { key, .. } => self.delete(key)?,
+                Change::Removed { key, value } => self.set_raw(key, value.clone())?,
+                Change::Modified { key, before, .. } => self.set_raw(key, before.clone())?,
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn restore_undoes_every_kind_of_change() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("kept", 1).unwrap();
+        config_store.set("modified", 2).unwrap();
+        config_store.set("removed", 3).unwrap();
+        let snapshot = config_store.snapshot().unwrap();
+
+        config_store.set("modified", 20).unwrap();
+        config_store.delete("removed").unwrap();
+        config_store.set("added", 4).unwrap();
+        let changes = config_store.restore(&snapshot).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                Change::Added {
+                    key: "added".to_string(),
+                    value: json!(4)
+                },
+                Change::Modified {
+                    key: "modified".to_string(),
+                    before: json!(2),
+                    after: json!(20)
+                },
+                Change::Removed {
+                    key: "removed".to_string(),
+                    value: json!(3)
+                },
+            ]
+        );
+        assert_eq!(config_store.snapshot().unwrap(), snapshot);
+        assert_eq!(
+            snapshot.keys().collect::<Vec<_>>(),
+            ["kept", "modified", "removed"]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::storage::{self, Entry};
+use anyhow::Result;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the database file inside the store directory
+pub(crate) const DATABASE_NAME: &str = "store.sqlite3";
+
+/// Storage keeping every entry as a row of a single SQLite database
+pub(crate) struct SqliteStorage {
+    /// None when a read-only store is opened before the database was ever created
+    connection: Option<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub(crate) fn open(dir: &Path, read_only: bool) -> Result<Self> {
+        let path = dir.join(DATABASE_NAME);
+        if read_only {
+            if !path.exists() {
+                return Ok(SqliteStorage { connection: None });
+            }
+            let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            connection.busy_timeout(Duration::from_secs(5))?;
+            return Ok(SqliteStorage {
+                connection: Some(Mutex::new(connection)),
+            });
+        }
+        let connection = Connection::open(&path)?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS entries (
+                name TEXT PRIMARY KEY,
+                value BLOB NOT NULL,
+                modified INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(SqliteStorage {
+            connection: Some(Mutex::new(connection)),
+        })
+    }
+
+    fn with_connection<T, F>(&self, empty: T, f: F) -> Result<T>
+    where
+        F: FnOnce(&Connection) -> rusqlite::Result<T>,
+    {
+        match &self.connection {
+            Some(connection) => Ok(f(&connection.lock().unwrap())?),
+            None => Ok(empty),
+        }
+    }
+
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>> {
+        self.with_connection(None, |connection| {
+            connection
+                .query_row(
+                    "SELECT value FROM entries WHERE name = ?1",
+                    params![name],
+                    |row| row.get(0),
+                )
+                .optional()
+        })?
+        .ok_or_else(|| not_found(name))
+    }
+
+    pub(crate) fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.with_connection((), |connection| {
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO entries (name, value, modified) VALUES (?1, ?2, ?3)",
+                    params![name, bytes, now_millis()],
+                )
+                .map(|_| ())
+        })
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> Result<()> {
+        let removed = self.with_connection(0, |connection| {
+            connection.execute("DELETE FROM entries WHERE name = ?1", params![name])
+        })?;
+        if removed == 0 {
+            return Err(not_found(name));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let renamed = self.with_connection(0, |connection| {
+            connection.execute(
+                "UPDATE OR REPLACE entries SET name = ?2 WHERE name = ?1",
+                params![from, to],
+            )
+        })?;
+        if renamed == 0 {
+            return Err(not_found(from));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn exists(&self, name: &str) -> Result<bool> {
+        self.with_connection(false, |connection| {
+            connection
+                .query_row(
+                    "SELECT 1 FROM entries WHERE name = ?1",
+                    params![name],
+                    |_| Ok(()),
+                )
+                .optional()
+                .map(|row| row.is_some())
+        })
+    }
+
+    pub(crate) fn touch(&self, name: &str) -> Result<()> {
+        self.with_connection((), |connection| {
+            connection
+                .execute(
+                    "UPDATE entries SET modified = ?2 WHERE name = ?1",
+                    params![name, now_millis()],
+                )
+                .map(|_| ())
+        })
+    }
+
+    pub(crate) fn entries(&self) -> Result<Vec<Entry>> {
+        let rows = self.with_connection(Vec::new(), |connection| {
+            let mut statement =
+                connection.prepare("SELECT name, length(value), modified FROM entries")?;
+            let rows = statement.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?;
+            rows.collect()
+        })?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(name, size, modified)| {
+                let key = storage::entry_key(&name)?.to_string();
+                Some(Entry {
+                    key,
+                    name,
+                    size: size as u64,
+                    modified: UNIX_EPOCH + Duration::from_millis(modified as u64),
+                })
+            })
+            .collect())
+    }
+}
+
+fn not_found(name: &str) -> anyhow::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} does not exist", name),
+    )
+    .into()
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Moves the write-ahead log of the store's database into the database file
+pub(crate) fn checkpoint(dir: &Path) -> Result<()> {
+    let path = dir.join(DATABASE_NAME);
+    if !path.exists() {
+        return Ok(());
+    }
+    let connection = Connection::open(&path)?;
+    connection.busy_timeout(Duration::from_secs(5))?;
+    connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AppUI, Backend, Configstore};
+
+    #[test]
+    fn values_and_blobs_round_trip() {
+        let config_store = Configstore::builder("tests-sqlite", AppUI::CommandLine)
+            .temp(true)
+            .backend(Backend::Sqlite)
+            .build()
+            .unwrap();
+        config_store.set("value", String::from("abc")).unwrap();
+        config_store.set_blob("value", b"12345").unwrap();
+        assert_eq!(config_store.get::<String>("value").unwrap(), "abc");
+        assert_eq!(config_store.get_blob("value").unwrap(), b"12345");
+        assert!(config_store.keys().unwrap().contains(&"value".to_string()));
+        assert_eq!(config_store.stats().unwrap().key_sizes["value"], 10);
+        config_store.delete("value").unwrap();
+        assert!(config_store.get::<String>("value").is_err());
+        assert!(config_store.delete("value").is_err());
+        assert!(!config_store.dir().join("value.json").exists());
+        assert!(config_store
+            .config_path("value")
+            .ends_with(super::DATABASE_NAME));
+    }
+
+    #[test]
+    fn read_only_without_database() {
+        let config_store = Configstore::builder("tests-sqlite-missing", AppUI::CommandLine)
+            .temp(true)
+            .backend(Backend::Sqlite)
+            .read_only(true)
+            .build()
+            .unwrap();
+        assert!(config_store.get::<String>("value").is_err());
+        assert!(config_store.keys().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::error::is_not_found;
+use crate::{atomic, Configstore, DirKind};
+use anyhow::Result;
+use platform_dirs::AppUI;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File holding the id of the running daemon, hidden so that it is never listed as a key
+const PID_FILE_NAME: &str = ".pid";
+
+/// Key holding when the app last ran, in seconds since the unix epoch
+const LAST_RUN_KEY: &str = "last_run";
+
+/// The PID file of a running daemon, written by `write_pid_file` and removed when dropped
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Where the PID file was written, to hand to service managers
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Configstore {
+    /// Creates a store in the platform's state directory, `XDG_STATE_HOME` on Linux and
+    /// `%LOCALAPPDATA%` on Windows, for daemon PID files, last-run times and other ephemeral state
+    /// that does not belong in the user's config directory
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let state = Configstore::state("myDaemon", AppUI::CommandLine).unwrap();
+    /// let _pid_file = state.write_pid_file().unwrap();
+    /// assert_eq!(state.read_pid().unwrap(), Some(std::process::id()));
+    /// if let Some(last_run) = state.mark_run().unwrap() {
+    ///     println!("Last ran {:?} ago", last_run.elapsed());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Same as new
+    pub fn state(app_name: &str, app_ui: AppUI) -> Result<Self> {
+        Self::new_in(app_name, app_ui, DirKind::State)
+    }
+
+    /// Writes the id of the current process to the store's PID file, removed when the guard is dropped
+    /// A process that crashed leaves its PID file behind, which `read_pid` then reports
+    /// Combine with `acquire_app_lock` to make sure a single daemon runs
+    ///
+    /// # Errors
+    /// Could error if the file cannot be written. Fails with `Error::ReadOnly` on read-only stores
+    pub fn write_pid_file(&self) -> Result<PidFile> {
+        self.check_writable()?;
+        let path = self.dir().join(PID_FILE_NAME);
+        atomic::write(&path, format!("{}\n", std::process::id()).as_bytes())?;
+        Ok(PidFile { path })
+    }
+
+    /// The process id recorded in the store's PID file, None if there is none
+    ///
+    /// # Errors
+    /// Could error if the PID file cannot be read or does not hold a process id
+    pub fn read_pid(&self) -> Result<Option<u32>> {
+        match std::fs::read_to_string(self.dir().join(PID_FILE_NAME)) {
+            Ok(pid) => Ok(Some(pid.trim().parse()?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Records that the app runs now and returns when it last ran, None on the first run
+    ///
+    /// # Errors
+    /// Same as set
+    pub fn mark_run(&self) -> Result<Option<SystemTime>> {
+        let now = now_secs();
+        let previous = self.update(LAST_RUN_KEY, |previous: Option<u64>| {
+            Ok((Some(now), previous))
+        })?;
+        Ok(previous.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)))
+    }
+
+    /// When the app last called `mark_run`, None if it never did
+    ///
+    /// # Errors
+    /// Same as get
+    pub fn last_run(&self) -> Result<Option<SystemTime>> {
+        match self.get::<u64>(LAST_RUN_KEY) {
+            Ok(secs) => Ok(Some(UNIX_EPOCH + Duration::from_secs(secs))),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether a periodic task is due, like checking for updates at most once a day
+    /// Records the time of the run in key when it is, so that concurrent processes
+    /// cannot both run the task. Tasks last run in the future, after the clock was set back, are due
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    /// use std::time::Duration;
+    ///
+    /// let state = Configstore::temp().unwrap();
+    /// let day = Duration::from_secs(24 * 60 * 60);
+    /// assert!(state.should_run("update_check", day).unwrap());
+    /// assert!(!state.should_run("update_check", day).unwrap());
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if key holds something else than a time in seconds, same as set otherwise
+    pub fn should_run(&self, key: &str, interval: Duration) -> Result<bool> {
+        let now = now_secs();
+        self.update(key, |last_run: Option<u64>| {
+            let due = last_run
+                .is_none_or(|last_run| last_run > now || now - last_run >= interval.as_secs());
+            Ok((if due { Some(now) } else { None }, due))
+        })
+    }
+}
+
+/// Seconds since the unix epoch, as recorded in the store
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use std::time::Duration;
+
+    #[test]
+    fn pid_files_and_runs_are_tracked() {
+        let state = Configstore::temp().unwrap();
+        assert_eq!(state.read_pid().unwrap(), None);
+        let pid_file = state.write_pid_file().unwrap();
+        assert_eq!(state.read_pid().unwrap(), Some(std::process::id()));
+        assert!(state.keys().unwrap().is_empty());
+        drop(pid_file);
+        assert_eq!(state.read_pid().unwrap(), None);
+
+        assert_eq!(state.last_run().unwrap(), None);
+        assert_eq!(state.mark_run().unwrap(), None);
+        let first = state.last_run().unwrap().unwrap();
+        assert_eq!(state.mark_run().unwrap(), Some(first));
+
+        assert!(state.should_run("check", Duration::from_secs(60)).unwrap());
+        assert!(!state.should_run("check", Duration::from_secs(60)).unwrap());
+        assert!(state.should_run("check", Duration::ZERO).unwrap());
+        state.set("check", u64::MAX).unwrap();
+        assert!(state.should_run("check", Duration::from_secs(60)).unwrap());
+    }
+}
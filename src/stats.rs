@@ -0,0 +1,169 @@
+use crate::storage::Entry;
+use crate::{ChangeEvent, Configstore, Error};
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// What to do when a write would make the store exceed its quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Eviction {
+    /// Fail the write with `Error::QuotaExceeded` (the default)
+    #[default]
+    None,
+    /// Delete the entries that were written the longest time ago until the new value fits
+    Fifo,
+    /// Delete the entries that were read or written the longest time ago until the new value fits
+    /// Reads refresh the modification time of the files they access
+    Lru,
+}
+
+/// Sizes of the files in a store, as returned by `stats`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StoreStats {
+    /// Total size in bytes of every value and blob
+    pub total_bytes: u64,
+    /// Number of distinct keys, a key holding both a value and a blob counts once
+    pub key_count: usize,
+    /// Size in bytes of each key, values and blobs combined
+    pub key_sizes: BTreeMap<String, u64>,
+}
+
+impl Configstore {
+    /// Computes the size of the store and of each of its keys
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set("key", "value".to_string()).unwrap();
+    /// let stats = config_store.stats().unwrap();
+    /// assert!(stats.total_bytes >= 7);
+    /// ```
+    ///
+    /// # Errors
+    /// Could error if the store directory cannot be read
+    pub fn stats(&self) -> Result<StoreStats> {
+        let mut stats = StoreStats::default();
+        for entry in self.inner.storage.entries()? {
+            stats.total_bytes += entry.size;
+            *stats.key_sizes.entry(entry.key).or_insert(0) += entry.size;
+        }
+        stats.key_count = stats.key_sizes.len();
+        Ok(stats)
+    }
+
+    /// Makes room for an entry of `size` bytes, evicting other entries if the policy allows it
+    pub(crate) fn enforce_quota(&self, name: &str, size: u64) -> Result<()> {
+        let quota = match self.inner.quota {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let mut entries: Vec<Entry> = self
+            .inner
+            .storage
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.name != name)
+            .collect();
+        let mut used: u64 = entries.iter().map(|entry| entry.size).sum();
+        if used + size <= quota {
+            return Ok(());
+        }
+        if self.inner.eviction == Eviction::None || size > quota {
+            return Err(Error::QuotaExceeded {
+                quota,
+                required: used + size,
+            }
+            .into());
+        }
+        entries.sort_by_key(|entry| entry.modified);
+        for entry in entries {
+            if used + size <= quota {
+                break;
+            }
+            self.inner.storage.remove(&entry.name)?;
+            used -= entry.size;
+            self.changed(ChangeEvent::Delete(entry.key), &entry.name)?;
+        }
+        Ok(())
+    }
+
+    /// Records an access to an entry for the Lru policy
+    pub(crate) fn touch(&self, name: &str) {
+        if self.inner.eviction != Eviction::Lru || self.inner.read_only {
+            return;
+        }
+        // Failing to record the access only makes eviction less accurate
+        let _ = self.inner.storage.touch(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AppUI, Configstore, Error, Eviction};
+
+    #[test]
+    fn stats_counts_values_and_blobs() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("value", String::from("abc")).unwrap();
+        config_store.set_blob("value", b"12345").unwrap();
+        let stats = config_store.stats().unwrap();
+        assert_eq!(stats.key_sizes["value"], 10);
+        assert_eq!(stats.key_count, stats.key_sizes.len());
+        assert_eq!(stats.total_bytes, stats.key_sizes.values().sum::<u64>());
+    }
+
+    #[test]
+    fn quota_fails_writes() {
+        let config_store = Configstore::builder("tests-quota-fail", AppUI::CommandLine)
+            .temp(true)
+            .quota(10)
+            .build()
+            .unwrap();
+        config_store.set("small", String::from("abc")).unwrap();
+        let err = config_store.set("large", "a".repeat(20)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::QuotaExceeded { quota: 10, .. })
+        ));
+        // Replacing a value only counts its new size
+        config_store.set("small", String::from("abcdefgh")).unwrap();
+    }
+
+    #[test]
+    fn fifo_evicts_oldest_entries() {
+        let config_store = Configstore::builder("tests-quota-fifo", AppUI::CommandLine)
+            .temp(true)
+            .quota(10)
+            .eviction(Eviction::Fifo)
+            .build()
+            .unwrap();
+        config_store.set("first", String::from("aa")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        config_store.set("second", String::from("bb")).unwrap();
+        config_store.set("third", String::from("cccc")).unwrap();
+        assert!(config_store.get::<String>("first").is_err());
+        assert_eq!(config_store.get::<String>("second").unwrap(), "bb");
+        assert_eq!(config_store.get::<String>("third").unwrap(), "cccc");
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_read() {
+        let config_store = Configstore::builder("tests-quota-lru", AppUI::CommandLine)
+            .temp(true)
+            .quota(10)
+            .eviction(Eviction::Lru)
+            .build()
+            .unwrap();
+        config_store.set("first", String::from("aa")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        config_store.set("second", String::from("bb")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert_eq!(config_store.get::<String>("first").unwrap(), "aa");
+        config_store.set("third", String::from("cccc")).unwrap();
+        assert_eq!(config_store.get::<String>("first").unwrap(), "aa");
+        assert!(config_store.get::<String>("second").is_err());
+    }
+}
@@ -0,0 +1,743 @@
+use crate::access::FileAccess;
+use crate::archive::Archive;
+use crate::atomic::{self, AtomicFile};
+use crate::debounce::{Change, Debouncer};
+use crate::filename::KeyNames;
+use crate::symlink::SymlinkPolicy;
+use anyhow::Result;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+/// Where a store keeps its values and blobs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Backend {
+    /// One file per value or blob in the store directory (the default)
+    #[cfg_attr(not(all(feature = "wasm", target_arch = "wasm32")), default)]
+    Files,
+    /// A single SQLite database in the store directory, requires the `sqlite` feature
+    /// Faster to list and safer to share between processes for stores with thousands of keys
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    /// Values and blobs up to 4 KiB kept together in an index file of the store directory,
+    /// larger ones in their own files. Faster to list and read for stores of many tiny values
+    Indexed,
+    /// The browser's localStorage, requires the `wasm` feature and is the default on wasm32
+    /// Values are shared by every page of the same origin, and limited to a few megabytes
+    #[cfg(feature = "wasm")]
+    #[cfg_attr(target_arch = "wasm32", default)]
+    LocalStorage,
+}
+
+impl Backend {
+    /// Whether the backend keeps its data in the platform's directories
+    pub(crate) fn uses_files(self) -> bool {
+        match self {
+            Backend::Files | Backend::Indexed => true,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => true,
+            #[cfg(feature = "wasm")]
+            Backend::LocalStorage => false,
+        }
+    }
+}
+
+/// Makes the writes to a store directory durable, even if the machine loses power
+/// Files are synced when written but their renames only once the directory is synced,
+/// SQLite databases move their write-ahead log into the database file
+pub(crate) fn flush_dir(backend: Backend, dir: &Path) -> Result<()> {
+    match backend {
+        Backend::Files | Backend::Indexed => sync_dir(dir),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => crate::sqlite::checkpoint(dir),
+        // The browser persists localStorage on its own
+        #[cfg(feature = "wasm")]
+        Backend::LocalStorage => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    match File::open(dir) {
+        Ok(dir) => Ok(dir.sync_all()?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Renames are durable once they return on platforms other than unix
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// A value or blob held by the storage
+pub(crate) struct Entry {
+    /// The key the entry belongs to
+    pub(crate) key: String,
+    /// The key followed by the extension of the entry, like `key.json` or `key.bin`
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    pub(crate) modified: SystemTime,
+}
+
+impl Entry {
+    /// The extension of the entry, like `json` or `bin`
+    pub(crate) fn extension(&self) -> &str {
+        self.name
+            .rsplit_once('.')
+            .map_or("", |(_, extension)| extension)
+    }
+}
+
+/// Keeps the entries of a store in its directory
+/// either as one file per entry, with an index of small entries or in a SQLite database
+pub(crate) struct Storage {
+    /// The store directory
+    pub(crate) dir: PathBuf,
+    pub(crate) backend: Backend,
+    /// Whether entries are written readable by the current user only
+    private: bool,
+    symlinks: SymlinkPolicy,
+    /// Lists the store among the ones flushed by `flush_all` while it is open
+    _registration: Option<crate::flush::Registration>,
+    /// Buffers writes when the store debounces them, dropped before the backends
+    /// so that its last writes can reach them
+    debouncer: Option<Debouncer>,
+    index: Option<crate::index::IndexStorage>,
+    /// The entries `compact` moved out of their files, for the files backend
+    archive: Option<Archive>,
+    /// The names of entries of keys that are not valid file names, for the backends using files
+    key_names: Option<KeyNames>,
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<crate::sqlite::SqliteStorage>,
+    #[cfg(feature = "wasm")]
+    local: Option<crate::local_storage::LocalStorage>,
+}
+
+impl Storage {
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub(crate) fn open(
+        backend: Backend,
+        dir: PathBuf,
+        read_only: bool,
+        private: bool,
+        symlinks: SymlinkPolicy,
+        debounce: Option<Duration>,
+    ) -> Result<Self> {
+        #[cfg(feature = "sqlite")]
+        let sqlite = match backend {
+            Backend::Sqlite => Some(crate::sqlite::SqliteStorage::open(&dir, read_only)?),
+            _ => None,
+        };
+        #[cfg(feature = "sqlite")]
+        if private && !read_only && sqlite.is_some() {
+            crate::secure::restrict_file(&dir.join(crate::sqlite::DATABASE_NAME))?;
+        }
+        #[cfg(feature = "wasm")]
+        let local = match backend {
+            Backend::LocalStorage => Some(crate::local_storage::LocalStorage::open(&dir)?),
+            _ => None,
+        };
+        let index = match backend {
+            Backend::Indexed => Some(crate::index::IndexStorage::open(&dir)),
+            _ => None,
+        };
+        let key_names = if matches!(backend, Backend::Files | Backend::Indexed) {
+            Some(KeyNames::open(&dir))
+        } else {
+            None
+        };
+        let archive = match backend {
+            Backend::Files => Some(Archive::open(&dir)),
+            _ => None,
+        };
+        let debouncer = match debounce {
+            Some(quiet) if !read_only && backend.uses_files() => {
+                Some(Debouncer::new(backend, dir.clone(), symlinks, quiet))
+            }
+            _ => None,
+        };
+        let registration = if read_only || !backend.uses_files() {
+            None
+        } else {
+            let pending = debouncer
+                .as_ref()
+                .map(|d| Arc::downgrade(d.shared()) as Weak<dyn crate::flush::Pending>);
+            Some(crate::flush::Registration::new(backend, &dir, pending))
+        };
+        let storage = Storage {
+            dir,
+            backend,
+            private,
+            symlinks,
+            _registration: registration,
+            debouncer,
+            index,
+            archive,
+            key_names,
+            #[cfg(feature = "sqlite")]
+            sqlite,
+            #[cfg(feature = "wasm")]
+            local,
+        };
+        if !read_only {
+            storage.migrate_names()?;
+        }
+        Ok(storage)
+    }
+
+    /// Moves the entries older versions of the crate wrote under the plain name of keys that
+    /// are not valid file names to their hashed name, so that they can be read and listed
+    /// Done once per store, under the lock of its writes, the manifest records it was done
+    /// An entry that already has a hashed name keeps it, its plain name is left as is
+    fn migrate_names(&self) -> Result<()> {
+        let key_names = match &self.key_names {
+            Some(key_names) => key_names,
+            None => return Ok(()),
+        };
+        if crate::manifest::has_hashed_names(&self.dir)? {
+            return Ok(());
+        }
+        if key_names.legacy_names(&self.stored_entries()?)?.is_empty() {
+            return crate::manifest::mark_hashed_names(&self.dir);
+        }
+        let _lock = atomic::lock(&self.dir.join(crate::version::WRITE_LOCK_NAME))?;
+        // Another process may have moved them while the lock was awaited
+        if crate::manifest::has_hashed_names(&self.dir)? {
+            return Ok(());
+        }
+        for (name, hashed) in key_names.legacy_names(&self.stored_entries()?)? {
+            if self.exists(&hashed)? {
+                continue;
+            }
+            match self.rename(&name, &hashed) {
+                Err(e) if crate::error::is_not_found(&e) => (),
+                renamed => renamed?,
+            }
+        }
+        crate::manifest::mark_hashed_names(&self.dir)
+    }
+
+    /// The name of the entry of a key with an extension
+    pub(crate) fn entry_name(&self, key: &str, extension: &str) -> String {
+        match &self.key_names {
+            Some(key_names) => key_names.entry_name(key, extension),
+            None => format!("{}.{}", key, extension),
+        }
+    }
+
+    /// The file holding an entry, the database file for the SQLite backend
+    pub(crate) fn path(&self, name: &str) -> PathBuf {
+        #[cfg(feature = "sqlite")]
+        if self.sqlite.is_some() {
+            return self.dir.join(crate::sqlite::DATABASE_NAME);
+        }
+        if let Some(index) = &self.index {
+            return index.path(name);
+        }
+        let path = self.dir.join(name);
+        match &self.archive {
+            Some(archive) if !path.exists() && archive.contains(name).unwrap_or(false) => {
+                archive.path()
+            }
+            _ => path,
+        }
+    }
+
+    /// Whether entries are written readable by the current user only
+    pub(crate) fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// Reads an entry, fails with a `NotFound` io error if it does not exist
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self),
+            fields(bytes = tracing::field::Empty),
+            err(level = "debug")
+        )
+    )]
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>> {
+        if let Some(change) = self.pending(name) {
+            return change
+                .map(|(bytes, _)| bytes)
+                .ok_or_else(|| crate::error::not_found(name));
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            return record_size(db.read(name));
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(local) = &self.local {
+            return record_size(local.read(name));
+        }
+        if let Some(index) = &self.index {
+            return record_size(index.read(name));
+        }
+        let path = self.dir.join(name);
+        match atomic::retry(&path, || std::fs::read(&path)) {
+            Err(e) if crate::error::is_not_found(&e) => match self.archived(name)? {
+                Some(bytes) => record_size(Ok(bytes)),
+                None => Err(e),
+            },
+            read => record_size(read),
+        }
+    }
+
+    /// The archived copy of an entry that has no file of its own
+    fn archived(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match &self.archive {
+            Some(archive) => archive.read(name),
+            None => Ok(None),
+        }
+    }
+
+    /// Atomically replaces an entry
+    pub(crate) fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.write_with(name, bytes, self.private)
+    }
+
+    /// Atomically replaces an entry with one only the current user can read,
+    /// even if the store is not private
+    pub(crate) fn write_private(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.write_with(name, bytes, true)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self, bytes),
+            fields(bytes = bytes.len()),
+            err(level = "debug")
+        )
+    )]
+    fn write_with(&self, name: &str, bytes: &[u8], private: bool) -> Result<()> {
+        self.record_key(name)?;
+        if let Some(debouncer) = &self.debouncer {
+            return debouncer.write(name, Some((bytes.to_vec(), private)));
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            db.write(name, bytes)?;
+            if private {
+                crate::secure::restrict_file(&self.path(name))?;
+            }
+            return Ok(());
+        }
+        // localStorage is readable by the pages of the origin only
+        #[cfg(feature = "wasm")]
+        if let Some(local) = &self.local {
+            return local.write(name, bytes);
+        }
+        if let Some(index) = &self.index {
+            return index.write(name, bytes, private);
+        }
+        let path = crate::symlink::write_path(&self.dir.join(name), self.symlinks)?;
+        if private {
+            atomic::write_private(&path, bytes)
+        } else {
+            atomic::write(&path, bytes)
+        }
+    }
+
+    /// Atomically replaces an entry with a file of its own that has the given access,
+    /// without the debouncer. Only the files backend keeps each entry in a file of its own
+    pub(crate) fn write_with_access(
+        &self,
+        name: &str,
+        bytes: &[u8],
+        access: FileAccess,
+    ) -> Result<()> {
+        self.record_key(name)?;
+        // A debounced older version must not replace this one later
+        self.drain()?;
+        let path = crate::symlink::write_path(&self.dir.join(name), self.symlinks)?;
+        let mut file = AtomicFile::create_private(&path)?;
+        file.write_all(bytes)?;
+        access.apply(file.as_file())?;
+        file.persist()
+    }
+
+    /// Removes an entry, fails with a `NotFound` io error if it does not exist
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err(level = "debug"))
+    )]
+    pub(crate) fn remove(&self, name: &str) -> Result<()> {
+        if let Some(debouncer) = &self.debouncer {
+            if !self.exists(name)? {
+                return Err(crate::error::not_found(name));
+            }
+            return debouncer.write(name, None);
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            return db.remove(name);
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(local) = &self.local {
+            return local.remove(name);
+        }
+        if let Some(index) = &self.index {
+            return index.remove(name);
+        }
+        let path = self.dir.join(name);
+        let removed = atomic::retry(&path, || std::fs::remove_file(&path));
+        match &self.archive {
+            // The archived copy would come back once the file is gone
+            Some(archive) => match (removed, archive.remove(&[name])?) {
+                (Err(e), _) if !crate::error::is_not_found(&e) => Err(e),
+                (Err(_), true) => Ok(()),
+                (removed, _) => removed,
+            },
+            None => removed,
+        }
+    }
+
+    /// Moves an entry to a new name, replacing any entry already there
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err(level = "debug"))
+    )]
+    pub(crate) fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.drain()?;
+        self.record_key(to)?;
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            return db.rename(from, to);
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(local) = &self.local {
+            return local.rename(from, to);
+        }
+        if let Some(index) = &self.index {
+            return index.rename(from, to);
+        }
+        let (from_path, to_path) = (self.dir.join(from), self.dir.join(to));
+        let archive = match &self.archive {
+            Some(archive) => archive,
+            None => return Ok(std::fs::rename(from_path, to_path)?),
+        };
+        if from_path.exists() {
+            std::fs::rename(from_path, &to_path)?;
+        } else {
+            // Archived entries get their file back under the new name
+            let bytes = archive
+                .read(from)?
+                .ok_or_else(|| crate::error::not_found(from))?;
+            if self.private {
+                atomic::write_private(&to_path, &bytes)?;
+            } else {
+                atomic::write(&to_path, &bytes)?;
+            }
+        }
+        archive.remove(&[from, to])?;
+        Ok(())
+    }
+
+    pub(crate) fn exists(&self, name: &str) -> Result<bool> {
+        if let Some(change) = self.pending(name) {
+            return Ok(change.is_some());
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            return db.exists(name);
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(local) = &self.local {
+            return local.exists(name);
+        }
+        if let Some(index) = &self.index {
+            return index.exists(name);
+        }
+        Ok(self.dir.join(name).exists() || self.archived(name)?.is_some())
+    }
+
+    /// Writes the debounced changes and makes every completed write durable, check `flush_dir`
+    pub(crate) fn flush(&self) -> Result<()> {
+        self.drain()?;
+        flush_dir(self.backend, &self.dir)
+    }
+
+    /// Writes the debounced changes now
+    fn drain(&self) -> Result<()> {
+        match &self.debouncer {
+            Some(debouncer) => debouncer.shared().drain(),
+            None => Ok(()),
+        }
+    }
+
+    /// The debounced change of an entry that did not reach the backend yet
+    fn pending(&self, name: &str) -> Option<Change> {
+        self.debouncer.as_ref()?.get(name)
+    }
+
+    /// Whether the last change of an entry is buffered in memory, not written yet
+    pub(crate) fn is_buffered(&self, name: &str) -> bool {
+        self.pending(name).is_some()
+    }
+
+    /// Maps the file of an entry, None if it is too small to be worth mapping
+    /// or if the entry does not live in its own file
+    #[cfg(all(feature = "mmap", unix))]
+    pub(crate) fn map(&self, name: &str) -> Result<Option<crate::mmap::Mmap>> {
+        if self.backend != Backend::Files || self.pending(name).is_some() {
+            return Ok(None);
+        }
+        // Archived entries are read from the archive
+        if !self.dir.join(name).exists() {
+            return Ok(None);
+        }
+        crate::mmap::Mmap::open(&self.dir.join(name))
+    }
+
+    /// Refreshes the modification time of an entry
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    pub(crate) fn touch(&self, name: &str) -> Result<()> {
+        // Debounced entries get a new modification time when they are written
+        if self.pending(name).is_some() {
+            return Ok(());
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            return db.touch(name);
+        }
+        // localStorage keeps no modification times
+        #[cfg(feature = "wasm")]
+        if self.local.is_some() {
+            return Ok(());
+        }
+        if let Some(index) = &self.index {
+            return index.touch(name);
+        }
+        // Archived entries get their file back, like when they are written
+        if let Some(bytes) = self.archived(name)? {
+            if !self.dir.join(name).exists() {
+                return self.write(name, &bytes);
+            }
+        }
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.dir.join(name))?;
+        Ok(file.set_modified(SystemTime::now())?)
+    }
+
+    /// Opens a sink to stream a new version of an entry, applied by `commit`
+    pub(crate) fn sink(&self, name: &str) -> Result<Sink> {
+        #[cfg(feature = "sqlite")]
+        if self.sqlite.is_some() {
+            return Ok(Sink::Memory(Vec::new()));
+        }
+        #[cfg(feature = "wasm")]
+        if self.local.is_some() {
+            return Ok(Sink::Memory(Vec::new()));
+        }
+        if self.index.is_some() {
+            return Ok(Sink::Memory(Vec::new()));
+        }
+        let path = self.dir.join(name);
+        Ok(Sink::File(if self.private {
+            AtomicFile::create_private(&path)?
+        } else {
+            AtomicFile::create(&path)?
+        }))
+    }
+
+    /// Atomically replaces an entry with what was written to the sink
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(self, sink),
+            fields(bytes = tracing::field::Empty),
+            err(level = "debug")
+        )
+    )]
+    pub(crate) fn commit(&self, name: &str, sink: Sink) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        if let Ok(len) = sink.len() {
+            tracing::Span::current().record("bytes", len);
+        }
+        match sink {
+            Sink::File(file) => {
+                self.record_key(name)?;
+                // A debounced older version must not replace the streamed one later
+                self.drain()?;
+                file.persist()
+            }
+            Sink::Memory(bytes) => self.write(name, &bytes),
+        }
+    }
+
+    /// Opens a reader streaming an entry
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err(level = "debug"))
+    )]
+    pub(crate) fn source(&self, name: &str) -> Result<Box<dyn Read + Send>> {
+        if self.pending(name).is_some() {
+            return Ok(Box::new(std::io::Cursor::new(self.read(name)?)));
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            return Ok(Box::new(std::io::Cursor::new(db.read(name)?)));
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(local) = &self.local {
+            return Ok(Box::new(std::io::Cursor::new(local.read(name)?)));
+        }
+        if let Some(index) = &self.index {
+            return Ok(Box::new(std::io::Cursor::new(index.read(name)?)));
+        }
+        match File::open(self.dir.join(name)) {
+            Err(e) if e.kind() == ErrorKind::NotFound => match self.archived(name)? {
+                Some(bytes) => Ok(Box::new(std::io::Cursor::new(bytes))),
+                None => Err(e.into()),
+            },
+            file => Ok(Box::new(BufReader::new(file?))),
+        }
+    }
+
+    /// Every entry, skipping hidden and temporary files
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err(level = "debug"))
+    )]
+    pub(crate) fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = self.stored_entries()?;
+        if let Some(debouncer) = &self.debouncer {
+            debouncer.overlay(&mut entries);
+        }
+        if let Some(key_names) = &self.key_names {
+            key_names.restore_keys(&mut entries)?;
+        }
+        Ok(entries)
+    }
+
+    /// The key of an entry name, including hashed names
+    pub(crate) fn key_of(&self, name: &str) -> Result<Option<String>> {
+        match &self.key_names {
+            Some(key_names) => key_names.key_of(name),
+            None => Ok(entry_key(name).map(str::to_string)),
+        }
+    }
+
+    /// Keeps the key of an entry with a hashed name, so that it is listed
+    fn record_key(&self, name: &str) -> Result<()> {
+        match &self.key_names {
+            Some(key_names) => key_names.record(name),
+            None => Ok(()),
+        }
+    }
+
+    fn stored_entries(&self) -> Result<Vec<Entry>> {
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.sqlite {
+            return db.entries();
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(local) = &self.local {
+            return local.entries();
+        }
+        if let Some(index) = &self.index {
+            return index.entries();
+        }
+        let mut entries = file_entries(&self.dir)?;
+        if let Some(archive) = &self.archive {
+            let names: std::collections::BTreeSet<_> =
+                entries.iter().map(|entry| entry.name.clone()).collect();
+            let archived = archive.entries()?;
+            entries.extend(
+                archived
+                    .into_iter()
+                    .filter(|entry| !names.contains(&entry.name)),
+            );
+        }
+        Ok(entries)
+    }
+}
+
+/// Destination of a streamed entry, a temporary file or a memory buffer
+pub(crate) enum Sink {
+    File(AtomicFile),
+    Memory(Vec<u8>),
+}
+
+impl Sink {
+    /// Number of bytes written so far
+    pub(crate) fn len(&self) -> Result<u64> {
+        match self {
+            Sink::File(file) => file.len(),
+            Sink::Memory(bytes) => Ok(bytes.len() as u64),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Sink::File(file) => file.write(buf),
+            Sink::Memory(bytes) => bytes.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Sink::File(file) => file.flush(),
+            Sink::Memory(_) => Ok(()),
+        }
+    }
+}
+
+/// Records the size of what was read on the current span
+fn record_size(result: Result<Vec<u8>>) -> Result<Vec<u8>> {
+    #[cfg(feature = "tracing")]
+    if let Ok(bytes) = &result {
+        tracing::Span::current().record("bytes", bytes.len());
+    }
+    result
+}
+
+pub(crate) fn file_entries(dir: &Path) -> Result<Vec<Entry>> {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries = Vec::new();
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry?;
+        let name = match dir_entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue,
+        };
+        // Entries deleted or renamed since the directory was listed are skipped
+        let metadata = match dir_entry.metadata() {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => continue,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(key) = entry_key(&name) {
+            entries.push(Entry {
+                key: key.to_string(),
+                size: metadata.len(),
+                modified: metadata.modified()?,
+                name,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// The key an entry name belongs to, the name without its extension
+pub(crate) fn entry_key(name: &str) -> Option<&str> {
+    name.rsplit_once('.').map(|(key, _)| key)
+}
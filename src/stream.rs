@@ -0,0 +1,119 @@
+use crate::storage::Sink;
+use crate::{compression, ChangeEvent, Configstore};
+use anyhow::Result;
+use std::io::{Read, Write};
+
+/// Streams a value into a key's file without building it in memory first
+/// Nothing is visible to readers until `finish` atomically moves the file in place
+/// Dropping the writer without calling `finish` discards what was written
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI};
+///
+/// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+/// let mut writer = config_store.writer("numbers").unwrap();
+/// serde_json::to_writer(&mut writer, &vec![1, 2, 3]).unwrap();
+/// writer.finish().unwrap();
+///
+/// let numbers: Vec<u32> = serde_json::from_reader(config_store.reader("numbers").unwrap()).unwrap();
+/// assert_eq!(numbers, vec![1, 2, 3]);
+/// ```
+pub struct KeyWriter<'a> {
+    store: &'a Configstore,
+    key: String,
+    name: String,
+    sink: Sink,
+}
+
+impl<'a> KeyWriter<'a> {
+    /// Moves the written file in place of the key's previous value
+    /// # Errors
+    /// Could error if the file cannot be flushed or renamed
+    /// or with `Error::QuotaExceeded` if the value does not fit in the store's quota
+    pub fn finish(mut self) -> Result<()> {
+        self.sink.flush()?;
+        let size = self.sink.len()?;
+        self.store.enforce_quota(&self.name, size)?;
+        self.store.inner.storage.commit(&self.name, self.sink)?;
+        self.store
+            .remove_other_formats(&self.key, self.store.key_format(&self.key))?;
+        self.store
+            .changed(ChangeEvent::Set(self.key.clone()), &self.name)
+    }
+}
+
+impl<'a> Write for KeyWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.sink.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+impl Configstore {
+    /// Opens a writer streaming the serialized value of a key, in the key's format
+    /// Values written this way are never compressed
+    /// Check the KeyWriter docs for usage
+    /// # Errors
+    /// Could error if the temporary file cannot be created
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn writer(&self, key: &str) -> Result<KeyWriter<'_>> {
+        self.check_writable()?;
+        let name = self.key_name(key);
+        Ok(KeyWriter {
+            store: self,
+            key: key.to_string(),
+            sink: self.inner.storage.sink(&name)?,
+            name,
+        })
+    }
+
+    /// Opens a reader streaming the serialized value of a key, in the format it is stored in
+    /// Compressed values are decompressed on the fly
+    /// Check the KeyWriter docs for usage
+    /// # Errors
+    /// Could error if the key was never set or if the file cannot be opened
+    pub fn reader(&self, key: &str) -> Result<impl Read> {
+        let format = self.stored_format(key)?.unwrap_or(self.key_format(key));
+        let name = self.entry_name(key, format.extension());
+        let source = self.inner.storage.source(&name)?;
+        self.touch(&name);
+        compression::reader(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn unfinished_writer_keeps_previous_value() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set("kept", String::from("before")).unwrap();
+        let mut writer = config_store.writer("kept").unwrap();
+        writer.write_all(b"\"after\"").unwrap();
+        drop(writer);
+        assert_eq!(config_store.get::<String>("kept").unwrap(), "before");
+    }
+
+    #[test]
+    fn finished_writer_replaces_value() {
+        let config_store = Configstore::temp().unwrap();
+        let mut writer = config_store.writer("streamed").unwrap();
+        writer.write_all(b"\"after\"").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(config_store.get::<String>("streamed").unwrap(), "after");
+        let mut contents = String::new();
+        config_store
+            .reader("streamed")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "\"after\"");
+    }
+}
@@ -0,0 +1,243 @@
+use crate::error::is_not_found;
+use crate::Configstore;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// How often the file of a subscribed key is checked for changes made by hand
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The value of a key kept up to date in memory, returned by `subscribe`
+/// Reading it is as cheap as cloning an Arc, so long-running services can read it for every
+/// request and always see the latest settings without decoding them again
+/// The value is decoded again when the key is set or deleted through the store, by other
+/// processes with the builder's `ipc_events`, or when its file is edited by hand. A key that
+/// is not set reads as `S::default()`, and a new value that fails to decode keeps the previous one
+///
+/// # Examples
+///
+/// ```
+/// use configstore::Configstore;
+/// use serde_derive::Deserialize;
+///
+/// #[derive(Deserialize, Default)]
+/// struct Limits {
+///     max_connections: u32,
+/// }
+///
+/// let config_store = Configstore::temp().unwrap();
+/// let limits = config_store.subscribe::<Limits>("limits").unwrap();
+/// assert_eq!(limits.current().max_connections, 0);
+///
+/// config_store.set_raw("limits", serde_json::json!({"max_connections": 64})).unwrap();
+/// assert_eq!(limits.current().max_connections, 64);
+/// assert_eq!(limits.generation(), 1);
+/// ```
+pub struct Subscription<S> {
+    shared: Arc<Shared<S>>,
+    /// Checks the file of the key, stopped and joined when dropped
+    thread: Option<JoinHandle<()>>,
+    /// The id of the listener reloading the value, removed from the store when dropped
+    listener: u64,
+}
+
+struct Shared<S> {
+    store: Configstore,
+    key: String,
+    current: RwLock<Arc<S>>,
+    generation: AtomicU64,
+    /// The modification time and size of the file of the key when it was last read
+    read: Mutex<Option<(SystemTime, u64)>>,
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl<S> Subscription<S> {
+    /// The latest value of the key
+    pub fn current(&self) -> Arc<S> {
+        self.shared
+            .current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// How many times the value was replaced since the subscription started,
+    /// to tell whether the value changed between two reads
+    pub fn generation(&self) -> u64 {
+        self.shared.generation.load(Ordering::Acquire)
+    }
+
+    /// The subscribed key
+    pub fn key(&self) -> &str {
+        &self.shared.key
+    }
+}
+
+impl<S> Drop for Subscription<S> {
+    fn drop(&mut self) {
+        self.shared.store.inner.listeners.remove(self.listener);
+        *self.shared.stopped() = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<S> Shared<S> {
+    fn stopped(&self) -> MutexGuard<'_, bool> {
+        self.stopped.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<S> Shared<S>
+where
+    S: DeserializeOwned + Default,
+{
+    fn load(&self) -> Result<S> {
+        match self.store.get(&self.key) {
+            Err(e) if is_not_found(&e) => Ok(S::default()),
+            loaded => loaded,
+        }
+    }
+
+    /// The modification time and size of the file of the key, None if it has no file
+    fn file_state(&self) -> Option<(SystemTime, u64)> {
+        let metadata = std::fs::metadata(self.store.config_path(&self.key)).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// Decodes the value again and swaps it in, unless it fails to decode
+    fn reload(&self) {
+        let mut read = self.read.lock().unwrap_or_else(|e| e.into_inner());
+        *read = self.file_state();
+        if let Ok(value) = self.load() {
+            *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(value);
+            self.generation.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Reloads the value whenever its file changes, until the subscription is dropped
+    fn run(&self) {
+        let mut stopped = self.stopped();
+        while !*stopped {
+            stopped = self
+                .wake
+                .wait_timeout(stopped, POLL_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+            if *stopped {
+                break;
+            }
+            drop(stopped);
+            let read = *self.read.lock().unwrap_or_else(|e| e.into_inner());
+            if self.file_state() != read {
+                self.reload();
+            }
+            stopped = self.stopped();
+        }
+    }
+}
+
+impl Configstore {
+    /// Subscribes to the value of a key decoded as S, check the Subscription docs
+    ///
+    /// # Errors
+    /// Fails if the current value of the key cannot be read or decoded as S,
+    /// or if the thread checking its file cannot be started
+    pub fn subscribe<S>(&self, key: &str) -> Result<Subscription<S>>
+    where
+        S: DeserializeOwned + Default + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            store: self.clone(),
+            key: key.to_string(),
+            current: RwLock::new(Arc::new(S::default())),
+            generation: AtomicU64::new(0),
+            read: Mutex::new(None),
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        });
+        *shared.read.lock().unwrap() = shared.file_state();
+        *shared.current.write().unwrap() = Arc::new(shared.load()?);
+
+        let background = Arc::clone(&shared);
+        let thread = std::thread::Builder::new()
+            .name("configstore-subscribe".to_string())
+            .spawn(move || background.run())?;
+        // The listener must not keep the subscription alive, the store outlives it
+        let listening = Arc::downgrade(&shared);
+        let key = key.to_string();
+        let listener = self
+            .inner
+            .listeners
+            .add(move |event| match listening.upgrade() {
+                Some(shared) if event.key() == key => shared.reload(),
+                _ => (),
+            });
+        Ok(Subscription {
+            shared,
+            thread: Some(thread),
+            listener,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+    use serde_derive::Deserialize;
+    use std::time::{Duration, Instant};
+
+    #[derive(Debug, Deserialize, Default, PartialEq)]
+    struct Settings {
+        workers: u32,
+    }
+
+    #[test]
+    fn values_follow_the_key_and_its_file() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .set_raw("settings", serde_json::json!({"workers": 4}))
+            .unwrap();
+        let settings = config_store.subscribe::<Settings>("settings").unwrap();
+        let before = settings.current();
+        assert_eq!(before.workers, 4);
+        assert_eq!(settings.generation(), 0);
+
+        config_store
+            .set_raw("settings", serde_json::json!({"workers": 8}))
+            .unwrap();
+        assert_eq!(settings.current().workers, 8);
+        assert_eq!(before.workers, 4);
+        assert_eq!(settings.generation(), 1);
+
+        // Edited by hand, a bad edit keeps the last good value
+        let path = config_store.config_path("settings");
+        std::fs::write(&path, r#"{"workers": 16}"#).unwrap();
+        let started = Instant::now();
+        while settings.generation() == 1 && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(settings.current().workers, 16);
+        std::fs::write(&path, "{").unwrap();
+        std::thread::sleep(Duration::from_millis(1500));
+        assert_eq!(settings.current().workers, 16);
+
+        config_store.delete("settings").unwrap();
+        assert_eq!(*settings.current(), Settings::default());
+        assert_eq!(
+            *config_store.subscribe::<u32>("settings").unwrap().current(),
+            0
+        );
+        assert_eq!(settings.key(), "settings");
+
+        // Dropped subscriptions stop listening to the store
+        drop(settings);
+        assert_eq!(config_store.inner.listeners.len(), 0);
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::Result;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// How many links are followed before giving up, so that cycles end
+const MAX_LINKS: usize = 40;
+
+/// What writes do with the files of keys that are symbolic links, like the ones dotfiles
+/// managers such as stow or chezmoi put in the config directory. Reads always follow links
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI, SymlinkPolicy};
+///
+/// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+///     .symlink_policy(SymlinkPolicy::Replace)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SymlinkPolicy {
+    /// Writes to the file the link points to and keeps the link (the default),
+    /// so that the dotfiles repository sees the change
+    #[default]
+    Follow,
+    /// Replaces the link with a regular file, the file it pointed to is left unchanged
+    Replace,
+}
+
+/// The file a write to path goes to under a policy
+pub(crate) fn write_path(path: &Path, policy: SymlinkPolicy) -> Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    if policy == SymlinkPolicy::Replace {
+        return Ok(path);
+    }
+    for _ in 0..MAX_LINKS {
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => (),
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            // A link to a file that does not exist yet creates it
+            _ => return Ok(path),
+        }
+        let target = std::fs::read_link(&path)?;
+        path = match path.parent() {
+            Some(dir) => dir.join(target),
+            None => target,
+        };
+    }
+    Err(anyhow::Error::msg(format!(
+        "Too many levels of symbolic links at {}",
+        path.display()
+    )))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::{Configstore, SymlinkPolicy};
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn writes_follow_or_replace_links() {
+        let config_store = Configstore::temp().unwrap();
+        let dotfiles = config_store.dir().join("dotfiles");
+        std::fs::create_dir(&dotfiles).unwrap();
+        std::fs::write(dotfiles.join("theme.json"), "\"light\"").unwrap();
+        symlink("dotfiles/theme.json", config_store.config_path("theme")).unwrap();
+        symlink("dotfiles/font.json", config_store.config_path("font")).unwrap();
+
+        assert_eq!(config_store.get::<String>("theme").unwrap(), "light");
+        config_store.set("theme", "dark").unwrap();
+        config_store.set("font", "mono").unwrap();
+        let read = |name: &str| std::fs::read_to_string(dotfiles.join(name)).unwrap();
+        assert_eq!(read("theme.json"), "\"dark\"");
+        assert_eq!(read("font.json"), "\"mono\"");
+        assert!(config_store
+            .config_path("theme")
+            .symlink_metadata()
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        let replacing = config_store
+            .reopen()
+            .symlink_policy(SymlinkPolicy::Replace)
+            .build()
+            .unwrap();
+        replacing.set("theme", "solarized").unwrap();
+        assert_eq!(read("theme.json"), "\"dark\"");
+        assert_eq!(replacing.get::<String>("theme").unwrap(), "solarized");
+
+        symlink("loop.json", config_store.config_path("loop")).unwrap();
+        assert!(config_store.set("loop", 1).is_err());
+    }
+}
@@ -0,0 +1,497 @@
+use crate::error::is_not_found;
+use crate::merge::{self, ArrayStrategy, Side, Stamps};
+use crate::{atomic, ChangeEvent, Configstore, Format};
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File holding the document as it was after the last sync, to tell local and remote changes apart
+const SYNC_BASE_NAME: &str = ".sync.json";
+const SYNC_LOCK_NAME: &str = ".sync.lock";
+
+/// Where a store's values are synced to, only has to move the synced document around
+/// Implemented by `FileRemote` and by `HttpRemote` with the `http-sync` feature,
+/// implement it to sync through any other service
+pub trait Remote {
+    /// Downloads the document last uploaded, None if nothing was uploaded yet
+    fn download(&self) -> Result<Option<Vec<u8>>>;
+    /// Replaces the remote document
+    fn upload(&self, document: &[u8]) -> Result<()>;
+}
+
+/// A remote kept in a single file, for example in a folder synced by Dropbox or Syncthing
+pub struct FileRemote {
+    path: PathBuf,
+}
+
+impl FileRemote {
+    /// Syncs through the file at path, created on the first push
+    pub fn new<P>(path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        FileRemote { path: path.into() }
+    }
+}
+
+impl Remote for FileRemote {
+    fn download(&self) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn upload(&self, document: &[u8]) -> Result<()> {
+        atomic::write(&self.path, document)
+    }
+}
+
+/// A remote reached over HTTP, requires the `http-sync` feature
+/// The document is read with GET and replaced with PUT, which works with WebDAV servers,
+/// S3 presigned urls and simple JSON APIs. A 404 response means nothing was uploaded yet
+#[cfg(feature = "http-sync")]
+pub struct HttpRemote {
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+#[cfg(feature = "http-sync")]
+impl HttpRemote {
+    /// Syncs through the document at url
+    pub fn new(url: &str) -> Self {
+        HttpRemote {
+            url: url.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a header sent with every request, for example to authenticate
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn request(&self, method: &str) -> ureq::Request {
+        self.headers.iter().fold(
+            ureq::request(method, &self.url),
+            |request, (name, value)| request.set(name, value),
+        )
+    }
+}
+
+#[cfg(feature = "http-sync")]
+impl Remote for HttpRemote {
+    fn download(&self) -> Result<Option<Vec<u8>>> {
+        use std::io::Read;
+        match self.request("GET").call() {
+            Ok(response) => {
+                let mut bytes = Vec::new();
+                response.into_reader().read_to_end(&mut bytes)?;
+                Ok(Some(bytes))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn upload(&self, document: &[u8]) -> Result<()> {
+        self.request("PUT")
+            .set("Content-Type", "application/json")
+            .send_bytes(document)?;
+        Ok(())
+    }
+}
+
+/// A key that was changed both locally and remotely since the last sync
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// The key changed on both sides
+    pub key: String,
+    /// The local value, None if the key was deleted locally
+    pub local: Option<Value>,
+    /// The remote value, None if the key was deleted remotely
+    pub remote: Option<Value>,
+    /// When the key was last changed locally
+    pub local_modified: SystemTime,
+    /// When the key was last changed remotely
+    pub remote_modified: SystemTime,
+}
+
+/// Which side of a conflict to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the local value and overwrite the remote one on the next push
+    KeepLocal,
+    /// Replace the local value with the remote one
+    KeepRemote,
+    /// Merge objects field by field, fields changed on both sides keep the newest change
+    /// Values that are not objects keep whichever side was changed last
+    Merge(ArrayStrategy),
+}
+
+/// Merges conflicting objects field by field and replaces conflicting arrays,
+/// the default of pull and push
+pub fn merge_fields(_conflict: &Conflict) -> Resolution {
+    Resolution::Merge(ArrayStrategy::Replace)
+}
+
+/// Keeps whichever side was changed last, as a whole
+pub fn last_write_wins(conflict: &Conflict) -> Resolution {
+    if conflict.remote_modified > conflict.local_modified {
+        Resolution::KeepRemote
+    } else {
+        Resolution::KeepLocal
+    }
+}
+
+/// The synced document, every key with its json value and when it was last changed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct SyncDocument {
+    pub(crate) keys: BTreeMap<String, Synced>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Synced {
+    /// None for keys that were deleted, so the deletion reaches the other machines
+    pub(crate) value: Option<Value>,
+    /// Milliseconds since the unix epoch
+    pub(crate) modified: u64,
+    /// When each field of object values was last changed, so merges keep the newest fields
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) fields: Stamps,
+}
+
+impl Synced {
+    fn side(&self) -> Side<'_> {
+        Side {
+            value: self.value.as_ref(),
+            stamps: &self.fields,
+            modified: self.modified,
+        }
+    }
+}
+
+impl Configstore {
+    /// Applies the changes made on the remote since the last sync to this store
+    /// Objects changed on both sides are merged field by field,
+    /// fields and other values changed on both sides keep whichever was changed last
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, FileRemote};
+    ///
+    /// let remote = FileRemote::new(std::env::temp_dir().join("configstore-sync-example.json"));
+    /// let laptop = Configstore::temp().unwrap();
+    /// laptop.set("theme", "dark").unwrap();
+    /// laptop.push(&remote).unwrap();
+    ///
+    /// let desktop = Configstore::temp().unwrap();
+    /// desktop.pull(&remote).unwrap();
+    /// assert_eq!(desktop.get::<String>("theme").unwrap(), "dark");
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the remote cannot be reached or holds an invalid document,
+    /// or if a value cannot be written. Fails with `Error::ReadOnly` on read-only stores
+    pub fn pull<R>(&self, remote: &R) -> Result<Vec<ChangeEvent>>
+    where
+        R: Remote + ?Sized,
+    {
+        self.pull_with(remote, merge_fields)
+    }
+
+    /// Same as pull, with a callback deciding which side of each conflict to keep
+    ///
+    /// # Errors
+    /// Same as pull
+    pub fn pull_with<R, F>(&self, remote: &R, resolve: F) -> Result<Vec<ChangeEvent>>
+    where
+        R: Remote + ?Sized,
+        F: Fn(&Conflict) -> Resolution,
+    {
+        self.sync(remote, resolve, false)
+    }
+
+    /// Pulls the remote changes, then uploads this store's values so other machines can pull them
+    /// Returns the keys changed locally by the pull
+    ///
+    /// # Errors
+    /// Same as pull, or if the document cannot be uploaded
+    pub fn push<R>(&self, remote: &R) -> Result<Vec<ChangeEvent>>
+    where
+        R: Remote + ?Sized,
+    {
+        self.push_with(remote, merge_fields)
+    }
+
+    /// Same as push, with a callback deciding which side of each conflict to keep
+    ///
+    /// # Errors
+    /// Same as push
+    pub fn push_with<R, F>(&self, remote: &R, resolve: F) -> Result<Vec<ChangeEvent>>
+    where
+        R: Remote + ?Sized,
+        F: Fn(&Conflict) -> Resolution,
+    {
+        self.sync(remote, resolve, true)
+    }
+
+    fn sync<R, F>(&self, remote: &R, resolve: F, upload: bool) -> Result<Vec<ChangeEvent>>
+    where
+        R: Remote + ?Sized,
+        F: Fn(&Conflict) -> Resolution,
+    {
+        self.check_writable()?;
+        let _lock = atomic::lock(&self.inner.storage.dir.join(SYNC_LOCK_NAME))?;
+        let base_path = self.inner.storage.dir.join(SYNC_BASE_NAME);
+        let base = match std::fs::read(&base_path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SyncDocument::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let remote_document = match remote.download()? {
+            Some(bytes) => serde_json::from_slice(&bytes)?,
+            None => SyncDocument::default(),
+        };
+        let local = self.sync_document(&base)?;
+
+        let mut merged = SyncDocument::default();
+        let mut changes = Vec::new();
+        let keys: BTreeSet<&String> = base
+            .keys
+            .keys()
+            .chain(local.keys.keys())
+            .chain(remote_document.keys.keys())
+            .collect();
+        for key in keys {
+            let base_value = base.keys.get(key).and_then(|synced| synced.value.as_ref());
+            let local = local.keys.get(key);
+            let remote = remote_document.keys.get(key);
+            let local_value = local.and_then(|synced| synced.value.as_ref());
+            let remote_value = remote.and_then(|synced| synced.value.as_ref());
+            let resolution = match (local_value != base_value, remote_value != base_value) {
+                (_, false) => Resolution::KeepLocal,
+                (false, true) => Resolution::KeepRemote,
+                (true, true) if local_value == remote_value => Resolution::KeepLocal,
+                (true, true) => {
+                    let conflict = Conflict {
+                        key: key.clone(),
+                        local: local_value.cloned(),
+                        remote: remote_value.cloned(),
+                        local_modified: to_time(local.map_or(0, |synced| synced.modified)),
+                        remote_modified: to_time(remote.map_or(0, |synced| synced.modified)),
+                    };
+                    resolve(&conflict)
+                }
+            };
+            let kept = match resolution {
+                Resolution::KeepLocal => local.cloned(),
+                Resolution::KeepRemote => {
+                    changes.push(self.apply_synced(key, remote_value)?);
+                    remote.cloned()
+                }
+                Resolution::Merge(arrays) => {
+                    let empty = Synced::default();
+                    let local = local.unwrap_or(&empty).side();
+                    let remote = remote.unwrap_or(&empty).side();
+                    let value = merge::three_way("", base_value, &local, &remote, arrays);
+                    if value.as_ref() != local_value {
+                        changes.push(self.apply_synced(key, value.as_ref())?);
+                    }
+                    let modified = local.modified.max(remote.modified);
+                    Some(Synced {
+                        fields: value.as_ref().map_or_else(Stamps::new, |value| {
+                            merge::stamp(value, &[local, remote], modified)
+                        }),
+                        value,
+                        modified,
+                    })
+                }
+            };
+            if let Some(kept) = kept {
+                merged.keys.insert(key.clone(), kept);
+            }
+        }
+
+        let document = serde_json::to_vec(&merged)?;
+        if upload {
+            remote.upload(&document)?;
+        }
+        atomic::write(&base_path, &document)?;
+        Ok(changes)
+    }
+
+    /// The store's values as a synced document, keys deleted since the last sync become tombstones
+    fn sync_document(&self, base: &SyncDocument) -> Result<SyncDocument> {
+        let mut document = SyncDocument::default();
+        for entry in self.inner.storage.entries()? {
+            let extension = entry.extension();
+            if Format::from_extension(extension).is_none() || document.keys.contains_key(&entry.key)
+            {
+                continue;
+            }
+            let value = match self.get_raw(&entry.key) {
+                Ok(value) => value,
+                // Deleted while listing
+                Err(e) if is_not_found(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            let modified = to_millis(entry.modified);
+            let base_side = base.keys.get(&entry.key).map(Synced::side);
+            let synced = Synced {
+                fields: merge::stamp(&value, base_side.as_slice(), modified),
+                value: Some(value),
+                modified,
+            };
+            document.keys.insert(entry.key, synced);
+        }
+        for (key, synced) in &base.keys {
+            if document.keys.contains_key(key) {
+                continue;
+            }
+            // Keys deleted since the last sync become tombstones, older tombstones are kept as is
+            let tombstone = match synced.value {
+                Some(_) => Synced {
+                    value: None,
+                    modified: to_millis(SystemTime::now()),
+                    fields: Stamps::new(),
+                },
+                None => synced.clone(),
+            };
+            document.keys.insert(key.clone(), tombstone);
+        }
+        Ok(document)
+    }
+
+    /// Writes a value pulled from the remote, or deletes the key for tombstones
+    fn apply_synced(&self, key: &str, value: Option<&Value>) -> Result<ChangeEvent> {
+        match value {
+            Some(value) => {
+                self.set_raw(key, value.clone())?;
+                Ok(ChangeEvent::Set(key.to_string()))
+            }
+            None => {
+                match self.delete(key) {
+                    Ok(()) => (),
+                    Err(e) if is_not_found(&e) => (),
+                    Err(e) => return Err(e),
+                }
+                Ok(ChangeEvent::Delete(key.to_string()))
+            }
+        }
+    }
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn to_time(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn remote() -> (crate::location::TempDir, FileRemote) {
+        let dir = crate::location::TempDir::new();
+        std::fs::create_dir_all(dir.path()).unwrap();
+        let remote = FileRemote::new(dir.path().join("remote.json"));
+        (dir, remote)
+    }
+
+    #[test]
+    fn changes_roam_between_stores() {
+        let (_dir, remote) = remote();
+        let laptop = Configstore::temp().unwrap();
+        let desktop = Configstore::temp().unwrap();
+        laptop.set("theme", "dark").unwrap();
+        laptop.set("font", 12).unwrap();
+        laptop.push(&remote).unwrap();
+
+        let changes = desktop.pull(&remote).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(desktop.get::<u32>("font").unwrap(), 12);
+
+        desktop.delete("theme").unwrap();
+        desktop.set("font", 14).unwrap();
+        desktop.push(&remote).unwrap();
+        assert_eq!(
+            laptop.pull(&remote).unwrap(),
+            vec![
+                ChangeEvent::Set("font".to_string()),
+                ChangeEvent::Delete("theme".to_string())
+            ]
+        );
+        assert!(laptop.get::<String>("theme").is_err());
+        assert_eq!(laptop.get::<u32>("font").unwrap(), 14);
+        // Nothing changed since
+        assert!(laptop.push(&remote).unwrap().is_empty());
+        assert!(desktop.pull(&remote).unwrap().is_empty());
+    }
+
+    #[test]
+    fn edits_of_different_fields_are_merged() {
+        let (_dir, remote) = remote();
+        let laptop = Configstore::temp().unwrap();
+        let desktop = Configstore::temp().unwrap();
+        laptop
+            .set("editor", json!({"theme": "light", "font": {"size": 12}}))
+            .unwrap();
+        laptop.push(&remote).unwrap();
+        desktop.pull(&remote).unwrap();
+
+        laptop.set_path("editor", "theme", "dark").unwrap();
+        laptop.push(&remote).unwrap();
+        desktop.set_path("editor", "font.size", 14).unwrap();
+        assert_eq!(
+            desktop.push(&remote).unwrap(),
+            vec![ChangeEvent::Set("editor".to_string())]
+        );
+        laptop.pull(&remote).unwrap();
+        for store in &[laptop, desktop] {
+            assert_eq!(
+                store.get_raw("editor").unwrap(),
+                json!({"theme": "dark", "font": {"size": 14}})
+            );
+        }
+    }
+
+    #[test]
+    fn conflicts_are_resolved_by_the_callback() {
+        let (_dir, remote) = remote();
+        let laptop = Configstore::temp().unwrap();
+        let desktop = Configstore::temp().unwrap();
+        laptop.set("volume", 1).unwrap();
+        laptop.push(&remote).unwrap();
+        desktop.pull(&remote).unwrap();
+
+        laptop.set("volume", 2).unwrap();
+        laptop.push(&remote).unwrap();
+        desktop.set("volume", 3).unwrap();
+        let conflicts = std::cell::RefCell::new(Vec::new());
+        desktop
+            .push_with(&remote, |conflict| {
+                conflicts.borrow_mut().push(conflict.clone());
+                Resolution::KeepLocal
+            })
+            .unwrap();
+        let conflicts = conflicts.into_inner();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].local, Some(json!(3)));
+        assert_eq!(conflicts[0].remote, Some(json!(2)));
+
+        laptop.pull(&remote).unwrap();
+        assert_eq!(laptop.get::<u32>("volume").unwrap(), 3);
+    }
+}
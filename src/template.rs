@@ -0,0 +1,151 @@
+use crate::error::is_not_found;
+use crate::{Configstore, Error, Format};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+impl Configstore {
+    /// Decodes a value read from key like `decode`, replacing the `${name}` references
+    /// of its strings with the value of the key name, or the environment variable name
+    /// when no such key is set. `$${` is kept as a literal `${`
+    pub(crate) fn decode_interpolated<T>(
+        &self,
+        key: &str,
+        format: Format,
+        bytes: &[u8],
+    ) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let value: Value = self.decode(key, format, bytes)?;
+        let value = self.interpolate(value, &mut vec![key.to_string()])?;
+        self.decode_document(key, value)
+    }
+
+    /// Interpolates the strings of a value, stack holds the keys being resolved,
+    /// the key that was read first
+    fn interpolate(&self, value: Value, stack: &mut Vec<String>) -> Result<Value> {
+        Ok(match value {
+            Value::String(text) => Value::String(self.interpolate_str(&text, stack)?),
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.interpolate(item, stack))
+                    .collect::<Result<_>>()?,
+            ),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| Ok((field, self.interpolate(value, stack)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            value => value,
+        })
+    }
+
+    fn interpolate_str(&self, text: &str, stack: &mut Vec<String>) -> Result<String> {
+        let mut interpolated = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            if rest[..start].ends_with('$') {
+                interpolated.push_str(&rest[..start - 1]);
+                interpolated.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            interpolated.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid(stack, format!("{:?} has an unclosed ${{", text)))?;
+            interpolated.push_str(&self.lookup(&rest[start + 2..start + end], stack)?);
+            rest = &rest[start + end + 1..];
+        }
+        interpolated.push_str(rest);
+        Ok(interpolated)
+    }
+
+    /// The text a reference is replaced with
+    fn lookup(&self, name: &str, stack: &mut Vec<String>) -> Result<String> {
+        if stack.iter().any(|key| key == name) {
+            let mut cycle = stack.clone();
+            cycle.push(name.to_string());
+            return Err(invalid(stack, format!("{} is a cycle", cycle.join(" -> "))));
+        }
+        let value: Value = match self.read_value(name) {
+            Ok((format, bytes)) => self.decode(name, format, &bytes)?,
+            Err(e) if is_not_found(&e) => {
+                return std::env::var(name).map_err(|_| {
+                    invalid(
+                        stack,
+                        format!("${{{}}} is neither a key nor an environment variable", name),
+                    )
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        stack.push(name.to_string());
+        let value = self.interpolate(value, stack);
+        stack.pop();
+        Ok(match value? {
+            Value::String(text) => text,
+            Value::Null => String::new(),
+            value => value.to_string(),
+        })
+    }
+}
+
+fn invalid(stack: &[String], reason: String) -> anyhow::Error {
+    Error::Invalid {
+        key: stack[0].clone(),
+        reason,
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, Error};
+
+    #[test]
+    fn references_are_resolved_with_cycle_detection() {
+        let plain = Configstore::temp().unwrap();
+        plain.set("root", "/srv").unwrap();
+        plain.set("cache", "${root}/cache and $${literal}").unwrap();
+        plain.set("port", 8080).unwrap();
+        plain.set("urls", vec!["http://localhost:${port}"]).unwrap();
+        plain.set("a", "${b}").unwrap();
+        plain.set("b", "x${a}").unwrap();
+        assert_eq!(
+            plain.get::<String>("cache").unwrap(),
+            "${root}/cache and $${literal}"
+        );
+
+        let config_store = plain.reopen().interpolate(true).build().unwrap();
+        assert_eq!(
+            config_store.get::<String>("cache").unwrap(),
+            "/srv/cache and ${literal}"
+        );
+        assert_eq!(
+            config_store.get::<Vec<String>>("urls").unwrap(),
+            vec!["http://localhost:8080"]
+        );
+        std::env::set_var("CONFIGSTORE_TEMPLATE_TEST", "from env");
+        config_store
+            .set("env", "${CONFIGSTORE_TEMPLATE_TEST}")
+            .unwrap();
+        assert_eq!(config_store.get::<String>("env").unwrap(), "from env");
+
+        let err = config_store.get::<String>("a").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Invalid {
+                key: "a".to_string(),
+                reason: "a -> b -> a is a cycle".to_string()
+            })
+        );
+        config_store
+            .set("missing", "${NOT_A_KEY_OR_VAR_9f2}")
+            .unwrap();
+        assert!(config_store.get::<String>("missing").is_err());
+    }
+}
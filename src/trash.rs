@@ -0,0 +1,203 @@
+use crate::{atomic, Configstore, Error, Format};
+use anyhow::Result;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Directory of the store holding deleted values, hidden so that it is never listed as a key
+const TRASH_DIR: &str = ".trash";
+
+impl Configstore {
+    /// Moves the entry of a deleted key to the trash, when the store keeps one
+    pub(crate) fn trash_entry(&self, key: &str, name: &str) -> Result<()> {
+        if self.inner.trash.is_none() {
+            return Ok(());
+        }
+        let bytes = self.inner.storage.read(name)?;
+        // A key is in the trash once, with the value it was last deleted with
+        self.remove_trashed(key)?;
+        let dir = self.trash_dir();
+        std::fs::create_dir_all(&dir)?;
+        // Values can be secrets, the trash is readable by the current user only
+        atomic::write_private(&dir.join(name), &bytes)
+    }
+
+    /// Puts back the value a key had when it was deleted, with the `trash` option
+    /// of the builder. Listeners are told about the key as for set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI};
+    /// use std::time::Duration;
+    ///
+    /// let config_store = Configstore::builder("myApp", AppUI::CommandLine)
+    ///     .temp(true)
+    ///     .trash(Duration::from_secs(30 * 24 * 60 * 60))
+    ///     .build()
+    ///     .unwrap();
+    /// config_store.set("shortcuts", vec!["ctrl+k"]).unwrap();
+    /// config_store.delete("shortcuts").unwrap();
+    /// assert_eq!(config_store.trashed_keys().unwrap(), vec!["shortcuts"]);
+    ///
+    /// config_store.restore_deleted("shortcuts").unwrap();
+    /// assert_eq!(config_store.get::<Vec<String>>("shortcuts").unwrap(), vec!["ctrl+k"]);
+    /// assert!(config_store.trashed_keys().unwrap().is_empty());
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the key is not in the trash, or with `Error::AlreadyExists` if it was set again
+    /// since it was deleted, in which case the trashed value is kept
+    /// Fails with `Error::ReadOnly` on read-only stores
+    pub fn restore_deleted(&self, key: &str) -> Result<()> {
+        self.check_writable()?;
+        let name = self
+            .trashed_name(key)?
+            .ok_or_else(|| anyhow::Error::msg(format!("{} is not in the trash", key)))?;
+        if self.stored_format(key)?.is_some() {
+            return Err(Error::AlreadyExists {
+                key: key.to_string(),
+            }
+            .into());
+        }
+        let path = self.trash_dir().join(&name);
+        let bytes = std::fs::read(&path)?;
+        self.write_entry(key, &name, &bytes, false)?;
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// The keys in the trash, in alphabetical order
+    ///
+    /// # Errors
+    /// Could error if the trash directory cannot be read
+    pub fn trashed_keys(&self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for name in self.trash_names()? {
+            keys.extend(self.inner.storage.key_of(&name)?);
+        }
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// Deletes the values that have been in the trash for longer than its retention period,
+    /// all of them if the store does not keep a trash anymore, and returns their keys
+    pub(crate) fn purge_trash(&self) -> Result<(Vec<String>, u64)> {
+        let retention = self.inner.trash.unwrap_or(Duration::ZERO);
+        let mut purged = Vec::new();
+        let mut freed_bytes = 0;
+        for name in self.trash_names()? {
+            let path = self.trash_dir().join(&name);
+            let metadata = std::fs::metadata(&path)?;
+            let expired = metadata
+                .modified()?
+                .elapsed()
+                .is_ok_and(|age| age >= retention);
+            if expired {
+                std::fs::remove_file(&path)?;
+                freed_bytes += metadata.len();
+                purged.extend(self.inner.storage.key_of(&name)?);
+            }
+        }
+        purged.sort();
+        Ok((purged, freed_bytes))
+    }
+
+    /// The name of the trashed entry of a key
+    fn trashed_name(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .trash_names()?
+            .into_iter()
+            .find(|name| match name.rsplit_once('.') {
+                Some((_, extension)) => self.entry_name(key, extension) == *name,
+                None => false,
+            }))
+    }
+
+    fn remove_trashed(&self, key: &str) -> Result<()> {
+        while let Some(name) = self.trashed_name(key)? {
+            std::fs::remove_file(self.trash_dir().join(name))?;
+        }
+        Ok(())
+    }
+
+    /// The names of the entries in the trash, skipping the ones being written
+    fn trash_names(&self) -> Result<Vec<String>> {
+        let read_dir = match std::fs::read_dir(self.trash_dir()) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = Vec::new();
+        for dir_entry in read_dir {
+            match dir_entry?.file_name().into_string() {
+                Ok(name) if is_value(&name) => names.push(name),
+                _ => (),
+            }
+        }
+        Ok(names)
+    }
+
+    fn trash_dir(&self) -> PathBuf {
+        self.dir().join(TRASH_DIR)
+    }
+}
+
+fn is_value(name: &str) -> bool {
+    match name.rsplit_once('.') {
+        Some((key, extension)) => {
+            !key.is_empty() && !key.starts_with('.') && Format::from_extension(extension).is_some()
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn vacuum_purges_expired_trash() {
+        let retention = Duration::from_secs(60 * 60);
+        let config_store = Configstore::temp().unwrap();
+        let trashing = config_store.reopen().trash(retention).build().unwrap();
+        trashing.set("old", 1).unwrap();
+        trashing.set("recent", 2).unwrap();
+        trashing.delete("old").unwrap();
+        trashing.delete("recent").unwrap();
+        trashing.set("recent", 3).unwrap();
+        assert!(matches!(
+            trashing
+                .restore_deleted("recent")
+                .unwrap_err()
+                .downcast_ref(),
+            Some(Error::AlreadyExists { .. })
+        ));
+        assert!(trashing.restore_deleted("never_set").is_err());
+
+        // Keys with hashed names are listed and restored by key
+        trashing.set("a:b", 4).unwrap();
+        trashing.delete("a:b").unwrap();
+        assert_eq!(
+            trashing.trashed_keys().unwrap(),
+            vec!["a:b", "old", "recent"]
+        );
+        trashing.restore_deleted("a:b").unwrap();
+        assert_eq!(trashing.get::<u32>("a:b").unwrap(), 4);
+
+        let path = trashing.trash_dir().join("old.json");
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - retention * 2)
+            .unwrap();
+        let report = trashing.vacuum().unwrap();
+        assert_eq!(report.trashed, vec!["old"]);
+        assert_eq!(trashing.trashed_keys().unwrap(), vec!["recent"]);
+
+        // Without the option nothing is trashed, and vacuum empties the trash
+        config_store.delete("recent").unwrap();
+        assert_eq!(config_store.vacuum().unwrap().trashed, vec!["recent"]);
+        assert!(config_store.trashed_keys().unwrap().is_empty());
+    }
+}
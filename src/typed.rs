@@ -0,0 +1,691 @@
+use crate::error::is_not_found;
+use crate::flush::{Pending, Registration};
+use crate::{Configstore, ReadMode};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, Weak};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Key holding the settings when no other key is chosen
+const DEFAULT_KEY: &str = "settings";
+
+/// Field of the saved settings holding their schema version, written along with them
+const VERSION_FIELD: &str = "$version";
+
+/// Field holding settings that are not an object, next to their schema version
+const VALUE_FIELD: &str = "$value";
+
+/// Upgrades a settings document from one schema version to the next
+type Migration = Box<dyn Fn(Value) -> Result<Value> + Send + Sync>;
+
+/// A store holding a single settings struct, the most common way to use a Configstore
+/// Settings that were never saved load as `S::default()`. Add `#[serde(default)]` to the struct
+/// so that fields added in a new version of the app are filled with their default too
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, TypedStore};
+/// use serde_derive::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Default)]
+/// #[serde(default)]
+/// struct Settings {
+///     theme: String,
+///     font_size: u32,
+/// }
+///
+/// let settings = TypedStore::<Settings>::new(Configstore::temp().unwrap());
+/// assert_eq!(settings.load().unwrap().font_size, 0);
+/// settings.modify(|s| s.font_size = 14).unwrap();
+/// assert_eq!(settings.load().unwrap().font_size, 14);
+/// ```
+pub struct TypedStore<S> {
+    store: Configstore,
+    key: String,
+    version: u32,
+    migrations: BTreeMap<u32, Migration>,
+    autosave: Option<Duration>,
+    /// Started by the first modify of an autosaving store
+    saver: OnceLock<Saver>,
+    _settings: PhantomData<fn() -> S>,
+}
+
+impl<S> TypedStore<S>
+where
+    S: Serialize + DeserializeOwned + Default,
+{
+    /// Keeps the settings in the `settings` key of the store, at schema version 0
+    pub fn new(store: Configstore) -> Self {
+        TypedStore {
+            store,
+            key: DEFAULT_KEY.to_string(),
+            version: 0,
+            migrations: BTreeMap::new(),
+            autosave: None,
+            saver: OnceLock::new(),
+            _settings: PhantomData,
+        }
+    }
+
+    /// Keeps the settings in another key, to have several settings structs in one store
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// Sets the schema version of S, saved along with the settings in a `$version` field
+    /// Bump it when a change to S needs a migration of the saved settings
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Registers how settings saved at version `from` are upgraded to version `from + 1`
+    /// Settings saved before any version was set are at version 0
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, TypedStore};
+    /// use serde_derive::{Deserialize, Serialize};
+    /// use serde_json::json;
+    ///
+    /// #[derive(Serialize, Deserialize, Default)]
+    /// struct Settings {
+    ///     font_size: u32,
+    /// }
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// // Saved by a previous version of the app, which named the field differently
+    /// config_store.set_raw("settings", json!({"size": 12})).unwrap();
+    /// let settings = TypedStore::<Settings>::new(config_store)
+    ///     .version(1)
+    ///     .migration(0, |mut old| {
+    ///         old["font_size"] = old["size"].take();
+    ///         Ok(old)
+    ///     });
+    /// assert_eq!(settings.load().unwrap().font_size, 12);
+    /// ```
+    pub fn migration<F>(mut self, from: u32, migrate: F) -> Self
+    where
+        F: Fn(Value) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.migrations.insert(from, Box::new(migrate));
+        self
+    }
+
+    /// Keeps the changes made by modify in memory and saves them on a background thread
+    /// at most once per interval, for settings changed many times a second
+    /// load sees the unsaved changes. `flush` saves them right away, and dropping the
+    /// TypedStore saves what is left, as does `Configstore::flush_all`
+    /// Changes that fail to save are kept for the next try, and the error is returned by the next `flush`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, TypedStore};
+    /// use std::time::Duration;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// let volume = TypedStore::<u32>::new(config_store).autosave(Duration::from_secs(30));
+    /// for level in 0..100 {
+    ///     volume.modify(|volume| *volume = level).unwrap();
+    /// }
+    /// assert_eq!(volume.load().unwrap(), 99);
+    /// volume.flush().unwrap();
+    /// assert_eq!(volume.store().get::<u32>("settings").unwrap(), 99);
+    /// ```
+    pub fn autosave(mut self, interval: Duration) -> Self {
+        self.autosave = Some(interval);
+        self
+    }
+
+    /// The store the settings are kept in
+    pub fn store(&self) -> &Configstore {
+        &self.store
+    }
+
+    /// Loads the settings, `S::default()` if they were never saved
+    /// Settings saved at an older version are migrated, and saved back unless the store is read-only
+    ///
+    /// # Errors
+    /// Fails if the settings cannot be read or decoded, if a migration fails or is missing,
+    /// or if they were saved by a newer version of the app
+    pub fn load(&self) -> Result<S> {
+        if let Some(document) = self.saver.get().and_then(Saver::unsaved) {
+            return self.decode(document);
+        }
+        let (saved, document) = match self.store.get_raw(&self.key) {
+            Ok(document) => unversioned(&self.key, document)?,
+            Err(e) if is_not_found(&e) => return Ok(S::default()),
+            Err(e) => return Err(e),
+        };
+        if saved > self.version {
+            return Err(anyhow::Error::msg(format!(
+                "{} was saved at version {} by a newer version of the app, this one supports version {}",
+                self.key, saved, self.version
+            )));
+        }
+        if saved == self.version {
+            return self.decode(document);
+        }
+        let mut document = document;
+        for version in saved..self.version {
+            let migrate = self.migrations.get(&version).ok_or_else(|| {
+                anyhow::Error::msg(format!(
+                    "No migration registered from version {} of {}",
+                    version, self.key
+                ))
+            })?;
+            document = migrate(document)?;
+        }
+        let settings = self.decode(document)?;
+        if !self.store.inner.read_only {
+            self.save(&settings)?;
+        }
+        Ok(settings)
+    }
+
+    /// Saves the settings along with the current schema version
+    ///
+    /// # Errors
+    /// Same as the store's set
+    pub fn save(&self, settings: &S) -> Result<()> {
+        let document = serde_json::to_value(settings)?;
+        if let Some(saver) = self.saver.get() {
+            saver.state().unsaved = None;
+        }
+        write(&self.store, &self.key, self.version, &document)
+    }
+
+    /// Loads the settings, changes them and saves them, returning the saved settings
+    /// Autosaving stores save them later, check the autosave docs
+    ///
+    /// # Errors
+    /// Same as load and save
+    pub fn modify<F>(&self, change: F) -> Result<S>
+    where
+        F: FnOnce(&mut S),
+    {
+        let mut settings = self.load()?;
+        change(&mut settings);
+        match self.autosave {
+            Some(interval) => {
+                let document = serde_json::to_value(&settings)?;
+                let saver = self.saver.get_or_init(|| {
+                    Saver::start(self.store.clone(), &self.key, self.version, interval)
+                });
+                saver.state().unsaved = Some(document);
+            }
+            None => self.save(&settings)?,
+        }
+        Ok(settings)
+    }
+
+    /// Saves the changes an autosaving store has not saved yet
+    ///
+    /// # Errors
+    /// Same as save, or the error of a previous background save
+    pub fn flush(&self) -> Result<()> {
+        match self.saver.get() {
+            Some(saver) => saver.shared.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// Describes the settings file with a JSON Schema inferred from `S::default()`, so that editors
+    /// can complete and check the file when users edit it by hand. Every field is optional,
+    /// with its default, and fields that default to None accept any value
+    /// Only the shape of the defaults is known: empty lists accept any item and enums only the
+    /// default variant. With the `schemars` feature, prefer `derived_json_schema`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, TypedStore};
+    /// use serde_derive::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// #[serde(default)]
+    /// struct Settings {
+    ///     theme: String,
+    ///     font_size: u32,
+    /// }
+    ///
+    /// impl Default for Settings {
+    ///     fn default() -> Self {
+    ///         Settings { theme: "light".to_string(), font_size: 12 }
+    ///     }
+    /// }
+    ///
+    /// let settings = TypedStore::<Settings>::new(Configstore::temp().unwrap());
+    /// let schema = settings.json_schema().unwrap();
+    /// assert_eq!(schema["properties"]["font_size"]["type"], "integer");
+    /// assert_eq!(schema["properties"]["theme"]["default"], "light");
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if `S::default()` cannot be serialized
+    pub fn json_schema(&self) -> Result<Value> {
+        let schema = crate::schema::infer(&serde_json::to_value(S::default())?);
+        Ok(self.describe(schema))
+    }
+
+    /// Writes the schema of `json_schema` to a file, like one shipped with the app for editors
+    /// Kept out of the store's directory, where it would be listed as a key
+    ///
+    /// # Errors
+    /// Same as json_schema, or if the file cannot be written
+    pub fn write_json_schema<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        write_schema(path.as_ref(), &self.json_schema()?)
+    }
+
+    /// Completes the schema of S into the schema of the settings file
+    fn describe(&self, mut schema: Value) -> Value {
+        let fields = match schema.as_object_mut() {
+            Some(fields) => fields,
+            None => return schema,
+        };
+        fields
+            .entry("$schema")
+            .or_insert_with(|| Value::from(crate::schema::DRAFT));
+        fields
+            .entry("title")
+            .or_insert_with(|| Value::from(self.key.clone()));
+        // Saved along with the settings, check write
+        if let (true, Some(Value::Object(properties))) =
+            (self.version > 0, fields.get_mut("properties"))
+        {
+            properties.insert(
+                VERSION_FIELD.to_string(),
+                serde_json::json!({ "const": self.version }),
+            );
+        }
+        schema
+    }
+
+    /// Decodes a settings document according to the read mode of the store
+    fn decode(&self, document: Value) -> Result<S> {
+        match self.store.inner.read_mode {
+            ReadMode::Lenient => self.store.decode_lenient_document(&self.key, document),
+            _ => self.store.decode_document(&self.key, document),
+        }
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<S> TypedStore<S>
+where
+    S: Serialize + DeserializeOwned + Default + schemars::JsonSchema,
+{
+    /// Describes the settings file with the JSON Schema schemars derives from S, which knows every
+    /// variant of its enums, the items of its lists, which fields are optional or required and
+    /// the doc comments of its fields, unlike the schema `json_schema` infers from the defaults
+    /// Requires the `schemars` feature
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, TypedStore};
+    /// use schemars::JsonSchema;
+    /// use serde_derive::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize, JsonSchema, Default)]
+    /// struct Settings {
+    ///     /// Where downloads are saved, the downloads directory if unset
+    ///     download_dir: Option<String>,
+    /// }
+    ///
+    /// let settings = TypedStore::<Settings>::new(Configstore::temp().unwrap());
+    /// let schema = settings.derived_json_schema().unwrap();
+    /// assert_eq!(
+    ///     schema["properties"]["download_dir"]["type"],
+    ///     serde_json::json!(["string", "null"])
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the schema cannot be serialized
+    pub fn derived_json_schema(&self) -> Result<Value> {
+        let schema = serde_json::to_value(schemars::schema_for!(S))?;
+        Ok(self.describe(schema))
+    }
+
+    /// Writes the schema of `derived_json_schema` to a file, check the `write_json_schema` docs
+    ///
+    /// # Errors
+    /// Same as derived_json_schema, or if the file cannot be written
+    pub fn write_derived_json_schema<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        write_schema(path.as_ref(), &self.derived_json_schema()?)
+    }
+}
+
+fn write_schema(path: &std::path::Path, schema: &Value) -> Result<()> {
+    let mut bytes = serde_json::to_vec_pretty(schema)?;
+    bytes.push(b'\n');
+    crate::atomic::write(path, &bytes)
+}
+
+/// Saves a settings document with its schema version, in a single write so that they always match
+fn write(store: &Configstore, key: &str, version: u32, document: &Value) -> Result<()> {
+    let versioned = match document {
+        _ if version == 0 => document.clone(),
+        Value::Object(fields) => {
+            let mut versioned = serde_json::Map::new();
+            versioned.insert(VERSION_FIELD.to_string(), Value::from(version));
+            versioned.extend(fields.clone());
+            Value::Object(versioned)
+        }
+        _ => serde_json::json!({ VERSION_FIELD: version, VALUE_FIELD: document }),
+    };
+    store.set(key, versioned)
+}
+
+/// Splits saved settings into their schema version and the settings document
+fn unversioned(key: &str, document: Value) -> Result<(u32, Value)> {
+    let mut fields = match document {
+        Value::Object(fields) if fields.contains_key(VERSION_FIELD) => fields,
+        document => return Ok((0, document)),
+    };
+    let version = fields
+        .remove(VERSION_FIELD)
+        .and_then(|version| version.as_u64())
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or_else(|| {
+            anyhow::Error::msg(format!(
+                "The {} field of {} is not a version",
+                VERSION_FIELD, key
+            ))
+        })?;
+    let document = match fields.remove(VALUE_FIELD) {
+        Some(value) if fields.is_empty() => value,
+        Some(value) => {
+            fields.insert(VALUE_FIELD.to_string(), value);
+            Value::Object(fields)
+        }
+        None => Value::Object(fields),
+    };
+    Ok((version, document))
+}
+
+/// The background thread of an autosaving TypedStore, stopped and joined when dropped
+struct Saver {
+    shared: Arc<SaverShared>,
+    thread: Option<JoinHandle<()>>,
+    /// Lists the unsaved changes among the ones saved by `flush_all`
+    _registration: Option<Registration>,
+}
+
+struct SaverShared {
+    store: Configstore,
+    key: String,
+    version: u32,
+    interval: Duration,
+    state: Mutex<SaverState>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct SaverState {
+    /// The settings changed since they were last saved
+    unsaved: Option<Value>,
+    /// Why the last background save failed, reported by the next flush
+    error: Option<String>,
+    stopped: bool,
+}
+
+impl Saver {
+    fn start(store: Configstore, key: &str, version: u32, interval: Duration) -> Self {
+        let shared = Arc::new(SaverShared {
+            store,
+            key: key.to_string(),
+            version,
+            interval,
+            state: Mutex::default(),
+            wake: Condvar::new(),
+        });
+        let background = Arc::clone(&shared);
+        let thread = std::thread::Builder::new()
+            .name("configstore-autosave".to_string())
+            .spawn(move || background.run())
+            .ok();
+        let registration = if shared.store.inner.read_only {
+            None
+        } else {
+            let pending = Arc::downgrade(&shared) as Weak<dyn Pending>;
+            let backend = shared.store.inner.storage.backend;
+            Some(Registration::new(
+                backend,
+                shared.store.dir(),
+                Some(pending),
+            ))
+        };
+        Saver {
+            shared,
+            thread,
+            _registration: registration,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, SaverState> {
+        self.shared.state()
+    }
+
+    fn unsaved(&self) -> Option<Value> {
+        self.state().unsaved.clone()
+    }
+}
+
+impl SaverShared {
+    fn state(&self) -> MutexGuard<'_, SaverState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn run(&self) {
+        let mut state = self.state();
+        while !state.stopped {
+            state = self
+                .wake
+                .wait_timeout(state, self.interval)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+            drop(state);
+            if let Err(e) = self.save() {
+                self.state().error = Some(e.to_string());
+            }
+            state = self.state();
+        }
+        drop(state);
+        if let Err(e) = self.save() {
+            self.state().error = Some(e.to_string());
+        }
+    }
+
+    /// Saves the unsaved settings now, reporting the error of a previous background save
+    /// Settings that fail to save stay unsaved, unless newer ones replaced them
+    fn save(&self) -> Result<()> {
+        let (unsaved, error) = {
+            let mut state = self.state();
+            (state.unsaved.take(), state.error.take())
+        };
+        let saved = match unsaved {
+            Some(document) => match write(&self.store, &self.key, self.version, &document) {
+                Err(e) => {
+                    self.state().unsaved.get_or_insert(document);
+                    Err(e)
+                }
+                saved => saved,
+            },
+            None => Ok(()),
+        };
+        match error {
+            Some(error) => Err(anyhow::Error::msg(error)),
+            None => saved,
+        }
+    }
+}
+
+impl Pending for SaverShared {
+    /// Saves the unsaved settings, and the writes the store itself keeps in memory
+    fn drain(&self) -> Result<()> {
+        self.save()?;
+        self.store.flush()
+    }
+}
+
+impl Drop for Saver {
+    fn drop(&mut self) {
+        self.state().stopped = true;
+        self.shared.wake.notify_all();
+        match self.thread.take() {
+            Some(thread) => {
+                let _ = thread.join();
+            }
+            // The thread could not start, the changes are saved here instead
+            None => {
+                let _ = self.shared.save();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::*;
+    use serde_json::json;
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    #[serde(default)]
+    struct Settings {
+        theme: String,
+        volume: u8,
+    }
+
+    #[test]
+    fn migrations_run_in_order_once() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .set_raw("settings", json!({"dark": true}))
+            .unwrap();
+        let open = |store: Configstore| {
+            TypedStore::<Settings>::new(store)
+                .version(2)
+                .migration(0, |old| {
+                    let theme = if old["dark"] == true { "dark" } else { "light" };
+                    Ok(json!({ "theme": theme }))
+                })
+                .migration(1, |mut old| {
+                    old["volume"] = json!(5);
+                    Ok(old)
+                })
+        };
+        let settings = open(config_store.reopen().build().unwrap());
+        let expected = Settings {
+            theme: "dark".to_string(),
+            volume: 5,
+        };
+        assert_eq!(settings.load().unwrap(), expected);
+        assert_eq!(
+            config_store.get_raw("settings").unwrap(),
+            json!({"$version": 2, "theme": "dark", "volume": 5})
+        );
+        assert_eq!(config_store.keys().unwrap(), vec!["settings"]);
+
+        config_store
+            .set_raw("settings", json!({"$version": 3}))
+            .unwrap();
+        assert!(settings.load().is_err());
+        assert!(open(config_store)
+            .key("other")
+            .load()
+            .unwrap()
+            .theme
+            .is_empty());
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn derived_schemas_describe_the_type() {
+        #[derive(Serialize, Deserialize, schemars::JsonSchema, Default)]
+        #[serde(deny_unknown_fields)]
+        struct Editor {
+            /// Lines kept visible around the cursor
+            margin: u32,
+            plugins: Vec<String>,
+            font: Option<String>,
+            mode: Mode,
+        }
+
+        #[derive(Serialize, Deserialize, schemars::JsonSchema, Default)]
+        enum Mode {
+            #[default]
+            Insert,
+            Normal,
+        }
+
+        let editor = TypedStore::<Editor>::new(Configstore::temp().unwrap()).version(2);
+        let schema = editor.derived_json_schema().unwrap();
+        let properties = &schema["properties"];
+        assert_eq!(
+            properties["margin"]["description"],
+            "Lines kept visible around the cursor"
+        );
+        assert_eq!(properties["plugins"]["items"]["type"], "string");
+        assert_eq!(properties["font"]["type"], json!(["string", "null"]));
+        assert_eq!(
+            schema["definitions"]["Mode"]["enum"],
+            json!(["Insert", "Normal"])
+        );
+        assert_eq!(schema["required"], json!(["margin", "mode", "plugins"]));
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(properties["$version"], json!({"const": 2}));
+
+        // Derived schemas can be enforced, their enums included
+        editor.store().add_schema("settings", schema).unwrap();
+        editor.save(&Editor::default()).unwrap();
+        let mut edited = editor.store().get_raw("settings").unwrap();
+        edited["mode"] = json!("Visual");
+        assert!(editor.store().set("settings", edited).is_err());
+    }
+
+    #[test]
+    fn autosave_saves_on_drop() {
+        let config_store = Configstore::temp().unwrap();
+        let settings = TypedStore::<Settings>::new(config_store.reopen().build().unwrap())
+            .autosave(Duration::from_secs(60));
+        settings.modify(|s| s.volume = 1).unwrap();
+        settings.modify(|s| s.volume += 1).unwrap();
+        assert_eq!(settings.load().unwrap().volume, 2);
+        assert!(config_store.get_raw("settings").is_err());
+        // Other tests flush their own stores too, only this one is checked
+        let _ = Configstore::flush_all();
+        assert_eq!(config_store.get_raw("settings").unwrap()["volume"], 2);
+
+        // Changes that fail to save are kept for the next try
+        settings.modify(|s| s.volume = 3).unwrap();
+        let blocked = config_store.dir().join("settings.json");
+        std::fs::remove_file(&blocked).unwrap();
+        std::fs::create_dir(&blocked).unwrap();
+        assert!(settings.flush().is_err());
+        assert_eq!(settings.load().unwrap().volume, 3);
+        std::fs::remove_dir(&blocked).unwrap();
+        drop(settings);
+        assert_eq!(config_store.get_raw("settings").unwrap()["volume"], 3);
+    }
+}
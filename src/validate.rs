@@ -0,0 +1,306 @@
+use crate::{Configstore, Error, Format};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Checks the values of a key before they are written, and optionally after they are read
+/// Implemented for closures taking the value and returning a description of what is wrong
+///
+/// # Examples
+///
+/// ```
+/// use configstore::{Configstore, AppUI, Error};
+///
+/// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+/// config_store.add_validator("brightness", |brightness: &u32| {
+///     if *brightness <= 100 {
+///         Ok(())
+///     } else {
+///         Err(format!("{} is above the maximum of 100", brightness))
+///     }
+/// });
+/// config_store.set("brightness", 80).unwrap();
+/// let err = config_store.set("brightness", 200).unwrap_err();
+/// assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Invalid { .. })));
+/// ```
+pub trait Validator<T>: Send + Sync {
+    /// Returns a description of the problem if the value is not acceptable
+    fn validate(&self, value: &T) -> std::result::Result<(), String>;
+}
+
+impl<T, F> Validator<T> for F
+where
+    F: Fn(&T) -> std::result::Result<(), String> + Send + Sync,
+{
+    fn validate(&self, value: &T) -> std::result::Result<(), String> {
+        self(value)
+    }
+}
+
+/// A validator with its value type erased, values are decoded into it from their json equivalent
+type Check = Arc<dyn Fn(&Value) -> std::result::Result<(), String> + Send + Sync>;
+
+/// The validators registered with `add_validator` and the schemas of `add_schema`, by key
+#[derive(Default)]
+pub(crate) struct Validators {
+    checks: Mutex<HashMap<String, Vec<Check>>>,
+    schemas: Mutex<HashMap<String, Arc<Value>>>,
+}
+
+impl Validators {
+    fn add(&self, key: &str, check: Check) {
+        self.checks
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .push(check);
+    }
+
+    fn get(&self, key: &str) -> Vec<Check> {
+        self.checks
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn schema(&self, key: &str) -> Option<Arc<Value>> {
+        self.schemas.lock().unwrap().get(key).cloned()
+    }
+}
+
+impl Configstore {
+    /// Registers a validator that runs whenever a value is written to key, through set,
+    /// set_raw, set_path or an overlay commit. Values it rejects fail with `Error::Invalid`
+    /// and are not written. Values that cannot be decoded as T are rejected too
+    /// With the builder's `validate_reads` option, values read by get are checked as well,
+    /// so a hand-edited file cannot push invalid state into the app
+    /// Check the Validator docs for usage
+    pub fn add_validator<T, V>(&self, key: &str, validator: V)
+    where
+        T: DeserializeOwned + 'static,
+        V: Validator<T> + 'static,
+    {
+        self.inner.validators.add(
+            key,
+            Arc::new(move |value: &Value| {
+                let value = T::deserialize(value).map_err(|e| e.to_string())?;
+                validator.validate(&value)
+            }),
+        );
+    }
+
+    /// Attaches a JSON Schema to key, replacing the previous one. Values written to key
+    /// are checked against it like with a validator, and so are values read by get,
+    /// whether or not the store validates reads, so that mistakes in hand-edited files are caught
+    /// The reason of the `Error::Invalid` lists each problem with the JSON Pointer to where it is
+    /// Schemas made by `TypedStore::json_schema` and `derived_json_schema` work, as do most
+    /// hand-written draft-07 schemas: references inside the schema, types, enum, const,
+    /// properties, required, additionalProperties, dependencies, items, lengths, ranges,
+    /// multipleOf, uniqueItems and the allOf, anyOf, oneOf, not and if combinators are enforced
+    ///
+    /// # Errors
+    /// Fails with `Error::SchemaUnsupported` if the schema uses a keyword that is not enforced,
+    /// like pattern, or refers to another document, rather than letting values skip it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, Error};
+    /// use serde_json::json;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.add_schema(
+    ///     "window",
+    ///     json!({
+    ///         "type": "object",
+    ///         "properties": {
+    ///             "width": {"type": "integer", "minimum": 100},
+    ///             "mode": {"enum": ["windowed", "fullscreen"]},
+    ///         },
+    ///         "required": ["width"],
+    ///     }),
+    /// ).unwrap();
+    /// config_store.set("window", json!({"width": 800, "mode": "windowed"})).unwrap();
+    ///
+    /// let err = config_store.set("window", json!({"width": 50, "mode": "tiny"})).unwrap_err();
+    /// match err.downcast_ref::<Error>() {
+    ///     Some(Error::Invalid { reason, .. }) => assert!(reason.contains("/width: 50 is less than 100")),
+    ///     _ => panic!("expected the value to be invalid"),
+    /// }
+    /// ```
+    pub fn add_schema(&self, key: &str, schema: Value) -> Result<()> {
+        if let Some(reason) = crate::schema::unsupported(&schema) {
+            return Err(Error::SchemaUnsupported {
+                key: key.to_string(),
+                reason,
+            }
+            .into());
+        }
+        self.inner
+            .validators
+            .schemas
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Arc::new(schema));
+        Ok(())
+    }
+
+    /// Runs the validators and schema of a key on its value serialized in format
+    pub(crate) fn validate(&self, key: &str, format: Format, bytes: &[u8]) -> Result<()> {
+        self.run_checks(key, format, bytes, true)
+    }
+
+    /// Runs the schema of a key on a value that was just read,
+    /// and its validators if the store checks reads
+    pub(crate) fn validate_read(&self, key: &str, format: Format, bytes: &[u8]) -> Result<()> {
+        self.run_checks(key, format, bytes, self.inner.validate_reads)
+    }
+
+    fn run_checks(&self, key: &str, format: Format, bytes: &[u8], validators: bool) -> Result<()> {
+        let checks = if validators {
+            self.inner.validators.get(key)
+        } else {
+            Vec::new()
+        };
+        let schema = self.inner.validators.schema(key);
+        if checks.is_empty() && schema.is_none() {
+            return Ok(());
+        }
+        let value: Value = format.deserialize(bytes)?;
+        let invalid = |reason| Error::Invalid {
+            key: key.to_string(),
+            reason,
+        };
+        if let Some(schema) = schema {
+            let problems = crate::schema::check(&schema, &value);
+            if !problems.is_empty() {
+                return Err(invalid(problems.join("; ")).into());
+            }
+        }
+        for check in checks {
+            check(&value).map_err(invalid)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, Error};
+
+    fn at_most_ten(value: &u32) -> Result<(), String> {
+        if *value <= 10 {
+            Ok(())
+        } else {
+            Err(String::from("too large"))
+        }
+    }
+
+    #[test]
+    fn validators_reject_writes() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.add_validator("level", at_most_ten);
+        config_store.set("level", 3).unwrap();
+        let err = config_store.set("level", 11).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Invalid {
+                key: String::from("level"),
+                reason: String::from("too large")
+            })
+        );
+        assert!(config_store.set("level", String::from("three")).is_err());
+        assert!(config_store
+            .set_raw("level", serde_json::json!(20))
+            .is_err());
+        assert_eq!(config_store.get::<u32>("level").unwrap(), 3);
+        // Other keys are not affected
+        config_store.set("other", 11).unwrap();
+    }
+
+    #[test]
+    fn validators_optionally_check_reads() {
+        let unchecked = Configstore::temp().unwrap();
+        unchecked.set("hand_edited", 50).unwrap();
+        let checked = unchecked.reopen().validate_reads(true).build().unwrap();
+        checked.add_validator("hand_edited", at_most_ten);
+        assert!(checked.get::<u32>("hand_edited").is_err());
+        assert!(checked.get_raw("hand_edited").is_err());
+        unchecked.add_validator("hand_edited", at_most_ten);
+        assert_eq!(unchecked.get::<u32>("hand_edited").unwrap(), 50);
+    }
+
+    #[test]
+    fn schemas_check_hand_edited_files() {
+        let config_store = Configstore::temp().unwrap();
+        config_store
+            .add_schema(
+                "editor",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "plugins": {"type": "array", "items": {"type": "string"}},
+                    },
+                    "additionalProperties": false,
+                }),
+            )
+            .unwrap();
+        config_store
+            .set("editor", serde_json::json!({"plugins": ["git"]}))
+            .unwrap();
+        std::fs::write(
+            config_store.config_path("editor"),
+            r#"{"colour": "red", "plugins": ["git", 3]}"#,
+        )
+        .unwrap();
+        let err = config_store.get_raw("editor").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Invalid {
+                key: String::from("editor"),
+                reason: String::from(
+                    "/colour: unknown field; /plugins/1: expected string, found 3"
+                )
+            })
+        );
+
+        // Nested structs of derived schemas are checked through their references
+        config_store
+            .add_schema(
+                "window",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {"size": {"$ref": "#/definitions/Size"}},
+                    "definitions": {
+                        "Size": {
+                            "type": "object",
+                            "properties": {"width": {"type": "integer", "minimum": 100}},
+                        },
+                    },
+                }),
+            )
+            .unwrap();
+        let err = config_store
+            .set("window", serde_json::json!({"size": {"width": 50}}))
+            .unwrap_err();
+        assert!(format!("{}", err).contains("/size/width: 50 is less than 100"));
+        let err = config_store
+            .add_schema(
+                "name",
+                serde_json::json!({"type": "string", "pattern": "^[a-z]+$"}),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::SchemaUnsupported {
+                key: String::from("name"),
+                reason: String::from("/pattern: the pattern keyword is not supported"),
+            })
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{Configstore, Format};
+use anyhow::Result;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The directory where package managers install the defaults of an app:
+/// `/usr/share/<app>/config` on Linux and other Unixes,
+/// `/Library/Application Support/<app>/defaults` on macOS
+/// and `%ProgramData%\<app>\defaults` on Windows
+pub(crate) fn dir(app_name: &str) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        std::env::var_os("PROGRAMDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("C:\\ProgramData"))
+            .join(app_name)
+            .join("defaults")
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from("/Library/Application Support")
+            .join(app_name)
+            .join("defaults")
+    } else {
+        PathBuf::from("/usr/share").join(app_name).join("config")
+    }
+}
+
+impl Configstore {
+    /// The directory the store reads the defaults shipped with the app from,
+    /// None unless enabled with the builder's `vendor_defaults` or `vendor_defaults_dir`
+    pub fn vendor_defaults_dir(&self) -> Option<&Path> {
+        self.inner.vendor_dir.as_deref()
+    }
+
+    /// The value of a key in the vendor defaults directory and its format, None if it has none
+    /// Each key is a file named like in the store, in any enabled format
+    pub(crate) fn read_vendor_default(&self, key: &str) -> Result<Option<(Format, Vec<u8>)>> {
+        let dir = match &self.inner.vendor_dir {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        for format in self.formats() {
+            match std::fs::read(dir.join(self.entry_name(key, format.extension()))) {
+                Ok(bytes) => return Ok(Some((format, bytes))),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Configstore;
+
+    #[test]
+    fn vendor_defaults_are_read_but_never_written() {
+        let vendor = Configstore::temp().unwrap();
+        vendor.set("theme", "light").unwrap();
+        vendor.set("mirror", "https://mirror.distro.org").unwrap();
+        let config_store = Configstore::temp().unwrap();
+        let config_store = config_store
+            .reopen()
+            .vendor_defaults_dir(vendor.dir())
+            .defaults_from_str(r#"{"mirror": "https://example.com"}"#, crate::Format::Json)
+            .build()
+            .unwrap();
+        config_store.set("theme", "dark").unwrap();
+
+        assert_eq!(config_store.get::<String>("theme").unwrap(), "dark");
+        assert_eq!(
+            config_store.get::<String>("mirror").unwrap(),
+            "https://mirror.distro.org"
+        );
+        assert_eq!(config_store.keys().unwrap(), vec!["theme"]);
+        config_store.delete("theme").unwrap();
+        assert_eq!(config_store.get::<String>("theme").unwrap(), "light");
+        assert_eq!(config_store.vendor_defaults_dir(), Some(vendor.dir()));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::error::is_not_found;
+use crate::{atomic, Configstore, Error};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// File locked while a conditional write compares and replaces a value
+pub(crate) const WRITE_LOCK_NAME: &str = ".write.lock";
+
+/// Identifies the contents of a key when it was read by `get_versioned`
+/// Pass it back to `set_if_unchanged` to only write if nobody changed the key in between
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Version(Option<u64>);
+
+impl Version {
+    /// The version of a key that is not set
+    pub const ABSENT: Version = Version(None);
+
+    fn of(bytes: Option<&[u8]>) -> Self {
+        Version(bytes.map(fnv1a))
+    }
+}
+
+/// 64-bit FNV-1a, stable across platforms and compiler versions unlike the std hasher
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+impl Configstore {
+    /// Gets a value along with its version, None if the key is not set
+    /// Used with `set_if_unchanged` for optimistic concurrency between processes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, AppUI, Error};
+    ///
+    /// let config_store = Configstore::new("myApp", AppUI::CommandLine).unwrap();
+    /// config_store.set("counter", 1).unwrap();
+    /// let (counter, version) = config_store.get_versioned::<u32>("counter").unwrap();
+    /// config_store.set_if_unchanged("counter", counter.unwrap() + 1, version).unwrap();
+    /// // The version is now outdated
+    /// let err = config_store.set_if_unchanged("counter", 0, version).unwrap_err();
+    /// assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Conflict { .. })));
+    /// ```
+    ///
+    /// # Errors
+    /// Could produce errors if the file cannot be read or decoded
+    pub fn get_versioned<T>(&self, key: &str) -> Result<(Option<T>, Version)>
+    where
+        T: DeserializeOwned,
+    {
+        let raw = self.read_raw(key)?;
+        let version = Version::of(raw.as_deref());
+        if raw.is_none() {
+            return Ok((None, version));
+        }
+        let (format, bytes) = self.read_bytes(key)?;
+        Ok((Some(self.decode(key, format, &bytes)?), version))
+    }
+
+    /// Sets a value only if the key still has the given version, and returns its new version
+    /// Use `Version::ABSENT` to only create keys that are not set yet
+    /// Concurrent conditional writes are serialized with a file lock, plain `set` calls are not
+    ///
+    /// # Errors
+    /// Fails with `Error::Conflict` if the key was changed since the version was read
+    /// Otherwise same as set
+    pub fn set_if_unchanged<T>(&self, key: &str, value: T, version: Version) -> Result<Version>
+    where
+        T: Serialize,
+    {
+        self.check_writable()?;
+        let _lock = self.lock_for(key)?;
+        if Version::of(self.read_raw(key)?.as_deref()) != version {
+            return Err(Error::Conflict {
+                key: key.to_string(),
+            }
+            .into());
+        }
+        let bytes = self.encode(key, &value)?;
+        self.write_bytes(key, &bytes)?;
+        Ok(Version::of(self.read_raw(key)?.as_deref()))
+    }
+
+    /// Sets a value only if the key is not set yet, for install ids, license acceptance times
+    /// and other one-shot values that must never be silently overwritten
+    /// The check and the write are atomic like `set_if_unchanged`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::{Configstore, Error};
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set_once("license_accepted_at", 1_700_000_000u64).unwrap();
+    /// let err = config_store.set_once("license_accepted_at", 1_800_000_000u64).unwrap_err();
+    /// assert!(matches!(err.downcast_ref::<Error>(), Some(Error::AlreadyExists { .. })));
+    /// assert_eq!(config_store.get::<u64>("license_accepted_at").unwrap(), 1_700_000_000);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with `Error::AlreadyExists` if the key is set, otherwise same as set
+    pub fn set_once<T>(&self, key: &str, value: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        match self.set_if_unchanged(key, value, Version::ABSENT) {
+            Err(e) if matches!(e.downcast_ref::<Error>(), Some(Error::Conflict { .. })) => {
+                Err(Error::AlreadyExists {
+                    key: key.to_string(),
+                }
+                .into())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Sets a value only if the key currently holds expected, returns whether it was swapped
+    /// For simple coordination between processes through the store, like electing a single
+    /// instance or advancing a shared cursor. The comparison and the write are atomic like
+    /// `set_if_unchanged`, the stored value is compared after being decoded as T
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use configstore::Configstore;
+    ///
+    /// let config_store = Configstore::temp().unwrap();
+    /// config_store.set("cursor", 10).unwrap();
+    /// assert!(config_store.compare_and_swap("cursor", &10, 20).unwrap());
+    /// assert!(!config_store.compare_and_swap("cursor", &10, 30).unwrap());
+    /// assert_eq!(config_store.get::<u32>("cursor").unwrap(), 20);
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if the stored value cannot be decoded as T, otherwise same as set
+    /// A key that is not set never holds expected, use `set_once` to create keys
+    pub fn compare_and_swap<T>(&self, key: &str, expected: &T, new: T) -> Result<bool>
+    where
+        T: Serialize + DeserializeOwned + PartialEq,
+    {
+        self.update(key, |current: Option<T>| {
+            if current.as_ref() == Some(expected) {
+                Ok((Some(new), true))
+            } else {
+                Ok((None, false))
+            }
+        })
+    }
+
+    /// Reads the value of a key, None if it is not set, and writes back the value returned by
+    /// change if any. Holds the lock of conditional writes, so that none comes in between
+    pub(crate) fn update<T, R, F>(&self, key: &str, change: F) -> Result<R>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> Result<(Option<T>, R)>,
+    {
+        self.check_writable()?;
+        let _lock = self.lock_for(key)?;
+        let current = match self.read_bytes(key) {
+            Ok((format, bytes)) => Some(self.decode(key, format, &bytes)?),
+            Err(e) if is_not_found(&e) => None,
+            Err(e) => return Err(e),
+        };
+        let (new, result) = change(current)?;
+        if let Some(new) = new {
+            let bytes = self.encode(key, &new)?;
+            self.write_bytes(key, &bytes)?;
+        }
+        Ok(result)
+    }
+
+    /// Takes the lock of the store's read-modify-writes, on behalf of key
+    pub(crate) fn lock_for(&self, key: &str) -> Result<std::fs::File> {
+        let path = self.inner.storage.dir.join(WRITE_LOCK_NAME);
+        self.observe(atomic::lock(&path), crate::Operation::Lock, key, || {
+            path.clone()
+        })
+    }
+
+    /// The stored bytes of a key, as written on disk
+    fn read_raw(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.read_stored(key)?.map(|(_, bytes)| bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Configstore, Error, Version};
+
+    #[test]
+    fn conditional_writes_detect_conflicts() {
+        let first = Configstore::temp().unwrap();
+        let second = first.reopen().build().unwrap();
+        let (value, version) = first.get_versioned::<u32>("shared").unwrap();
+        assert_eq!((value, version), (None, Version::ABSENT));
+
+        let created = first.set_if_unchanged("shared", 1, version).unwrap();
+        let err = second.set_if_unchanged("shared", 2, version).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::Conflict {
+                key: String::from("shared")
+            })
+        );
+
+        let (value, version) = second.get_versioned::<u32>("shared").unwrap();
+        assert_eq!((value, version), (Some(1), created));
+        second.set_if_unchanged("shared", 2, version).unwrap();
+        assert!(first.set_if_unchanged("shared", 3, created).is_err());
+        assert_eq!(first.get::<u32>("shared").unwrap(), 2);
+    }
+
+    #[test]
+    fn set_once_never_overwrites() {
+        let config_store = Configstore::temp().unwrap();
+        config_store.set_once("id", "first").unwrap();
+        let err = config_store.set_once("id", "second").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::AlreadyExists {
+                key: String::from("id")
+            })
+        );
+        assert_eq!(config_store.get::<String>("id").unwrap(), "first");
+        config_store.delete("id").unwrap();
+        config_store.set_once("id", "third").unwrap();
+    }
+
+    #[test]
+    fn compare_and_swap_elects_one_instance() {
+        let config_store = Configstore::temp().unwrap();
+        assert!(!config_store.compare_and_swap("leader", &0, 1).unwrap());
+        config_store.set("leader", 0).unwrap();
+        let elected: Vec<bool> = (1..=8)
+            .map(|pid| {
+                let store = config_store.reopen().build().unwrap();
+                std::thread::spawn(move || store.compare_and_swap("leader", &0, pid).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        assert_eq!(elected.iter().filter(|elected| **elected).count(), 1);
+        assert_ne!(config_store.get::<u32>("leader").unwrap(), 0);
+    }
+}